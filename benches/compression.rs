@@ -0,0 +1,179 @@
+//! Throughput and compression ratio of the recording path on representative frames.
+//!
+//! `save` and `load` go through `Saver`/`Loader` as used by the recorder and the player,
+//! the `zlib` group compresses the same frames at different levels to compare the
+//! trade-off against the default level used by `Saver`. The fixtures are generated from a
+//! fixed seed, so results are comparable across runs.
+
+use std::hint::black_box;
+use std::io::{Cursor, Write};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use ksana::SimInfo;
+use ksana::io::{Loader, Saver};
+
+/// Small xorshift generator, good enough for noise in the fixtures.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn noise(&mut self) -> f32 {
+        (self.next() % 1000) as f32 / 1000.0 - 0.5
+    }
+}
+
+/// 1MB iRacing-like frame: mostly floats slowly changing channels with some noise,
+/// per-car arrays and zeroed unused slots.
+fn iracing_frame() -> Vec<u8> {
+    let mut rng = Rng(0x1234_5678_9abc_def0);
+    let mut frame = Vec::with_capacity(1024 * 1024);
+    let mut channel = 0;
+    while frame.len() < 1024 * 1024 {
+        let value = match channel % 8 {
+            // unused car slots
+            0 | 1 => 0.0,
+            // integer-like values stored as floats (gear, lap, car index)
+            2 => (channel % 7) as f32,
+            _ => (channel as f32 * 0.01).sin() * 100.0 + rng.noise(),
+        };
+        frame.extend_from_slice(&value.to_le_bytes());
+        channel += 1;
+    }
+    frame
+}
+
+/// AC-like frame: 16 byte frame header, graphics with strings and ints, physics floats and
+/// a static page with names and versions followed by zero padding.
+fn ac_frame() -> Vec<u8> {
+    let mut rng = Rng(0x0fed_cba9_8765_4321);
+    let mut frame = vec![0x01];
+    frame.extend_from_slice(&[0; 15]);
+
+    // graphics
+    let mut graphics = Vec::new();
+    graphics.extend_from_slice(&1234i32.to_le_bytes());
+    graphics.extend_from_slice(&2i32.to_le_bytes());
+    for text in ["1:23.456", "1:22.987", "-:--.---"] {
+        for unit in text.encode_utf16().chain(std::iter::repeat(0)).take(15) {
+            graphics.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+    while graphics.len() < 2048 {
+        graphics.extend_from_slice(&((rng.next() % 64) as i32).to_le_bytes());
+    }
+    frame.extend_from_slice(&graphics[..2048]);
+
+    // physics
+    for i in 0..256 {
+        let value = (i as f32 * 0.1).cos() * 10.0 + rng.noise();
+        frame.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // statics
+    let mut statics = Vec::new();
+    for text in ["1.7", "1.16", "ks_porsche_911_gt3_r_2016", "spa"] {
+        for unit in text.encode_utf16().chain(std::iter::repeat(0)).take(33) {
+            statics.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+    statics.resize(2048, 0);
+    frame.extend_from_slice(&statics);
+
+    frame
+}
+
+fn fixtures() -> [(&'static str, Vec<u8>); 2] {
+    [("iracing", iracing_frame()), ("ac", ac_frame())]
+}
+
+fn info() -> SimInfo {
+    SimInfo {
+        id: *b"test",
+        payload_version: 2,
+        native_hz: 0,
+    }
+}
+
+fn record(frame: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut saver = Saver::new(&mut buffer, 60, info()).expect("header");
+    saver.save(frame).expect("save");
+    saver.finalize().expect("finalize");
+    buffer
+}
+
+fn bench_save(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save");
+    for (name, frame) in fixtures() {
+        let recorded = record(&frame);
+        println!(
+            "{}: {} -> {} bytes, ratio {:.2}",
+            name,
+            frame.len(),
+            recorded.len(),
+            frame.len() as f64 / recorded.len() as f64
+        );
+
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_function(name, |b| {
+            let mut buffer = Vec::with_capacity(frame.len());
+            b.iter(|| {
+                buffer.clear();
+                let mut saver = Saver::new(&mut buffer, 60, info()).expect("header");
+                saver.save(black_box(&frame)).expect("save");
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    for (name, frame) in fixtures() {
+        let recorded = record(&frame);
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut loader = Loader::new(Cursor::new(black_box(&recorded))).expect("header");
+                loader.load().expect("load")
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_zlib_levels(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zlib");
+    for (name, frame) in fixtures() {
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        for level in [1, 6, 9] {
+            let compress = |data: &[u8]| {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+                encoder.write_all(data).expect("compress");
+                encoder.finish().expect("compress")
+            };
+            println!(
+                "{} level {}: ratio {:.2}",
+                name,
+                level,
+                frame.len() as f64 / compress(&frame).len() as f64
+            );
+
+            group.bench_with_input(BenchmarkId::new(name, level), &frame, |b, frame| {
+                b.iter(|| compress(black_box(frame)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_save, bench_load, bench_zlib_levels);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to the Assetto Corsa `FrameData::deserialize`, as
+//! `AssettoCorsaPlayer::update` does with the frames of a recording. Frames shorter than
+//! the pages they contain must fail with an error instead of reading past the input.
+
+#![no_main]
+
+use ksana::sims::assettocorsa::data::FrameData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (bool, &[u8])| {
+    let (v1, bytes) = input;
+    let _ = FrameData::deserialize(bytes, if v1 { 1 } else { 2 });
+});
@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to the iRacing `FrameData::deserialize`, as `IRacingPlayer::update`
+//! does with the frames of a recording. Parsing must fail with an error instead of
+//! panicking or allocating more than the frame size.
+
+#![no_main]
+
+use ksana::sims::iracing::data;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (bool, &[u8])| {
+    let (v1, bytes) = input;
+    let _ = data::FrameData::deserialize(bytes, if v1 { 1 } else { 2 });
+});
@@ -0,0 +1,23 @@
+//! Feeds arbitrary bytes to `Loader` as if they were a recording. Loading must fail with
+//! an error instead of panicking or allocating based on the length fields of the file.
+//!
+//! Run with `cargo fuzz run loader` from the repository root, `corpus/loader` is seeded
+//! with a valid recording.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use ksana::io::Loader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut loader) = Loader::new(Cursor::new(data)) else {
+        return;
+    };
+
+    let _ = loader.duration_frames();
+
+    // encrypted files are not unlocked, deriving a key per input would be too slow
+    while let Ok(Some(_)) = loader.load() {}
+});
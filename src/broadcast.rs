@@ -0,0 +1,84 @@
+//! Fan-out of live frames to in-process consumers, e.g. a GUI showing the telemetry while it
+//! is being recorded.
+
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Hands every frame sent to it to all subscribers. Sending never blocks: a subscriber
+/// whose queue is full misses the frame, and dropped receivers are forgotten on the next
+/// send. Without subscribers a send costs a lock and nothing is copied.
+#[derive(Default)]
+pub struct FrameBroadcast {
+    subscribers: Mutex<Vec<SyncSender<Arc<[u8]>>>>,
+}
+
+impl FrameBroadcast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receiver of the frames sent from now on, queueing up to `capacity` frames the
+    /// subscriber hasn't taken yet.
+    pub fn subscribe(&self, capacity: usize) -> Receiver<Arc<[u8]>> {
+        let (sender, receiver) = sync_channel(capacity);
+        self.lock().push(sender);
+        receiver
+    }
+
+    /// Number of receivers that haven't been dropped as of the last send.
+    pub fn subscriber_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Queues `frame` for every subscriber with room for it, shared instead of copied per
+    /// subscriber.
+    pub fn send(&self, frame: &[u8]) {
+        let mut subscribers = self.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let frame: Arc<[u8]> = Arc::from(frame);
+        subscribers.retain(|sender| match sender.try_send(Arc::clone(&frame)) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SyncSender<Arc<[u8]>>>> {
+        // a subscriber list left by a panicking thread is still consistent
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast() {
+        let broadcast = FrameBroadcast::new();
+        // nobody listening
+        broadcast.send(b"lost");
+
+        let first = broadcast.subscribe(2);
+        let second = broadcast.subscribe(1);
+        assert_eq!(broadcast.subscriber_count(), 2);
+
+        broadcast.send(b"one");
+        broadcast.send(b"two");
+        assert_eq!(&*first.try_recv().unwrap(), b"one");
+        assert_eq!(&*first.try_recv().unwrap(), b"two");
+        assert!(first.try_recv().is_err());
+        // a full queue drops the newer frame instead of blocking the sender
+        assert_eq!(&*second.try_recv().unwrap(), b"one");
+        assert!(second.try_recv().is_err());
+
+        drop(second);
+        broadcast.send(b"three");
+        assert_eq!(broadcast.subscriber_count(), 1);
+        assert_eq!(&*first.try_recv().unwrap(), b"three");
+    }
+}
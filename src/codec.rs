@@ -0,0 +1,161 @@
+//! Compression of the frame data. The codec of a recording is stored in the file header
+//! as a byte id, and `CodecRegistry` maps the ids back to codecs when loading.
+
+use std::io::{Read, Write};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::io::IOError;
+
+pub const CODEC_ZLIB: u8 = 0;
+pub const CODEC_NONE: u8 = 1;
+
+pub trait Codec: Send {
+    fn compress(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Decompresses a frame of `raw_len` bytes. Must not produce more than `raw_len` bytes
+    /// whatever the input, the data comes from a file.
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>, IOError>;
+
+    /// Id stored in the file header, unique among the codecs of a registry.
+    fn id(&self) -> u8;
+
+    /// Sets the compression level, for codecs that have levels.
+    fn set_level(&mut self, _level: u32) {}
+}
+
+/// zlib, the default codec.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZlibCodec {
+    compression: Compression,
+}
+
+impl ZlibCodec {
+    /// zlib at `level` [0-9], the default is 6.
+    pub fn new(level: u32) -> Self {
+        Self {
+            compression: Compression::new(level.min(9)),
+        }
+    }
+}
+
+impl Codec for ZlibCodec {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
+        // writing to a Vec can't fail
+        let _ = encoder.write_all(data);
+        encoder.finish().unwrap_or_default()
+    }
+
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>, IOError> {
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(data)
+            .take(raw_len as u64)
+            .read_to_end(&mut decompressed)
+            .map_err(|_| IOError::DecompressionFailed)?;
+        Ok(decompressed)
+    }
+
+    fn id(&self) -> u8 {
+        CODEC_ZLIB
+    }
+
+    fn set_level(&mut self, level: u32) {
+        self.compression = Compression::new(level.min(9));
+    }
+}
+
+/// Stores the data as is, for when compressing costs more than the disk space.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoneCodec;
+
+impl Codec for NoneCodec {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>, IOError> {
+        if data.len() != raw_len {
+            return Err(IOError::DecompressionFailed);
+        }
+        Ok(data.to_vec())
+    }
+
+    fn id(&self) -> u8 {
+        CODEC_NONE
+    }
+}
+
+pub type CodecFactory = fn() -> Box<dyn Codec>;
+
+/// Codecs available for loading recordings, by id. The default registry has the built-in
+/// codecs; codecs of other ids can be registered to read recordings written with them.
+pub struct CodecRegistry {
+    factories: Vec<(u8, CodecFactory)>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        let mut registry = Self { factories: vec![] };
+        registry.register(CODEC_ZLIB, || Box::new(ZlibCodec::default()));
+        registry.register(CODEC_NONE, || Box::new(NoneCodec));
+        registry
+    }
+}
+
+impl CodecRegistry {
+    /// Registers the codec created by `factory` under `id`, replacing a codec registered
+    /// with the same id before.
+    pub fn register(&mut self, id: u8, factory: CodecFactory) {
+        self.factories.retain(|(existing, _)| *existing != id);
+        self.factories.push((id, factory));
+    }
+
+    pub fn create(&self, id: u8) -> Option<Box<dyn Codec>> {
+        self.factories
+            .iter()
+            .find(|(existing, _)| *existing == id)
+            .map(|(_, factory)| factory())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_codecs_round_trip() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 7) as u8).collect();
+        let registry = CodecRegistry::default();
+
+        for id in [CODEC_ZLIB, CODEC_NONE] {
+            let codec = registry.create(id).unwrap();
+            assert_eq!(codec.id(), id);
+            let compressed = codec.compress(&data);
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+        }
+
+        // never more than the frame claims
+        let zlib = ZlibCodec::default();
+        assert_eq!(
+            zlib.decompress(&zlib.compress(&data), 10).unwrap(),
+            &data[..10]
+        );
+        assert!(NoneCodec.decompress(&data, 10).is_err());
+
+        assert!(registry.create(42).is_none());
+    }
+
+    #[test]
+    fn test_zlib_level() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        let mut codec = ZlibCodec::default();
+        codec.set_level(0);
+        let stored = codec.compress(&data);
+        codec.set_level(9);
+        assert!(codec.compress(&data).len() < stored.len());
+        assert!(ZlibCodec::new(1).decompress(b"not zlib", 8).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+use crate::frames::DecodedFrames;
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::data::VarHeader;
+use crate::sims::iracing::vars;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangedChannelsError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Changed channels are only supported for iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Recording has no frame with var headers")]
+    NoVarHeaders,
+}
+
+/// Range and variance of one element of a channel over the frames, NaN values are skipped.
+#[derive(Default)]
+struct ElementStats {
+    count: u64,
+    mean: f64,
+    // sum of squared differences from the mean (Welford)
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl ElementStats {
+    fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.m2 / self.count as f64
+        }
+    }
+}
+
+/// How a channel behaved over a recording. Array channels are summed up over all their
+/// elements: the range covers every element and the variance is that of the element that
+/// varied most.
+#[derive(Debug, PartialEq)]
+struct ChannelSummary {
+    name: String,
+    unit: String,
+    min: f64,
+    max: f64,
+    variance: f64,
+}
+
+impl ChannelSummary {
+    fn changed(&self) -> bool {
+        self.max > self.min
+    }
+}
+
+struct ChannelStats {
+    name: String,
+    unit: String,
+    elements: Vec<ElementStats>,
+}
+
+impl ChannelStats {
+    fn summary(&self) -> Option<ChannelSummary> {
+        let seen: Vec<&ElementStats> = self.elements.iter().filter(|e| e.count > 0).collect();
+        if seen.is_empty() {
+            return None;
+        }
+        Some(ChannelSummary {
+            name: self.name.clone(),
+            unit: self.unit.clone(),
+            min: seen.iter().map(|e| e.min).fold(f64::INFINITY, f64::min),
+            max: seen.iter().map(|e| e.max).fold(f64::NEG_INFINITY, f64::max),
+            variance: seen.iter().map(|e| e.variance()).fold(0.0, f64::max),
+        })
+    }
+}
+
+/// Decodes every frame and sums up each channel, changed channels first by descending
+/// variance, then the constant ones in layout order. Channels are matched by name, so a
+/// layout change during the recording keeps the statistics of the channels in both.
+fn summarize<R: Read + Seek>(
+    loader: &mut Loader<R>,
+) -> Result<Vec<ChannelSummary>, ChangedChannelsError> {
+    let mut var_headers: Option<Vec<VarHeader>> = None;
+    let mut channels: Vec<ChannelStats> = Vec::new();
+    let mut index_by_name: HashMap<String, usize> = HashMap::new();
+
+    for frame in loader.iracing_frames() {
+        let frame = frame.map_err(ChangedChannelsError::FailedToLoadFrame)?;
+        if frame.var_headers.is_some() {
+            var_headers = frame.var_headers;
+        }
+        // var headers are only stored in full frames, the first one is normally the first frame
+        let Some(var_headers) = &var_headers else {
+            continue;
+        };
+
+        for vh in var_headers {
+            let name = vh.name_str().into_owned();
+            let index = *index_by_name.entry(name.clone()).or_insert_with(|| {
+                channels.push(ChannelStats {
+                    name,
+                    unit: vh.unit_str().into_owned(),
+                    elements: Vec::new(),
+                });
+                channels.len() - 1
+            });
+
+            let count = vh.count.max(0) as usize;
+            let elements = &mut channels[index].elements;
+            if elements.len() < count {
+                elements.resize_with(count, ElementStats::default);
+            }
+            for (i, element) in elements.iter_mut().take(count).enumerate() {
+                if let Some(value) = vars::read(vh, &frame.raw_data, i) {
+                    element.add(value.as_f64());
+                }
+            }
+        }
+    }
+
+    if var_headers.is_none() {
+        return Err(ChangedChannelsError::NoVarHeaders);
+    }
+
+    let (mut changed, constant): (Vec<_>, Vec<_>) = channels
+        .iter()
+        .filter_map(ChannelStats::summary)
+        .partition(ChannelSummary::changed);
+    changed.sort_by(|a, b| b.variance.total_cmp(&a.variance));
+    changed.extend(constant);
+    Ok(changed)
+}
+
+/// Whole numbers without decimals, others rounded to 4 decimals.
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.4}", value)
+    }
+}
+
+/// Prints which channels of an iRacing recording changed and which stayed constant, with
+/// their ranges. Constant channels, like most of the car setup, can be left out of exports.
+pub fn run(input_file: &str) -> Result<(), ChangedChannelsError> {
+    let file = File::open(input_file).map_err(ChangedChannelsError::FailedToOpenFile)?;
+    let mut loader =
+        Loader::new(BufReader::new(file)).map_err(ChangedChannelsError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(ChangedChannelsError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    let summaries = summarize(&mut loader)?;
+    let changed = summaries.iter().filter(|s| s.changed()).count();
+
+    println!("Changed channels: {}", changed);
+    for summary in summaries.iter().filter(|s| s.changed()) {
+        println!(
+            "{:<32} {:>12} .. {:<12} variance {:<12} {}",
+            summary.name,
+            format_value(summary.min),
+            format_value(summary.max),
+            format_value(summary.variance),
+            summary.unit
+        );
+    }
+
+    println!();
+    println!("Constant channels: {}", summaries.len() - changed);
+    for summary in summaries.iter().filter(|s| !s.changed()) {
+        println!(
+            "{:<32} {:>12} {}",
+            summary.name,
+            format_value(summary.min),
+            summary.unit
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::Saver;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_summarize() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 10,
+        };
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 10, info).unwrap();
+        // 2 seconds of the first lap while accelerating, so without braking
+        for frame in iracing_frames(10, 20) {
+            saver.save(&frame.unwrap()).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let mut loader = Loader::new(Cursor::new(&recording)).unwrap();
+        let summaries = summarize(&mut loader).unwrap();
+        let names: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+        // the engine revs vary the most, constant channels come last in layout order
+        assert_eq!(names[0], "RPM");
+        assert_eq!(names[names.len() - 2..], ["Lap", "Brake"]);
+
+        let time = summaries.iter().find(|s| s.name == "SessionTime").unwrap();
+        assert!(time.changed());
+        assert_eq!((time.min, time.max), (0.0, 1.9));
+        assert_eq!(time.unit, "s");
+
+        let lap = summaries.iter().find(|s| s.name == "Lap").unwrap();
+        assert!(!lap.changed());
+        assert_eq!((lap.min, lap.max, lap.variance), (1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_element_stats() {
+        let mut stats = ElementStats::default();
+        for value in [2.0, 4.0, f64::NAN, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.add(value);
+        }
+        assert_eq!(stats.count, 8);
+        assert_eq!((stats.min, stats.max), (2.0, 9.0));
+        assert_eq!(stats.variance(), 4.0);
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(3.0), "3");
+        assert_eq!(format_value(-1.0), "-1");
+        assert_eq!(format_value(0.123456), "0.1235");
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+
+use prost::Message;
+
+use crate::io::{IOError, Loader};
+use crate::telemetry::{Common, Decoder};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConvertError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Conversion is not supported for {0} recordings")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to decode frame {index}: {error}")]
+    FailedToDecodeFrame { index: u64, error: std::io::Error },
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(std::io::Error),
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// Length-delimited `Frame` messages of proto/telemetry.proto
+    #[default]
+    Protobuf,
+}
+
+/// `Frame` of proto/telemetry.proto, keep the tags in sync with the schema.
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoFrame {
+    #[prost(uint64, tag = "1")]
+    pub index: u64,
+    #[prost(double, tag = "2")]
+    pub time: f64,
+    #[prost(float, tag = "3")]
+    pub speed: f32,
+    #[prost(float, tag = "4")]
+    pub rpm: f32,
+    #[prost(sint32, tag = "5")]
+    pub gear: i32,
+    #[prost(float, tag = "6")]
+    pub throttle: f32,
+    #[prost(float, tag = "7")]
+    pub brake: f32,
+    #[prost(float, tag = "8")]
+    pub steering: f32,
+    #[prost(int32, tag = "9")]
+    pub lap: i32,
+}
+
+impl ProtoFrame {
+    /// Frame `index` of the recording, saved in tick `tick`, see `Loader::frame_tick`.
+    fn new(index: u64, tick: u64, fps: i32, common: Common) -> Self {
+        Self {
+            index,
+            time: tick as f64 / fps.max(1) as f64,
+            speed: common.speed,
+            rpm: common.rpm,
+            gear: common.gear,
+            throttle: common.throttle,
+            brake: common.brake,
+            steering: common.steering,
+            lap: common.lap,
+        }
+    }
+}
+
+/// Writes every frame of the recording to `writer` as a length-delimited message, returns
+/// the number of frames written.
+fn convert<R: Read + Seek, W: Write>(
+    loader: &mut Loader<R>,
+    mut writer: W,
+) -> Result<u64, ConvertError> {
+    let id = loader.id();
+    let Some(mut decoder) = Decoder::new(id, loader.payload_version()) else {
+        return Err(ConvertError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    };
+    let fps = loader.fps();
+
+    let mut index: u64 = 0;
+    while let Some(data) = loader.load().map_err(ConvertError::FailedToLoadFrame)? {
+        let common = decoder
+            .decode(&data)
+            .map_err(|error| ConvertError::FailedToDecodeFrame { index, error })?;
+        let message = ProtoFrame::new(index, loader.frame_tick(), fps, common)
+            .encode_length_delimited_to_vec();
+        writer
+            .write_all(&message)
+            .map_err(ConvertError::FailedToWriteOutput)?;
+        index += 1;
+    }
+
+    writer.flush().map_err(ConvertError::FailedToWriteOutput)?;
+    Ok(index)
+}
+
+/// Converts the recording to the sim independent channels in `format`, for pipelines that
+/// don't read ksana recordings.
+pub fn run(input_file: &str, output_file: &str, format: ConvertFormat) -> Result<(), ConvertError> {
+    let file = File::open(input_file).map_err(ConvertError::FailedToOpenFile)?;
+    let mut loader = Loader::new(BufReader::new(file)).map_err(ConvertError::FailedToReadHeader)?;
+
+    let output = File::create(output_file).map_err(ConvertError::FailedToWriteOutput)?;
+    let frames = match format {
+        ConvertFormat::Protobuf => convert(&mut loader, BufWriter::new(output))?,
+    };
+
+    println!("Converted {} frames to: {}", frames, output_file);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::codec::ZlibCodec;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::{FrameFlags, Saver, SaverOptions};
+    use crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION;
+    use std::io::Cursor;
+
+    fn recording(id: [u8; 4], frames: &[Vec<u8>]) -> Vec<u8> {
+        let info = SimInfo {
+            id,
+            payload_version: CURRENT_PAYLOAD_VERSION,
+            native_hz: 10,
+        };
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 10, info).unwrap();
+        for frame in frames {
+            saver.save(frame).unwrap();
+        }
+        saver.finalize().unwrap();
+        recording
+    }
+
+    #[test]
+    fn test_convert_round_trip() {
+        let frames: Vec<Vec<u8>> = iracing_frames(10, 30).map(Option::unwrap).collect();
+        let data = recording(*b"irac", &frames);
+
+        let mut output = Vec::new();
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(convert(&mut loader, &mut output).unwrap(), 30);
+
+        let mut stream = output.as_slice();
+        let decoded: Vec<ProtoFrame> = std::iter::from_fn(|| {
+            (!stream.is_empty()).then(|| ProtoFrame::decode_length_delimited(&mut stream).unwrap())
+        })
+        .collect();
+        assert_eq!(decoded.len(), frames.len());
+
+        let mut decoder = Decoder::new(*b"irac", CURRENT_PAYLOAD_VERSION).unwrap();
+        for (index, (decoded, frame)) in decoded.iter().zip(&frames).enumerate() {
+            let index = index as u64;
+            let expected = ProtoFrame::new(index, index, 10, decoder.decode(frame).unwrap());
+            assert_eq!(*decoded, expected);
+        }
+        assert!((decoded[29].time - 2.9).abs() < 1e-9);
+        assert!(decoded[29].speed > 0.0);
+    }
+
+    #[test]
+    fn test_convert_times_by_ticks() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: CURRENT_PAYLOAD_VERSION,
+            native_hz: 10,
+        };
+        let options = SaverOptions {
+            codec: Box::new(ZlibCodec::default()),
+            passphrase: None,
+            start_time: None,
+            frame_flags: true,
+        };
+        let mut data = Vec::new();
+        let mut saver = Saver::with_options(&mut data, 10, info, options).unwrap();
+        // saved in ticks 0, 5 and 6
+        for (frame, missed_ticks) in iracing_frames(10, 3).zip([0, 4, 0]) {
+            let flags = FrameFlags { missed_ticks };
+            saver.save_with_flags(&frame.unwrap(), flags).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let mut output = Vec::new();
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(convert(&mut loader, &mut output).unwrap(), 3);
+
+        let mut stream = output.as_slice();
+        let frames: Vec<(u64, f64)> = std::iter::from_fn(|| {
+            (!stream.is_empty()).then(|| ProtoFrame::decode_length_delimited(&mut stream).unwrap())
+        })
+        .map(|frame| (frame.index, frame.time))
+        .collect();
+        assert_eq!(frames, [(0, 0.0), (1, 0.5), (2, 0.6)]);
+    }
+
+    #[test]
+    fn test_convert_unsupported_sim() {
+        let data = recording(*b"pc2_", &[b"frame".to_vec()]);
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        assert!(matches!(
+            convert(&mut loader, Vec::new()),
+            Err(ConvertError::UnsupportedSim(sim)) if sim == "pc2_"
+        ));
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+use crate::commands::play::{PLAYABLE_SIMS, existing_mapping, sim_mappings};
+use crate::io::{IOError, Loader};
+use crate::shm::{SharedMemoryWriter, is_elevated};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DoctorError {
+    #[error("{failed} of {total} checks failed")]
+    ChecksFailed { failed: usize, total: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of one check, with what to do about it unless it passed.
+#[derive(Debug)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail,
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: String, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail,
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &'static str, detail: String, hint: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail,
+            hint: Some(hint),
+        }
+    }
+
+    fn print(&self) {
+        let status = match self.status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        println!("[{}] {:<12} {}", status, self.name, self.detail);
+        if let Some(hint) = self.hint {
+            println!("       {}", hint);
+        }
+    }
+}
+
+fn sim_name(id: &[u8; 4]) -> &str {
+    std::str::from_utf8(id).unwrap_or("????")
+}
+
+/// Whether the recording can be read, decrypting it with `key` and loading its first frame.
+fn check_recording<R: Read + Seek>(loader: &mut Loader<R>, key: Option<&str>) -> Check {
+    const NAME: &str = "Recording";
+
+    if loader.is_encrypted() {
+        let Some(key) = key else {
+            return Check::fail(
+                NAME,
+                "the recording is encrypted".to_string(),
+                "Pass the passphrase it was recorded with as --key",
+            );
+        };
+        loader.unlock(key);
+    }
+
+    match loader.load() {
+        Ok(Some(frame)) => Check::pass(
+            NAME,
+            format!(
+                "sim {}, {} fps, first frame of {} bytes",
+                sim_name(&loader.id()),
+                loader.fps(),
+                frame.len()
+            ),
+        ),
+        Ok(None) => Check::fail(
+            NAME,
+            "the recording has no frames".to_string(),
+            "Record again, the recorder stopped before the sim sent any data",
+        ),
+        Err(IOError::DecryptionFailed) => Check::fail(
+            NAME,
+            "failed to decrypt the first frame".to_string(),
+            "Check the passphrase passed as --key",
+        ),
+        Err(e) => Check::fail(
+            NAME,
+            format!("failed to load the first frame: {}", e),
+            "Try playing it with --salvage to skip corrupt frames",
+        ),
+    }
+}
+
+fn check_sim(id: &[u8; 4]) -> Check {
+    const NAME: &str = "Sim";
+
+    if PLAYABLE_SIMS.contains(&id) {
+        Check::pass(NAME, format!("{} recordings can be played", sim_name(id)))
+    } else {
+        Check::fail(
+            NAME,
+            format!("no player for {} recordings", sim_name(id)),
+            "Play it with a ksana version supporting the sim, or with --player file",
+        )
+    }
+}
+
+/// Whether the sim is running, i.e. the shared memory the player would create already exists,
+/// `existing` being the first of it that does.
+fn check_sim_running(id: &[u8; 4], existing: Option<&str>) -> Check {
+    const NAME: &str = "Sim running";
+
+    match existing {
+        Some(name) => Check::fail(
+            NAME,
+            format!("shared memory {} already exists", name),
+            "Close the sim and apps keeping its shared memory open, or play with --force",
+        ),
+        None if sim_mappings(id).is_empty() => Check::pass(
+            NAME,
+            "the player sends UDP packets, make sure the sim is closed".to_string(),
+        ),
+        None => Check::pass(NAME, "none of the shared memory exists".to_string()),
+    }
+}
+
+/// Whether the mappings of the player can be created. Existing ones are left alone, they are
+/// reported by `check_sim_running`.
+fn check_mappings(id: &[u8; 4]) -> Check {
+    const NAME: &str = "Mappings";
+
+    let mut created = Vec::new();
+    for name in sim_mappings(id) {
+        match SharedMemoryWriter::probe(name) {
+            Ok(true) => created.push(*name),
+            Ok(false) => {}
+            Err(e) => {
+                return Check::fail(
+                    NAME,
+                    e.to_string(),
+                    "Access denied (0x80070005) means another user or an elevated process holds the name",
+                );
+            }
+        }
+    }
+
+    if created.is_empty() {
+        Check::pass(NAME, "nothing to create".to_string())
+    } else {
+        Check::pass(NAME, format!("can create {}", created.join(", ")))
+    }
+}
+
+fn check_privileges(elevated: bool) -> Check {
+    const NAME: &str = "Privileges";
+
+    if elevated {
+        Check::warn(
+            NAME,
+            "running as administrator".to_string(),
+            "Only consumers also running as administrator can open the shared memory, run ksana without it",
+        )
+    } else {
+        Check::pass(NAME, "running without administrator rights".to_string())
+    }
+}
+
+/// Runs the checks for playing `input_file` without playing it, so failures are explained
+/// up front instead of by a cryptic error of the player. The mappings of the sim are only
+/// created when they don't exist yet, and dropped right away.
+pub fn run(input_file: &str, key: Option<&str>) -> Result<(), DoctorError> {
+    println!("Checking playback of: {}", input_file);
+
+    let mut checks = Vec::new();
+    let loader = File::open(input_file)
+        .map_err(IOError::from)
+        .and_then(|file| Loader::new(BufReader::new(file)));
+    match loader {
+        Ok(mut loader) => {
+            checks.push(check_recording(&mut loader, key));
+            let id = loader.id();
+            checks.push(check_sim(&id));
+            let existing = existing_mapping(sim_mappings(&id));
+            checks.push(check_sim_running(&id, existing));
+            checks.push(check_mappings(&id));
+        }
+        Err(e) => checks.push(Check::fail(
+            "Recording",
+            format!("failed to open: {}", e),
+            "Check the path, and that the file is a ksana recording (`ksana header` shows its header)",
+        )),
+    }
+    checks.push(check_privileges(is_elevated()));
+
+    for check in &checks {
+        check.print();
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(DoctorError::ChecksFailed {
+            failed,
+            total: checks.len(),
+        });
+    }
+    println!("Ready to play");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::Saver;
+    use std::io::Cursor;
+
+    fn recording(frames: u64) -> Vec<u8> {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 60, info).unwrap();
+        for frame in iracing_frames(60, frames) {
+            saver.save(&frame.unwrap()).unwrap();
+        }
+        saver.finalize().unwrap();
+        recording
+    }
+
+    #[test]
+    fn test_check_recording() {
+        let data = recording(2);
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        let check = check_recording(&mut loader, None);
+        assert_eq!(check.status, Status::Pass, "{:?}", check);
+        assert!(check.detail.starts_with("sim irac, 60 fps"));
+
+        let data = recording(0);
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(check_recording(&mut loader, None).status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_sim() {
+        assert_eq!(check_sim(b"irac").status, Status::Pass);
+        assert_eq!(check_sim(b"f1__").status, Status::Pass);
+        assert_eq!(check_sim(b"test").status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_sim_running() {
+        let check = check_sim_running(b"irac", Some("Local\\IRSDKMemMapFileName"));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.hint.unwrap().contains("--force"));
+        assert_eq!(check_sim_running(b"irac", None).status, Status::Pass);
+        assert_eq!(check_sim_running(b"dirt", None).status, Status::Pass);
+    }
+
+    #[test]
+    fn test_check_privileges() {
+        assert_eq!(check_privileges(false).status, Status::Pass);
+        assert_eq!(check_privileges(true).status, Status::Warn);
+    }
+}
@@ -0,0 +1,420 @@
+use std::f64::consts::TAU;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::SimInfo;
+use crate::codec::ZlibCodec;
+use crate::io::{IOError, Saver};
+use crate::sims::assettocorsa::data as ac;
+use crate::sims::iracing::data::{self as iracing, Header, StatusField, VarHeader};
+use crate::sims::iracing::vars::VarType;
+
+// Synthetic lap: 90 seconds around a 4km track
+const LAP_SECONDS: f64 = 90.0;
+const TRACK_LENGTH_M: f64 = 4000.0;
+
+// irsdk memory map layout of the generated frames: header, var headers, session info and
+// then the data buffers
+pub(crate) const IRSDK_NUM_BUFS: usize = 3;
+const IRSDK_BUF_ALIGN: usize = 4096;
+
+const SESSION_INFO: &str = "---
+WeekendInfo:
+ TrackName: ksana_synthetic
+ TrackDisplayName: Ksana Synthetic Track
+ TrackLength: 4.00 km
+DriverInfo:
+ DriverCarIdx: 0
+ Drivers:
+ - CarIdx: 0
+   UserName: Ksana Generator
+   CarScreenName: Synthetic Car
+...
+";
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sim {
+    Iracing,
+    Ac,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GenerateError {
+    #[error("Invalid fps: {0}")]
+    InvalidFps(u32),
+
+    #[error("Failed to create output file: {0}")]
+    FailedToCreateFile(std::io::Error),
+
+    #[error("Failed to serialize frame")]
+    FailedToSerializeFrame,
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(IOError),
+}
+
+/// Values of the synthetic car at `t` seconds: a lap of constant length with speed, rpm,
+/// gear and pedals following sine waves.
+struct Telemetry {
+    time: f64,
+    lap: i32,
+    lap_dist_pct: f64,
+    speed: f64,
+    rpm: f64,
+    gear: i32,
+    throttle: f64,
+    brake: f64,
+}
+
+impl Telemetry {
+    fn at(time: f64) -> Self {
+        let wave = (TAU * time / 15.0).sin();
+        Self {
+            time,
+            lap: (time / LAP_SECONDS) as i32 + 1,
+            lap_dist_pct: (time / LAP_SECONDS).fract(),
+            speed: TRACK_LENGTH_M / LAP_SECONDS + 15.0 * wave,
+            rpm: 6000.0 + 2000.0 * (TAU * time / 3.0).sin(),
+            gear: (4.0 + 2.0 * wave).round() as i32,
+            throttle: (0.5 + wave).clamp(0.0, 1.0),
+            brake: (-wave - 0.5).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// iRacing channels written by the generator, in raw data order.
+pub(crate) const IRACING_CHANNELS: [(&str, VarType, &str, &str); 9] = [
+    (
+        "SessionTime",
+        VarType::Double,
+        "s",
+        "Seconds since session start",
+    ),
+    ("SessionTick", VarType::Int, "", "Current update number"),
+    ("Lap", VarType::Int, "", "Laps started count"),
+    (
+        "LapDistPct",
+        VarType::Float,
+        "%",
+        "Percentage distance around lap",
+    ),
+    ("Speed", VarType::Float, "m/s", "GPS vehicle speed"),
+    ("RPM", VarType::Float, "revs/min", "Engine rpm"),
+    (
+        "Gear",
+        VarType::Int,
+        "",
+        "-1=reverse  0=neutral  1..n=current gear",
+    ),
+    (
+        "Throttle",
+        VarType::Float,
+        "%",
+        "0=off throttle to 1=full throttle",
+    ),
+    (
+        "Brake",
+        VarType::Float,
+        "%",
+        "0=brake released to 1=max pedal force",
+    ),
+];
+
+pub(crate) fn copy_str(dst: &mut [u8], src: &str) {
+    let len = src.len().min(dst.len() - 1);
+    dst[..len].copy_from_slice(&src.as_bytes()[..len]);
+}
+
+fn iracing_var_headers() -> (Vec<VarHeader>, usize) {
+    let mut offset = 0;
+    let headers = IRACING_CHANNELS
+        .iter()
+        .map(|&(name, var_type, unit, desc)| {
+            let mut vh = VarHeader {
+                var_type: var_type as i32,
+                offset: offset as i32,
+                count: 1,
+                ..Default::default()
+            };
+            copy_str(&mut vh.name, name);
+            copy_str(&mut vh.unit, unit);
+            copy_str(&mut vh.desc, desc);
+            offset += var_type.size();
+            vh
+        })
+        .collect();
+    (headers, offset)
+}
+
+/// Header of a memory map holding `var_headers`, `session_info_len` bytes of session info
+/// and `IRSDK_NUM_BUFS` data buffers of `buf_len` bytes.
+pub(crate) fn iracing_header(
+    var_headers: &[VarHeader],
+    buf_len: usize,
+    tick_rate: u32,
+    session_info_len: usize,
+) -> Header {
+    let var_header_offset = Header::SIZE;
+    let session_info_offset = var_header_offset + size_of_val(var_headers);
+    let buf_start = (session_info_offset + session_info_len).next_multiple_of(IRSDK_BUF_ALIGN);
+    let buf_stride = buf_len.next_multiple_of(IRSDK_BUF_ALIGN);
+
+    let mut header = Header {
+        ver: 2,
+        status: StatusField::Connected as i32,
+        tick_rate: tick_rate as i32,
+        session_info_update: 1,
+        session_info_len: session_info_len as i32,
+        session_info_offset: session_info_offset as i32,
+        num_vars: var_headers.len() as i32,
+        var_header_offset: var_header_offset as i32,
+        num_buf: IRSDK_NUM_BUFS as i32,
+        buf_len: buf_len as i32,
+        ..Default::default()
+    };
+    for (i, buf) in header.var_buf.iter_mut().take(IRSDK_NUM_BUFS).enumerate() {
+        buf.buf_offset = (buf_start + i * buf_stride) as i32;
+    }
+    header
+}
+
+fn iracing_raw_data(telemetry: &Telemetry, tick: i32, buf_len: usize) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(buf_len);
+    raw.extend_from_slice(&telemetry.time.to_le_bytes());
+    raw.extend_from_slice(&tick.to_le_bytes());
+    raw.extend_from_slice(&telemetry.lap.to_le_bytes());
+    for value in [telemetry.lap_dist_pct, telemetry.speed, telemetry.rpm] {
+        raw.extend_from_slice(&(value as f32).to_le_bytes());
+    }
+    raw.extend_from_slice(&telemetry.gear.to_le_bytes());
+    for value in [telemetry.throttle, telemetry.brake] {
+        raw.extend_from_slice(&(value as f32).to_le_bytes());
+    }
+    raw
+}
+
+/// Serialized iRacing frames, the first one carrying the var headers and session info
+/// like the first frame recorded from the sim.
+pub(crate) fn iracing_frames(fps: u32, frames: u64) -> impl Iterator<Item = Option<Vec<u8>>> {
+    let (var_headers, buf_len) = iracing_var_headers();
+    let mut header = iracing_header(&var_headers, buf_len, fps, SESSION_INFO.len());
+
+    (0..frames).map(move |index| {
+        // the sim rotates the data buffers, the one with the highest tick is the latest
+        let tick = index as i32 + 1;
+        header.var_buf[index as usize % IRSDK_NUM_BUFS].tick_count = tick;
+
+        let telemetry = Telemetry::at(index as f64 / fps as f64);
+        let first = index == 0;
+        iracing::FrameData {
+            header,
+            var_headers: first.then(|| var_headers.clone()),
+            session_info: first.then(|| SESSION_INFO.as_bytes().to_vec()),
+            raw_data: iracing_raw_data(&telemetry, tick, buf_len),
+        }
+        .serialize()
+    })
+}
+
+fn write_f32(content: &mut [u8], offset: usize, value: f64) {
+    content[offset..offset + 4].copy_from_slice(&(value as f32).to_le_bytes());
+}
+
+fn write_i32(content: &mut [u8], offset: usize, value: i32) {
+    content[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_wstr(content: &mut [u8], offset: usize, len: usize, value: &str) {
+    for (i, unit) in value.encode_utf16().take(len - 1).enumerate() {
+        content[offset + i * 2..offset + i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+}
+
+fn ac_statics() -> ac::StaticPage {
+    // SPageFileStatic: smVersion[15], acVersion[15], numberOfSessions, numCars,
+    // carModel[33], track[33]
+    let mut statics = ac::StaticPage::default();
+    write_wstr(&mut statics.content, 0, 15, "1.7");
+    write_wstr(&mut statics.content, 30, 15, "1.16");
+    write_i32(&mut statics.content, 60, 1);
+    write_i32(&mut statics.content, 64, 1);
+    write_wstr(&mut statics.content, 68, 33, "ksana_synthetic_car");
+    write_wstr(&mut statics.content, 134, 33, "ksana_synthetic");
+    statics
+}
+
+/// Serialized AC frames, the first one carrying the static page like the first frame
+/// recorded from the sim.
+pub(crate) fn ac_frames(fps: u32, frames: u64) -> impl Iterator<Item = Option<Vec<u8>>> {
+    let statics = ac_statics();
+
+    (0..frames).map(move |index| {
+        let telemetry = Telemetry::at(index as f64 / fps as f64);
+        let packet_id = index as i32 + 1;
+
+        let mut graphics = ac::GraphicsPage {
+            packet_id,
+            status: ac::AC_LIVE,
+            ..Default::default()
+        };
+        // SPageFileGraphic after packetId and status: session, 4 time strings of 15 wchars,
+        // then completedLaps
+        write_i32(&mut graphics.content, 4 + 4 * 30, telemetry.lap - 1);
+
+        // SPageFilePhysics: packetId, gas, brake, fuel, gear (0 = reverse), rpms,
+        // steerAngle, speedKmh
+        let mut physics = ac::PhysicsPage::default();
+        write_i32(&mut physics.content, 0, packet_id);
+        write_f32(&mut physics.content, 4, telemetry.throttle);
+        write_f32(&mut physics.content, 8, telemetry.brake);
+        write_f32(&mut physics.content, 12, 50.0);
+        write_i32(&mut physics.content, 16, telemetry.gear + 1);
+        write_i32(&mut physics.content, 20, telemetry.rpm as i32);
+        write_f32(&mut physics.content, 28, telemetry.speed * 3.6);
+
+        let frame = ac::FrameData {
+            graphics,
+            physics,
+            statics: (index == 0).then_some(statics),
+        };
+        Some(frame.serialize())
+    })
+}
+
+/// Writes `seconds` of synthetic frames for `sim` at `fps`. The output is deterministic,
+/// the same arguments always produce the same frames.
+fn write_recording<W: Write>(
+    writer: W,
+    sim: Sim,
+    seconds: u32,
+    fps: u32,
+) -> Result<u64, GenerateError> {
+    let info = match sim {
+        Sim::Iracing => SimInfo {
+            id: *b"irac",
+            payload_version: iracing::CURRENT_PAYLOAD_VERSION,
+            native_hz: fps,
+        },
+        Sim::Ac => SimInfo {
+            id: *b"acsa",
+            payload_version: ac::CURRENT_PAYLOAD_VERSION,
+            native_hz: 0,
+        },
+    };
+    let frames = u64::from(seconds) * u64::from(fps);
+
+    // no start time, so the same arguments always write the same file
+    let mut saver = Saver::with_start_time(
+        writer,
+        fps as i32,
+        info,
+        Box::new(ZlibCodec::default()),
+        None,
+        None,
+    )
+    .map_err(GenerateError::FailedToWriteOutput)?;
+    let serialized: Box<dyn Iterator<Item = Option<Vec<u8>>>> = match sim {
+        Sim::Iracing => Box::new(iracing_frames(fps, frames)),
+        Sim::Ac => Box::new(ac_frames(fps, frames)),
+    };
+    for frame in serialized {
+        let frame = frame.ok_or(GenerateError::FailedToSerializeFrame)?;
+        saver
+            .save(&frame)
+            .map_err(GenerateError::FailedToWriteOutput)?;
+    }
+    saver
+        .finalize()
+        .map_err(GenerateError::FailedToWriteOutput)?;
+
+    Ok(frames)
+}
+
+pub fn run(sim: Sim, seconds: u32, fps: u32, output_file: &str) -> Result<(), GenerateError> {
+    if fps == 0 {
+        return Err(GenerateError::InvalidFps(fps));
+    }
+    let file = File::create(output_file).map_err(GenerateError::FailedToCreateFile)?;
+    let frames = write_recording(BufWriter::new(file), sim, seconds, fps)?;
+
+    println!(
+        "Synthetic recording written to: {} ({} frames at {} fps)",
+        output_file, frames, fps
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Loader;
+    use crate::sims::iracing::vars::read_f64;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_generate_iracing() {
+        let mut buffer = Vec::new();
+        assert_eq!(
+            write_recording(&mut buffer, Sim::Iracing, 2, 10).unwrap(),
+            20
+        );
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.id(), *b"irac");
+        assert_eq!(loader.native_hz(), Some(10));
+        assert_eq!(loader.duration_frames().unwrap(), 20);
+
+        let first = loader.load().unwrap().unwrap();
+        let first = iracing::FrameData::deserialize(&first, loader.payload_version()).unwrap();
+        let var_headers = first.var_headers.unwrap();
+        assert!(first.session_info.is_some());
+        assert!(first.header.is_connected());
+        assert_eq!(first.header.buf_len as usize, first.raw_data.len());
+        assert_eq!(read_f64(&var_headers, &first.raw_data, "Lap", 0), Some(1.0));
+
+        let mut last = None;
+        while let Some(data) = loader.load().unwrap() {
+            let frame = iracing::FrameData::deserialize(&data, loader.payload_version()).unwrap();
+            assert!(frame.var_headers.is_none());
+            last = Some(frame);
+        }
+        let last = last.unwrap();
+        assert_eq!(
+            read_f64(&var_headers, &last.raw_data, "SessionTick", 0),
+            Some(20.0)
+        );
+        let time = read_f64(&var_headers, &last.raw_data, "SessionTime", 0).unwrap();
+        assert!((time - 1.9).abs() < 1e-9);
+        let latest = last.header.latest_buf_index();
+        assert_eq!(last.header.var_buf[latest].tick_count, 20);
+    }
+
+    #[test]
+    fn test_generate_ac() {
+        let mut buffer = Vec::new();
+        assert_eq!(write_recording(&mut buffer, Sim::Ac, 1, 5).unwrap(), 5);
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.id(), *b"acsa");
+
+        let first = loader.load().unwrap().unwrap();
+        let first = ac::FrameData::deserialize(&first, loader.payload_version()).unwrap();
+        assert_eq!(first.graphics.status, ac::AC_LIVE);
+        assert_eq!(first.statics.unwrap().ac_version_str(), "1.16");
+
+        let second = loader.load().unwrap().unwrap();
+        let second = ac::FrameData::deserialize(&second, loader.payload_version()).unwrap();
+        assert_eq!(second.graphics.packet_id, 2);
+        assert!(second.statics.is_none());
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        write_recording(&mut first, Sim::Iracing, 1, 60).unwrap();
+        write_recording(&mut second, Sim::Iracing, 1, 60).unwrap();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::io::{GZIP_MAGIC, HEADER_SIZE, header_fields};
+
+#[derive(thiserror::Error, Debug)]
+pub enum HeaderError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(std::io::Error),
+}
+
+/// Up to `HEADER_SIZE` bytes from the start of `reader`, decompressed if the file is gzip
+/// compressed. Shorter files return what they have.
+fn read_header<R: Read + Seek>(mut reader: R) -> std::io::Result<Vec<u8>> {
+    let mut magic = [0u8; 2];
+    let is_gzip = reader.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    if is_gzip {
+        MultiGzDecoder::new(reader)
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+    } else {
+        reader.take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    }
+    Ok(header)
+}
+
+/// Hex and ASCII table of `bytes`, 16 per row, prefixed with the offset.
+pub(crate) fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:04x}  {:<47}  {}", row * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints the header bytes of a recording and the fields they decode to. Only the header is
+/// read and nothing is validated, so it works for files that fail to load.
+pub fn run(input_file: &str) -> Result<(), HeaderError> {
+    let file = File::open(input_file).map_err(HeaderError::FailedToOpenFile)?;
+    let header = read_header(BufReader::new(file)).map_err(HeaderError::FailedToReadHeader)?;
+
+    println!("{}", hex_dump(&header));
+    if header.len() < HEADER_SIZE {
+        println!(
+            "File ends after {} of {} header bytes",
+            header.len(),
+            HEADER_SIZE
+        );
+    }
+    println!();
+
+    for field in header_fields(&header) {
+        println!(
+            "{:>2}..{:<2}  {:<16} {}",
+            field.offset,
+            field.offset + field.len,
+            field.name,
+            field.value
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::io::Saver;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_read_header() {
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(
+            &mut recording,
+            30,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 60,
+            },
+        )
+        .unwrap();
+        saver.save(b"frame").unwrap();
+        saver.finalize().unwrap();
+
+        let header = read_header(Cursor::new(&recording)).unwrap();
+        assert_eq!(header, &recording[..HEADER_SIZE]);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&recording).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(read_header(Cursor::new(&gzipped)).unwrap(), header);
+
+        assert_eq!(
+            read_header(Cursor::new(&recording[..10])).unwrap().len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = hex_dump(b"RECROCKS\x03\x00\x00\x00\x1e\x00\x00\x00irac");
+        assert_eq!(
+            dump,
+            "0000  52 45 43 52 4f 43 4b 53 03 00 00 00 1e 00 00 00  RECROCKS........\n\
+             0010  69 72 61 63                                      irac"
+        );
+    }
+}
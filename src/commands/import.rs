@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::SimInfo;
+use crate::codec::ZlibCodec;
+use crate::commands::generate::{IRACING_CHANNELS, IRSDK_NUM_BUFS, Sim, copy_str, iracing_header};
+use crate::io::{IOError, Saver};
+use crate::sims::iracing::data::{self as iracing, IRSDK_MAX_STRING, VarHeader};
+use crate::sims::iracing::vars::{self, VarType};
+
+const SESSION_INFO: &str = "---
+WeekendInfo:
+ TrackName: ksana_import
+ TrackDisplayName: Ksana Imported Telemetry
+DriverInfo:
+ DriverCarIdx: 0
+ Drivers:
+ - CarIdx: 0
+   UserName: Ksana Import
+...
+";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("Invalid fps: {0}")]
+    InvalidFps(u32),
+
+    #[error("CSV import is only supported for iRacing, got: {0:?}")]
+    UnsupportedSim(Sim),
+
+    #[error("Failed to read CSV file: {0}")]
+    FailedToReadFile(std::io::Error),
+
+    #[error("CSV file has no columns")]
+    NoColumns,
+
+    #[error("Invalid column name {0:?}, names must be unique and 1-31 characters")]
+    InvalidColumnName(String),
+
+    #[error("Line {line} has {actual} values, expected {expected}")]
+    WrongValueCount {
+        line: usize,
+        actual: usize,
+        expected: usize,
+    },
+
+    #[error("Invalid value {value:?} on line {line} in column {column}")]
+    InvalidValue {
+        line: usize,
+        column: String,
+        value: String,
+    },
+
+    #[error("Failed to create output file: {0}")]
+    FailedToCreateFile(std::io::Error),
+
+    #[error("Failed to serialize frame")]
+    FailedToSerializeFrame,
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(IOError),
+}
+
+/// Channels of a CSV file, one row per frame. A column is an int channel if every value in
+/// it is an integer.
+struct Table {
+    columns: Vec<String>,
+    integral: Vec<bool>,
+    rows: Vec<Vec<f64>>,
+}
+
+/// Parses a CSV file with a header row of channel names and one row of numbers per frame.
+/// Quoting isn't supported, empty values are 0 and blank lines are skipped.
+fn parse_csv(text: &str) -> Result<Table, ImportError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let columns: Vec<String> = match lines.next() {
+        Some((_, header)) => header.split(',').map(|c| c.trim().to_string()).collect(),
+        None => return Err(ImportError::NoColumns),
+    };
+    let mut seen = HashSet::new();
+    for column in &columns {
+        if column.is_empty() || column.len() >= IRSDK_MAX_STRING || !seen.insert(column) {
+            return Err(ImportError::InvalidColumnName(column.clone()));
+        }
+    }
+
+    let mut integral = vec![true; columns.len()];
+    let mut rows = Vec::new();
+    for (line, text) in lines {
+        let values: Vec<&str> = text.split(',').map(str::trim).collect();
+        if values.len() != columns.len() {
+            return Err(ImportError::WrongValueCount {
+                line,
+                actual: values.len(),
+                expected: columns.len(),
+            });
+        }
+
+        let mut row = Vec::with_capacity(values.len());
+        for (i, value) in values.into_iter().enumerate() {
+            if value.is_empty() {
+                row.push(0.0);
+                continue;
+            }
+            integral[i] &= value.parse::<i32>().is_ok();
+            row.push(value.parse().map_err(|_| ImportError::InvalidValue {
+                line,
+                column: columns[i].clone(),
+                value: value.to_string(),
+            })?);
+        }
+        rows.push(row);
+    }
+
+    Ok(Table {
+        columns,
+        integral,
+        rows,
+    })
+}
+
+/// Var headers for the columns of `table` in column order and the resulting buffer length.
+/// Channels the generator writes keep their iRacing type, unit and description, other
+/// columns become int or float channels.
+fn var_headers(table: &Table) -> (Vec<VarHeader>, usize) {
+    let mut offset = 0;
+    let headers = table
+        .columns
+        .iter()
+        .zip(&table.integral)
+        .map(|(name, &integral)| {
+            let known = IRACING_CHANNELS.iter().find(|channel| channel.0 == name);
+            let (var_type, unit, desc) = match known {
+                Some(&(_, var_type, unit, desc)) => (var_type, unit, desc),
+                None if integral => (VarType::Int, "", ""),
+                None => (VarType::Float, "", ""),
+            };
+
+            let mut vh = VarHeader {
+                var_type: var_type as i32,
+                offset: offset as i32,
+                count: 1,
+                ..Default::default()
+            };
+            copy_str(&mut vh.name, name);
+            copy_str(&mut vh.unit, unit);
+            copy_str(&mut vh.desc, desc);
+            offset += var_type.size();
+            vh
+        })
+        .collect();
+    (headers, offset)
+}
+
+/// Writes one iRacing frame per row of `table` at `fps`, the first one carrying the var
+/// headers and session info. Returns the number of frames written.
+fn write_recording<W: Write>(writer: W, table: &Table, fps: u32) -> Result<u64, ImportError> {
+    let (var_headers, buf_len) = var_headers(table);
+    let mut header = iracing_header(&var_headers, buf_len, fps, SESSION_INFO.len());
+
+    let info = SimInfo {
+        id: *b"irac",
+        payload_version: iracing::CURRENT_PAYLOAD_VERSION,
+        native_hz: fps,
+    };
+    let mut saver = Saver::with_start_time(
+        writer,
+        fps as i32,
+        info,
+        Box::new(ZlibCodec::default()),
+        None,
+        None,
+    )
+    .map_err(ImportError::FailedToWriteOutput)?;
+
+    for (index, row) in table.rows.iter().enumerate() {
+        // the sim rotates the data buffers, the one with the highest tick is the latest
+        header.var_buf[index % IRSDK_NUM_BUFS].tick_count = index as i32 + 1;
+
+        let mut raw_data = vec![0u8; buf_len];
+        for (vh, &value) in var_headers.iter().zip(row) {
+            vars::write(vh, &mut raw_data, 0, value).ok_or(ImportError::FailedToSerializeFrame)?;
+        }
+
+        let first = index == 0;
+        let frame = iracing::FrameData {
+            header,
+            var_headers: first.then(|| var_headers.clone()),
+            session_info: first.then(|| SESSION_INFO.as_bytes().to_vec()),
+            raw_data,
+        }
+        .serialize()
+        .ok_or(ImportError::FailedToSerializeFrame)?;
+        saver
+            .save(&frame)
+            .map_err(ImportError::FailedToWriteOutput)?;
+    }
+    saver.finalize().map_err(ImportError::FailedToWriteOutput)?;
+
+    Ok(table.rows.len() as u64)
+}
+
+/// Builds a recording for `sim` from a CSV file of channels, one frame per row at `fps`.
+pub fn run(csv_file: &str, sim: Sim, fps: u32, output_file: &str) -> Result<(), ImportError> {
+    if sim != Sim::Iracing {
+        return Err(ImportError::UnsupportedSim(sim));
+    }
+    if fps == 0 {
+        return Err(ImportError::InvalidFps(fps));
+    }
+
+    let text = std::fs::read_to_string(csv_file).map_err(ImportError::FailedToReadFile)?;
+    let table = parse_csv(&text)?;
+
+    let file = File::create(output_file).map_err(ImportError::FailedToCreateFile)?;
+    let frames = write_recording(BufWriter::new(file), &table, fps)?;
+
+    println!(
+        "Recording written to: {} ({} frames of {} channels at {} fps)",
+        output_file,
+        frames,
+        table.columns.len(),
+        fps
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Loader;
+    use crate::sims::iracing::vars::read_f64;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_csv() {
+        let table = parse_csv("Speed, Gear,Custom\n1.5,3,7\n\n2.5,,-1\n").unwrap();
+        assert_eq!(table.columns, ["Speed", "Gear", "Custom"]);
+        assert_eq!(table.integral, [false, true, true]);
+        assert_eq!(table.rows, [[1.5, 3.0, 7.0], [2.5, 0.0, -1.0]]);
+
+        assert!(matches!(parse_csv(""), Err(ImportError::NoColumns)));
+        assert!(matches!(
+            parse_csv("Speed,Speed\n1,2\n"),
+            Err(ImportError::InvalidColumnName(_))
+        ));
+        assert!(matches!(
+            parse_csv(&format!("{}\n1\n", "x".repeat(IRSDK_MAX_STRING))),
+            Err(ImportError::InvalidColumnName(_))
+        ));
+        assert!(matches!(
+            parse_csv("Speed,Gear\n1,2\n3\n"),
+            Err(ImportError::WrongValueCount {
+                line: 3,
+                actual: 1,
+                expected: 2
+            })
+        ));
+        assert!(matches!(
+            parse_csv("Speed\nfast\n"),
+            Err(ImportError::InvalidValue { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_import_iracing() {
+        let table = parse_csv("SessionTime,Speed,Lap,Custom\n0,10.5,1,0.25\n0.5,20,1,3\n").unwrap();
+
+        let mut buffer = Vec::new();
+        assert_eq!(write_recording(&mut buffer, &table, 2).unwrap(), 2);
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.id(), *b"irac");
+        assert_eq!(loader.native_hz(), Some(2));
+
+        let first = loader.load().unwrap().unwrap();
+        let first = iracing::FrameData::deserialize(&first, loader.payload_version()).unwrap();
+        let var_headers = first.var_headers.unwrap();
+        assert!(first.session_info.is_some());
+        assert_eq!(first.header.buf_len as usize, first.raw_data.len());
+        // SessionTime keeps the iRacing double type, Custom has a fraction so is a float
+        assert_eq!(var_headers[0].var_type, VarType::Double as i32);
+        assert_eq!(var_headers[0].unit_str(), "s");
+        assert_eq!(var_headers[3].var_type, VarType::Float as i32);
+        assert_eq!(
+            read_f64(&var_headers, &first.raw_data, "Speed", 0),
+            Some(10.5)
+        );
+
+        let second = loader.load().unwrap().unwrap();
+        let second = iracing::FrameData::deserialize(&second, loader.payload_version()).unwrap();
+        assert!(second.var_headers.is_none());
+        assert_eq!(
+            read_f64(&var_headers, &second.raw_data, "SessionTime", 0),
+            Some(0.5)
+        );
+        assert_eq!(
+            read_f64(&var_headers, &second.raw_data, "Custom", 0),
+            Some(3.0)
+        );
+        let latest = second.header.latest_buf_index();
+        assert_eq!(second.header.var_buf[latest].tick_count, 2);
+        assert!(loader.load().unwrap().is_none());
+    }
+}
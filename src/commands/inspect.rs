@@ -1,11 +1,138 @@
 use std::fs::File;
 use std::io::BufReader;
 
+use chrono::{DateTime, Local};
 use humantime::format_duration;
 
-use crate::{io::Loader, traits::PlayError};
+use crate::{
+    codec::CODEC_NONE,
+    io::{FrameFlags, Loader},
+    sims::assettocorsa::data::FrameData as AcFrameData,
+    traits::PlayError,
+};
 
-pub fn run(input_file: &str) -> Result<(), PlayError> {
+/// Capture quality summed up from the frame flags of a recording.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CaptureHealth {
+    missed_ticks: u64,
+    gaps: u64,
+    longest_gap: u16,
+}
+
+impl CaptureHealth {
+    fn add(&mut self, flags: FrameFlags) {
+        if flags.missed_ticks > 0 {
+            self.missed_ticks += u64::from(flags.missed_ticks);
+            self.gaps += 1;
+            self.longest_gap = self.longest_gap.max(flags.missed_ticks);
+        }
+    }
+}
+
+impl std::fmt::Display for CaptureHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ticks without new data in {} gaps (longest {})",
+            self.missed_ticks, self.gaps, self.longest_gap
+        )
+    }
+}
+
+// width of the longest bar of the frame size histogram
+const HISTOGRAM_WIDTH: u64 = 40;
+
+/// Frame sizes bucketed by powers of two, so a few frames much larger than the rest, e.g.
+/// the ones carrying an updated iRacing session info, stand out.
+#[derive(Debug, Default)]
+struct SizeHistogram {
+    // count of frames of [2^i, 2^(i+1)) bytes at index i, empty frames at 0
+    buckets: Vec<u64>,
+    frames: u64,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+impl SizeHistogram {
+    fn add(&mut self, size: u64) {
+        let bucket = size.max(1).ilog2() as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+
+        self.min = if self.frames == 0 {
+            size
+        } else {
+            self.min.min(size)
+        };
+        self.max = self.max.max(size);
+        self.frames += 1;
+        self.total += size;
+    }
+
+    /// A line per bucket from the smallest to the largest frames, with a bar scaled to the
+    /// fullest bucket.
+    fn lines(&self) -> Vec<String> {
+        let fullest = self.buckets.iter().copied().max().unwrap_or(0).max(1);
+        let first = self
+            .buckets
+            .iter()
+            .position(|&count| count > 0)
+            .unwrap_or(0);
+        self.buckets
+            .iter()
+            .enumerate()
+            .skip(first)
+            .map(|(i, &count)| {
+                // a bucket with any frames gets at least one mark
+                let width = (count * HISTOGRAM_WIDTH).div_ceil(fullest) as usize;
+                format!(
+                    "{:>6} - {:<6} {:<width$} {}",
+                    format_size(1 << i),
+                    format_size(1 << (i + 1)),
+                    "#".repeat(width),
+                    count,
+                    width = HISTOGRAM_WIDTH as usize
+                )
+            })
+            .collect()
+    }
+}
+
+/// Power of two byte counts in the largest unit they are whole in.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 && b % (1 << 20) == 0 => format!("{}MB", b >> 20),
+        b if b >= 1 << 10 && b % (1 << 10) == 0 => format!("{}KB", b >> 10),
+        b => format!("{}B", b),
+    }
+}
+
+/// Line with the game and shared memory versions of an Assetto Corsa recording, from the
+/// static page of its first frame.
+fn ac_versions_line(data: &[u8], payload_version: i32) -> Option<String> {
+    let statics = AcFrameData::deserialize(data, payload_version)
+        .ok()?
+        .statics?;
+    Some(format!(
+        "Game version: {} (shared memory {})",
+        statics.ac_version_str(),
+        statics.sm_version_str()
+    ))
+}
+
+/// Line with the frame count, `complete` if every frame up to the end of the file was read.
+fn frames_line(frames: u64, complete: bool) -> String {
+    match (frames, complete) {
+        (0, true) => "Recording is empty: 0 frames.".to_string(),
+        (frames, true) => format!("Total frames: {}", frames),
+        (frames, false) => format!("Stopped prematurely. Total frames: {}", frames),
+    }
+}
+
+pub fn run(input_file: &str, histogram: bool) -> Result<(), PlayError> {
     let file = match File::open(input_file) {
         Ok(f) => f,
         Err(e) => {
@@ -30,11 +157,41 @@ pub fn run(input_file: &str) -> Result<(), PlayError> {
         std::str::from_utf8(&id).unwrap_or("????"),
         fps
     );
+    match loader.native_hz() {
+        Some(hz) => println!("Native rate: {} Hz (recorded at {} fps)", hz, fps),
+        None => println!("Native rate: unknown"),
+    }
+    if let Some(start) = loader.start_time() {
+        println!(
+            "Started: {}",
+            DateTime::<Local>::from(start).format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    if let Some(version) = loader.writer_version() {
+        println!("Written by: ksana {}", version);
+    }
+    if loader.codec_id() == CODEC_NONE {
+        println!("Compression: none");
+    }
 
     let mut exited_cleanly = false;
     let mut frame_counter: u64 = 0;
+    let mut health = CaptureHealth::default();
+    let mut sizes = SizeHistogram::default();
     loop {
-        match loader.seek() {
+        let loaded = if frame_counter == 0 && &id == b"acsa" && !loader.is_encrypted() {
+            let payload_version = loader.payload_version();
+            loader.load().map(|data| {
+                data.map(|data| {
+                    if let Some(line) = ac_versions_line(&data, payload_version) {
+                        println!("{}", line);
+                    }
+                })
+            })
+        } else {
+            loader.seek()
+        };
+        match loaded {
             Ok(Some(data)) => data,
             Ok(None) => {
                 exited_cleanly = true;
@@ -46,13 +203,16 @@ pub fn run(input_file: &str) -> Result<(), PlayError> {
             }
         };
 
+        if let Some(flags) = loader.frame_flags() {
+            health.add(flags);
+        }
+        sizes.add(loader.frame_compressed_len() as u64);
         frame_counter += 1;
     }
 
-    if exited_cleanly {
-        println!("Total frames: {}", frame_counter);
-    } else {
-        println!("Stopped prematurely. Total frames: {}", frame_counter);
+    println!("{}", frames_line(frame_counter, exited_cleanly));
+    if exited_cleanly && frame_counter == 0 {
+        return Ok(());
     }
     println!(
         "Total duration: {}",
@@ -60,6 +220,107 @@ pub fn run(input_file: &str) -> Result<(), PlayError> {
             (frame_counter as f64 / fps as f64) as u64
         ))
     );
+    if loader.features().frame_flags {
+        println!("Capture: {}", health);
+    }
+    if histogram && sizes.frames > 0 {
+        println!(
+            "Compressed frame sizes: min {} bytes, max {} bytes, mean {} bytes",
+            sizes.min,
+            sizes.max,
+            sizes.total / sizes.frames
+        );
+        for line in sizes.lines() {
+            println!("{}", line);
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate;
+    use crate::sims::assettocorsa::data as ac;
+
+    #[test]
+    fn test_capture_health() {
+        let mut health = CaptureHealth::default();
+        for missed_ticks in [0, 2, 0, 5] {
+            health.add(FrameFlags { missed_ticks });
+        }
+        assert_eq!(
+            health.to_string(),
+            "7 ticks without new data in 2 gaps (longest 5)"
+        );
+    }
+
+    #[test]
+    fn test_ac_versions_line() {
+        let mut frames = generate::ac_frames(10, 2).map(Option::unwrap);
+        let payload_version = ac::CURRENT_PAYLOAD_VERSION;
+        assert_eq!(
+            ac_versions_line(&frames.next().unwrap(), payload_version).as_deref(),
+            Some("Game version: 1.16 (shared memory 1.7)")
+        );
+        // only the first frame carries the static page
+        assert_eq!(
+            ac_versions_line(&frames.next().unwrap(), payload_version),
+            None
+        );
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let mut sizes = SizeHistogram::default();
+        for size in [300, 400, 500, 700, 1500, 100_000] {
+            sizes.add(size);
+        }
+        assert_eq!((sizes.min, sizes.max, sizes.total), (300, 100_000, 103_400));
+
+        let lines = sizes.lines();
+        // 256B up to 128KB, the empty buckets in between included
+        assert_eq!(lines.len(), 9);
+        assert!(lines[0].starts_with("  256B - 512B   ########################################"));
+        assert!(lines[0].ends_with(" 3"));
+        assert!(lines[1].starts_with("  512B - 1KB    ##############"));
+        assert!(lines[3].ends_with(" 0"));
+        assert!(!lines[3].contains('#'));
+        assert!(lines[8].starts_with("  64KB - 128KB  ##############"));
+    }
+
+    #[test]
+    fn test_frames_line() {
+        assert_eq!(frames_line(0, true), "Recording is empty: 0 frames.");
+        assert_eq!(frames_line(3, true), "Total frames: 3");
+        assert_eq!(
+            frames_line(0, false),
+            "Stopped prematurely. Total frames: 0"
+        );
+    }
+
+    #[test]
+    fn test_inspect_empty_recording() {
+        let input =
+            std::env::temp_dir().join(format!("ksana_inspect_empty_{}.ksr", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"acsa",
+            payload_version: 1,
+            native_hz: 0,
+        };
+        let saver = crate::io::Saver::new(File::create(&input).unwrap(), 10, info).unwrap();
+        saver.finalize().unwrap();
+
+        assert!(run(input.to_str().unwrap(), true).is_ok());
+
+        std::fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(2048), "2KB");
+        assert_eq!(format_size(1 << 20), "1MB");
+    }
+}
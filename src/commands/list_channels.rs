@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::frames::DecodedFrames;
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::vars::VarType;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListChannelsError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Listing channels is only supported for iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Recording has no frame with var headers")]
+    NoVarHeaders,
+}
+
+pub fn run(input_file: &str) -> Result<(), ListChannelsError> {
+    let file = File::open(input_file).map_err(ListChannelsError::FailedToOpenFile)?;
+    let loader =
+        Loader::new(BufReader::new(file)).map_err(ListChannelsError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(ListChannelsError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    // var headers are only stored in full frames, the first one is normally the first frame
+    let mut var_headers = None;
+    for frame in loader.iracing_frames() {
+        let frame = frame.map_err(ListChannelsError::FailedToLoadFrame)?;
+        if frame.var_headers.is_some() {
+            var_headers = frame.var_headers;
+            break;
+        }
+    }
+    let var_headers = var_headers.ok_or(ListChannelsError::NoVarHeaders)?;
+
+    for vh in &var_headers {
+        let var_type = match VarType::from_raw(vh.var_type) {
+            Some(t) => format!("{:?}", t),
+            None => format!("Unknown({})", vh.var_type),
+        };
+        println!(
+            "{:<32} {:<10} {:>4} {:<12} {}",
+            vh.name_str(),
+            var_type,
+            vh.count,
+            vh.unit_str(),
+            vh.desc_str()
+        );
+    }
+
+    println!("Total channels: {}", var_headers.len());
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::commands::record::{ConnectPoll, ConnectorGuard, wait_for_connection};
+use crate::commands::timestamp;
+use crate::sims::iracing::connector::IRacingConnector;
+use crate::sims::iracing::data::{CURRENT_PAYLOAD_VERSION, IRSDK_MEMMAPFILENAME};
+use crate::sims::iracing::player::IRacingPlayer;
+use crate::sims::pcars2::connector::Pcars2Connector;
+use crate::sims::pcars2::player::Pcars2Player;
+use crate::sims::pcars2::shm::PCARS2_SHM;
+use crate::sleeper::{AdaptiveSleeper, Pacing, SystemClock};
+use crate::{Connector, Player};
+
+// the mirror stops like the recorder when the sim stops updating for this long
+const NO_DATA_SECONDS: u32 = 2;
+
+/// Sims with a single shared memory mapping that can be mirrored under another name.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MirrorSim {
+    Iracing,
+    Pcars2,
+}
+
+impl MirrorSim {
+    fn shm_name(self) -> &'static str {
+        match self {
+            Self::Iracing => IRSDK_MEMMAPFILENAME,
+            Self::Pcars2 => PCARS2_SHM,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MirrorError {
+    #[error("Target {0} is the mapping of the sim itself")]
+    SameMapping(String),
+
+    #[error("Failed to create mirror: {0}")]
+    FailedToCreateMirror(anyhow::Error),
+
+    #[error("Failed to update mirror: {0}")]
+    FailedToUpdateMirror(anyhow::Error),
+}
+
+pub enum MirrorFinished {
+    SimDisconnected,
+    QuitRequested,
+}
+
+/// Whether two mapping names refer to the same mapping of the session, names are case
+/// insensitive and `Local\` is the default namespace.
+pub(crate) fn same_mapping(a: &str, b: &str) -> bool {
+    let local = |name: &str| {
+        let lower = name.to_ascii_lowercase();
+        lower
+            .strip_prefix("local\\")
+            .map(str::to_string)
+            .unwrap_or(lower)
+    };
+    local(a) == local(b)
+}
+
+/// Writes every frame of `connector` to `player` as soon as it shows up, polling
+/// `poll_hz` times per second until the sim stops updating or quit is requested.
+fn mirror(
+    quit_flag: &AtomicBool,
+    mut connector: ConnectorGuard,
+    player: &mut dyn Player,
+    pacing: Pacing,
+    poll_hz: u32,
+) -> Result<MirrorFinished, MirrorError> {
+    let poll_ms = 1000.0 / poll_hz as f64;
+    let max_no_data = poll_hz * NO_DATA_SECONDS;
+    let mut no_data_count = 0;
+
+    while !quit_flag.load(Ordering::Relaxed) {
+        let start = pacing.clock.now();
+
+        match connector.update() {
+            Some(frame) => {
+                no_data_count = 0;
+                player
+                    .update(&frame)
+                    .map_err(MirrorError::FailedToUpdateMirror)?;
+            }
+            None => {
+                no_data_count += 1;
+                if no_data_count > max_no_data {
+                    return Ok(MirrorFinished::SimDisconnected);
+                }
+            }
+        }
+
+        let elapsed_ms = pacing.elapsed_ms(start);
+        if elapsed_ms < poll_ms {
+            pacing.sleeper.sleep_ms((poll_ms - elapsed_ms) as u64);
+        }
+    }
+
+    Ok(MirrorFinished::QuitRequested)
+}
+
+/// Copies the shared memory of `sim` to the mapping `to_name` in real time, for running a
+/// second consumer against its own copy. The layout is rebuilt from the same frames the
+/// recorder saves, so readers of the copy see what they would see reading the sim.
+pub fn run(
+    quit_flag: Arc<AtomicBool>,
+    sim: MirrorSim,
+    to_name: &str,
+    poll_hz: u32,
+) -> Result<MirrorFinished, MirrorError> {
+    if same_mapping(sim.shm_name(), to_name) {
+        return Err(MirrorError::SameMapping(to_name.to_string()));
+    }
+
+    let (connector, mut player): (Box<dyn Connector>, Box<dyn Player>) = match sim {
+        MirrorSim::Iracing => (
+            Box::new(IRacingConnector::default()),
+            Box::new(
+                IRacingPlayer::with_name(to_name, CURRENT_PAYLOAD_VERSION)
+                    .map_err(MirrorError::FailedToCreateMirror)?,
+            ),
+        ),
+        MirrorSim::Pcars2 => (
+            Box::new(Pcars2Connector::default()),
+            Box::new(Pcars2Player::with_name(to_name).map_err(MirrorError::FailedToCreateMirror)?),
+        ),
+    };
+
+    let sleeper = AdaptiveSleeper::default();
+    let clock = SystemClock::default();
+    let mut connectors = [connector];
+    let connector = wait_for_connection(
+        &quit_flag,
+        &mut connectors,
+        &sleeper,
+        ConnectPoll::default(),
+    );
+    let Some(connector) = connector else {
+        return Ok(MirrorFinished::QuitRequested);
+    };
+
+    println!(
+        "{} Mirroring {} to {}",
+        timestamp(),
+        sim.shm_name(),
+        to_name
+    );
+    let pacing = Pacing {
+        sleeper: &sleeper,
+        clock: &clock,
+    };
+    let result = mirror(&quit_flag, connector, &mut *player, pacing, poll_hz);
+    player.stop();
+
+    match &result {
+        Ok(MirrorFinished::SimDisconnected) => {
+            println!("{} Sim disconnected, mirror stopped", timestamp())
+        }
+        Ok(MirrorFinished::QuitRequested) => println!("{} Mirror stopped", timestamp()),
+        Err(_) => {}
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sleeper::MockClock;
+    use crate::{Clock, SimInfo};
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    struct FakeConnector {
+        responses: VecDeque<Option<Vec<u8>>>,
+    }
+
+    impl Connector for FakeConnector {
+        fn connect(&mut self) -> bool {
+            true
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            self.responses.pop_front().flatten()
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct FakePlayer {
+        frames: Vec<Vec<u8>>,
+    }
+
+    impl Player for FakePlayer {
+        fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+            self.frames.push(data.to_vec());
+            Ok(())
+        }
+
+        fn stop(&mut self) {}
+    }
+
+    #[test]
+    fn test_mirror_until_sim_stops() {
+        let quit_flag = AtomicBool::new(false);
+        let mut connector = FakeConnector {
+            responses: VecDeque::from([Some(b"first".to_vec()), None, Some(b"second".to_vec())]),
+        };
+        let mut player = FakePlayer::default();
+        let clock = MockClock::default();
+        let start = clock.now();
+
+        let result = mirror(
+            &quit_flag,
+            ConnectorGuard::new(&mut connector),
+            &mut player,
+            clock.pacing(),
+            10,
+        );
+        assert!(matches!(result, Ok(MirrorFinished::SimDisconnected)));
+        assert_eq!(player.frames, [b"first".to_vec(), b"second".to_vec()]);
+        // 3 polls and 21 without data, 100ms apart
+        assert_eq!(clock.now() - start, Duration::from_millis(2300));
+    }
+
+    #[test]
+    fn test_same_mapping() {
+        assert!(same_mapping(IRSDK_MEMMAPFILENAME, "irsdkmemmapfilename"));
+        assert!(same_mapping(PCARS2_SHM, "Local\\$PCARS2$"));
+        assert!(!same_mapping(IRSDK_MEMMAPFILENAME, "Local\\KsanaMirror"));
+    }
+}
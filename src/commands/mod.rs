@@ -1,3 +1,27 @@
+pub mod changed_channels;
+pub mod convert;
+pub mod doctor;
+pub mod generate;
+pub mod header;
+pub mod import;
 pub mod inspect;
+pub mod list_channels;
+pub mod mirror;
+pub mod optimize;
 pub mod play;
+pub mod play_multi;
+pub mod probe;
 pub mod record;
+pub mod session_info;
+pub mod split_laps;
+pub mod srt;
+pub mod track_map;
+
+/// Capacity of the file buffers of record and play. A compressed iRacing frame is around
+/// 0.5MB, so the default holds several frames per write or read syscall.
+pub const DEFAULT_IO_BUFFER_MB: u32 = 4;
+
+/// Local time prefix for the lifecycle messages of long running commands.
+pub fn timestamp() -> String {
+    format!("[{}]", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))
+}
@@ -0,0 +1,510 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, Timelike};
+
+use crate::SimInfo;
+use crate::codec::ZlibCodec;
+use crate::io::{IOError, Loader, Saver, SaverOptions};
+use crate::sims::assettocorsa::data::{FrameData as AcFrameData, StaticPage};
+use crate::sims::iracing::data::{FrameData as IRacingFrameData, VarHeader};
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum OptimizeError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to create output file: {0}")]
+    FailedToCreateFile(std::io::Error),
+
+    #[error("Failed to decode frame: {0}")]
+    FailedToDecodeFrame(std::io::Error),
+
+    #[error("Failed to serialize frame")]
+    FailedToSerializeFrame,
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(IOError),
+
+    #[error("Output file is the input file: {0}")]
+    OutputIsInput(String),
+
+    #[error(
+        "The recording has no capture start time to find a time of day in, give an offset like +90s instead"
+    )]
+    NoStartTime,
+}
+
+/// Point of a recording `--since` and `--until` refer to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimePoint {
+    /// Offset from the start of the recording
+    Offset(Duration),
+    /// Local time of day as time since midnight, found by the capture start time
+    TimeOfDay(Duration),
+}
+
+impl TimePoint {
+    /// Offset from the start of a recording captured from `start`. A time of day up to 12
+    /// hours before the start is the start, an earlier one is taken as the next day, for
+    /// sessions past midnight.
+    fn offset(self, start: Option<SystemTime>) -> Result<Duration, OptimizeError> {
+        let time_of_day = match self {
+            Self::Offset(offset) => return Ok(offset),
+            Self::TimeOfDay(time_of_day) => time_of_day,
+        };
+        let start = DateTime::<Local>::from(start.ok_or(OptimizeError::NoStartTime)?).time();
+        let start_of_day =
+            f64::from(start.num_seconds_from_midnight()) + f64::from(start.nanosecond()) / 1e9;
+
+        const DAY: f64 = 24.0 * 3600.0;
+        let mut offset = time_of_day.as_secs_f64() - start_of_day;
+        if offset < -DAY / 2.0 {
+            offset += DAY;
+        }
+        Ok(Duration::from_secs_f64(offset.max(0.0)))
+    }
+}
+
+/// Part of a recording to copy.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeWindow {
+    pub since: Option<TimePoint>,
+    pub until: Option<TimePoint>,
+}
+
+impl TimeWindow {
+    /// First save tick in the window and the tick the window ends before, at `fps` for a
+    /// recording captured from `start`.
+    fn ticks(
+        &self,
+        fps: i32,
+        start: Option<SystemTime>,
+    ) -> Result<(u64, Option<u64>), OptimizeError> {
+        let frame = |point: TimePoint| {
+            let offset = point.offset(start)?;
+            Ok((offset.as_secs_f64() * fps.max(1) as f64).round() as u64)
+        };
+        Ok((
+            self.since.map(frame).transpose()?.unwrap_or(0),
+            self.until.map(frame).transpose()?,
+        ))
+    }
+}
+
+/// Whether `output` names the existing file `input`, which creating the output would
+/// truncate before it is read.
+fn is_same_file(input: &str, output: &str) -> bool {
+    match (std::fs::canonicalize(input), std::fs::canonicalize(output)) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
+}
+
+/// Parses a point of a recording: a local time of day as `HH:MM[:SS]`, e.g. `14:32:00`, or
+/// an offset from its start as a duration or `+[[H:]M:]S`, e.g. `+90s`, `1m30s` or `+1:30`.
+pub fn parse_time_point(arg: &str) -> Result<TimePoint, String> {
+    let invalid = || {
+        format!(
+            "invalid time '{}', expected a time of day as HH:MM[:SS] or an offset like +90s",
+            arg
+        )
+    };
+    let offset = |arg: &str| {
+        humantime::parse_duration(arg)
+            .ok()
+            .or_else(|| parse_offset(arg))
+            .map(TimePoint::Offset)
+            .ok_or_else(invalid)
+    };
+
+    if let Some(rest) = arg.strip_prefix('+') {
+        return offset(rest);
+    }
+    if !arg.contains(':') {
+        return offset(arg);
+    }
+
+    let parts: Vec<&str> = arg.split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes] => (hours, minutes, "0"),
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        _ => return Err(invalid()),
+    };
+    let hours: u64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+    let seconds: f64 = seconds.parse().map_err(|_| invalid())?;
+    if hours >= 24 || minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return Err(invalid());
+    }
+    Ok(TimePoint::TimeOfDay(
+        Duration::from_secs((hours * 60 + minutes) * 60) + Duration::from_secs_f64(seconds),
+    ))
+}
+
+/// Parses an offset as `[[H:]M:]S`, with optional fractions of a second, e.g. `90`, `1:30`
+/// or `0:01:30.5`.
+fn parse_offset(arg: &str) -> Option<Duration> {
+    let parts: Vec<&str> = arg.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let (seconds, units) = parts.split_last()?;
+    let seconds: f64 = seconds.parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 || (!units.is_empty() && seconds >= 60.0) {
+        return None;
+    }
+
+    let mut total = 0u64;
+    for (i, unit) in units.iter().enumerate() {
+        let value: u64 = unit.parse().ok()?;
+        // minutes after hours are below 60
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        total = total * 60 + value;
+    }
+
+    Some(Duration::from_secs(total * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// State a frame may rely on earlier frames for: the iRacing var headers and session info
+/// and the AC static page. A trimmed recording starts with a frame carrying all of it, so
+/// it plays on its own.
+#[derive(Default)]
+struct CarriedState {
+    var_headers: Option<Vec<VarHeader>>,
+    session_info: Option<Vec<u8>>,
+    statics: Option<StaticPage>,
+}
+
+impl CarriedState {
+    /// Keeps the state of a frame that is skipped.
+    fn skip(&mut self, id: &[u8; 4], payload_version: i32, data: &[u8]) -> std::io::Result<()> {
+        match id {
+            b"irac" => {
+                let frame = IRacingFrameData::deserialize(data, payload_version)?;
+                self.var_headers = frame.var_headers.or(self.var_headers.take());
+                self.session_info = frame.session_info.or(self.session_info.take());
+            }
+            b"acsa" => {
+                let frame = AcFrameData::deserialize(data, payload_version)?;
+                self.statics = frame.statics.or(self.statics);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The first copied frame, with the state it lacks from the skipped frames.
+    fn complete(
+        &mut self,
+        id: &[u8; 4],
+        payload_version: i32,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, OptimizeError> {
+        // payload version 1 frames have no frame header to mark the added state with, and
+        // other sims write complete frames
+        if payload_version < 2 {
+            return Ok(data);
+        }
+
+        match id {
+            b"irac" => {
+                let mut frame = IRacingFrameData::deserialize(&data, payload_version)
+                    .map_err(OptimizeError::FailedToDecodeFrame)?;
+                if frame.var_headers.is_none() {
+                    frame.var_headers = self.var_headers.take();
+                }
+                if frame.session_info.is_none() {
+                    frame.session_info = self.session_info.take();
+                }
+                frame
+                    .serialize()
+                    .ok_or(OptimizeError::FailedToSerializeFrame)
+            }
+            b"acsa" => {
+                let mut frame = AcFrameData::deserialize(&data, payload_version)
+                    .map_err(OptimizeError::FailedToDecodeFrame)?;
+                if frame.statics.is_none() {
+                    frame.statics = self.statics.take();
+                }
+                Ok(frame.serialize())
+            }
+            _ => Ok(data),
+        }
+    }
+}
+
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Recompresses every frame of a recording at the given zlib level. The frames are copied
+/// as is, so the fps, sim id and payload version of the recording are kept. Encrypted
+/// recordings are re-encrypted with the same key. With `limit_frames` only the first
+/// frames are copied. With `salvage` corrupt frames are skipped instead of failing, which
+/// repairs a partially corrupt recording. `window` trims the recording to the frames
+/// between two offsets from its start or times of day, timed by the recording fps; the
+/// capture start time of the output is moved to the first copied frame. Frame flags are
+/// copied along.
+pub fn run(
+    input_file: &str,
+    output_file: &str,
+    level: u32,
+    key: Option<String>,
+    limit_frames: Option<u64>,
+    salvage: bool,
+    window: TimeWindow,
+) -> Result<(), OptimizeError> {
+    if is_same_file(input_file, output_file) {
+        return Err(OptimizeError::OutputIsInput(output_file.to_string()));
+    }
+    let file = File::open(input_file).map_err(OptimizeError::FailedToOpenFile)?;
+    let mut loader =
+        Loader::new(BufReader::new(file)).map_err(OptimizeError::FailedToReadHeader)?;
+
+    if loader.is_encrypted() {
+        let Some(key) = &key else {
+            return Err(OptimizeError::FailedToReadHeader(IOError::KeyRequired));
+        };
+        loader.unlock(key);
+    }
+
+    let fps = loader.fps();
+    let info = SimInfo {
+        id: loader.id(),
+        payload_version: loader.payload_version(),
+        native_hz: loader.native_hz().unwrap_or(0),
+    };
+
+    // the recording has no per-frame timestamps, frames are timed by their save tick
+    let (first_tick, end_tick) = window.ticks(fps, loader.start_time())?;
+
+    let output = File::create(output_file).map_err(OptimizeError::FailedToCreateFile)?;
+    let options = SaverOptions {
+        codec: Box::new(ZlibCodec::new(level)),
+        passphrase: key.as_deref().filter(|_| loader.is_encrypted()),
+        start_time: loader.tick_time(first_tick),
+        frame_flags: loader.features().frame_flags,
+    };
+    let mut saver = Saver::with_options(BufWriter::new(output), fps, info, options)
+        .map_err(OptimizeError::FailedToWriteOutput)?;
+    let mut carried = CarriedState::default();
+
+    let mut frames: u64 = 0;
+    while limit_frames.is_none_or(|limit| frames < limit) {
+        let loaded = if salvage {
+            loader.load_salvaging()
+        } else {
+            loader.load()
+        };
+        let Some(data) = loaded.map_err(OptimizeError::FailedToLoadFrame)? else {
+            break;
+        };
+        let tick = loader.frame_tick();
+        if end_tick.is_some_and(|end| tick >= end) {
+            break;
+        }
+
+        if tick < first_tick {
+            carried
+                .skip(&info.id, info.payload_version, &data)
+                .map_err(OptimizeError::FailedToDecodeFrame)?;
+            continue;
+        }
+        let mut flags = loader.frame_flags().unwrap_or_default();
+        let data = if frames == 0 && first_tick > 0 {
+            // the gap before the first frame counts from the new start
+            flags.missed_ticks = u16::try_from(tick - first_tick).unwrap_or(u16::MAX);
+            carried.complete(&info.id, info.payload_version, data)?
+        } else {
+            data
+        };
+
+        saver
+            .save_with_flags(&data, flags)
+            .map_err(OptimizeError::FailedToWriteOutput)?;
+        frames += 1;
+    }
+    saver
+        .finalize()
+        .map_err(OptimizeError::FailedToWriteOutput)?;
+
+    let before = file_size(input_file);
+    let after = file_size(output_file);
+    println!("Frames written: {}", frames);
+    if salvage {
+        println!("Salvaged: {}", loader.salvage_stats());
+    }
+    println!(
+        "Size: {} -> {} bytes ({:.1}%)",
+        before,
+        after,
+        after as f64 * 100.0 / before.max(1) as f64
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::FrameFlags;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_time_point() {
+        let offset = |secs: f64| Ok(TimePoint::Offset(Duration::from_secs_f64(secs)));
+        assert_eq!(parse_time_point("90"), offset(90.0));
+        assert_eq!(parse_time_point("+90s"), offset(90.0));
+        assert_eq!(parse_time_point("1m30s"), offset(90.0));
+        assert_eq!(parse_time_point("+1:30"), offset(90.0));
+        assert_eq!(parse_time_point("+1:02:03.5"), offset(3723.5));
+
+        let time_of_day = |secs: u64| Ok(TimePoint::TimeOfDay(Duration::from_secs(secs)));
+        assert_eq!(parse_time_point("14:32:00"), time_of_day(52_320));
+        assert_eq!(parse_time_point("14:32"), time_of_day(52_320));
+        assert_eq!(parse_time_point("0:00:05"), time_of_day(5));
+        for invalid in [
+            "", "+", "24:00", "14:60", "14:32:60", "1:2:3:4", "-5", "a:10", "+1:60", "inf",
+        ] {
+            assert!(parse_time_point(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_time_window() {
+        let window = TimeWindow {
+            since: Some(TimePoint::Offset(Duration::from_secs(2))),
+            until: Some(TimePoint::Offset(Duration::from_millis(4500))),
+        };
+        assert_eq!(window.ticks(60, None).unwrap(), (120, Some(270)));
+        assert_eq!(TimeWindow::default().ticks(60, None).unwrap(), (0, None));
+
+        // times of day are found by the start time, 14:30:00 local time
+        let start = Local
+            .with_ymd_and_hms(2026, 3, 19, 14, 30, 0)
+            .unwrap()
+            .into();
+        let at = |h: u64, m: u64, s: u64| {
+            TimePoint::TimeOfDay(Duration::from_secs((h * 60 + m) * 60 + s))
+        };
+        let window = TimeWindow {
+            since: Some(at(14, 32, 0)),
+            until: Some(at(14, 35, 30)),
+        };
+        assert_eq!(window.ticks(10, Some(start)).unwrap(), (1200, Some(3300)));
+        // before the start is the start, far before it the next day
+        assert_eq!(at(14, 0, 0).offset(Some(start)).unwrap(), Duration::ZERO);
+        assert_eq!(
+            at(0, 30, 0).offset(Some(start)).unwrap(),
+            Duration::from_secs(10 * 3600)
+        );
+        assert!(matches!(
+            window.ticks(10, None),
+            Err(OptimizeError::NoStartTime)
+        ));
+    }
+
+    #[test]
+    fn test_output_is_input() {
+        let path = std::env::temp_dir().join(format!("ksana_optimize_{}.ksr", std::process::id()));
+        let input = path.to_str().unwrap();
+        std::fs::write(&path, b"not a recording").unwrap();
+
+        let result = run(input, input, 9, None, None, false, TimeWindow::default());
+        assert!(matches!(result, Err(OptimizeError::OutputIsInput(_))));
+        // the input is left alone
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a recording");
+
+        // an output that doesn't exist yet isn't the input
+        let other = format!("{}.out", input);
+        assert!(!is_same_file(input, &other));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_window_counts_missed_ticks() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ksana_optimize_ticks_{}.ksr", std::process::id()));
+        let output = dir.join(format!(
+            "ksana_optimize_ticks_{}.out.ksr",
+            std::process::id()
+        ));
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let info = SimInfo {
+            id: *b"dirt",
+            payload_version: 1,
+            native_hz: 60,
+        };
+        let options = SaverOptions {
+            codec: Box::new(ZlibCodec::default()),
+            passphrase: None,
+            start_time: Some(start),
+            frame_flags: true,
+        };
+        let mut saver =
+            Saver::with_options(File::create(&input).unwrap(), 10, info, options).unwrap();
+        // saved in ticks 0, 4, 5 and 7
+        for (data, missed_ticks) in [(b"a", 0), (b"b", 3), (b"c", 0), (b"d", 1)] {
+            let flags = FrameFlags { missed_ticks };
+            saver.save_with_flags(data, flags).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let window = TimeWindow {
+            since: Some(TimePoint::Offset(Duration::from_millis(300))),
+            until: Some(TimePoint::Offset(Duration::from_millis(700))),
+        };
+        let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+        run(input, output, 9, None, None, false, window).unwrap();
+
+        let mut loader = Loader::new(File::open(output).unwrap()).unwrap();
+        assert_eq!(
+            loader.start_time(),
+            Some(start + Duration::from_millis(300))
+        );
+        assert_eq!(loader.load().unwrap().unwrap(), b"b");
+        assert_eq!(loader.frame_tick(), 1);
+        assert_eq!(loader.load().unwrap().unwrap(), b"c");
+        assert_eq!(loader.frame_tick(), 2);
+        assert!(loader.load().unwrap().is_none());
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_trimmed_start_carries_state() {
+        // only the first generated frame has the var headers and session info
+        let frames: Vec<Vec<u8>> = iracing_frames(10, 3).map(Option::unwrap).collect();
+        let mut carried = CarriedState::default();
+        carried.skip(b"irac", 2, &frames[0]).unwrap();
+        carried.skip(b"irac", 2, &frames[1]).unwrap();
+
+        let first = carried.complete(b"irac", 2, frames[2].clone()).unwrap();
+        let first = IRacingFrameData::deserialize(&first, 2).unwrap();
+        let original = IRacingFrameData::deserialize(&frames[0], 2).unwrap();
+        assert_eq!(first.var_headers, original.var_headers);
+        assert_eq!(first.session_info, original.session_info);
+        assert_eq!(
+            first.raw_data,
+            IRacingFrameData::deserialize(&frames[2], 2)
+                .unwrap()
+                .raw_data
+        );
+
+        // other sims' frames are complete already
+        assert_eq!(carried.complete(b"dirt", 2, vec![1, 2]).unwrap(), [1, 2]);
+    }
+}
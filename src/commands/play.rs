@@ -1,21 +1,470 @@
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, Write};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::io::Loader;
+use crate::Player;
+use crate::commands::timestamp;
+use crate::io::{IOError, Loader};
+use crate::shm::{EventHandle, mapping_exists};
 use crate::sims::assettocorsa::player::AssettoCorsaPlayer;
+use crate::sims::assettocorsa::shm::{AC_GRAPHICS_SHM, AC_PHYSICS_SHM, AC_STATIC_SHM};
+use crate::sims::beamng::player::BeamNGPlayer;
+use crate::sims::dirt::player::DirtPlayer;
+use crate::sims::f1::player::F1Player;
+use crate::sims::iracing::data::{IRSDK_DATAVALIDEVENTNAME, IRSDK_MEMMAPFILENAME};
 use crate::sims::iracing::player::IRacingPlayer;
-use crate::sleeper::AdaptiveSleeper;
+use crate::sims::pcars2::player::Pcars2Player;
+use crate::sims::pcars2::shm::PCARS2_SHM;
+use crate::sleeper::{AdaptiveSleeper, Pacing, QUIT_CHECK_MS, SystemClock};
 use crate::traits::PlayError;
-use crate::{Player, Sleeper};
 
 pub enum PlayResult {
     EndOfFile,
     QuitRequested,
 }
 
-pub fn run(quit_flag: Arc<AtomicBool>, input_file: &str) -> Result<PlayResult, PlayError> {
+/// Where played frames go.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlayerKind {
+    /// The shared memory or UDP port of the recorded sim
+    #[default]
+    Sim,
+    /// Nowhere, for timing the playback loop
+    Null,
+    /// A log line per frame with its size and checksum
+    File,
+}
+
+/// Discards the frames.
+struct NullPlayer;
+
+impl Player for NullPlayer {
+    fn update(&mut self, _data: &[u8]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+}
+
+/// Logs every frame instead of writing it to the sim, for checking what the loader returns
+/// and when without a consumer or the shared memory of Windows.
+struct FilePlayer<W: Write> {
+    writer: W,
+    start: Instant,
+    frames: u64,
+}
+
+impl<W: Write> FilePlayer<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+}
+
+impl<W: Write> Player for FilePlayer<W> {
+    fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        writeln!(
+            self.writer,
+            "{:>8} {:>10.1} ms {:>8} bytes crc32 {:08x}",
+            self.frames,
+            self.start.elapsed().as_secs_f64() * 1000.0,
+            data.len(),
+            crc.sum()
+        )?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            eprintln!("Failed to write player log: {}", e);
+        }
+    }
+}
+
+/// Sims `sim_player` has a player for.
+pub(crate) const PLAYABLE_SIMS: [&[u8; 4]; 6] =
+    [b"irac", b"acsa", b"dirt", b"bng_", b"f1__", b"pc2_"];
+
+/// Shared memory mappings the player of the sim `id` writes to, none for UDP sims.
+pub(crate) fn sim_mappings(id: &[u8; 4]) -> &'static [&'static str] {
+    match id {
+        b"irac" => &[IRSDK_MEMMAPFILENAME],
+        b"acsa" => &[AC_GRAPHICS_SHM, AC_PHYSICS_SHM, AC_STATIC_SHM],
+        b"pc2_" => &[PCARS2_SHM],
+        _ => &[],
+    }
+}
+
+/// First of `mappings` that already exists. Creating it again would open the mapping of
+/// the running sim, and the player would write over what the sim writes.
+pub(crate) fn existing_mapping<'a>(mappings: &[&'a str]) -> Option<&'a str> {
+    mappings.iter().copied().find(|name| mapping_exists(name))
+}
+
+/// Names the iRacing player writes to, the ones of the sim unless overridden to run several
+/// players side by side.
+struct IRacingNames<'a> {
+    map: &'a str,
+    event: &'a str,
+}
+
+/// Player writing to the shared memory or UDP port of the sim `id`.
+fn sim_player(
+    id: &[u8; 4],
+    pv: i32,
+    resequence: bool,
+    iracing: IRacingNames,
+) -> Result<Box<dyn Player>, PlayError> {
+    let player: Box<dyn Player> = match id {
+        b"irac" => {
+            let p = IRacingPlayer::with_names(iracing.map, iracing.event, pv)
+                .map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        b"acsa" => {
+            let p =
+                AssettoCorsaPlayer::new(pv, resequence).map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        b"dirt" => {
+            let p = DirtPlayer::new().map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        b"bng_" => {
+            let p = BeamNGPlayer::new().map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        b"f1__" => {
+            let p = F1Player::new().map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        b"pc2_" => {
+            let p = Pcars2Player::new().map_err(PlayError::FailedToCreatePlayer)?;
+            Box::new(p) as Box<dyn Player>
+        }
+        _ => {
+            return Err(PlayError::UnknownSimError(
+                std::str::from_utf8(id).unwrap_or("????").to_string(),
+            ));
+        }
+    };
+    Ok(player)
+}
+
+pub struct PlayOptions {
+    /// Passphrase of an encrypted recording
+    pub key: Option<String>,
+    /// Wait for new frames at the end of the file
+    pub follow: bool,
+    /// Skip over corrupt frames
+    pub salvage: bool,
+    /// Capacity of the file read buffer in MB
+    pub io_buffer_mb: u32,
+    /// Rewrite the AC packet ids to be consecutive
+    pub resequence: bool,
+    /// Frames played per recorded frame, synthesizing the ones in between. 1 plays the
+    /// recorded frames only
+    pub interpolate: u32,
+    /// Minimum rate the last frame is written at while no new frame is due
+    pub keepalive_hz: Option<u32>,
+    /// Maximum rate frames are written at, slowing playback down below real time if the
+    /// recording, with interpolation, is faster
+    pub max_fps: Option<u32>,
+    /// Where the frames are played to
+    pub player: PlayerKind,
+    /// Log file of the file player, `<input>.log` if None
+    pub player_log: Option<String>,
+    /// Play to the shared memory of the sim even if it already exists
+    pub force: bool,
+    /// iRacing shared memory to play to, `IRSDK_MEMMAPFILENAME` if None
+    pub map_name: Option<String>,
+    /// iRacing data valid event to signal, `IRSDK_DATAVALIDEVENTNAME` if None
+    pub event_name: Option<String>,
+    /// Skip frames the player fails to write instead of aborting, until this many fail in
+    /// a row. None aborts on the first failure
+    pub tolerant: Option<u32>,
+    /// Event the consumer signals when it is ready for the next frame, replacing the
+    /// recorded timing. Playback is timed if the event doesn't exist
+    pub sync_event: Option<String>,
+    /// How long playback may take to stop after quit is requested before the process exits
+    pub quit_timeout_ms: u64,
+    /// Write the first frame, then wait for Enter before playing the rest
+    pub start_paused: bool,
+}
+
+/// Time playback gets to stop after Ctrl+C unless given.
+pub const DEFAULT_QUIT_TIMEOUT_MS: u64 = 5000;
+
+/// Waits for quit to be requested, then calls `on_timeout` unless `done` is set within
+/// `timeout`. Returns early once `done` is set. An update blocked on a mapping a consumer
+/// holds can't be interrupted, so the process has to exit without stopping the player.
+fn watch_quit(
+    quit_flag: &AtomicBool,
+    done: &AtomicBool,
+    timeout: Duration,
+    on_timeout: impl FnOnce(),
+) {
+    let check = Duration::from_millis(QUIT_CHECK_MS);
+    while !quit_flag.load(Ordering::Relaxed) {
+        if done.load(Ordering::Relaxed) {
+            return;
+        }
+        std::thread::sleep(check);
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if done.load(Ordering::Relaxed) {
+            return;
+        }
+        std::thread::sleep(check.min(deadline.saturating_duration_since(Instant::now())));
+    }
+    if !done.load(Ordering::Relaxed) {
+        on_timeout();
+    }
+}
+
+/// Sets the flag when dropped, however playback ends.
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Consecutive failures allowed by `--tolerant` unless given.
+pub const DEFAULT_MAX_UPDATE_FAILURES: u32 = 10;
+
+/// Decides whether a failed player update aborts the playback. Some consumers briefly lock
+/// the mapping, so in tolerant mode the frame is skipped unless the failures go on.
+struct UpdateFailures {
+    max_consecutive: Option<u32>,
+    consecutive: u32,
+    skipped: u64,
+}
+
+impl UpdateFailures {
+    fn new(max_consecutive: Option<u32>) -> Self {
+        Self {
+            max_consecutive,
+            consecutive: 0,
+            skipped: 0,
+        }
+    }
+
+    fn check(&mut self, result: anyhow::Result<()>) -> Result<(), PlayError> {
+        let Err(error) = result else {
+            self.consecutive = 0;
+            return Ok(());
+        };
+        let Some(max) = self.max_consecutive else {
+            return Err(PlayError::FailedToUpdatePlayer(error));
+        };
+
+        self.consecutive += 1;
+        if self.consecutive >= max {
+            return Err(PlayError::TooManyUpdateFailures {
+                count: self.consecutive,
+                error,
+            });
+        }
+        self.skipped += 1;
+        eprintln!(
+            "Warning: failed to update player, skipping the frame: {}",
+            error
+        );
+        Ok(())
+    }
+}
+
+/// Re-writes the last played frame when nothing was written for an interval, for consumers
+/// that time out when the shared memory goes quiet, e.g. while following a recording.
+struct Keepalive {
+    interval: Duration,
+    last_write: Instant,
+    quit_flag: Arc<AtomicBool>,
+}
+
+impl Keepalive {
+    fn new(hz: u32, quit_flag: Arc<AtomicBool>, now: Instant) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / hz.max(1),
+            last_write: now,
+            quit_flag,
+        }
+    }
+
+    fn written(&mut self, now: Instant) {
+        self.last_write = now;
+    }
+
+    /// Sleeps `ms`, writing `frame` again whenever the interval passes on the way. Returns
+    /// early once quit is requested, without another write.
+    fn sleep_ms(
+        &mut self,
+        pacing: Pacing,
+        player: &mut dyn Player,
+        failures: &mut UpdateFailures,
+        frame: &[u8],
+        ms: u64,
+    ) -> Result<(), PlayError> {
+        let deadline = pacing.clock.now() + Duration::from_millis(ms);
+        loop {
+            let now = pacing.clock.now();
+            if now >= deadline || self.quit_flag.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let due = self.last_write + self.interval;
+            if now >= due {
+                // the iRacing player also signals the data valid event on every update
+                failures.check(player.update(frame))?;
+                self.written(pacing.clock.now());
+                continue;
+            }
+            pacing
+                .sleeper
+                .sleep_ms((due.min(deadline) - now).as_millis() as u64);
+        }
+    }
+}
+
+/// Milliseconds between recorded frames, 1000 / `fps` unless the `interpolate` frames
+/// written per recorded frame would exceed `max_fps`, then long enough to write at `max_fps`.
+fn recorded_tick_ms(fps: i32, interpolate: u32, max_fps: Option<u32>) -> f64 {
+    let tick_ms = 1000.0 / fps.max(1) as f64;
+    match max_fps {
+        Some(max_fps) => tick_ms.max(1000.0 * interpolate as f64 / max_fps.max(1) as f64),
+        None => tick_ms,
+    }
+}
+
+/// Wall clock times the frames are due at, counted from the first one. Sleeping until the
+/// next due time instead of for what is left of the current period keeps the whole
+/// millisecond sleeps and slow frames from adding up, so a long replay stays in sync with
+/// e.g. a video of the session.
+struct Schedule {
+    origin: Instant,
+    period_ms: f64,
+    frames: u64,
+}
+
+impl Schedule {
+    fn new(origin: Instant, period_ms: f64) -> Self {
+        Self {
+            origin,
+            period_ms,
+            frames: 0,
+        }
+    }
+
+    /// Counts from `now` again, after waiting for the recording to grow or when the period
+    /// changes.
+    fn restart(&mut self, now: Instant, period_ms: f64) {
+        *self = Self::new(now, period_ms);
+    }
+
+    /// Milliseconds from `now` until the next frame is due, 0 if it is already late.
+    fn next_ms(&mut self, now: Instant) -> u64 {
+        self.frames += 1;
+        let due_ms = self.period_ms * self.frames as f64;
+        let elapsed_ms = now.duration_since(self.origin).as_secs_f64() * 1000.0;
+        (due_ms - elapsed_ms).max(0.0) as u64
+    }
+}
+
+/// Waits for the consumer to signal `event` that it is ready for the next frame, true unless
+/// quit was requested first.
+fn wait_for_consumer(quit_flag: &AtomicBool, event: &EventHandle) -> bool {
+    while !quit_flag.load(Ordering::Relaxed) {
+        if event.wait(QUIT_CHECK_MS as u32) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Sleeps `ms`, keeping the last written frame alive if a keepalive is set.
+fn wait(
+    pacing: Pacing,
+    keepalive: &mut Option<Keepalive>,
+    player: &mut dyn Player,
+    failures: &mut UpdateFailures,
+    last: Option<&[u8]>,
+    ms: u64,
+) -> Result<(), PlayError> {
+    match (keepalive, last) {
+        (Some(keepalive), Some(frame)) => keepalive.sleep_ms(pacing, player, failures, frame, ms),
+        _ => {
+            pacing.sleeper.sleep_ms(ms);
+            Ok(())
+        }
+    }
+}
+
+pub fn run(
+    quit_flag: Arc<AtomicBool>,
+    input_file: &str,
+    options: PlayOptions,
+) -> Result<PlayResult, PlayError> {
+    let sleeper = AdaptiveSleeper::default();
+    let clock = SystemClock::default();
+    let pacing = Pacing {
+        sleeper: &sleeper,
+        clock: &clock,
+    };
+
+    let resume_flag = Arc::new(AtomicBool::new(false));
+    if options.start_paused {
+        std::thread::spawn({
+            let resume_flag = resume_flag.clone();
+            move || {
+                // also resumes at the end of the input, when there is no console to press Enter in
+                let _ = std::io::stdin().read_line(&mut String::new());
+                resume_flag.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    play(quit_flag, resume_flag, input_file, options, pacing)
+}
+
+/// Plays `input_file`. With `start_paused` playback waits after the first frame until
+/// `resume_flag` is set.
+fn play(
+    quit_flag: Arc<AtomicBool>,
+    resume_flag: Arc<AtomicBool>,
+    input_file: &str,
+    options: PlayOptions,
+    pacing: Pacing,
+) -> Result<PlayResult, PlayError> {
+    let PlayOptions {
+        key,
+        follow,
+        salvage,
+        io_buffer_mb,
+        resequence,
+        mut interpolate,
+        keepalive_hz,
+        max_fps,
+        player: player_kind,
+        player_log,
+        force,
+        map_name,
+        event_name,
+        tolerant,
+        sync_event,
+        quit_timeout_ms,
+        start_paused,
+    } = options;
+
     let file = match File::open(input_file) {
         Ok(f) => f,
         Err(e) => {
@@ -23,7 +472,7 @@ pub fn run(quit_flag: Arc<AtomicBool>, input_file: &str) -> Result<PlayResult, P
         }
     };
 
-    let reader = BufReader::new(file);
+    let reader = BufReader::with_capacity(io_buffer_mb as usize * 1024 * 1024, file);
     let mut loader = match Loader::new(reader) {
         Ok(l) => l,
         Err(e) => {
@@ -31,68 +480,741 @@ pub fn run(quit_flag: Arc<AtomicBool>, input_file: &str) -> Result<PlayResult, P
         }
     };
 
-    let fps = loader.fps();
+    if loader.is_encrypted() {
+        let Some(key) = key else {
+            return Err(PlayError::FailedToReadHeader(IOError::KeyRequired));
+        };
+        loader.unlock(&key);
+    }
+
+    // the loader rejects a rate below 1, a timer dividing by it must not loop on NaN anyway
+    let fps = loader.fps().max(1);
     let id = loader.id();
 
     println!(
-        "Playing: {} (sim: {}, fps: {})",
+        "{} Playing: {} (sim: {}, fps: {})",
+        timestamp(),
         input_file,
         std::str::from_utf8(&id).unwrap_or("????"),
         fps
     );
+    if interpolate > 1 {
+        println!("Interpolating to {} fps", fps * interpolate as i32);
+    }
+    let mut tick_ms = recorded_tick_ms(fps, interpolate, max_fps);
+    if let Some(max_fps) = max_fps
+        && tick_ms > recorded_tick_ms(fps, interpolate, None)
+    {
+        println!(
+            "Capping playback at {} fps, {:.0}% of real time",
+            max_fps,
+            100.0 * recorded_tick_ms(fps, interpolate, None) / tick_ms
+        );
+    }
+    if follow {
+        println!("Following the file, waiting for new frames at the end");
+    } else if let Ok(frames) = loader.duration_frames() {
+        // e.g. the sim disconnected right after the recorder started, nothing to play
+        if frames == 0 {
+            println!("Recording is empty");
+            return Ok(PlayResult::EndOfFile);
+        }
+        println!(
+            "Duration: {}",
+            humantime::format_duration(std::time::Duration::from_secs(frames / fps as u64))
+        );
+    }
 
     let pv = loader.payload_version();
-    let mut player: Box<dyn Player> = match &id {
-        b"irac" => {
-            let p = IRacingPlayer::new(pv).map_err(PlayError::FailedToCreatePlayer)?;
-            Box::new(p) as Box<dyn Player>
+    if (map_name.is_some() || event_name.is_some()) && &id != b"irac" {
+        println!("Map and event names ignored, only the iRacing player can be renamed");
+    }
+    let iracing = IRacingNames {
+        map: map_name.as_deref().unwrap_or(IRSDK_MEMMAPFILENAME),
+        event: event_name.as_deref().unwrap_or(IRSDK_DATAVALIDEVENTNAME),
+    };
+    let mut player: Box<dyn Player> = match player_kind {
+        PlayerKind::Sim => {
+            let mappings = match &id {
+                b"irac" => vec![iracing.map],
+                _ => sim_mappings(&id).to_vec(),
+            };
+            if let Some(name) = existing_mapping(&mappings) {
+                if !force {
+                    return Err(PlayError::SimRunning(name.to_string()));
+                }
+                println!("Warning: {} already exists, playing anyway", name);
+            }
+            sim_player(&id, pv, resequence, iracing)?
         }
-        b"acsa" => {
-            let p = AssettoCorsaPlayer::new(pv).map_err(PlayError::FailedToCreatePlayer)?;
-            Box::new(p) as Box<dyn Player>
+        PlayerKind::Null => {
+            println!("Discarding the frames");
+            Box::new(NullPlayer)
         }
-        _ => {
-            return Err(PlayError::UnknownSimError(
-                std::str::from_utf8(&id).unwrap_or("????").to_string(),
-            ));
+        PlayerKind::File => {
+            let log_file = player_log.unwrap_or_else(|| format!("{}.log", input_file));
+            let file =
+                File::create(&log_file).map_err(|e| PlayError::FailedToCreatePlayer(e.into()))?;
+            println!("Logging the frames to: {}", log_file);
+            Box::new(FilePlayer::new(BufWriter::new(file)))
         }
     };
 
-    println!("Player ready, starting playback");
+    // the consumer creates the event, without it there is nobody to pace to
+    let sync_event = sync_event.and_then(|name| match EventHandle::open(&name) {
+        Ok(event) => {
+            println!("Writing a frame whenever {} is signaled", name);
+            Some(event)
+        }
+        Err(e) => {
+            println!("Warning: {}, playing at the recorded rate", e);
+            None
+        }
+    });
+
+    let done = Arc::new(AtomicBool::new(false));
+    let _done = SetOnDrop(done.clone());
+    std::thread::spawn({
+        let quit_flag = quit_flag.clone();
+        move || {
+            watch_quit(
+                &quit_flag,
+                &done,
+                Duration::from_millis(quit_timeout_ms),
+                || {
+                    eprintln!(
+                        "Playback didn't stop within {} ms, exiting without stopping the player",
+                        quit_timeout_ms
+                    );
+                    std::process::exit(130);
+                },
+            )
+        }
+    });
 
-    let sleeper = AdaptiveSleeper::default();
-    let tick_ms = 1000.0 / fps as f64;
+    println!("{} Player ready, starting playback", timestamp());
+
+    let expected_frame_size = player.expected_frame_size();
 
     let mut result = PlayResult::QuitRequested;
+    // last recorded frame played, kept for interpolating towards the next one and for the
+    // keepalive
+    let mut previous: Option<Vec<u8>> = None;
+    let mut keepalive =
+        keepalive_hz.map(|hz| Keepalive::new(hz, quit_flag.clone(), pacing.clock.now()));
+    let mut failures = UpdateFailures::new(tolerant);
+    let mut paused = start_paused;
 
-    while !quit_flag.load(Ordering::Relaxed) {
-        let start = std::time::Instant::now();
+    let mut schedule = Schedule::new(pacing.clock.now(), tick_ms / interpolate as f64);
 
-        let frame = match loader.load() {
+    while !quit_flag.load(Ordering::Relaxed) {
+        let loaded = if follow {
+            loader.load_available()
+        } else if salvage {
+            loader.load_salvaging()
+        } else {
+            loader.load()
+        };
+        let frame = match loaded {
             Ok(Some(data)) => data,
+            Ok(None) if follow => {
+                // the recorder has not written the next frame yet
+                let last = previous.as_deref();
+                wait(
+                    pacing,
+                    &mut keepalive,
+                    &mut *player,
+                    &mut failures,
+                    last,
+                    tick_ms as u64,
+                )?;
+                // the next frame plays as soon as it is written, not catching up on the wait
+                schedule.restart(pacing.clock.now(), tick_ms / interpolate as f64);
+                continue;
+            }
             Ok(None) => {
                 result = PlayResult::EndOfFile;
                 break;
             }
+            Err(IOError::TruncatedFrame) => {
+                // a recording interrupted by a crash, play it up to the last complete frame
+                eprintln!("Warning: {}", IOError::TruncatedFrame);
+                result = PlayResult::EndOfFile;
+                break;
+            }
             Err(e) => {
                 return Err(PlayError::FailedToLoadFrame(e));
             }
         };
 
-        if let Err(e) = player.update(&frame) {
-            return Err(PlayError::FailedToUpdatePlayer(e));
+        if let Some(expected) = &expected_frame_size
+            && !expected.contains(&frame.len())
+        {
+            return Err(PlayError::FrameSizeMismatch {
+                actual: frame.len(),
+                min: *expected.start(),
+                max: *expected.end(),
+            });
+        }
+
+        if let Some(previous) = &previous {
+            for step in 1..interpolate {
+                if quit_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let t = step as f64 / interpolate as f64;
+                let synthesized = player
+                    .interpolate(previous, &frame, t)
+                    .map_err(PlayError::FailedToUpdatePlayer)?;
+                let Some(synthesized) = synthesized else {
+                    eprintln!("Warning: interpolation is not supported for this recording");
+                    tick_ms = recorded_tick_ms(fps, 1, max_fps);
+                    schedule.restart(pacing.clock.now(), tick_ms);
+                    interpolate = 1;
+                    break;
+                };
+                failures.check(player.update(&synthesized))?;
+                if let Some(keepalive) = &mut keepalive {
+                    keepalive.written(pacing.clock.now());
+                }
+                let ms = schedule.next_ms(pacing.clock.now());
+                wait(
+                    pacing,
+                    &mut keepalive,
+                    &mut *player,
+                    &mut failures,
+                    Some(&synthesized),
+                    ms,
+                )?;
+            }
+        }
+
+        // no new write once quit was requested, the player is stopped instead
+        if quit_flag.load(Ordering::Relaxed) {
+            break;
+        }
+        if let Some(event) = &sync_event
+            && !wait_for_consumer(&quit_flag, event)
+        {
+            break;
+        }
+
+        failures.check(player.update(&frame))?;
+        if let Some(keepalive) = &mut keepalive {
+            keepalive.written(pacing.clock.now());
         }
 
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-        if elapsed_ms < tick_ms {
-            sleeper.sleep_ms((tick_ms - elapsed_ms) as u64);
+        if paused {
+            paused = false;
+            println!("{} Paused at frame 0, press Enter to play", timestamp());
+            while !resume_flag.load(Ordering::Relaxed) && !quit_flag.load(Ordering::Relaxed) {
+                wait(
+                    pacing,
+                    &mut keepalive,
+                    &mut *player,
+                    &mut failures,
+                    Some(&frame),
+                    QUIT_CHECK_MS,
+                )?;
+            }
+            // the second frame is due a period after resuming, not when it was recorded
+            schedule.restart(pacing.clock.now(), tick_ms / interpolate as f64);
+        }
+
+        if sync_event.is_none() {
+            let ms = schedule.next_ms(pacing.clock.now());
+            wait(
+                pacing,
+                &mut keepalive,
+                &mut *player,
+                &mut failures,
+                Some(&frame),
+                ms,
+            )?;
         }
+        previous = Some(frame);
     }
 
     player.stop();
 
-    println!("Player stopped.");
+    if salvage {
+        println!("Salvaged: {}", loader.salvage_stats());
+    }
+    if tolerant.is_some() {
+        println!("Frames skipped after failed updates: {}", failures.skipped);
+    }
+
+    println!("{} Player stopped.", timestamp());
     println!("You can now close this window.");
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sleeper::MockClock;
+    use crate::{Clock, Sleeper};
+
+    #[derive(Default)]
+    struct CountingPlayer {
+        updates: usize,
+    }
+
+    impl Player for CountingPlayer {
+        fn update(&mut self, _data: &[u8]) -> anyhow::Result<()> {
+            self.updates += 1;
+            Ok(())
+        }
+
+        fn stop(&mut self) {}
+    }
+
+    #[test]
+    fn test_keepalive_rewrites_during_wait() {
+        let clock = MockClock::default();
+        let mut player = CountingPlayer::default();
+        let mut failures = UpdateFailures::new(None);
+
+        // no keepalive, or nothing played yet: just a sleep
+        wait(
+            clock.pacing(),
+            &mut None,
+            &mut player,
+            &mut failures,
+            Some(b"frame"),
+            30,
+        )
+        .unwrap();
+        wait(
+            clock.pacing(),
+            &mut Some(Keepalive::new(100, Arc::default(), clock.now())),
+            &mut player,
+            &mut failures,
+            None,
+            30,
+        )
+        .unwrap();
+        assert_eq!(player.updates, 0);
+
+        // every 10ms over 55ms
+        let mut keepalive = Some(Keepalive::new(100, Arc::default(), clock.now()));
+        wait(
+            clock.pacing(),
+            &mut keepalive,
+            &mut player,
+            &mut failures,
+            Some(b"frame"),
+            55,
+        )
+        .unwrap();
+        assert_eq!(player.updates, 5);
+
+        // a frame written just now restarts the interval, the rewrite due at 60ms is dropped
+        player.updates = 0;
+        if let Some(keepalive) = &mut keepalive {
+            keepalive.written(clock.now());
+        }
+        wait(
+            clock.pacing(),
+            &mut keepalive,
+            &mut player,
+            &mut failures,
+            Some(b"frame"),
+            9,
+        )
+        .unwrap();
+        assert_eq!(player.updates, 0);
+    }
+
+    #[test]
+    fn test_update_failures() {
+        let failed = || Err(anyhow::anyhow!("mapping locked"));
+
+        let mut strict = UpdateFailures::new(None);
+        assert!(strict.check(Ok(())).is_ok());
+        assert!(matches!(
+            strict.check(failed()),
+            Err(PlayError::FailedToUpdatePlayer(_))
+        ));
+
+        // a success in between resets the count, the third failure in a row aborts
+        let mut tolerant = UpdateFailures::new(Some(3));
+        assert!(tolerant.check(failed()).is_ok());
+        assert!(tolerant.check(failed()).is_ok());
+        assert!(tolerant.check(Ok(())).is_ok());
+        assert!(tolerant.check(failed()).is_ok());
+        assert!(tolerant.check(failed()).is_ok());
+        assert!(matches!(
+            tolerant.check(failed()),
+            Err(PlayError::TooManyUpdateFailures { count: 3, .. })
+        ));
+        assert_eq!(tolerant.skipped, 4);
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_wait_for_consumer() {
+        let consumer = EventHandle::create("Local\\KsanaTestSyncEvent").unwrap();
+        let event = EventHandle::open("Local\\KsanaTestSyncEvent").unwrap();
+        let quit_flag = AtomicBool::new(false);
+
+        consumer.signal();
+        assert!(wait_for_consumer(&quit_flag, &event));
+
+        // not signaled again, quit ends the wait
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                quit_flag.store(true, Ordering::Relaxed);
+            });
+            assert!(!wait_for_consumer(&quit_flag, &event));
+        });
+    }
+
+    #[test]
+    fn test_recorded_tick_ms() {
+        assert_eq!(recorded_tick_ms(50, 1, None), 20.0);
+        // below the cap the recorded rate is kept
+        assert_eq!(recorded_tick_ms(50, 1, Some(60)), 20.0);
+        assert_eq!(recorded_tick_ms(200, 1, Some(100)), 10.0);
+        // interpolated frames count towards the cap
+        assert_eq!(recorded_tick_ms(50, 2, Some(50)), 40.0);
+        assert_eq!(recorded_tick_ms(0, 1, None), 1000.0);
+    }
+
+    #[test]
+    fn test_watch_quit() {
+        let timed_out = |quit: bool, done: bool| {
+            let mut called = false;
+            watch_quit(
+                &AtomicBool::new(quit),
+                &AtomicBool::new(done),
+                Duration::from_millis(20),
+                || called = true,
+            );
+            called
+        };
+        assert!(timed_out(true, false));
+        assert!(!timed_out(true, true));
+        assert!(!timed_out(false, true));
+
+        // stopping within the timeout
+        let quit_flag = AtomicBool::new(true);
+        let done = AtomicBool::new(false);
+        let mut called = false;
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                done.store(true, Ordering::Relaxed);
+            });
+            watch_quit(&quit_flag, &done, Duration::from_secs(5), || called = true);
+        });
+        assert!(!called);
+    }
+
+    fn play_options(player: PlayerKind, player_log: Option<String>) -> PlayOptions {
+        PlayOptions {
+            key: None,
+            follow: false,
+            salvage: false,
+            io_buffer_mb: crate::commands::DEFAULT_IO_BUFFER_MB,
+            resequence: false,
+            interpolate: 1,
+            keepalive_hz: None,
+            max_fps: None,
+            player,
+            player_log,
+            force: false,
+            map_name: None,
+            event_name: None,
+            tolerant: None,
+            sync_event: None,
+            quit_timeout_ms: DEFAULT_QUIT_TIMEOUT_MS,
+            start_paused: false,
+        }
+    }
+
+    #[test]
+    fn test_play_to_file_player() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ksana_play_{}.ksr", std::process::id()));
+        let log = dir.join(format!("ksana_play_{}.log", std::process::id()));
+
+        let frames: Vec<Vec<u8>> = crate::commands::generate::iracing_frames(60, 5)
+            .map(Option::unwrap)
+            .collect();
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let mut saver = crate::io::Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        for frame in &frames {
+            saver.save(frame).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let input_file = input.to_str().unwrap();
+        let quit_flag = Arc::new(AtomicBool::new(false));
+        let options = play_options(PlayerKind::File, Some(log.to_str().unwrap().to_string()));
+        let result = run(quit_flag.clone(), input_file, options).unwrap();
+        assert!(matches!(result, PlayResult::EndOfFile));
+
+        let lines: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(lines.len(), frames.len());
+        for (line, frame) in lines.iter().zip(&frames) {
+            let mut crc = flate2::Crc::new();
+            crc.update(frame);
+            assert!(
+                line.ends_with(&format!("crc32 {:08x}", crc.sum())),
+                "{}",
+                line
+            );
+        }
+
+        let options = play_options(PlayerKind::Null, None);
+        assert!(matches!(
+            run(quit_flag, input_file, options),
+            Ok(PlayResult::EndOfFile)
+        ));
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_play_paces_on_clock() {
+        let input =
+            std::env::temp_dir().join(format!("ksana_play_paced_{}.ksr", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let mut saver = crate::io::Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        for frame in crate::commands::generate::iracing_frames(60, 5) {
+            saver.save(&frame.unwrap()).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        // frames are due every 16.7ms, or 33.3ms capped at 30 fps, the whole millisecond
+        // sleeps round down without adding up
+        for (max_fps, played_ms) in [(None, 83), (Some(30), 166)] {
+            let clock = MockClock::default();
+            let start = clock.now();
+            let options = PlayOptions {
+                max_fps,
+                ..play_options(PlayerKind::Null, None)
+            };
+            let result = play(
+                Arc::default(),
+                Arc::default(),
+                input.to_str().unwrap(),
+                options,
+                clock.pacing(),
+            );
+            assert!(matches!(result, Ok(PlayResult::EndOfFile)));
+            assert_eq!(clock.now() - start, Duration::from_millis(played_ms));
+        }
+
+        std::fs::remove_file(&input).unwrap();
+    }
+
+    /// Sleeps on the mock clock and sets the flag once `after` has passed.
+    struct ResumingSleeper<'a> {
+        clock: &'a MockClock,
+        start: Instant,
+        after: Duration,
+        resume_flag: Arc<AtomicBool>,
+    }
+
+    impl Sleeper for ResumingSleeper<'_> {
+        fn sleep_ms(&self, ms: u64) {
+            self.clock.sleep_ms(ms);
+            if self.clock.now() - self.start >= self.after {
+                self.resume_flag.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_play_start_paused() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ksana_play_paused_{}.ksr", std::process::id()));
+        let log = dir.join(format!("ksana_play_paused_{}.log", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let mut saver = crate::io::Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        for frame in crate::commands::generate::iracing_frames(60, 5) {
+            saver.save(&frame.unwrap()).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let clock = MockClock::default();
+        let resume_flag = Arc::new(AtomicBool::new(false));
+        let sleeper = ResumingSleeper {
+            clock: &clock,
+            start: clock.now(),
+            after: Duration::from_millis(250),
+            resume_flag: resume_flag.clone(),
+        };
+        let pacing = Pacing {
+            sleeper: &sleeper,
+            clock: &clock,
+        };
+        let options = PlayOptions {
+            start_paused: true,
+            keepalive_hz: Some(10),
+            ..play_options(PlayerKind::File, Some(log.to_str().unwrap().to_string()))
+        };
+        let result = play(
+            Arc::default(),
+            resume_flag,
+            input.to_str().unwrap(),
+            options,
+            pacing,
+        );
+        assert!(matches!(result, Ok(PlayResult::EndOfFile)));
+
+        // the first frame kept alive while paused, then the rest at the recorded rate
+        let lines: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(lines.len(), 7);
+        let crc = |line: &str| line.rsplit(' ').next().unwrap().to_string();
+        assert_eq!(crc(&lines[1]), crc(&lines[0]));
+        assert_eq!(crc(&lines[2]), crc(&lines[0]));
+        assert_ne!(crc(&lines[3]), crc(&lines[0]));
+        assert_eq!(clock.now() - sleeper.start, Duration::from_millis(250 + 83));
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_play_empty_recording() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ksana_play_empty_{}.ksr", std::process::id()));
+        let log = dir.join(format!("ksana_play_empty_{}.log", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let saver = crate::io::Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        saver.finalize().unwrap();
+
+        // the player isn't created, so there is no log
+        let options = play_options(PlayerKind::File, Some(log.to_str().unwrap().to_string()));
+        let result = run(Arc::default(), input.to_str().unwrap(), options);
+        assert!(matches!(result, Ok(PlayResult::EndOfFile)));
+        assert!(!log.exists());
+
+        std::fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_play_zero_fps_fails_cleanly() {
+        let input =
+            std::env::temp_dir().join(format!("ksana_play_fps0_{}.ksr", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 0,
+        };
+        let mut saver = crate::io::Saver::new(File::create(&input).unwrap(), 0, info).unwrap();
+        saver.save(b"frame").unwrap();
+        saver.finalize().unwrap();
+
+        let quit_flag = Arc::new(AtomicBool::new(false));
+        let options = play_options(PlayerKind::Null, None);
+        let result = run(quit_flag, input.to_str().unwrap(), options);
+        assert!(matches!(
+            result,
+            Err(PlayError::FailedToReadHeader(IOError::InvalidFps(0)))
+        ));
+
+        std::fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_existing_mapping() {
+        let free = "Local\\KsanaTestPlayFree";
+        let taken = "Local\\KsanaTestPlayTaken";
+        assert_eq!(existing_mapping(&[free, taken]), None);
+
+        let _sim = crate::shm::SharedMemoryWriter::create(taken, 64).unwrap();
+        assert_eq!(existing_mapping(&[free, taken]), Some(taken));
+        assert!(sim_mappings(b"f1__").is_empty());
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_play_checks_map_name() {
+        let input = std::env::temp_dir().join(format!("ksana_play_map_{}.ksr", std::process::id()));
+        let info = crate::SimInfo {
+            id: *b"irac",
+            payload_version: crate::sims::iracing::data::CURRENT_PAYLOAD_VERSION,
+            native_hz: 60,
+        };
+        let mut saver = crate::io::Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        saver.save(b"frame").unwrap();
+        saver.finalize().unwrap();
+
+        // the mapping of another player, not the one of the sim
+        let name = "Local\\KsanaTestPlayMapName";
+        let _other = crate::shm::SharedMemoryWriter::create(name, 64).unwrap();
+        let options = PlayOptions {
+            map_name: Some(name.to_string()),
+            ..play_options(PlayerKind::Sim, None)
+        };
+        let result = run(Arc::default(), input.to_str().unwrap(), options);
+        assert!(matches!(result, Err(PlayError::SimRunning(taken)) if taken == name));
+
+        std::fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_schedule_does_not_drift() {
+        let clock = MockClock::default();
+        let origin = clock.now();
+
+        // 40 minutes at 60 fps, every frame taking 0.3-2.1ms to load and write, and one
+        // frame late by a whole period
+        let frames = 40 * 60 * 60;
+        let mut schedule = Schedule::new(origin, 1000.0 / 60.0);
+        for frame in 0..frames {
+            let work_us = 300 + (frame * 7919 % 1800);
+            clock.advance(Duration::from_micros(work_us));
+            if frame == 1000 {
+                clock.advance(Duration::from_millis(20));
+            }
+            clock.sleep_ms(schedule.next_ms(clock.now()));
+        }
+
+        // sleeping whole milliseconds leaves at most one behind, not one per frame
+        let played = clock.now() - origin;
+        let expected = Duration::from_secs(40 * 60);
+        assert!(
+            played.abs_diff(expected) <= Duration::from_millis(1),
+            "{:?}",
+            played
+        );
+
+        // restarting counts from the new origin
+        let now = clock.now() + Duration::from_secs(5);
+        schedule.restart(now, 10.0);
+        assert_eq!(schedule.next_ms(now + Duration::from_micros(2500)), 7);
+    }
+}
@@ -0,0 +1,403 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use crate::Player;
+use crate::commands::mirror::same_mapping;
+use crate::commands::play::{PlayResult, existing_mapping};
+use crate::commands::timestamp;
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::player::IRacingPlayer;
+use crate::sleeper::{AdaptiveSleeper, Pacing, SystemClock, sleep_unless_quit};
+use crate::traits::PlayError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlayMultiError {
+    #[error("Got {inputs} inputs and {targets} targets, every input needs a target")]
+    TargetCount { inputs: usize, targets: usize },
+
+    #[error("Targets {0} and {1} are the same mapping")]
+    SameTarget(String, String),
+
+    #[error("Shared memory {0} already exists, play to another target")]
+    TargetExists(String),
+
+    #[error("Only iRacing recordings can be played side by side, {file} is a {sim} recording")]
+    UnsupportedSim { file: String, sim: String },
+
+    #[error("{file}: {error}")]
+    Play { file: String, error: PlayError },
+}
+
+/// A recording and the player it is played to.
+struct Stream<R: Read + Seek> {
+    loader: Loader<R>,
+    player: Box<dyn Player>,
+    /// Milliseconds from the start of the playback to the first frame
+    offset_ms: f64,
+    frame_ms: f64,
+    next: Option<Vec<u8>>,
+}
+
+impl<R: Read + Seek> Stream<R> {
+    fn new(
+        mut loader: Loader<R>,
+        player: Box<dyn Player>,
+        offset_ms: f64,
+    ) -> Result<Self, PlayError> {
+        let next = load(&mut loader)?;
+        Ok(Self {
+            frame_ms: 1000.0 / loader.fps().max(1) as f64,
+            loader,
+            player,
+            offset_ms,
+            next,
+        })
+    }
+
+    /// Milliseconds from the start of the playback the next frame is due at, by the save tick
+    /// it was captured in, None once the recording ended.
+    fn due_ms(&self) -> Option<f64> {
+        self.next
+            .as_ref()
+            .map(|_| self.offset_ms + self.loader.frame_tick() as f64 * self.frame_ms)
+    }
+
+    /// Writes the next frame and loads the one after it.
+    fn play_next(&mut self) -> Result<(), PlayError> {
+        let Some(frame) = self.next.take() else {
+            return Ok(());
+        };
+        if let Some(expected) = self.player.expected_frame_size()
+            && !expected.contains(&frame.len())
+        {
+            return Err(PlayError::FrameSizeMismatch {
+                actual: frame.len(),
+                min: *expected.start(),
+                max: *expected.end(),
+            });
+        }
+        self.player
+            .update(&frame)
+            .map_err(PlayError::FailedToUpdatePlayer)?;
+        self.next = load(&mut self.loader)?;
+        Ok(())
+    }
+}
+
+/// The next frame, None at the end of the recording or of an interrupted one.
+fn load<R: Read + Seek>(loader: &mut Loader<R>) -> Result<Option<Vec<u8>>, PlayError> {
+    match loader.load() {
+        Err(IOError::TruncatedFrame) => {
+            eprintln!("Warning: {}", IOError::TruncatedFrame);
+            Ok(None)
+        }
+        loaded => loaded.map_err(PlayError::FailedToLoadFrame),
+    }
+}
+
+/// Milliseconds each recording starts after the earliest one by the time its capture
+/// started, None unless all recordings have a start time.
+fn start_offsets_ms(start_times: &[Option<SystemTime>]) -> Option<Vec<f64>> {
+    let start_times: Vec<SystemTime> = start_times.iter().copied().collect::<Option<_>>()?;
+    let earliest = *start_times.iter().min()?;
+    Some(
+        start_times
+            .iter()
+            .map(|t| t.duration_since(earliest).unwrap_or_default().as_secs_f64() * 1000.0)
+            .collect(),
+    )
+}
+
+/// Plays every stream on one timeline, writing whichever frame is due next. Frames due at
+/// the same time are written in the order of the streams.
+fn play_streams<R: Read + Seek>(
+    quit_flag: &AtomicBool,
+    streams: &mut [Stream<R>],
+    pacing: Pacing,
+) -> Result<PlayResult, (usize, PlayError)> {
+    let origin = pacing.clock.now();
+    let mut result = PlayResult::QuitRequested;
+
+    while !quit_flag.load(Ordering::Relaxed) {
+        let next = streams
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stream)| stream.due_ms().map(|due_ms| (index, due_ms)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((index, due_ms)) = next else {
+            result = PlayResult::EndOfFile;
+            break;
+        };
+
+        // due times are counted from the start, so the whole millisecond sleeps don't add up
+        let wait_ms = (due_ms - pacing.elapsed_ms(origin)).max(0.0) as u64;
+        sleep_unless_quit(quit_flag, pacing.sleeper, wait_ms);
+        if quit_flag.load(Ordering::Relaxed) {
+            break;
+        }
+        streams[index].play_next().map_err(|error| (index, error))?;
+    }
+
+    for stream in streams.iter_mut() {
+        stream.player.stop();
+    }
+    Ok(result)
+}
+
+/// Opens `input_file` to be played to the iRacing mapping `target`, starting with the
+/// playback until `run` sets its offset.
+fn open(input_file: &str, target: &str) -> Result<Stream<BufReader<File>>, PlayMultiError> {
+    let play_error = |error| PlayMultiError::Play {
+        file: input_file.to_string(),
+        error,
+    };
+
+    let file = File::open(input_file).map_err(|e| play_error(PlayError::FailedToOpenFile(e)))?;
+    let loader = Loader::new(BufReader::new(file))
+        .map_err(|e| play_error(PlayError::FailedToReadHeader(e)))?;
+    if loader.is_encrypted() {
+        return Err(play_error(PlayError::FailedToReadHeader(
+            IOError::KeyRequired,
+        )));
+    }
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(PlayMultiError::UnsupportedSim {
+            file: input_file.to_string(),
+            sim: std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        });
+    }
+    if let Some(name) = existing_mapping(&[target]) {
+        return Err(PlayMultiError::TargetExists(name.to_string()));
+    }
+
+    let player = IRacingPlayer::with_name(target, loader.payload_version())
+        .map_err(|e| play_error(PlayError::FailedToCreatePlayer(e)))?;
+    Stream::new(loader, Box::new(player), 0.0).map_err(play_error)
+}
+
+/// Plays several iRacing recordings at the same time, each to the mapping at the same
+/// position in `targets`. The recordings are aligned by the time their capture started, or
+/// all start together with `ignore_start_times` or if one of them has no start time.
+pub fn run(
+    quit_flag: Arc<AtomicBool>,
+    inputs: &[String],
+    targets: &[String],
+    ignore_start_times: bool,
+) -> Result<PlayResult, PlayMultiError> {
+    if inputs.len() != targets.len() {
+        return Err(PlayMultiError::TargetCount {
+            inputs: inputs.len(),
+            targets: targets.len(),
+        });
+    }
+    for (i, a) in targets.iter().enumerate() {
+        if let Some(b) = targets[i + 1..].iter().find(|b| same_mapping(a, b)) {
+            return Err(PlayMultiError::SameTarget(a.clone(), b.clone()));
+        }
+    }
+
+    let mut streams = Vec::with_capacity(inputs.len());
+    for (input_file, target) in inputs.iter().zip(targets) {
+        streams.push(open(input_file, target)?);
+    }
+
+    let start_times: Vec<Option<SystemTime>> = streams
+        .iter()
+        .map(|stream| stream.loader.start_time())
+        .collect();
+    let offsets_ms = match start_offsets_ms(&start_times) {
+        Some(_) if ignore_start_times => vec![0.0; inputs.len()],
+        Some(offsets_ms) => {
+            println!("Aligning the recordings by the time they were captured");
+            offsets_ms
+        }
+        None => {
+            println!("Not every recording has a start time, starting them together");
+            vec![0.0; inputs.len()]
+        }
+    };
+
+    for ((stream, offset_ms), (input_file, target)) in streams
+        .iter_mut()
+        .zip(offsets_ms)
+        .zip(inputs.iter().zip(targets))
+    {
+        stream.offset_ms = offset_ms;
+        println!(
+            "{} Playing: {} to {} (fps: {}, starting after {:.1}s)",
+            timestamp(),
+            input_file,
+            target,
+            stream.loader.fps(),
+            offset_ms / 1000.0
+        );
+    }
+
+    let sleeper = AdaptiveSleeper::default();
+    let clock = SystemClock::default();
+    let pacing = Pacing {
+        sleeper: &sleeper,
+        clock: &clock,
+    };
+    let result = play_streams(&quit_flag, &mut streams, pacing).map_err(|(index, error)| {
+        PlayMultiError::Play {
+            file: inputs[index].clone(),
+            error,
+        }
+    })?;
+
+    println!("{} Players stopped.", timestamp());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Clock;
+    use crate::SimInfo;
+    use crate::io::Saver;
+    use crate::sleeper::MockClock;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    type Log = Rc<RefCell<Vec<(usize, u64, u8)>>>;
+
+    /// Logs the stream, the millisecond and the first byte of every frame written.
+    struct LoggingPlayer {
+        stream: usize,
+        clock: Rc<MockClock>,
+        origin: Instant,
+        log: Log,
+    }
+
+    impl Player for LoggingPlayer {
+        fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+            let ms = (self.clock.now() - self.origin).as_millis() as u64;
+            self.log.borrow_mut().push((self.stream, ms, data[0]));
+            Ok(())
+        }
+
+        fn stop(&mut self) {}
+    }
+
+    fn recording(fps: i32, frames: u8) -> Loader<Cursor<Vec<u8>>> {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: fps as u32,
+        };
+        let mut data = Vec::new();
+        let mut saver = Saver::new(&mut data, fps, info).unwrap();
+        for frame in 0..frames {
+            saver.save(&[frame]).unwrap();
+        }
+        saver.finalize().unwrap();
+        Loader::new(Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn test_play_streams_on_one_timeline() {
+        let clock = Rc::new(MockClock::default());
+        let log = Log::default();
+        let player = |stream: usize| -> Box<dyn Player> {
+            Box::new(LoggingPlayer {
+                stream,
+                clock: clock.clone(),
+                origin: clock.now(),
+                log: log.clone(),
+            })
+        };
+
+        // 10 fps from the start, 20 fps starting 150ms later
+        let mut streams = vec![
+            Stream::new(recording(10, 3), player(0), 0.0).unwrap(),
+            Stream::new(recording(20, 4), player(1), 150.0).unwrap(),
+        ];
+        let result = play_streams(&AtomicBool::new(false), &mut streams, clock.pacing());
+        assert!(matches!(result, Ok(PlayResult::EndOfFile)));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                (0, 0, 0),
+                (0, 100, 1),
+                (1, 150, 0),
+                (0, 200, 2),
+                (1, 200, 1),
+                (1, 250, 2),
+                (1, 300, 3),
+            ]
+        );
+
+        // nothing is written once quit is requested
+        let mut streams = vec![Stream::new(recording(10, 3), player(0), 0.0).unwrap()];
+        let result = play_streams(&AtomicBool::new(true), &mut streams, clock.pacing());
+        assert!(matches!(result, Ok(PlayResult::QuitRequested)));
+        assert_eq!(log.borrow().len(), 7);
+    }
+
+    #[test]
+    fn test_start_offsets() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            start_offsets_ms(&[
+                Some(start + Duration::from_millis(2500)),
+                Some(start),
+                Some(start + Duration::from_millis(40)),
+            ]),
+            Some(vec![2500.0, 0.0, 40.0])
+        );
+        assert_eq!(start_offsets_ms(&[Some(start), None]), None);
+    }
+
+    #[test]
+    fn test_run_checks_inputs() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(matches!(
+            run(
+                Arc::default(),
+                &names(&["a.ksr", "b.ksr"]),
+                &names(&["Local\\A"]),
+                false
+            ),
+            Err(PlayMultiError::TargetCount {
+                inputs: 2,
+                targets: 1
+            })
+        ));
+        assert!(matches!(
+            run(
+                Arc::default(),
+                &names(&["a.ksr", "b.ksr"]),
+                &names(&["Local\\Driver", "driver"]),
+                false
+            ),
+            Err(PlayMultiError::SameTarget(..))
+        ));
+
+        let input =
+            std::env::temp_dir().join(format!("ksana_play_multi_{}.ksr", std::process::id()));
+        let info = SimInfo {
+            id: *b"pc2_",
+            payload_version: 1,
+            native_hz: 60,
+        };
+        let mut saver = Saver::new(File::create(&input).unwrap(), 60, info).unwrap();
+        saver.save(b"frame").unwrap();
+        saver.finalize().unwrap();
+        let result = run(
+            Arc::default(),
+            &names(&[input.to_str().unwrap()]),
+            &names(&["Local\\KsanaTestPlayMulti"]),
+            false,
+        );
+        assert!(matches!(result, Err(PlayMultiError::UnsupportedSim { sim, .. }) if sim == "pc2_"));
+        std::fs::remove_file(&input).unwrap();
+    }
+}
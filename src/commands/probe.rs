@@ -0,0 +1,133 @@
+use crate::commands::header::hex_dump;
+use crate::shm::{SharedMemoryError, SharedMemoryReader};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProbeError {
+    #[error("Failed to open mapping: {0}")]
+    FailedToOpen(SharedMemoryError),
+}
+
+pub const DEFAULT_PROBE_BYTES: u32 = 256;
+
+/// Whether `value` looks like a float a sim would write, rather than the bits of an integer
+/// or of text read as one.
+fn plausible_f32(value: f32) -> bool {
+    value == 0.0 || (value.is_finite() && (1e-6..=1e7).contains(&value.abs()))
+}
+
+fn plausible_f64(value: f64) -> bool {
+    value == 0.0 || (value.is_finite() && (1e-9..=1e12).contains(&value.abs()))
+}
+
+/// Interpretations of the little-endian value at every 4 byte aligned offset: the i32, and
+/// the f32 and the f64 starting there if they look like floats. Zero words are skipped.
+fn guesses(data: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for offset in (0..data.len()).step_by(4) {
+        let Some(word) = data.get(offset..offset + 4) else {
+            break;
+        };
+        let word = [word[0], word[1], word[2], word[3]];
+        let f64_value = data
+            .get(offset..offset + 8)
+            .filter(|_| offset.is_multiple_of(8))
+            .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+            .map(f64::from_le_bytes)
+            .filter(|&value| plausible_f64(value));
+        // the low half of a double is often zero
+        if word == [0; 4] && f64_value.is_none_or(|value| value == 0.0) {
+            continue;
+        }
+
+        let mut line = format!("{:04x}  i32 {:<12}", offset, i32::from_le_bytes(word));
+        let f32_value = f32::from_le_bytes(word);
+        if plausible_f32(f32_value) {
+            line.push_str(&format!("  f32 {:<14}", f32_value));
+        }
+        if let Some(f64_value) = f64_value {
+            line.push_str(&format!("  f64 {}", f64_value));
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// Prints the first `bytes` of the mapping `name` in hex and what the values at each offset
+/// could be, for working out the layout of a sim that isn't supported yet or whose layout
+/// changed. The mapping is only read, and never past its end.
+pub fn run(name: &str, bytes: usize) -> Result<(), ProbeError> {
+    let reader = SharedMemoryReader::open(name, bytes).map_err(ProbeError::FailedToOpen)?;
+    let len = bytes.min(reader.size());
+    // SAFETY: the reader caps its size at the mapped region
+    let data = unsafe { std::slice::from_raw_parts(reader.as_ptr(), len) }.to_vec();
+
+    println!("Mapping: {}", name);
+    if len < bytes {
+        println!("Only {} bytes are mapped", len);
+    }
+
+    println!();
+    println!("{}", hex_dump(&data));
+
+    println!();
+    println!("Values at each offset (little-endian, zero words skipped):");
+    for line in guesses(&data) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guesses() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&60i32.to_le_bytes());
+        data.extend_from_slice(&42.5f32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&0.25f64.to_le_bytes());
+        // cut off, no complete word
+        data.extend_from_slice(&[1, 2]);
+
+        let lines = guesses(&data);
+        assert_eq!(lines.len(), 4, "{:#?}", lines);
+        // the bits of a small integer are a denormal float
+        assert!(lines[0].starts_with("0000  i32 60 "));
+        assert!(!lines[0].contains("f32"));
+        assert_eq!(lines[1], "0004  i32 1110048768    f32 42.5");
+        assert!(lines[2].starts_with("0010  i32 0"));
+        assert!(lines[2].ends_with("f64 0.25"));
+        assert_eq!(lines[3], "0014  i32 1070596096    f32 1.625");
+    }
+
+    #[test]
+    fn test_plausible_floats() {
+        assert!(plausible_f32(0.0));
+        assert!(plausible_f32(-7200.5));
+        assert!(!plausible_f32(f32::NAN));
+        assert!(!plausible_f32(f32::from_bits(60)));
+        assert!(plausible_f64(1773908199.111));
+        assert!(!plausible_f64(f64::INFINITY));
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_run() {
+        let name = "Local\\KsanaTestProbe";
+        let mut writer = crate::shm::SharedMemoryWriter::create(name, 64).unwrap();
+        unsafe {
+            writer.write(0, &2i32.to_le_bytes());
+        }
+        assert!(run(name, 32).is_ok());
+        // capped at the mapped size
+        assert!(run(name, 1 << 20).is_ok());
+        assert!(matches!(
+            run("Local\\KsanaTestProbeMissing", 32),
+            Err(ProbeError::FailedToOpen(_))
+        ));
+    }
+}
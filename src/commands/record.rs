@@ -1,17 +1,29 @@
-use std::fs::File;
-use std::io::BufWriter;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration, Instant};
+use std::time::{Duration, SystemTime};
 
-use crate::io::{IOError, Saver};
+use humantime::format_duration;
+
+use crate::codec::{Codec, NoneCodec, ZlibCodec};
+use crate::commands::timestamp;
+use crate::io::{IOError, Loader, Saver, SaverOptions};
+pub use crate::recorder::{FlushEvery, RecordingError, RecordingFinished};
+use crate::recorder::{LoopOptions, Output, Rates, record};
 use crate::sims::assettocorsa::connector::AssettoCorsaConnector;
+use crate::sims::beamng::connector::BeamNGConnector;
+use crate::sims::dirt::connector::DirtConnector;
+use crate::sims::f1::connector::F1Connector;
 use crate::sims::iracing::connector::IRacingConnector;
-use crate::sleeper::AdaptiveSleeper;
-use crate::{Connector, Sleeper};
+use crate::sims::iracing::data::IRSDK_MEMMAPFILENAME;
+use crate::sims::pcars2::connector::Pcars2Connector;
+use crate::sleeper::{AdaptiveSleeper, Pacing, SystemClock, sleep_unless_quit};
+use crate::{Clock, Connector, SimInfo, Sleeper};
 
-struct ConnectorGuard<'a> {
+/// Disconnects the connector when dropped, also when leaving early on an error.
+pub(crate) struct ConnectorGuard<'a> {
     inner: &'a mut dyn Connector,
 }
 
@@ -40,28 +52,19 @@ impl<'a> DerefMut for ConnectorGuard<'a> {
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum RecordingError {
-    #[error("Failed to save frame: {0}")]
-    SavingFrameFailed(#[from] IOError),
-}
-
-pub enum RecordingFinished {
-    SimDisconnected,
-    QuitRequested,
-    MaxDurationReached,
-}
-
 #[derive(thiserror::Error, Debug)]
 pub enum RecordError {
     #[error("Failed to create file: {0}")]
     CreateFileError(std::io::Error),
 
+    #[error("Failed to open file for appending: {0}")]
+    OpenFileError(std::io::Error),
+
     #[error("Failed to initialize saver: {0}")]
     SaverInitError(IOError),
 
-    #[error("Flush failed: {0}")]
-    FlushFailed(IOError),
+    #[error("Finalize failed: {0}")]
+    FinalizeFailed(IOError),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -107,94 +110,291 @@ fn parse_duration(arg: &str) -> Result<Duration, ParseDurationError> {
     Err(ParseDurationError::InvalidFormat)
 }
 
-fn wait_for_connection<'a>(
-    quit_flag: &AtomicBool,
-    connectors: &'a mut [Box<dyn Connector>],
-    sleeper: &dyn Sleeper,
-) -> Option<ConnectorGuard<'a>> {
-    println!("Waiting for simulator connection...");
+pub const DEFAULT_FPS: u32 = 5;
+pub const MAX_FPS: u32 = 60;
+pub const MAX_POLL_HZ: u32 = 1000;
 
-    while !quit_flag.load(Ordering::Relaxed) {
-        #[allow(clippy::needless_range_loop)]
-        // indexed loop used to get mutable reference on a single element, not the whole slice
-        for i in 0..connectors.len() {
-            if connectors[i].connect() {
-                return Some(ConnectorGuard::new(&mut *connectors[i]));
-            }
+/// Frames per second requested on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fps {
+    /// Not set, records at `DEFAULT_FPS`
+    Unset,
+    /// Picks the rate recommended for the connected sim
+    Auto,
+    Fixed(u32),
+}
+
+impl std::str::FromStr for Fps {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        if arg == "auto" {
+            return Ok(Fps::Auto);
         }
-        sleeper.sleep_ms(1000);
+        arg.parse()
+            .map(|fps: u32| Fps::Fixed(fps.clamp(1, MAX_FPS)))
+            .map_err(|_| format!("expected a number or \"auto\", got \"{}\"", arg))
     }
+}
 
-    None
+/// Parses `--flush-every`, a number of frames or a duration like 30s or 1m.
+pub fn parse_flush_every(arg: &str) -> Result<FlushEvery, String> {
+    let invalid = || {
+        format!(
+            "expected a number of frames or a duration like 30s or 1m, got \"{}\"",
+            arg
+        )
+    };
+    match arg.parse::<u64>() {
+        Ok(0) => Err(invalid()),
+        Ok(frames) => Ok(FlushEvery::Frames(frames)),
+        Err(_) => match parse_duration(arg) {
+            Ok(duration) if !duration.is_zero() => Ok(FlushEvery::Duration(duration)),
+            _ => Err(invalid()),
+        },
+    }
 }
 
-fn record(
-    quit_flag: &AtomicBool,
-    fps: u32,
-    mut connector: ConnectorGuard,
-    saver: &mut Saver<BufWriter<File>>,
-    sleeper: &mut dyn Sleeper,
-    duration: Option<Duration>,
-) -> Result<RecordingFinished, RecordingError> {
-    let tick_ms = 1000.0 / fps as f64;
-    let mut no_data_count = 0;
-    let max_no_data = 20; // disconnect after ~20 frames with no data
+/// Sims the recorder connects to, for `--sim` and `--prefer`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordSim {
+    Iracing,
+    Ac,
+    Pcars2,
+    Dirt,
+    Beamng,
+    F1,
+}
 
-    let start = Instant::now();
+impl RecordSim {
+    fn id(self) -> [u8; 4] {
+        match self {
+            Self::Iracing => *b"irac",
+            Self::Ac => *b"acsa",
+            Self::Pcars2 => *b"pc2_",
+            Self::Dirt => *b"dirt",
+            Self::Beamng => *b"bng_",
+            Self::F1 => *b"f1__",
+        }
+    }
 
-    while !quit_flag.load(Ordering::Relaxed) {
-        if let Some(max_dur) = duration
-            && start.elapsed() >= max_dur
-        {
-            return Ok(RecordingFinished::MaxDurationReached);
+    fn connector(self, options: &RecordOptions) -> Box<dyn Connector> {
+        match self {
+            Self::Iracing => Box::new(IRacingConnector::with_name(
+                options.map_name.as_deref().unwrap_or(IRSDK_MEMMAPFILENAME),
+                options.validate_offsets,
+            )),
+            Self::Ac => Box::new(AssettoCorsaConnector::default()),
+            Self::Pcars2 => Box::new(Pcars2Connector::default()),
+            Self::Dirt => Box::new(DirtConnector::default()),
+            Self::Beamng => Box::new(BeamNGConnector::default()),
+            Self::F1 => Box::new(match options.packet_filter.clone() {
+                Some(packet_ids) => F1Connector::with_packet_filter(packet_ids),
+                None => F1Connector::default(),
+            }),
         }
+    }
+}
 
-        let start = Instant::now();
+/// Sims scanned for without `--sim`. The UDP sims are left out: listening holds their port
+/// for as long as the scan runs, keeping other telemetry apps from receiving the packets,
+/// and DiRT and F1 both send to port 20777 by default.
+const DEFAULT_SCAN: [RecordSim; 3] = [RecordSim::Iracing, RecordSim::Ac, RecordSim::Pcars2];
 
-        match connector.update() {
-            Some(data) => {
-                no_data_count = 0;
-                if let Err(e) = saver.save(&data) {
-                    return Err(RecordingError::SavingFrameFailed(e));
-                }
-            }
-            None => {
-                no_data_count += 1;
-                if no_data_count > max_no_data {
-                    return Ok(RecordingFinished::SimDisconnected);
-                }
+/// Connectors to scan: the one of `--sim`, or the default ones with the one of `--prefer`
+/// first, also if it's a UDP sim.
+fn scanned_connectors(options: &RecordOptions) -> Vec<Box<dyn Connector>> {
+    let sims = match options.sim {
+        Some(sim) => vec![sim],
+        None => DEFAULT_SCAN.to_vec(),
+    };
+    let mut connectors: Vec<Box<dyn Connector>> =
+        sims.iter().map(|sim| sim.connector(options)).collect();
+    match options.prefer {
+        Some(sim) if sims.contains(&sim) => prefer(&mut connectors, sim.id()),
+        Some(sim) => connectors.insert(0, sim.connector(options)),
+        None => {}
+    }
+    connectors
+}
+
+/// Moves the connector of the sim `id` to the front, so it is tried first in every scan and
+/// wins when several sims are up, e.g. one only through mappings a launcher left behind.
+/// The others keep their order.
+fn prefer(connectors: &mut [Box<dyn Connector>], id: [u8; 4]) {
+    if let Some(index) = connectors.iter().position(|c| c.info().id == id) {
+        connectors[..=index].rotate_right(1);
+    }
+}
+
+/// Recommended frames per second for a sim and the reason for it.
+fn recommended_fps(id: &[u8; 4]) -> (u32, &'static str) {
+    match id {
+        b"irac" => (60, "iRacing updates telemetry at 60Hz"),
+        b"acsa" => (
+            MAX_FPS,
+            "Assetto Corsa updates physics faster than the max fps",
+        ),
+        _ => (DEFAULT_FPS, "no recommendation for this sim"),
+    }
+}
+
+/// Resolves the requested fps for the connected sim, printing the chosen rate and why. The
+/// native rate reported by the connector takes precedence over the per-sim recommendation.
+fn resolve_fps(fps: Fps, id: &[u8; 4], native_hz: Option<u32>) -> u32 {
+    let (recommended, reason) = match native_hz {
+        Some(hz) => (hz.min(MAX_FPS), "native rate of the sim"),
+        None => recommended_fps(id),
+    };
+    match fps {
+        Fps::Fixed(fps) => {
+            println!("Frames per second: {}", fps);
+            fps
+        }
+        Fps::Auto => {
+            println!("Frames per second: {} (auto: {})", recommended, reason);
+            recommended
+        }
+        Fps::Unset => {
+            println!("Frames per second: {} (default)", DEFAULT_FPS);
+            if recommended > DEFAULT_FPS {
+                println!(
+                    "Tip: use --fps auto to record at {} fps, {}",
+                    recommended, reason
+                );
             }
+            DEFAULT_FPS
         }
+    }
+}
+
+/// Sim id of raw sidecar files, which can't be played back.
+const RAW_SIDECAR_ID: [u8; 4] = *b"raw_";
+
+pub const DEFAULT_CONNECT_POLL_MS: u64 = 1000;
+
+/// How long to wait between scans for a sim while none is running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectPoll {
+    /// Wait after the first scan
+    pub interval_ms: u64,
+    /// Doubles the wait after every further scan up to this, the wait stays fixed if None
+    pub backoff_max_ms: Option<u64>,
+}
 
-        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
-        if elapsed_ms < tick_ms {
-            sleeper.sleep_ms((tick_ms - elapsed_ms) as u64);
+impl Default for ConnectPoll {
+    fn default() -> Self {
+        Self {
+            interval_ms: DEFAULT_CONNECT_POLL_MS,
+            backoff_max_ms: None,
         }
     }
+}
 
-    Ok(RecordingFinished::QuitRequested)
+impl ConnectPoll {
+    /// Wait after the scan `attempt`, counting from 0.
+    fn interval_after(&self, attempt: u32) -> u64 {
+        match self.backoff_max_ms {
+            Some(max_ms) => self
+                .interval_ms
+                .saturating_mul(2u64.saturating_pow(attempt))
+                .min(max_ms.max(self.interval_ms)),
+            None => self.interval_ms,
+        }
+    }
 }
 
-pub fn run(
-    quit_flag: Arc<AtomicBool>,
-    fps: u32,
-    max_duration: Option<String>,
-) -> Result<RecordingFinished, Error> {
-    let mut sleeper = AdaptiveSleeper::default();
+/// Scans the connectors until one connects, `None` if quit was requested first. Quit is
+/// checked before every connect attempt and during the waits between scans, so it takes
+/// effect within a single `Connector::connect` call or `QUIT_CHECK_MS`.
+pub(crate) fn wait_for_connection<'a>(
+    quit_flag: &AtomicBool,
+    connectors: &'a mut [Box<dyn Connector>],
+    sleeper: &dyn Sleeper,
+    poll: ConnectPoll,
+) -> Option<ConnectorGuard<'a>> {
+    println!("{} Waiting for simulator connection...", timestamp());
 
-    println!("Frames per second: {}", fps);
+    let mut attempt = 0;
+    while !quit_flag.load(Ordering::Relaxed) {
+        #[allow(clippy::needless_range_loop)]
+        // indexed loop used to get mutable reference on a single element, not the whole slice
+        for i in 0..connectors.len() {
+            // a sim that is half up can make an attempt slow, the next one waits for it
+            if quit_flag.load(Ordering::Relaxed) {
+                return None;
+            }
+            if connectors[i].connect() {
+                // the others release what a connect attempt took, e.g. a UDP port
+                for (j, other) in connectors.iter_mut().enumerate() {
+                    if j != i {
+                        other.disconnect();
+                    }
+                }
+                return Some(ConnectorGuard::new(&mut *connectors[i]));
+            }
+        }
+        sleep_unless_quit(quit_flag, sleeper, poll.interval_after(attempt));
+        attempt = attempt.saturating_add(1);
+    }
+
+    None
+}
 
-    let duration = match max_duration {
+pub struct RecordOptions {
+    /// Frames saved per second
+    pub fps: Fps,
+    /// Connector polls per second, at least `fps`. Defaults to `fps`
+    pub poll_hz: Option<u32>,
+    pub max_duration: Option<String>,
+    /// Existing recording to append to instead of creating a new file
+    pub append: Option<String>,
+    /// Passphrase to encrypt the recording with
+    pub key: Option<String>,
+    /// Stop after this many frames were saved
+    pub limit_frames: Option<u64>,
+    /// Store frames uncompressed, trading file size for CPU time
+    pub no_compress: bool,
+    /// Capacity of the file write buffer in MB
+    pub io_buffer_mb: u32,
+    /// Flush the recording periodically instead of only when the write buffer is full
+    pub flush_every: Option<FlushEvery>,
+    /// Packet ids to record for sims sending several kinds of packets, all if None
+    pub packet_filter: Option<Vec<u8>>,
+    /// Skip iRacing frames whose header offsets don't fit the shared memory
+    pub validate_offsets: bool,
+    /// iRacing shared memory to record from, `IRSDK_MEMMAPFILENAME` if None
+    pub map_name: Option<String>,
+    /// Store with every frame how many ticks passed without new data before it
+    pub frame_flags: bool,
+    /// Also write the untouched shared memory of every frame to `<recording>.raw`
+    pub raw_sidecar: bool,
+    /// Wait between scans for a sim before one is running
+    pub connect_poll: ConnectPoll,
+    /// Load every frame of the recording again once it is finalized
+    pub verify_on_stop: bool,
+    /// Only sim to scan for, the shared memory sims if None
+    pub sim: Option<RecordSim>,
+    /// Sim tried first in every scan, the built-in order if None
+    pub prefer: Option<RecordSim>,
+}
+
+pub fn run(quit_flag: Arc<AtomicBool>, options: RecordOptions) -> Result<RecordingFinished, Error> {
+    let sleeper = AdaptiveSleeper::default();
+    let clock = SystemClock::default();
+    let pacing = Pacing {
+        sleeper: &sleeper,
+        clock: &clock,
+    };
+
+    let duration = match options.max_duration {
         None => None,
         Some(ref s) => Some(parse_duration(s)?),
     };
 
-    let mut connectors: Vec<Box<dyn Connector>> = vec![
-        Box::new(IRacingConnector::default()),
-        Box::new(AssettoCorsaConnector::default()),
-    ];
-
-    let connector = wait_for_connection(&quit_flag, &mut connectors, &sleeper);
+    let mut connectors = scanned_connectors(&options);
+    let connector =
+        wait_for_connection(&quit_flag, &mut connectors, &sleeper, options.connect_poll);
 
     let Some(connector) = connector else {
         return Ok(RecordingFinished::QuitRequested);
@@ -203,7 +403,61 @@ pub fn run(
     let info = connector.info();
 
     let sim_name = std::str::from_utf8(&info.id).map_err(|_| Error::InvalidSimId)?;
-    println!("Connected to: {}", sim_name);
+    println!("{} Connected to: {}", timestamp(), sim_name);
+    if options.packet_filter.is_some() && &info.id != b"f1__" {
+        println!(
+            "Packet filter ignored, {} doesn't send several kinds of packets",
+            sim_name
+        );
+    }
+
+    let native_hz = connector.native_hz();
+    if let Some(hz) = native_hz {
+        println!("Native rate: {} Hz", hz);
+    }
+
+    let fps = resolve_fps(options.fps, &info.id, native_hz);
+    let rates = Rates {
+        fps,
+        poll_hz: options.poll_hz.unwrap_or(fps).clamp(fps, MAX_POLL_HZ),
+    };
+    if rates.poll_hz > fps {
+        println!("Polls per second: {}", rates.poll_hz);
+    }
+    let loop_options = LoopOptions {
+        max_duration: duration,
+        limit_frames: options.limit_frames,
+        flush_every: options.flush_every,
+        broadcast: None,
+    };
+
+    if let Some(ref filename) = options.append {
+        let file = match OpenOptions::new().read(true).write(true).open(filename) {
+            Ok(f) => f,
+            Err(e) => {
+                return Err(Error::from(RecordError::OpenFileError(e)));
+            }
+        };
+
+        let capacity = options.io_buffer_mb as usize * 1024 * 1024;
+        let saver = match Saver::append_file(file, fps as i32, info, capacity) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(Error::from(RecordError::SaverInitError(e)));
+            }
+        };
+
+        println!("{} Appending to: {}", timestamp(), filename);
+        let output = Output {
+            saver,
+            raw_sidecar: None,
+        };
+        let result = record_to_file(&quit_flag, &loop_options, &rates, connector, output, pacing)?;
+        if options.verify_on_stop {
+            print_verification(filename, None);
+        }
+        return Ok(result);
+    }
 
     let filename = generate_filename(sim_name);
     let file = match File::create(&filename) {
@@ -213,35 +467,157 @@ pub fn run(
         }
     };
 
-    let writer = BufWriter::new(file);
-    let mut saver = match Saver::new(writer, fps as i32, info) {
+    let start_time = clock.wall_time();
+    let writer = BufWriter::with_capacity(options.io_buffer_mb as usize * 1024 * 1024, file);
+    let saver_options = SaverOptions {
+        codec: new_codec(options.no_compress),
+        passphrase: options.key.as_deref(),
+        start_time: Some(start_time),
+        frame_flags: options.frame_flags,
+    };
+    let saver = Saver::with_options(writer, fps as i32, info, saver_options);
+    let saver = match saver {
         Ok(s) => s,
         Err(e) => {
             return Err(Error::from(RecordError::SaverInitError(e)));
         }
     };
 
-    println!("Recording to: {}", filename);
-    if let Some(duration) = max_duration {
-        println!("Max duration: {}", duration);
+    let raw_sidecar = if !options.raw_sidecar {
+        None
+    } else if connector.peek_raw().is_none() {
+        println!(
+            "Raw sidecar ignored, {} has no single shared memory mapping",
+            sim_name
+        );
+        None
+    } else {
+        let sidecar_filename = format!("{}.raw", filename);
+        let sidecar = create_raw_sidecar(&sidecar_filename, &options, fps, info, start_time)?;
+        println!("{} Raw sidecar: {}", timestamp(), sidecar_filename);
+        Some(sidecar)
+    };
+
+    println!("{} Recording to: {}", timestamp(), filename);
+    if options.key.is_some() {
+        println!("Recording is encrypted");
+    }
+    if options.no_compress {
+        println!("Recording is not compressed");
+    }
+    if options.frame_flags {
+        println!("Recording frame flags");
+    }
+    let output = Output { saver, raw_sidecar };
+    let result = record_to_file(&quit_flag, &loop_options, &rates, connector, output, pacing)?;
+    if options.verify_on_stop {
+        print_verification(&filename, options.key.as_deref());
+    }
+    Ok(result)
+}
+
+/// Reopens the finalized recording and loads every frame, see `Loader::verify`. Returns
+/// the number of frames or what is wrong with the file.
+fn verify_recording(filename: &str, key: Option<&str>) -> Result<u64, String> {
+    let file = File::open(filename).map_err(|e| format!("failed to open the file: {}", e))?;
+    let mut loader = Loader::new(BufReader::new(file))
+        .map_err(|e| format!("failed to read the header: {}", e))?;
+    if let Some(key) = key {
+        loader.unlock(key);
+    }
+    loader
+        .verify()
+        .map_err(|(index, e)| format!("frame {} failed to load: {}", index, e))
+}
+
+/// Checks the recording while the user is still there to record again, a failed check
+/// doesn't change the result of the recording.
+fn print_verification(filename: &str, key: Option<&str>) {
+    println!("{} Verifying: {}", timestamp(), filename);
+    match verify_recording(filename, key) {
+        Ok(frames) => println!("Verify: OK, {} frames", frames),
+        Err(e) => println!("Verify: FAILED, {}", e),
+    }
+}
+
+fn new_codec(no_compress: bool) -> Box<dyn Codec> {
+    if no_compress {
+        Box::new(NoneCodec)
+    } else {
+        Box::new(ZlibCodec::default())
+    }
+}
+
+/// Saver for the raw sidecar of a recording, compressed and encrypted like the recording
+/// itself. Its sim id keeps it from being played back as the sim.
+fn create_raw_sidecar(
+    filename: &str,
+    options: &RecordOptions,
+    fps: u32,
+    info: SimInfo,
+    start_time: SystemTime,
+) -> Result<Saver<BufWriter<File>>, Error> {
+    let file = File::create(filename).map_err(RecordError::CreateFileError)?;
+    let writer = BufWriter::with_capacity(options.io_buffer_mb as usize * 1024 * 1024, file);
+    let sidecar_options = SaverOptions {
+        codec: new_codec(options.no_compress),
+        passphrase: options.key.as_deref(),
+        start_time: Some(start_time),
+        frame_flags: false,
+    };
+    let info = SimInfo {
+        id: RAW_SIDECAR_ID,
+        ..info
+    };
+    Saver::with_options(writer, fps as i32, info, sidecar_options)
+        .map_err(|e| Error::from(RecordError::SaverInitError(e)))
+}
+
+fn record_to_file<W: Write>(
+    quit_flag: &AtomicBool,
+    options: &LoopOptions,
+    rates: &Rates,
+    mut connector: ConnectorGuard,
+    mut output: Output<W>,
+    pacing: Pacing,
+) -> Result<RecordingFinished, Error> {
+    if let Some(duration) = options.max_duration {
+        println!("Max duration: {}", format_duration(duration));
     } else {
         println!("Max duration: unlimited (press Ctrl+C to stop)");
     }
+    if let Some(limit) = options.limit_frames {
+        println!("Frame limit: {}", limit);
+    }
+    match options.flush_every {
+        Some(FlushEvery::Frames(frames)) => println!("Flushing every {} frames", frames),
+        Some(FlushEvery::Duration(duration)) => {
+            println!("Flushing every {}", format_duration(duration))
+        }
+        None => {}
+    }
 
     let result = record(
-        &quit_flag,
-        fps,
-        connector,
-        &mut saver,
-        &mut sleeper,
-        duration,
+        quit_flag,
+        options,
+        rates,
+        &mut *connector,
+        &mut output,
+        pacing,
     )?;
 
-    if let Err(e) = saver.flush() {
-        return Err(Error::from(RecordError::FlushFailed(e)));
+    let stats = output.saver.stats();
+    if let Err(e) = output.saver.finalize() {
+        return Err(Error::from(RecordError::FinalizeFailed(e)));
+    }
+    if let Some(raw_sidecar) = output.raw_sidecar
+        && let Err(e) = raw_sidecar.finalize()
+    {
+        return Err(Error::from(RecordError::FinalizeFailed(e)));
     }
 
-    println!("Recording stopped");
+    println!("{} Recording stopped", timestamp());
+    println!("Saved: {}", stats);
     println!("You can now close this window.");
 
     Ok(result)
@@ -255,6 +631,784 @@ fn generate_filename(name: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Clock;
+    use crate::commands::DEFAULT_IO_BUFFER_MB;
+    use crate::sleeper::MockClock;
+    use ksana::broadcast::FrameBroadcast;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    struct FakeConnector<'a> {
+        responses: VecDeque<Option<Vec<u8>>>,
+        quit_flag: &'a AtomicBool,
+    }
+
+    impl Connector for FakeConnector<'_> {
+        fn connect(&mut self) -> bool {
+            true
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            if self.responses.is_empty() {
+                self.quit_flag.store(true, Ordering::Relaxed);
+            }
+            self.responses.pop_front().flatten()
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+
+        /// The number of responses left, to tell which frame a raw copy was taken with.
+        fn peek_raw(&self) -> Option<Vec<u8>> {
+            Some(vec![self.responses.len() as u8])
+        }
+    }
+
+    /// Sends a frame on every update, each taking `update_ms` on `clock`.
+    struct SlowConnector<'a> {
+        clock: &'a MockClock,
+        update_ms: u64,
+    }
+
+    impl Connector for SlowConnector<'_> {
+        fn connect(&mut self) -> bool {
+            true
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            self.clock.advance(Duration::from_millis(self.update_ms));
+            Some(b"frame".to_vec())
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    fn test_options() -> RecordOptions {
+        RecordOptions {
+            fps: Fps::Fixed(1),
+            poll_hz: None,
+            max_duration: None,
+            append: None,
+            key: None,
+            limit_frames: None,
+            no_compress: false,
+            io_buffer_mb: DEFAULT_IO_BUFFER_MB,
+            flush_every: None,
+            packet_filter: None,
+            validate_offsets: false,
+            map_name: None,
+            frame_flags: false,
+            raw_sidecar: false,
+            connect_poll: ConnectPoll::default(),
+            verify_on_stop: false,
+            sim: None,
+            prefer: None,
+        }
+    }
+
+    fn count_frames(recording: &[u8]) -> usize {
+        let mut loader = Loader::new(Cursor::new(recording)).unwrap();
+        std::iter::from_fn(|| loader.load().unwrap()).count()
+    }
+
+    #[test]
+    fn test_record_polls_within_tick() {
+        let quit_flag = AtomicBool::new(false);
+        let mut connector = FakeConnector {
+            responses: VecDeque::from([
+                None,
+                Some(b"first".to_vec()),
+                None,
+                Some(b"second".to_vec()),
+            ]),
+            quit_flag: &quit_flag,
+        };
+        let options = LoopOptions::default();
+        let rates = Rates {
+            fps: 1,
+            poll_hz: 1000,
+        };
+
+        let mut buffer = Vec::new();
+        let mut output = Output {
+            saver: Saver::new(&mut buffer, 1, connector.info()).unwrap(),
+            raw_sidecar: None,
+        };
+        let clock = MockClock::default();
+        let start = clock.now();
+        let result = record(
+            &quit_flag,
+            &options,
+            &rates,
+            &mut connector,
+            &mut output,
+            clock.pacing(),
+        )
+        .unwrap();
+        output.saver.finalize().unwrap();
+
+        assert!(matches!(result, RecordingFinished::QuitRequested));
+        // both frames are found by polling within the first tick, which quits
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+
+        // only the latest one is saved
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"second".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
+    }
+
+    #[test]
+    fn test_record_stops_at_frame_limit() {
+        let quit_flag = AtomicBool::new(false);
+        let mut connector = FakeConnector {
+            responses: VecDeque::from([
+                Some(b"first".to_vec()),
+                Some(b"second".to_vec()),
+                Some(b"third".to_vec()),
+            ]),
+            quit_flag: &quit_flag,
+        };
+        let options = LoopOptions {
+            limit_frames: Some(2),
+            broadcast: Some(Arc::new(FrameBroadcast::new())),
+            ..Default::default()
+        };
+        let live = options.broadcast.as_ref().unwrap().subscribe(8);
+        let rates = Rates { fps: 1, poll_hz: 1 };
+
+        let mut buffer = Vec::new();
+        let mut output = Output {
+            saver: Saver::new(&mut buffer, 1, connector.info()).unwrap(),
+            raw_sidecar: None,
+        };
+        let result = record(
+            &quit_flag,
+            &options,
+            &rates,
+            &mut connector,
+            &mut output,
+            MockClock::default().pacing(),
+        )
+        .unwrap();
+        output.saver.finalize().unwrap();
+
+        assert!(matches!(result, RecordingFinished::FrameLimitReached));
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"first".to_vec()));
+        assert_eq!(loader.load().unwrap(), Some(b"second".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
+
+        // live consumers get the same frames as the file
+        let live: Vec<Vec<u8>> = live.try_iter().map(|frame| frame.to_vec()).collect();
+        assert_eq!(live, [b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn test_record_raw_sidecar() {
+        let quit_flag = AtomicBool::new(false);
+        let mut connector = FakeConnector {
+            responses: VecDeque::from([Some(b"first".to_vec()), None, Some(b"second".to_vec())]),
+            quit_flag: &quit_flag,
+        };
+        let options = LoopOptions::default();
+        let rates = Rates { fps: 1, poll_hz: 1 };
+        let info = SimInfo {
+            id: RAW_SIDECAR_ID,
+            ..connector.info()
+        };
+
+        let mut buffer = Vec::new();
+        let mut sidecar_buffer = Vec::new();
+        let mut output = Output {
+            saver: Saver::new(&mut buffer, 1, connector.info()).unwrap(),
+            raw_sidecar: Some(Saver::new(&mut sidecar_buffer, 1, info).unwrap()),
+        };
+        record(
+            &quit_flag,
+            &options,
+            &rates,
+            &mut connector,
+            &mut output,
+            MockClock::default().pacing(),
+        )
+        .unwrap();
+        output.saver.finalize().unwrap();
+        output.raw_sidecar.unwrap().finalize().unwrap();
+
+        // one raw copy per saved frame, none for the tick without data
+        let mut loader = Loader::new(Cursor::new(&sidecar_buffer)).unwrap();
+        assert_eq!(loader.id(), RAW_SIDECAR_ID);
+        assert_eq!(loader.load().unwrap(), Some(vec![2]));
+        assert_eq!(loader.load().unwrap(), Some(vec![0]));
+        assert_eq!(loader.load().unwrap(), None);
+    }
+
+    #[test]
+    fn test_record_stops_at_max_duration() {
+        let quit_flag = AtomicBool::new(false);
+        let options = LoopOptions {
+            max_duration: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let rates = Rates {
+            fps: 10,
+            poll_hz: 10,
+        };
+
+        // updates of 30ms leave 70ms of every tick to sleep, updates of 250ms overrun the
+        // 100ms ticks and aren't followed by a sleep
+        for (update_ms, frames) in [(30, 10), (250, 4)] {
+            let clock = MockClock::default();
+            let start = clock.now();
+            let mut connector = SlowConnector {
+                clock: &clock,
+                update_ms,
+            };
+            let mut buffer = Vec::new();
+            let mut output = Output {
+                saver: Saver::new(&mut buffer, 10, connector.info()).unwrap(),
+                raw_sidecar: None,
+            };
+            let result = record(
+                &quit_flag,
+                &options,
+                &rates,
+                &mut connector,
+                &mut output,
+                clock.pacing(),
+            )
+            .unwrap();
+            output.saver.finalize().unwrap();
+
+            assert!(matches!(result, RecordingFinished::MaxDurationReached));
+            assert_eq!(clock.now() - start, Duration::from_secs(1));
+            assert_eq!(count_frames(&buffer), frames, "{}ms updates", update_ms);
+        }
+    }
+
+    /// Keeps the written bytes, counting the flushes.
+    #[derive(Default)]
+    struct FlushCounter {
+        data: Vec<u8>,
+        flushes: u32,
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_flushes_periodically() {
+        let rates = Rates { fps: 1, poll_hz: 1 };
+
+        // frames are saved once a second, from 0 to 4 seconds in
+        for (every, flushes) in [
+            (FlushEvery::Frames(2), 2),
+            (FlushEvery::Duration(Duration::from_secs(3)), 1),
+        ] {
+            let quit_flag = AtomicBool::new(false);
+            let mut connector = FakeConnector {
+                responses: std::iter::repeat_n(Some(b"frame".to_vec()), 5).collect(),
+                quit_flag: &quit_flag,
+            };
+            let options = LoopOptions {
+                flush_every: Some(every),
+                ..Default::default()
+            };
+            let mut counter = FlushCounter::default();
+            let mut output = Output {
+                saver: Saver::new(&mut counter, 1, connector.info()).unwrap(),
+                raw_sidecar: None,
+            };
+            record(
+                &quit_flag,
+                &options,
+                &rates,
+                &mut connector,
+                &mut output,
+                MockClock::default().pacing(),
+            )
+            .unwrap();
+            drop(output);
+
+            assert_eq!(counter.flushes, flushes, "{:?}", every);
+            assert_eq!(count_frames(&counter.data), 5);
+        }
+    }
+
+    /// Sends one frame, then panics.
+    struct PanickingConnector {
+        updates: u32,
+    }
+
+    impl Connector for PanickingConnector {
+        fn connect(&mut self) -> bool {
+            true
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            self.updates += 1;
+            assert!(self.updates < 2, "connector failed");
+            Some(b"frame".to_vec())
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_record_flushes_on_panic() {
+        let quit_flag = AtomicBool::new(false);
+        let rates = Rates { fps: 1, poll_hz: 1 };
+        let mut connector = PanickingConnector { updates: 0 };
+        let mut counter = FlushCounter::default();
+        let mut output = Output {
+            saver: Saver::new(&mut counter, 1, connector.info()).unwrap(),
+            raw_sidecar: None,
+        };
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            record(
+                &quit_flag,
+                &LoopOptions::default(),
+                &rates,
+                &mut connector,
+                &mut output,
+                MockClock::default().pacing(),
+            )
+        }));
+        drop(output);
+
+        assert!(result.is_err());
+        assert_eq!(counter.flushes, 1);
+        assert_eq!(count_frames(&counter.data), 1);
+    }
+
+    #[test]
+    fn test_record_detects_disconnect() {
+        let quit_flag = AtomicBool::new(false);
+        let mut responses = VecDeque::from([Some(b"first".to_vec())]);
+        responses.extend(std::iter::repeat_n(None, 21));
+        let mut connector = FakeConnector {
+            responses,
+            quit_flag: &quit_flag,
+        };
+        let rates = Rates { fps: 1, poll_hz: 1 };
+
+        let clock = MockClock::default();
+        let start = clock.now();
+        let mut buffer = Vec::new();
+        let mut output = Output {
+            saver: Saver::new(&mut buffer, 1, connector.info()).unwrap(),
+            raw_sidecar: None,
+        };
+        let result = record(
+            &quit_flag,
+            &LoopOptions::default(),
+            &rates,
+            &mut connector,
+            &mut output,
+            clock.pacing(),
+        )
+        .unwrap();
+        output.saver.finalize().unwrap();
+
+        // the 21st tick in a row without data gives up, without sleeping after it
+        assert!(matches!(result, RecordingFinished::SimDisconnected));
+        assert!(!quit_flag.load(Ordering::Relaxed));
+        assert_eq!(clock.now() - start, Duration::from_secs(21));
+        assert_eq!(count_frames(&buffer), 1);
+    }
+
+    /// Connects after failing a number of times.
+    struct LateConnector {
+        failures_left: u32,
+    }
+
+    impl Connector for LateConnector {
+        fn connect(&mut self) -> bool {
+            self.failures_left = self.failures_left.saturating_sub(1);
+            self.failures_left == 0
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct SleepRecorder {
+        sleeps: std::cell::RefCell<Vec<u64>>,
+    }
+
+    impl Sleeper for SleepRecorder {
+        fn sleep_ms(&self, ms: u64) {
+            self.sleeps.borrow_mut().push(ms);
+        }
+    }
+
+    #[test]
+    fn test_connect_poll_interval() {
+        let fixed = ConnectPoll {
+            interval_ms: 250,
+            backoff_max_ms: None,
+        };
+        assert_eq!(fixed.interval_after(0), 250);
+        assert_eq!(fixed.interval_after(5), 250);
+
+        let backoff = ConnectPoll {
+            interval_ms: 250,
+            backoff_max_ms: Some(1500),
+        };
+        assert_eq!(backoff.interval_after(0), 250);
+        assert_eq!(backoff.interval_after(1), 500);
+        assert_eq!(backoff.interval_after(2), 1000);
+        assert_eq!(backoff.interval_after(3), 1500);
+        assert_eq!(backoff.interval_after(u32::MAX), 1500);
+
+        // a cap below the interval doesn't shorten it
+        let low_cap = ConnectPoll {
+            interval_ms: 250,
+            backoff_max_ms: Some(100),
+        };
+        assert_eq!(low_cap.interval_after(3), 250);
+    }
+
+    #[test]
+    fn test_wait_for_connection_backs_off_in_steps() {
+        let quit_flag = AtomicBool::new(false);
+        let mut connectors: Vec<Box<dyn Connector>> =
+            vec![Box::new(LateConnector { failures_left: 3 })];
+        let sleeper = SleepRecorder::default();
+        let poll = ConnectPoll {
+            interval_ms: 150,
+            backoff_max_ms: Some(400),
+        };
+
+        let connector = wait_for_connection(&quit_flag, &mut connectors, &sleeper, poll);
+        assert!(connector.is_some());
+        // waits of 150 and 300ms after the two failed scans, in steps short enough for
+        // a quit request to take effect
+        assert_eq!(*sleeper.sleeps.borrow(), [50; 9]);
+    }
+
+    #[test]
+    fn test_wait_for_connection_quits() {
+        let quit_flag = AtomicBool::new(true);
+        let mut connectors: Vec<Box<dyn Connector>> =
+            vec![Box::new(LateConnector { failures_left: 3 })];
+        let sleeper = SleepRecorder::default();
+
+        let connector = wait_for_connection(
+            &quit_flag,
+            &mut connectors,
+            &sleeper,
+            ConnectPoll::default(),
+        );
+        assert!(connector.is_none());
+        assert!(sleeper.sleeps.borrow().is_empty());
+    }
+
+    /// Connects if `up`, counting the disconnects.
+    struct CountingConnector {
+        up: bool,
+        disconnects: Rc<Cell<u32>>,
+    }
+
+    impl Connector for CountingConnector {
+        fn connect(&mut self) -> bool {
+            self.up
+        }
+
+        fn disconnect(&mut self) {
+            self.disconnects.set(self.disconnects.get() + 1);
+        }
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_wait_for_connection_disconnects_others() {
+        let quit_flag = AtomicBool::new(false);
+        let disconnects = Rc::new(Cell::new(0));
+        let mut connectors: Vec<Box<dyn Connector>> = [false, true, false]
+            .into_iter()
+            .map(|up| {
+                Box::new(CountingConnector {
+                    up,
+                    disconnects: disconnects.clone(),
+                }) as Box<dyn Connector>
+            })
+            .collect();
+        let sleeper = SleepRecorder::default();
+
+        let connector = wait_for_connection(
+            &quit_flag,
+            &mut connectors,
+            &sleeper,
+            ConnectPoll::default(),
+        );
+        assert!(connector.is_some());
+        // the ones that didn't win, the winner when recording ends
+        assert_eq!(disconnects.get(), 2);
+        drop(connector);
+        assert_eq!(disconnects.get(), 3);
+    }
+
+    /// Never connects, like a sim that is half up, and gets a quit request during the
+    /// attempt `quit_on`, as if it came in while the attempt was blocked.
+    struct QuitDuringConnect {
+        attempts: Rc<Cell<u32>>,
+        quit_flag: Arc<AtomicBool>,
+        quit_on: u32,
+    }
+
+    impl Connector for QuitDuringConnect {
+        fn connect(&mut self) -> bool {
+            self.attempts.set(self.attempts.get() + 1);
+            if self.attempts.get() == self.quit_on {
+                self.quit_flag.store(true, Ordering::Relaxed);
+            }
+            false
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: *b"test",
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_wait_for_connection_quits_between_attempts() {
+        let quit_flag = Arc::new(AtomicBool::new(false));
+        let attempts = Rc::new(Cell::new(0));
+        // quit is requested during the attempt of the first connector in the second scan
+        let mut connectors: Vec<Box<dyn Connector>> = (0..3)
+            .map(|_| {
+                Box::new(QuitDuringConnect {
+                    attempts: attempts.clone(),
+                    quit_flag: quit_flag.clone(),
+                    quit_on: 4,
+                }) as Box<dyn Connector>
+            })
+            .collect();
+        let sleeper = SleepRecorder::default();
+
+        let connector = wait_for_connection(
+            &quit_flag,
+            &mut connectors,
+            &sleeper,
+            ConnectPoll::default(),
+        );
+        assert!(connector.is_none());
+        // the other two connectors aren't tried again, and there is no wait after the scan
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(*sleeper.sleeps.borrow(), [50; 20]);
+    }
+
+    /// Never connects, only has a sim id.
+    struct IdConnector([u8; 4]);
+
+    impl Connector for IdConnector {
+        fn connect(&mut self) -> bool {
+            false
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn update(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn info(&self) -> SimInfo {
+            SimInfo {
+                id: self.0,
+                payload_version: 1,
+                native_hz: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefer() {
+        let ids = |connectors: &[Box<dyn Connector>]| -> Vec<[u8; 4]> {
+            connectors.iter().map(|c| c.info().id).collect()
+        };
+        let mut connectors: Vec<Box<dyn Connector>> = [*b"irac", *b"acsa", *b"pc2_", *b"f1__"]
+            .into_iter()
+            .map(|id| Box::new(IdConnector(id)) as Box<dyn Connector>)
+            .collect();
+
+        prefer(&mut connectors, RecordSim::Pcars2.id());
+        assert_eq!(ids(&connectors), [*b"pc2_", *b"irac", *b"acsa", *b"f1__"]);
+        prefer(&mut connectors, RecordSim::Pcars2.id());
+        assert_eq!(ids(&connectors), [*b"pc2_", *b"irac", *b"acsa", *b"f1__"]);
+        prefer(&mut connectors, RecordSim::F1.id());
+        assert_eq!(ids(&connectors), [*b"f1__", *b"pc2_", *b"irac", *b"acsa"]);
+        // a sim without a connector in the list changes nothing
+        prefer(&mut connectors, RecordSim::Dirt.id());
+        assert_eq!(ids(&connectors), [*b"f1__", *b"pc2_", *b"irac", *b"acsa"]);
+    }
+
+    #[test]
+    fn test_scanned_connectors() {
+        let scanned = |sim, prefer| -> Vec<[u8; 4]> {
+            let options = RecordOptions {
+                sim,
+                prefer,
+                ..test_options()
+            };
+            scanned_connectors(&options)
+                .iter()
+                .map(|c| c.info().id)
+                .collect()
+        };
+
+        // UDP sims are only scanned for when asked for
+        assert_eq!(scanned(None, None), [*b"irac", *b"acsa", *b"pc2_"]);
+        assert_eq!(scanned(Some(RecordSim::F1), None), [*b"f1__"]);
+        assert_eq!(
+            scanned(None, Some(RecordSim::Pcars2)),
+            [*b"pc2_", *b"irac", *b"acsa"]
+        );
+        assert_eq!(
+            scanned(None, Some(RecordSim::Dirt)),
+            [*b"dirt", *b"irac", *b"acsa", *b"pc2_"]
+        );
+    }
+
+    #[test]
+    fn test_verify_recording() {
+        let filename = std::env::temp_dir()
+            .join(format!("ksana_record_verify_{}.ksr", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let info = SimInfo {
+            id: *b"test",
+            payload_version: 1,
+            native_hz: 0,
+        };
+        let options = SaverOptions {
+            codec: Box::new(ZlibCodec::default()),
+            passphrase: Some("secret"),
+            start_time: None,
+            frame_flags: false,
+        };
+        let mut saver =
+            Saver::with_options(File::create(&filename).unwrap(), 10, info, options).unwrap();
+        saver.save(b"first").unwrap();
+        saver.save(b"second").unwrap();
+        saver.finalize().unwrap();
+
+        assert_eq!(verify_recording(&filename, Some("secret")), Ok(2));
+        let error = verify_recording(&filename, Some("wrong")).unwrap_err();
+        assert!(error.starts_with("frame 0 failed to load"), "{}", error);
+
+        // the last frame cut off, as if it wasn't flushed
+        let data = std::fs::read(&filename).unwrap();
+        std::fs::write(&filename, &data[..data.len() - 4]).unwrap();
+        let error = verify_recording(&filename, Some("secret")).unwrap_err();
+        assert!(error.starts_with("frame 1 failed to load"), "{}", error);
+
+        std::fs::remove_file(&filename).unwrap();
+        assert!(verify_recording(&filename, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_fps() {
+        assert_eq!("auto".parse::<Fps>().unwrap(), Fps::Auto);
+        assert_eq!("30".parse::<Fps>().unwrap(), Fps::Fixed(30));
+        assert_eq!("0".parse::<Fps>().unwrap(), Fps::Fixed(1));
+        assert_eq!("240".parse::<Fps>().unwrap(), Fps::Fixed(MAX_FPS));
+        assert!("fast".parse::<Fps>().is_err());
+    }
+
+    #[test]
+    fn test_parse_flush_every() {
+        assert_eq!(parse_flush_every("500").unwrap(), FlushEvery::Frames(500));
+        assert_eq!(
+            parse_flush_every("30s").unwrap(),
+            FlushEvery::Duration(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_flush_every("2m").unwrap(),
+            FlushEvery::Duration(Duration::from_secs(120))
+        );
+        assert!(parse_flush_every("0").is_err());
+        assert!(parse_flush_every("0s").is_err());
+        assert!(parse_flush_every("often").is_err());
+    }
+
+    #[test]
+    fn test_resolve_fps() {
+        assert_eq!(resolve_fps(Fps::Fixed(10), b"irac", Some(60)), 10);
+        assert_eq!(resolve_fps(Fps::Unset, b"irac", Some(60)), DEFAULT_FPS);
+        assert_eq!(resolve_fps(Fps::Auto, b"irac", None), 60);
+        assert_eq!(resolve_fps(Fps::Auto, b"test", None), DEFAULT_FPS);
+        assert_eq!(resolve_fps(Fps::Auto, b"test", Some(30)), 30);
+        assert_eq!(resolve_fps(Fps::Auto, b"test", Some(360)), MAX_FPS);
+    }
 
     #[test]
     fn test_parse_duration_happy() {
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::data::FrameData;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SessionInfoError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Session info is only stored in iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to decode frame: {0}")]
+    FailedToDecodeFrame(std::io::Error),
+
+    #[error("Recording has no frame with session info")]
+    NoSessionInfo,
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(std::io::Error),
+}
+
+/// Index and session info of the last frame carrying session info. The sim rewrites the
+/// whole YAML on every change, so the last one has the most complete results.
+fn last_session_info<R: Read + Seek>(
+    loader: &mut Loader<R>,
+) -> Result<Option<(u64, Vec<u8>)>, SessionInfoError> {
+    let payload_version = loader.payload_version();
+    let mut last = None;
+    let mut index: u64 = 0;
+
+    while let Some(data) = loader.load().map_err(SessionInfoError::FailedToLoadFrame)? {
+        let frame = FrameData::deserialize(&data, payload_version)
+            .map_err(SessionInfoError::FailedToDecodeFrame)?;
+        if let Some(session_info) = frame.session_info {
+            last = Some((index, session_info));
+        }
+        index += 1;
+    }
+
+    Ok(last)
+}
+
+/// Session info without the null padding of the sim's buffer.
+fn trim_nul(session_info: &[u8]) -> &[u8] {
+    let len = session_info
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |i| i + 1);
+    &session_info[..len]
+}
+
+/// Writes the last session info YAML of an iRacing recording to `output_file` as is.
+pub fn run(input_file: &str, output_file: &str) -> Result<(), SessionInfoError> {
+    let file = File::open(input_file).map_err(SessionInfoError::FailedToOpenFile)?;
+    let mut loader =
+        Loader::new(BufReader::new(file)).map_err(SessionInfoError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(SessionInfoError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    let Some((index, session_info)) = last_session_info(&mut loader)? else {
+        return Err(SessionInfoError::NoSessionInfo);
+    };
+    let yaml = trim_nul(&session_info);
+    std::fs::write(output_file, yaml).map_err(SessionInfoError::FailedToWriteOutput)?;
+
+    println!(
+        "Session info written to: {} ({} bytes, from frame {})",
+        output_file,
+        yaml.len(),
+        index
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::Saver;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_last_session_info() {
+        let mut frames: Vec<Vec<u8>> = iracing_frames(10, 5).map(Option::unwrap).collect();
+        let mut updated = FrameData::deserialize(&frames[2], 2).unwrap();
+        updated.session_info = Some(b"WeekendInfo:\n TrackName: spa\n\0\0\0".to_vec());
+        frames[2] = updated.serialize().unwrap();
+
+        let mut buffer = Vec::new();
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let mut saver = Saver::new(&mut buffer, 10, info).unwrap();
+        for frame in &frames {
+            saver.save(frame).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let mut loader = Loader::new(Cursor::new(buffer)).unwrap();
+        let (index, session_info) = last_session_info(&mut loader).unwrap().unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(trim_nul(&session_info), b"WeekendInfo:\n TrackName: spa\n");
+        assert_eq!(trim_nul(b"\0\0"), b"");
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::SimInfo;
+use crate::codec::ZlibCodec;
+use crate::io::{IOError, Loader, Saver};
+use crate::sims::iracing::data::{CURRENT_PAYLOAD_VERSION, FrameData, VarHeader};
+use crate::sims::iracing::vars::read_f64;
+
+// Lap distance has to drop from above the high mark to below the low mark to count as a wrap
+const WRAP_HIGH: f64 = 0.9;
+const WRAP_LOW: f64 = 0.1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SplitLapsError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Lap splitting is only supported for iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to decode frame: {0}")]
+    FailedToDecodeFrame(std::io::Error),
+
+    #[error("Failed to serialize frame")]
+    FailedToSerializeFrame,
+
+    #[error("Failed to create output directory: {0}")]
+    FailedToCreateDir(std::io::Error),
+
+    #[error("Failed to create output file: {0}")]
+    FailedToCreateFile(std::io::Error),
+
+    #[error("Failed to write lap: {0}")]
+    FailedToWriteLap(IOError),
+
+    #[error("Failed to remove partial lap: {0}")]
+    FailedToRemovePartialLap(std::io::Error),
+}
+
+/// Detects lap boundaries from the lap distance wrapping around the start/finish line.
+/// When the lap counter is available, a wrap only counts once the lap number increased as
+/// well, which filters out resets and teleports crossing the line. The counter may lag
+/// behind the wrap by a few frames, so the wrap is kept pending until it catches up.
+#[derive(Default)]
+pub(crate) struct LapDetector {
+    last_pct: Option<f64>,
+    lap: Option<i64>,
+    pending_wrap: bool,
+}
+
+impl LapDetector {
+    pub(crate) fn update(&mut self, pct: f64, lap: Option<i64>) -> bool {
+        let wrapped = matches!(self.last_pct, Some(last) if last > WRAP_HIGH && pct < WRAP_LOW);
+        self.last_pct = Some(pct);
+
+        if self.lap.is_none() {
+            self.lap = lap;
+        }
+
+        if wrapped {
+            self.pending_wrap = true;
+        } else if pct >= WRAP_LOW {
+            self.pending_wrap = false;
+        }
+
+        if !self.pending_wrap {
+            return false;
+        }
+
+        let boundary = match (self.lap, lap) {
+            (Some(previous), Some(current)) => current > previous,
+            _ => true,
+        };
+        if boundary {
+            self.lap = lap;
+            self.pending_wrap = false;
+        }
+        boundary
+    }
+}
+
+fn lap_path(output_dir: &Path, lap: u32) -> PathBuf {
+    output_dir.join(format!("lap_{:02}.bin", lap))
+}
+
+pub fn run(input_file: &str, output_dir: &str) -> Result<(), SplitLapsError> {
+    let file = File::open(input_file).map_err(SplitLapsError::FailedToOpenFile)?;
+    let mut loader =
+        Loader::new(BufReader::new(file)).map_err(SplitLapsError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(SplitLapsError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    let fps = loader.fps();
+    let payload_version = loader.payload_version();
+    let info = SimInfo {
+        id,
+        payload_version: CURRENT_PAYLOAD_VERSION,
+        native_hz: loader.native_hz().unwrap_or(0),
+    };
+
+    let output_dir = Path::new(output_dir);
+    std::fs::create_dir_all(output_dir).map_err(SplitLapsError::FailedToCreateDir)?;
+
+    let mut detector = LapDetector::default();
+    let mut var_headers: Vec<VarHeader> = vec![];
+    let mut session_info: Option<Vec<u8>> = None;
+    let mut saver: Option<Saver<BufWriter<File>>> = None;
+    let mut lap_count: u32 = 0;
+
+    while let Some(data) = loader.load().map_err(SplitLapsError::FailedToLoadFrame)? {
+        let mut frame = FrameData::deserialize(&data, payload_version)
+            .map_err(SplitLapsError::FailedToDecodeFrame)?;
+
+        if let Some(headers) = &frame.var_headers {
+            var_headers = headers.clone();
+        }
+        if frame.session_info.is_some() {
+            session_info = frame.session_info.clone();
+        }
+
+        if let Some(pct) = read_f64(&var_headers, &frame.raw_data, "LapDistPct", 0) {
+            let lap = read_f64(&var_headers, &frame.raw_data, "Lap", 0).map(|l| l as i64);
+            if detector.update(pct, lap) {
+                if let Some(finished) = saver.take() {
+                    finished
+                        .finalize()
+                        .map_err(SplitLapsError::FailedToWriteLap)?;
+                    println!("Lap {:02} written", lap_count);
+                }
+
+                lap_count += 1;
+                let path = lap_path(output_dir, lap_count);
+                let file = File::create(&path).map_err(SplitLapsError::FailedToCreateFile)?;
+                let new_saver = Saver::with_start_time(
+                    BufWriter::new(file),
+                    fps,
+                    info,
+                    Box::new(ZlibCodec::default()),
+                    None,
+                    loader.tick_time(loader.frame_tick()),
+                )
+                .map_err(SplitLapsError::FailedToWriteLap)?;
+                saver = Some(new_saver);
+
+                // every lap file has to be playable on its own
+                frame.var_headers = Some(var_headers.clone());
+                frame.session_info = session_info.clone();
+            }
+        }
+
+        // frames before the first boundary belong to a partial lap and are skipped
+        if let Some(saver) = saver.as_mut() {
+            let bytes = frame
+                .serialize()
+                .ok_or(SplitLapsError::FailedToSerializeFrame)?;
+            saver
+                .save(&bytes)
+                .map_err(SplitLapsError::FailedToWriteLap)?;
+        }
+    }
+
+    // the last lap was not completed, so it is partial as well
+    if saver.take().is_some() {
+        let path = lap_path(output_dir, lap_count);
+        std::fs::remove_file(&path).map_err(SplitLapsError::FailedToRemovePartialLap)?;
+        lap_count -= 1;
+    }
+
+    println!("Complete laps found: {}", lap_count);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lap_detector_wrap() {
+        let mut detector = LapDetector::default();
+        assert!(!detector.update(0.5, None));
+        assert!(!detector.update(0.95, None));
+        assert!(detector.update(0.01, None));
+        assert!(!detector.update(0.05, None));
+    }
+
+    #[test]
+    fn test_lap_detector_ignores_backwards_jump() {
+        let mut detector = LapDetector::default();
+        assert!(!detector.update(0.02, None));
+        assert!(!detector.update(0.98, None));
+        assert!(!detector.update(0.97, None));
+    }
+
+    #[test]
+    fn test_lap_detector_debounces_with_lap_number() {
+        let mut detector = LapDetector::default();
+        assert!(!detector.update(0.95, Some(3)));
+        assert!(detector.update(0.01, Some(4)));
+
+        // reset to just before the line and crossing it again without a lap increment
+        assert!(!detector.update(0.99, Some(4)));
+        assert!(!detector.update(0.02, Some(4)));
+
+        assert!(!detector.update(0.95, Some(4)));
+        assert!(detector.update(0.03, Some(5)));
+    }
+
+    #[test]
+    fn test_lap_detector_lap_number_lagging() {
+        let mut detector = LapDetector::default();
+        assert!(!detector.update(0.95, Some(3)));
+        assert!(!detector.update(0.01, Some(3)));
+        assert!(detector.update(0.02, Some(4)));
+        assert!(!detector.update(0.03, Some(4)));
+    }
+}
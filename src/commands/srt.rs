@@ -0,0 +1,337 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+use crate::commands::split_laps::LapDetector;
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::data::{FrameData, VarHeader};
+use crate::sims::iracing::vars::{self, Value, read_f64};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SrtError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Subtitle export is only supported for iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("No channels selected")]
+    NoChannels,
+
+    #[error("Failed to read channels file: {0}")]
+    FailedToReadChannelsFile(std::io::Error),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to decode frame: {0}")]
+    FailedToDecodeFrame(std::io::Error),
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(std::io::Error),
+}
+
+/// Formats a time in milliseconds as an SRT timestamp, `HH:MM:SS,mmm`.
+fn timestamp(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// Channel names of a channels file: one per line, `#` starts a comment, blank lines are
+/// skipped.
+fn parse_channels(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads the channels listed in `path`, for reusing a standard channel set across exports.
+pub fn read_channels_file(path: &str) -> Result<Vec<String>, SrtError> {
+    let text = std::fs::read_to_string(path).map_err(SrtError::FailedToReadChannelsFile)?;
+    Ok(parse_channels(&text))
+}
+
+/// Channels not in the recording, in the order they were selected.
+fn unknown_channels<'a>(var_headers: &[VarHeader], channels: &'a [String]) -> Vec<&'a str> {
+    channels
+        .iter()
+        .filter(|name| vars::find(var_headers, name).is_none())
+        .map(String::as_str)
+        .collect()
+}
+
+fn format_value(value: Value) -> String {
+    match value {
+        Value::Float(v) => format!("{:.3}", v),
+        Value::Double(v) => format!("{:.3}", v),
+        Value::BitField(v) => format!("0x{:08x}", v),
+        other => format!("{}", other.as_f64()),
+    }
+}
+
+/// One line per channel, `Name: value unit`, or `Name: -` if the channel is missing.
+fn cue_text(var_headers: &[VarHeader], raw_data: &[u8], channels: &[String]) -> String {
+    channels
+        .iter()
+        .map(|name| {
+            let Some(vh) = vars::find(var_headers, name) else {
+                return format!("{}: -", name);
+            };
+            match vars::read(vh, raw_data, 0) {
+                Some(value) => {
+                    let unit = vh.unit_str();
+                    if unit.is_empty() {
+                        format!("{}: {}", name, format_value(value))
+                    } else {
+                        format!("{}: {} {}", name, format_value(value), unit)
+                    }
+                }
+                None => format!("{}: -", name),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct SrtWriter<W: Write> {
+    writer: W,
+    fps: u64,
+    cues: u64,
+}
+
+impl<W: Write> SrtWriter<W> {
+    /// Writes a cue shown from save tick `start` until tick `end`, timed from the fps.
+    /// Ticks count the time without frames as well, see `Loader::frame_tick`.
+    fn cue(&mut self, start: u64, end: u64, text: &str) -> std::io::Result<()> {
+        self.cues += 1;
+        writeln!(self.writer, "{}", self.cues)?;
+        writeln!(
+            self.writer,
+            "{} --> {}",
+            timestamp(start * 1000 / self.fps),
+            timestamp(end * 1000 / self.fps)
+        )?;
+        writeln!(self.writer, "{}\n", text)
+    }
+}
+
+/// Writes the selected channels of an iRacing recording as SRT subtitles, one cue per
+/// second, or one cue per lap showing the values at the start of the lap.
+pub fn run(
+    input_file: &str,
+    output_file: &str,
+    channels: &[String],
+    per_lap: bool,
+) -> Result<(), SrtError> {
+    if channels.is_empty() {
+        return Err(SrtError::NoChannels);
+    }
+
+    let file = File::open(input_file).map_err(SrtError::FailedToOpenFile)?;
+    let mut loader = Loader::new(BufReader::new(file)).map_err(SrtError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(SrtError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    let fps = loader.fps().max(1) as u64;
+    let payload_version = loader.payload_version();
+
+    let output = File::create(output_file).map_err(SrtError::FailedToWriteOutput)?;
+    let mut srt = SrtWriter {
+        writer: BufWriter::new(output),
+        fps,
+        cues: 0,
+    };
+
+    let mut var_headers: Vec<VarHeader> = vec![];
+    let mut detector = LapDetector::default();
+    let mut pending: Option<(u64, String)> = None;
+    let mut end: u64 = 0;
+
+    while let Some(data) = loader.load().map_err(SrtError::FailedToLoadFrame)? {
+        let frame = FrameData::deserialize(&data, payload_version)
+            .map_err(SrtError::FailedToDecodeFrame)?;
+
+        if let Some(headers) = frame.var_headers {
+            if var_headers.is_empty() {
+                let unknown = unknown_channels(&headers, channels);
+                if !unknown.is_empty() {
+                    eprintln!(
+                        "Warning: channels not in the recording: {}",
+                        unknown.join(", ")
+                    );
+                }
+            }
+            var_headers = headers;
+        }
+        let raw = &frame.raw_data;
+        let tick = loader.frame_tick();
+
+        let starts_cue = if per_lap {
+            let lap = read_f64(&var_headers, raw, "Lap", 0).map(|l| l as i64);
+            match read_f64(&var_headers, raw, "LapDistPct", 0) {
+                Some(pct) => detector.update(pct, lap) || pending.is_none(),
+                None => pending.is_none(),
+            }
+        } else {
+            // the first frame of every second, a second without frames has no cue
+            pending
+                .as_ref()
+                .is_none_or(|(start, _)| tick / fps != start / fps)
+        };
+
+        if starts_cue {
+            if let Some((start, text)) = pending.take() {
+                srt.cue(start, tick, &text)
+                    .map_err(SrtError::FailedToWriteOutput)?;
+            }
+            pending = Some((tick, cue_text(&var_headers, raw, channels)));
+        }
+
+        end = tick + 1;
+    }
+
+    if let Some((start, text)) = pending.take() {
+        srt.cue(start, end, &text)
+            .map_err(SrtError::FailedToWriteOutput)?;
+    }
+
+    srt.writer.flush().map_err(SrtError::FailedToWriteOutput)?;
+
+    println!("Subtitles written to: {} ({} cues)", output_file, srt.cues);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::codec::ZlibCodec;
+    use crate::commands::generate::iracing_frames;
+    use crate::io::{FrameFlags, Saver, SaverOptions};
+    use crate::sims::iracing::vars::VarType;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "00:00:00,000");
+        assert_eq!(timestamp(61_500), "00:01:01,500");
+        assert_eq!(timestamp(3_723_004), "01:02:03,004");
+    }
+
+    #[test]
+    fn test_cue_text() {
+        let mut speed = VarHeader {
+            var_type: VarType::Float as i32,
+            offset: 0,
+            count: 1,
+            ..Default::default()
+        };
+        speed.name[..5].copy_from_slice(b"Speed");
+        speed.unit[..3].copy_from_slice(b"m/s");
+        let mut gear = VarHeader {
+            var_type: VarType::Int as i32,
+            offset: 4,
+            count: 1,
+            ..Default::default()
+        };
+        gear.name[..4].copy_from_slice(b"Gear");
+
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&42.5f32.to_le_bytes());
+        raw.extend_from_slice(&3i32.to_le_bytes());
+
+        let channels = ["Speed", "Gear", "Rpm"].map(String::from);
+        assert_eq!(
+            cue_text(&[speed, gear], &raw, &channels),
+            "Speed: 42.500 m/s\nGear: 3\nRpm: -"
+        );
+    }
+
+    #[test]
+    fn test_parse_channels() {
+        let text = "# standard set\nSpeed\n\n  Gear  # current gear\r\nRPM\n#Brake\n";
+        assert_eq!(parse_channels(text), ["Speed", "Gear", "RPM"]);
+
+        let mut speed = VarHeader::default();
+        speed.name[..5].copy_from_slice(b"Speed");
+        let channels = parse_channels(text);
+        assert_eq!(unknown_channels(&[speed], &channels), ["Gear", "RPM"]);
+    }
+
+    #[test]
+    fn test_cue_timing() {
+        let mut srt = SrtWriter {
+            writer: Vec::new(),
+            fps: 60,
+            cues: 0,
+        };
+        srt.cue(0, 60, "a").unwrap();
+        srt.cue(60, 150, "b").unwrap();
+        assert_eq!(
+            String::from_utf8(srt.writer).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,000\na\n\n2\n00:00:01,000 --> 00:00:02,500\nb\n\n"
+        );
+    }
+
+    #[test]
+    fn test_cues_timed_by_ticks() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ksana_srt_ticks_{}.ksr", std::process::id()));
+        let output = dir.join(format!("ksana_srt_ticks_{}.srt", std::process::id()));
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 10,
+        };
+        let options = SaverOptions {
+            codec: Box::new(ZlibCodec::default()),
+            passphrase: None,
+            start_time: None,
+            frame_flags: true,
+        };
+        let mut saver =
+            Saver::with_options(File::create(&input).unwrap(), 10, info, options).unwrap();
+        // saved in ticks 0, 10, 11 and 24, nothing in the third second
+        for (frame, missed_ticks) in iracing_frames(10, 4).zip([0, 9, 0, 12]) {
+            let flags = FrameFlags { missed_ticks };
+            saver.save_with_flags(&frame.unwrap(), flags).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let channels = ["Gear".to_string()];
+        run(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            &channels,
+            false,
+        )
+        .unwrap();
+        let srt = std::fs::read_to_string(&output).unwrap();
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+
+        let timings: Vec<&str> = srt.lines().filter(|line| line.contains("-->")).collect();
+        assert_eq!(
+            timings,
+            [
+                "00:00:00,000 --> 00:00:01,000",
+                "00:00:01,000 --> 00:00:02,400",
+                "00:00:02,400 --> 00:00:02,500",
+            ]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+
+use crate::io::{IOError, Loader};
+use crate::sims::iracing::data::{FrameData, VarHeader};
+use crate::sims::iracing::vars::{self, read_f64};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrackMapError {
+    #[error("Failed to open file: {0}")]
+    FailedToOpenFile(std::io::Error),
+
+    #[error("Failed to read header: {0}")]
+    FailedToReadHeader(IOError),
+
+    #[error("Track map is only supported for iRacing recordings, got: {0}")]
+    UnsupportedSim(String),
+
+    #[error("Failed to load frame: {0}")]
+    FailedToLoadFrame(IOError),
+
+    #[error("Failed to decode frame: {0}")]
+    FailedToDecodeFrame(std::io::Error),
+
+    #[error("Failed to write output: {0}")]
+    FailedToWriteOutput(std::io::Error),
+}
+
+/// Extracts the player car index from the `DriverCarIdx` key of the session info YAML.
+fn player_car_idx(session_info: &[u8]) -> Option<usize> {
+    let text = std::str::from_utf8(session_info).ok()?;
+    text.lines()
+        .find_map(|line| line.trim().strip_prefix("DriverCarIdx:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+pub fn run(input_file: &str, output_file: &str) -> Result<(), TrackMapError> {
+    let file = File::open(input_file).map_err(TrackMapError::FailedToOpenFile)?;
+    let mut loader =
+        Loader::new(BufReader::new(file)).map_err(TrackMapError::FailedToReadHeader)?;
+
+    let id = loader.id();
+    if &id != b"irac" {
+        return Err(TrackMapError::UnsupportedSim(
+            std::str::from_utf8(&id).unwrap_or("????").to_string(),
+        ));
+    }
+
+    let output = File::create(output_file).map_err(TrackMapError::FailedToWriteOutput)?;
+    let points = write_track_map(&mut loader, BufWriter::new(output))?;
+
+    println!("Track map written to: {} ({} points)", output_file, points);
+
+    Ok(())
+}
+
+/// Writes the CSV a row at a time as the frames are decoded, only the current frame is held
+/// in memory whatever the length of the recording. Returns the number of rows written.
+fn write_track_map<R: Read + Seek, W: Write>(
+    loader: &mut Loader<R>,
+    mut writer: W,
+) -> Result<u64, TrackMapError> {
+    let fps = loader.fps().max(1) as f64;
+    let payload_version = loader.payload_version();
+
+    writeln!(writer, "x,y,lap_dist_pct").map_err(TrackMapError::FailedToWriteOutput)?;
+
+    let mut var_headers: Vec<VarHeader> = vec![];
+    let mut player_idx: Option<usize> = None;
+    let (mut x, mut y) = (0.0f64, 0.0f64);
+    let mut points: u64 = 0;
+
+    while let Some(data) = loader.load().map_err(TrackMapError::FailedToLoadFrame)? {
+        let frame = FrameData::deserialize(&data, payload_version)
+            .map_err(TrackMapError::FailedToDecodeFrame)?;
+
+        if let Some(headers) = frame.var_headers {
+            var_headers = headers;
+        }
+        if let Some(session_info) = &frame.session_info {
+            player_idx = player_car_idx(session_info).or(player_idx);
+        }
+
+        let raw = &frame.raw_data;
+
+        if read_f64(&var_headers, raw, "IsOnTrack", 0) == Some(0.0) {
+            continue;
+        }
+
+        // Lat/Lon are only present in some telemetry outputs, otherwise fall back to
+        // integrating Speed and Yaw, giving meters from the starting point
+        if vars::find(&var_headers, "Lat").is_some() && vars::find(&var_headers, "Lon").is_some() {
+            let (Some(lat), Some(lon)) = (
+                read_f64(&var_headers, raw, "Lat", 0),
+                read_f64(&var_headers, raw, "Lon", 0),
+            ) else {
+                continue;
+            };
+            x = lon;
+            y = lat;
+        } else {
+            let (Some(speed), Some(yaw)) = (
+                read_f64(&var_headers, raw, "Speed", 0),
+                read_f64(&var_headers, raw, "Yaw", 0),
+            ) else {
+                continue;
+            };
+            x += speed * yaw.cos() / fps;
+            y += speed * yaw.sin() / fps;
+        }
+
+        // LapDistPct is only for the player car, CarIdxLapDistPct covers every car
+        let lap_dist_pct = match player_idx {
+            Some(idx) => read_f64(&var_headers, raw, "CarIdxLapDistPct", idx),
+            None => None,
+        }
+        .or_else(|| read_f64(&var_headers, raw, "LapDistPct", 0));
+
+        match lap_dist_pct {
+            Some(pct) => writeln!(writer, "{},{},{}", x, y, pct),
+            None => writeln!(writer, "{},{},", x, y),
+        }
+        .map_err(TrackMapError::FailedToWriteOutput)?;
+
+        points += 1;
+    }
+
+    writer.flush().map_err(TrackMapError::FailedToWriteOutput)?;
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::codec::NoneCodec;
+    use crate::io::Saver;
+    use crate::sims::iracing::data::Header;
+    use crate::sims::iracing::vars::VarType;
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_player_car_idx() {
+        let session_info =
+            b"WeekendInfo:\n TrackName: spa\nDriverInfo:\n DriverCarIdx: 12\n DriverUserID: 1\n";
+        assert_eq!(player_car_idx(session_info), Some(12));
+        assert_eq!(player_car_idx(b"DriverInfo:\n"), None);
+    }
+
+    /// Counts the bytes read through it, to tell how far the export got into the input.
+    struct CountingReader<R> {
+        inner: R,
+        read: Rc<Cell<u64>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.set(self.read.get() + n as u64);
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    /// Records the size of every write and how much input was read before the first one.
+    #[derive(Default)]
+    struct Probe {
+        input_read: Rc<Cell<u64>>,
+        read_at_first_write: Option<u64>,
+        largest_write: usize,
+        written: usize,
+    }
+
+    impl Write for Probe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.read_at_first_write
+                .get_or_insert(self.input_read.get());
+            self.largest_write = self.largest_write.max(buf.len());
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_export_streams() {
+        // 40 minutes at 60 fps of a car driving in circles
+        const FRAMES: u32 = 40 * 60 * 60;
+        let var_header = |name: &str, offset| {
+            let mut vh = VarHeader {
+                var_type: VarType::Float as i32,
+                offset,
+                count: 1,
+                ..Default::default()
+            };
+            vh.name[..name.len()].copy_from_slice(name.as_bytes());
+            vh
+        };
+        let var_headers = vec![var_header("Speed", 0), var_header("Yaw", 4)];
+
+        let mut recording = Vec::new();
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 60,
+        };
+        // stored uncompressed to keep the test fast
+        let mut saver =
+            Saver::with_codec(&mut recording, 60, info, Box::new(NoneCodec), None).unwrap();
+        for i in 0..FRAMES {
+            let mut raw_data = 50.0f32.to_le_bytes().to_vec();
+            raw_data.extend_from_slice(&(i as f32 * 0.01).to_le_bytes());
+            let mut frame = FrameData {
+                header: Header {
+                    num_buf: 1,
+                    num_vars: 2,
+                    buf_len: 8,
+                    ..Default::default()
+                },
+                var_headers: (i == 0).then(|| var_headers.clone()),
+                session_info: None,
+                raw_data,
+            };
+            frame.header.var_buf[0].tick_count = i as i32;
+            saver.save(&frame.serialize().unwrap()).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let input_len = recording.len() as u64;
+        let input_read = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(recording),
+            read: input_read.clone(),
+        };
+        let mut loader = Loader::new(reader).unwrap();
+        let mut probe = Probe {
+            input_read,
+            ..Default::default()
+        };
+
+        let points = write_track_map(&mut loader, BufWriter::new(&mut probe)).unwrap();
+        assert_eq!(points, u64::from(FRAMES));
+
+        // rows go out while the input is still being read, in buffer sized writes, instead
+        // of being collected until the end
+        assert!(probe.read_at_first_write.unwrap() < input_len / 100);
+        assert!(probe.largest_write <= 8 * 1024);
+        assert!(probe.written > 100 * probe.largest_write);
+    }
+}
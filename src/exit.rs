@@ -0,0 +1,167 @@
+//! Exit codes of the process, so scripts launching ksana can tell e.g. a sim that quit
+//! from a bad file without parsing the output. Documented in the README, keep them stable.
+
+use crate::commands::mirror::MirrorFinished;
+use crate::commands::play_multi::PlayMultiError;
+use crate::commands::record::{self, RecordError, RecordingError, RecordingFinished};
+use crate::io::IOError;
+use crate::traits::PlayError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// Finished, stopped by Ctrl+C or reached its limit
+    Clean = 0,
+    /// Any failure without a code of its own
+    Failed = 1,
+    /// The sim stopped sending data while recording or mirroring
+    SimDisconnected = 2,
+    /// The recording is of a sim ksana has no player for
+    UnknownSim = 3,
+    /// The recording or output file can't be opened, read or written
+    FileError = 4,
+    /// Playback refused because the shared memory of the sim exists
+    SimRunning = 5,
+}
+
+impl From<Exit> for std::process::ExitCode {
+    fn from(exit: Exit) -> Self {
+        Self::from(exit as u8)
+    }
+}
+
+impl From<RecordingFinished> for Exit {
+    fn from(finished: RecordingFinished) -> Self {
+        match finished {
+            RecordingFinished::SimDisconnected => Self::SimDisconnected,
+            RecordingFinished::QuitRequested
+            | RecordingFinished::MaxDurationReached
+            | RecordingFinished::FrameLimitReached => Self::Clean,
+        }
+    }
+}
+
+impl From<MirrorFinished> for Exit {
+    fn from(finished: MirrorFinished) -> Self {
+        match finished {
+            MirrorFinished::SimDisconnected => Self::SimDisconnected,
+            MirrorFinished::QuitRequested => Self::Clean,
+        }
+    }
+}
+
+fn for_play_error(error: &PlayError) -> Exit {
+    match error {
+        PlayError::UnknownSimError(_) => Exit::UnknownSim,
+        PlayError::SimRunning(_) => Exit::SimRunning,
+        PlayError::FailedToOpenFile(_)
+        | PlayError::FailedToReadHeader(_)
+        | PlayError::FailedToLoadFrame(_)
+        | PlayError::FrameSizeMismatch { .. } => Exit::FileError,
+        _ => Exit::Failed,
+    }
+}
+
+/// Code for a command that failed with `error`.
+pub fn for_error(error: &anyhow::Error) -> Exit {
+    if let Some(error) = error.downcast_ref::<PlayError>() {
+        return for_play_error(error);
+    }
+    if let Some(error) = error.downcast_ref::<PlayMultiError>() {
+        return match error {
+            PlayMultiError::Play { error, .. } => for_play_error(error),
+            PlayMultiError::TargetExists(_) => Exit::SimRunning,
+            _ => Exit::Failed,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<record::Error>() {
+        return match error {
+            record::Error::Record(
+                RecordError::CreateFileError(_)
+                | RecordError::OpenFileError(_)
+                | RecordError::SaverInitError(_)
+                | RecordError::FinalizeFailed(_),
+            )
+            | record::Error::Recording(RecordingError::SavingFrameFailed(_)) => Exit::FileError,
+            _ => Exit::Failed,
+        };
+    }
+    if error.is::<IOError>() || error.is::<std::io::Error>() {
+        return Exit::FileError;
+    }
+    Exit::Failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_error() {
+        let play = |error: PlayError| for_error(&anyhow::Error::from(error));
+        assert_eq!(
+            play(PlayError::UnknownSimError("test".to_string())),
+            Exit::UnknownSim
+        );
+        assert_eq!(
+            play(PlayError::FailedToReadHeader(IOError::InvalidMagic)),
+            Exit::FileError
+        );
+        assert_eq!(
+            play(PlayError::SimRunning(
+                "Local\\IRSDKMemMapFileName".to_string()
+            )),
+            Exit::SimRunning
+        );
+        assert_eq!(
+            play(PlayError::FailedToUpdatePlayer(anyhow::anyhow!("locked"))),
+            Exit::Failed
+        );
+
+        let multi = |error: PlayMultiError| for_error(&anyhow::Error::from(error));
+        assert_eq!(
+            multi(PlayMultiError::Play {
+                file: "a.ksr".to_string(),
+                error: PlayError::FailedToReadHeader(IOError::InvalidMagic),
+            }),
+            Exit::FileError
+        );
+        assert_eq!(
+            multi(PlayMultiError::TargetExists("Local\\Driver".to_string())),
+            Exit::SimRunning
+        );
+        assert_eq!(
+            multi(PlayMultiError::TargetCount {
+                inputs: 2,
+                targets: 1
+            }),
+            Exit::Failed
+        );
+
+        let record =
+            record::Error::from(RecordError::CreateFileError(std::io::Error::other("full")));
+        assert_eq!(for_error(&record.into()), Exit::FileError);
+        let saving = record::Error::from(RecordingError::SavingFrameFailed(IOError::Io(
+            std::io::Error::other("disk full"),
+        )));
+        assert_eq!(for_error(&saving.into()), Exit::FileError);
+        assert_eq!(for_error(&record::Error::InvalidSimId.into()), Exit::Failed);
+        assert_eq!(
+            for_error(&std::io::Error::other("denied").into()),
+            Exit::FileError
+        );
+        assert_eq!(for_error(&anyhow::anyhow!("other")), Exit::Failed);
+    }
+
+    #[test]
+    fn test_recording_finished() {
+        assert_eq!(
+            Exit::from(RecordingFinished::SimDisconnected),
+            Exit::SimDisconnected
+        );
+        assert_eq!(Exit::from(RecordingFinished::QuitRequested), Exit::Clean);
+        assert_eq!(
+            Exit::from(RecordingFinished::FrameLimitReached),
+            Exit::Clean
+        );
+    }
+}
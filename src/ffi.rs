@@ -0,0 +1,257 @@
+//! C ABI for reading recordings from other languages.
+//!
+//! A recording is opened with `ksana_open_recording` which returns an opaque handle.
+//! Frames are read one by one with `ksana_next_frame`, the returned buffer is owned by
+//! the handle and stays valid until the next call on the same handle. The handle must be
+//! released with `ksana_close`. Every function returns one of the `KSANA_*` codes and
+//! never panics across the boundary.
+
+use std::ffi::{CStr, c_char};
+use std::fs::File;
+use std::io::BufReader;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use crate::io::Loader;
+
+pub const KSANA_OK: i32 = 0;
+pub const KSANA_END_OF_FILE: i32 = 1;
+pub const KSANA_ERR_NULL_POINTER: i32 = -1;
+pub const KSANA_ERR_INVALID_PATH: i32 = -2;
+pub const KSANA_ERR_OPEN_FAILED: i32 = -3;
+pub const KSANA_ERR_INVALID_FILE: i32 = -4;
+pub const KSANA_ERR_READ_FAILED: i32 = -5;
+pub const KSANA_ERR_PANIC: i32 = -6;
+
+/// Opaque recording handle.
+pub struct KsanaRecording {
+    loader: Loader<BufReader<File>>,
+    frame: Vec<u8>,
+    frame_index: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KsanaFrameInfo {
+    pub file_version: i32,
+    pub payload_version: i32,
+    pub fps: i32,
+    pub sim_id: [u8; 4],
+    /// Number of frames read so far
+    pub frame_index: u64,
+}
+
+fn guard(f: impl FnOnce() -> i32) -> i32 {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(KSANA_ERR_PANIC)
+}
+
+/// Opens a recording and stores the handle in `out`.
+///
+/// # Safety
+///
+/// `path` must be a valid null-terminated string and `out` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ksana_open_recording(
+    path: *const c_char,
+    out: *mut *mut KsanaRecording,
+) -> i32 {
+    guard(|| {
+        if path.is_null() || out.is_null() {
+            return KSANA_ERR_NULL_POINTER;
+        }
+
+        let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+            return KSANA_ERR_INVALID_PATH;
+        };
+        let Ok(file) = File::open(path) else {
+            return KSANA_ERR_OPEN_FAILED;
+        };
+        let Ok(loader) = Loader::new(BufReader::new(file)) else {
+            return KSANA_ERR_INVALID_FILE;
+        };
+
+        let recording = Box::new(KsanaRecording {
+            loader,
+            frame: vec![],
+            frame_index: 0,
+        });
+        unsafe { *out = Box::into_raw(recording) };
+        KSANA_OK
+    })
+}
+
+/// Reads the next frame. On `KSANA_OK` `data` and `len` describe the frame payload, on
+/// `KSANA_END_OF_FILE` there are no more frames.
+///
+/// # Safety
+///
+/// `recording` must be a handle returned by `ksana_open_recording`, `data` and `len` must
+/// be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ksana_next_frame(
+    recording: *mut KsanaRecording,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> i32 {
+    guard(|| {
+        if recording.is_null() || data.is_null() || len.is_null() {
+            return KSANA_ERR_NULL_POINTER;
+        }
+
+        let recording = unsafe { &mut *recording };
+        match recording.loader.load() {
+            Ok(Some(frame)) => {
+                recording.frame = frame;
+                recording.frame_index += 1;
+                unsafe {
+                    *data = recording.frame.as_ptr();
+                    *len = recording.frame.len();
+                }
+                KSANA_OK
+            }
+            Ok(None) => KSANA_END_OF_FILE,
+            Err(_) => KSANA_ERR_READ_FAILED,
+        }
+    })
+}
+
+/// Fills `info` with the recording header and the number of frames read so far.
+///
+/// # Safety
+///
+/// `recording` must be a handle returned by `ksana_open_recording` and `info` must be
+/// valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ksana_frame_info(
+    recording: *const KsanaRecording,
+    info: *mut KsanaFrameInfo,
+) -> i32 {
+    guard(|| {
+        if recording.is_null() || info.is_null() {
+            return KSANA_ERR_NULL_POINTER;
+        }
+
+        let recording = unsafe { &*recording };
+        unsafe {
+            *info = KsanaFrameInfo {
+                file_version: recording.loader.version(),
+                payload_version: recording.loader.payload_version(),
+                fps: recording.loader.fps(),
+                sim_id: recording.loader.id(),
+                frame_index: recording.frame_index,
+            };
+        }
+        KSANA_OK
+    })
+}
+
+/// Closes the recording. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `recording` must be null or a handle returned by `ksana_open_recording` that was not
+/// closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ksana_close(recording: *mut KsanaRecording) -> i32 {
+    guard(|| {
+        if !recording.is_null() {
+            drop(unsafe { Box::from_raw(recording) });
+        }
+        KSANA_OK
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::io::Saver;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_read_recording() {
+        let path = std::env::temp_dir().join(format!("ksana_ffi_{}.ksr", std::process::id()));
+        {
+            let file = File::create(&path).unwrap();
+            let mut saver = Saver::new(
+                file,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            saver.save(b"first").unwrap();
+            saver.save(b"second").unwrap();
+            saver.finalize().unwrap();
+        }
+
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let mut recording: *mut KsanaRecording = std::ptr::null_mut();
+        let mut data: *const u8 = std::ptr::null();
+        let mut len: usize = 0;
+        let mut info = KsanaFrameInfo::default();
+
+        unsafe {
+            assert_eq!(
+                ksana_open_recording(c_path.as_ptr(), &mut recording),
+                KSANA_OK
+            );
+
+            assert_eq!(ksana_next_frame(recording, &mut data, &mut len), KSANA_OK);
+            assert_eq!(std::slice::from_raw_parts(data, len), b"first");
+            assert_eq!(ksana_next_frame(recording, &mut data, &mut len), KSANA_OK);
+            assert_eq!(std::slice::from_raw_parts(data, len), b"second");
+            assert_eq!(
+                ksana_next_frame(recording, &mut data, &mut len),
+                KSANA_END_OF_FILE
+            );
+
+            assert_eq!(ksana_frame_info(recording, &mut info), KSANA_OK);
+            assert_eq!(info.fps, 30);
+            assert_eq!(&info.sim_id, b"irac");
+            assert_eq!(info.frame_index, 2);
+
+            assert_eq!(ksana_close(recording), KSANA_OK);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        let mut recording: *mut KsanaRecording = std::ptr::null_mut();
+        let mut data: *const u8 = std::ptr::null();
+        let mut len: usize = 0;
+
+        unsafe {
+            assert_eq!(
+                ksana_open_recording(std::ptr::null(), &mut recording),
+                KSANA_ERR_NULL_POINTER
+            );
+            assert_eq!(
+                ksana_next_frame(std::ptr::null_mut(), &mut data, &mut len),
+                KSANA_ERR_NULL_POINTER
+            );
+            assert_eq!(
+                ksana_frame_info(std::ptr::null(), std::ptr::null_mut()),
+                KSANA_ERR_NULL_POINTER
+            );
+            assert_eq!(ksana_close(std::ptr::null_mut()), KSANA_OK);
+        }
+    }
+
+    #[test]
+    fn test_open_missing_file() {
+        let c_path = CString::new("nonexistent.ksr").unwrap();
+        let mut recording: *mut KsanaRecording = std::ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                ksana_open_recording(c_path.as_ptr(), &mut recording),
+                KSANA_ERR_OPEN_FAILED
+            );
+        }
+        assert!(recording.is_null());
+    }
+}
@@ -0,0 +1,199 @@
+use std::borrow::BorrowMut;
+use std::io::{self, Read, Seek};
+
+use crate::io::{IOError, Loader};
+use crate::sims::assettocorsa::data::FrameData as AcFrameData;
+use crate::sims::iracing::data::FrameData as IRacingFrameData;
+
+/// Decoded frames of a recording, for tools analysing them instead of playing them back.
+/// Implemented for `Loader` and `&mut Loader`, the frames are read from the current
+/// position.
+pub trait DecodedFrames {
+    /// Frames of an iRacing recording, deserialized. Only frames where they changed carry
+    /// the var headers and the session info.
+    fn iracing_frames(self) -> impl Iterator<Item = Result<IRacingFrameData, IOError>>;
+
+    /// Frames of an Assetto Corsa recording, deserialized. Only frames where it changed
+    /// carry the static page.
+    fn ac_frames(self) -> impl Iterator<Item = Result<AcFrameData, IOError>>;
+}
+
+/// Deserializes every frame of a recording of sim `id`. A recording of another sim yields
+/// a single error. A frame that fails to deserialize is an `IOError::Io` and the following
+/// frames are still read, the iterator ends after the first error loading a frame.
+fn decoded<R, L, T>(
+    mut loader: L,
+    id: &'static [u8; 4],
+    deserialize: fn(&[u8], i32) -> io::Result<T>,
+) -> impl Iterator<Item = Result<T, IOError>>
+where
+    R: Read + Seek,
+    L: BorrowMut<Loader<R>>,
+{
+    let found = loader.borrow().id();
+    let payload_version = loader.borrow().payload_version();
+    let mut error = (&found != id).then(|| {
+        IOError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "expected a {} recording, got {}",
+                String::from_utf8_lossy(id),
+                String::from_utf8_lossy(&found)
+            ),
+        ))
+    });
+    let mut done = false;
+
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        if let Some(error) = error.take() {
+            done = true;
+            return Some(Err(error));
+        }
+        match loader.borrow_mut().load() {
+            Ok(Some(data)) => Some(deserialize(&data, payload_version).map_err(IOError::Io)),
+            Ok(None) => {
+                done = true;
+                None
+            }
+            Err(error) => {
+                done = true;
+                Some(Err(error))
+            }
+        }
+    })
+}
+
+impl<R: Read + Seek> DecodedFrames for Loader<R> {
+    fn iracing_frames(self) -> impl Iterator<Item = Result<IRacingFrameData, IOError>> {
+        decoded(self, b"irac", IRacingFrameData::deserialize)
+    }
+
+    fn ac_frames(self) -> impl Iterator<Item = Result<AcFrameData, IOError>> {
+        decoded(self, b"acsa", AcFrameData::deserialize)
+    }
+}
+
+impl<R: Read + Seek> DecodedFrames for &mut Loader<R> {
+    fn iracing_frames(self) -> impl Iterator<Item = Result<IRacingFrameData, IOError>> {
+        decoded(self, b"irac", IRacingFrameData::deserialize)
+    }
+
+    fn ac_frames(self) -> impl Iterator<Item = Result<AcFrameData, IOError>> {
+        decoded(self, b"acsa", AcFrameData::deserialize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimInfo;
+    use crate::io::Saver;
+    use crate::sims::assettocorsa::data::{GraphicsPage, StaticPage};
+    use crate::sims::iracing::data::{Header, VarHeader};
+    use std::io::Cursor;
+
+    fn recording(id: [u8; 4], frames: &[Vec<u8>]) -> Vec<u8> {
+        let info = SimInfo {
+            id,
+            payload_version: 2,
+            native_hz: 10,
+        };
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 10, info).unwrap();
+        for frame in frames {
+            saver.save(frame).unwrap();
+        }
+        saver.finalize().unwrap();
+        recording
+    }
+
+    /// iRacing frames with a single var, the first one carrying the var headers and the
+    /// session info.
+    fn iracing_frames(count: u8) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|index| {
+                let first = index == 0;
+                IRacingFrameData {
+                    header: Header {
+                        num_vars: 1,
+                        ..Default::default()
+                    },
+                    var_headers: first.then(|| vec![VarHeader::default()]),
+                    session_info: first.then(|| b"---\n...\n".to_vec()),
+                    raw_data: vec![index; 4],
+                }
+                .serialize()
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_iracing_frames() {
+        let frames = iracing_frames(5);
+        let data = recording(*b"irac", &frames);
+
+        let loader = Loader::new(Cursor::new(&data)).unwrap();
+        let decoded: Vec<IRacingFrameData> = loader.iracing_frames().map(Result::unwrap).collect();
+        assert_eq!(decoded.len(), 5);
+        assert!(decoded[0].var_headers.is_some());
+        assert!(decoded[1].var_headers.is_none());
+        for (frame, data) in decoded.iter().zip(&frames) {
+            assert_eq!(frame.serialize().as_ref(), Some(data));
+        }
+
+        // by reference, from where the loader is
+        let mut loader = Loader::new(Cursor::new(&data)).unwrap();
+        loader.load().unwrap();
+        assert_eq!((&mut loader).iracing_frames().count(), 4);
+        assert_eq!(loader.load().unwrap(), None);
+    }
+
+    #[test]
+    fn test_ac_frames() {
+        let frames: Vec<Vec<u8>> = (1..=3)
+            .map(|packet_id| {
+                AcFrameData {
+                    graphics: GraphicsPage {
+                        packet_id,
+                        ..Default::default()
+                    },
+                    statics: (packet_id == 1).then(StaticPage::default),
+                    ..Default::default()
+                }
+                .serialize()
+            })
+            .collect();
+        let data = recording(*b"acsa", &frames);
+
+        let loader = Loader::new(Cursor::new(&data)).unwrap();
+        let decoded: Vec<AcFrameData> = loader.ac_frames().map(Result::unwrap).collect();
+        assert_eq!(decoded.len(), 3);
+        assert!(decoded[0].statics.is_some());
+        assert!(decoded[1].statics.is_none());
+        assert_eq!(decoded[2].graphics.packet_id, 3);
+    }
+
+    #[test]
+    fn test_frames_errors() {
+        let data = recording(*b"irac", &[b"not a frame".to_vec()]);
+
+        let loader = Loader::new(Cursor::new(&data)).unwrap();
+        let mut frames = loader.ac_frames();
+        assert!(
+            matches!(frames.next(), Some(Err(IOError::Io(e))) if e.kind() == io::ErrorKind::InvalidInput)
+        );
+        assert!(frames.next().is_none());
+
+        // a frame that doesn't deserialize doesn't end the iteration
+        let data = recording(*b"irac", &[vec![0xff; 64], iracing_frames(1).remove(0)]);
+        let loader = Loader::new(Cursor::new(&data)).unwrap();
+        let results: Vec<_> = loader.iracing_frames().collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0], Err(IOError::Io(_))));
+        assert!(results[1].is_ok());
+    }
+}
@@ -5,26 +5,93 @@
 //   - FPS: i32 little-endian
 //   - Sim ID: [u8; 4] (4 bytes)
 //   - Payload version: i32 little-endian  (sim-specific frame format; added in file v2)
-//   - Padding: 48 bytes (reserved for future use)
+//   - Encryption: u32 little-endian, 0 - none, 1 - AES-256-GCM (added in file v3)
+//   - KDF salt: [u8; 16], PBKDF2-HMAC-SHA256 salt of the key, zeroed if not encrypted (added in file v3)
+//   - Native rate: u32 little-endian, Hz the sim updates its data at, 0 if unknown (taken from
+//     the v3 padding, older files read as unknown)
+//   - Codec: u32 little-endian, id of the codec in `codec::CodecRegistry`, 0 - zlib, 1 - none
+//     (taken from the v3 padding, older files read as zlib)
+//   - Struct encoding: u32 little-endian, layout of the sim structs copied into the frames as
+//     raw bytes, 0 - little-endian with the alignment of 64-bit x86 Windows, the only layout
+//     written so far (taken from the v3 padding, older files read as 0)
+//   - Start time: i64 little-endian, wall-clock time the capture started at in milliseconds
+//     since the Unix epoch, 0 if unknown (taken from the v3 padding, older files read as
+//     unknown)
+//   - Flags: u32 little-endian, `HeaderFlags` bits of the features the file uses, so new
+//     features don't each claim padding bytes of their own (taken from the v3 padding, older
+//     files read as none set):
+//     - bits 0-7: optional features, loaders not knowing them can still read the file
+//       - 0: native rate stored
+//       - 1: start time stored
+//       - 2: frame headers carry frame flags
+//       - 3: writer version stored
+//     - bits 8-15: required features, loaders reject files with ones they don't know
+//       - 8: frames encrypted
+//       - 9: frames stored with a codec other than zlib
+//     - bits 16-31: third-party extensions, never set by ksana
+//   - Writer version: [u8; 3], major, minor and patch of the ksana version that wrote the
+//     file, zeroed if not stored (taken from the v3 padding, older files read as unknown)
+//   - Padding: 1 byte (reserved for future use)
 // - Frames (repeated until EOF):
 //   - Header length (at least 12 bytes for header, compressed and raw length): i32
 //   - Compressed length: u32 little-endian
 //   - Raw length: u32 little-endian
+//   - Nonce: [u8; 12], only present in encrypted files
+//   - Frame flags: u32 little-endian, `FrameFlags` of how the frame was captured, only present
+//     in files with the frame flags feature
 //   - The rest of the header can be reserved for future use
-//   - Compressed data: [u8; compressed_length]
+//   - Compressed data: [u8; compressed_length], encrypted after compression in encrypted files
+//     (the length includes the 16 byte authentication tag). With codec none the data is
+//     stored as is and the compressed length equals the raw length (plus the tag)
+//
+// The whole file may additionally be gzip compressed, the loader detects it by the gzip magic.
 
 use crate::SimInfo;
+use crate::codec::{CODEC_ZLIB, Codec, CodecRegistry, ZlibCodec};
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{Aead, KeyInit};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use flate2::Compression;
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
-use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use flate2::read::MultiGzDecoder;
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+/// Size of the file header, the same in every file version.
+pub const HEADER_SIZE: usize = 72;
+
 const MAGIC: &[u8; 8] = b"RECROCKS";
-const PADDING_SIZE: usize = 48; // 72 - 8 (magic) - 4 (version) - 4 (fps) - 4 (id) - 4 (payload_version)
-const CURRENT_VERSION: i32 = 2;
+/// First bytes of a gzip stream, recordings starting with them are gzip compressed.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const PADDING_SIZE: usize = 1; // 72 - 8 (magic) - 4 (version) - 4 (fps) - 4 (id) - 4 (payload_version) - 4 (encryption) - 16 (salt) - 4 (native_hz) - 4 (codec) - 4 (struct encoding) - 8 (start time) - 4 (flags) - 3 (writer version)
+const V2_PADDING_SIZE: usize = 48; // v2 had no encryption and salt fields
+const V1_PADDING_SIZE: usize = 52; // v1 had no payload version field
+const CURRENT_VERSION: i32 = 3;
 const FRAME_HEADER_SIZE: i32 = 12; // header size + compressed len raw len
+const FRAME_FLAGS_SIZE: i32 = 4;
+
+// The frames hold the sim structs as the sims lay them out in memory, see sims/mod.rs
+const STRUCT_ENCODING_LE64: u32 = 0;
+
+const ENCRYPTION_NONE: u32 = 0;
+const ENCRYPTION_AES_256_GCM: u32 = 1;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KDF_ROUNDS: u32 = 100_000;
+
+// Frame headers written so far are 12 to 28 bytes, anything much larger is not a frame
+const MAX_FRAME_HEADER_SIZE: i32 = 64;
+const RESYNC_WINDOW: usize = 64 * 1024;
+// Decompressed bytes a gzip compressed recording can seek back over without decompressing
+// it again from the start, as resyncing does for every window and candidate frame
+const GZIP_HISTORY_SIZE: usize = 1024 * 1024;
+
+fn derive_cipher(passphrase: &str, salt: &[u8; SALT_SIZE]) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    Aes256Gcm::new(&key.into())
+}
 
 #[derive(Error, Debug)]
 pub enum IOError {
@@ -34,258 +101,2592 @@ pub enum IOError {
     #[error("Invalid header size: {0}")]
     InvalidHeaderSize(i32),
 
+    #[error("Invalid fps: {0}, the file header is corrupt")]
+    InvalidFps(i32),
+
     #[error("Invalid file format: expected RECROCKS header")]
     InvalidMagic,
 
+    #[error("Existing recording does not match: {0}")]
+    HeaderMismatch(&'static str),
+
+    #[error(
+        "Existing recording has file version {0}: recorded by an older version, run optimize to upgrade it"
+    )]
+    OutdatedVersion(i32),
+
+    #[error("Existing recording is gzip compressed, decompress it to append to it")]
+    GzipAppend,
+
     #[error("Failed to decompress data: file may be corrupted")]
     DecompressionFailed,
 
+    #[error("File ends in the middle of a frame: recording was likely interrupted")]
+    TruncatedFrame,
+
+    #[error("Unsupported codec: {0}")]
+    UnsupportedCodec(u32),
+
+    #[error("Unsupported encryption: {0}")]
+    UnsupportedEncryption(u32),
+
+    #[error("Unsupported struct encoding: {0}, the recording was written with another data layout")]
+    UnsupportedStructEncoding(u32),
+
+    #[error("Unsupported file features: 0x{0:08x}, the recording was written by a newer version")]
+    UnsupportedFeatures(u32),
+
+    #[error("Recording is encrypted, a key is required")]
+    KeyRequired,
+
+    #[error("Failed to encrypt data")]
+    EncryptionFailed,
+
+    #[error("Failed to decrypt data: wrong key or file is corrupted")]
+    DecryptionFailed,
+
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 }
 
+/// Feature bits of the file header, see the format description at the top of this file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderFlags(pub u32);
+
+impl HeaderFlags {
+    pub const NATIVE_HZ: u32 = 1 << 0;
+    pub const START_TIME: u32 = 1 << 1;
+    pub const FRAME_FLAGS: u32 = 1 << 2;
+    pub const WRITER_VERSION: u32 = 1 << 3;
+    pub const ENCRYPTED: u32 = 1 << 8;
+    pub const CODEC: u32 = 1 << 9;
+
+    /// Bits a loader must know to read the file.
+    pub const REQUIRED_MASK: u32 = 0x0000_ff00;
+    /// Bits left to third-party extensions.
+    pub const EXTENSION_MASK: u32 = 0xffff_0000;
+    const KNOWN: u32 = Self::NATIVE_HZ
+        | Self::START_TIME
+        | Self::FRAME_FLAGS
+        | Self::WRITER_VERSION
+        | Self::ENCRYPTED
+        | Self::CODEC;
+
+    pub fn contains(self, bits: u32) -> bool {
+        self.0 & bits == bits
+    }
+
+    pub fn set(&mut self, bits: u32, enabled: bool) {
+        if enabled {
+            self.0 |= bits;
+        } else {
+            self.0 &= !bits;
+        }
+    }
+
+    /// Required ksana bits this version doesn't know.
+    fn unknown_required(self) -> u32 {
+        self.0 & Self::REQUIRED_MASK & !Self::KNOWN
+    }
+}
+
+/// Features of a recording as declared by the flags of its header. Files written before the
+/// flags declare none, whatever they use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileFeatures {
+    pub native_hz: bool,
+    pub start_time: bool,
+    /// Frame headers carry `FrameFlags`
+    pub frame_flags: bool,
+    pub writer_version: bool,
+    pub encrypted: bool,
+    /// Frames are stored with a codec other than zlib
+    pub codec: bool,
+    /// Bits 16-31 of the flags, for third-party extensions
+    pub extensions: u16,
+}
+
+impl From<HeaderFlags> for FileFeatures {
+    fn from(flags: HeaderFlags) -> Self {
+        Self {
+            native_hz: flags.contains(HeaderFlags::NATIVE_HZ),
+            start_time: flags.contains(HeaderFlags::START_TIME),
+            frame_flags: flags.contains(HeaderFlags::FRAME_FLAGS),
+            writer_version: flags.contains(HeaderFlags::WRITER_VERSION),
+            encrypted: flags.contains(HeaderFlags::ENCRYPTED),
+            codec: flags.contains(HeaderFlags::CODEC),
+            extensions: (flags.0 >> 16) as u16,
+        }
+    }
+}
+
+/// How a frame was captured, stored with every frame of files with
+/// `HeaderFlags::FRAME_FLAGS` for judging the capture quality afterwards.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFlags {
+    /// Save ticks that passed without new data from the sim right before this frame
+    pub missed_ticks: u16,
+}
+
+// The low 16 bits of the stored flags are reserved
+impl FrameFlags {
+    fn to_bits(self) -> u32 {
+        u32::from(self.missed_ticks) << 16
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        Self {
+            missed_ticks: (bits >> 16) as u16,
+        }
+    }
+}
+
+/// Version of ksana that wrote a recording, see `Loader::writer_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WriterVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl WriterVersion {
+    /// Version of this build. Parts above 255 are stored as 255.
+    pub fn current() -> Self {
+        let part = |text: &str| text.parse::<u64>().map_or(0, |n| n.min(255) as u8);
+        Self {
+            major: part(env!("CARGO_PKG_VERSION_MAJOR")),
+            minor: part(env!("CARGO_PKG_VERSION_MINOR")),
+            patch: part(env!("CARGO_PKG_VERSION_PATCH")),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 3] {
+        [self.major, self.minor, self.patch]
+    }
+
+    fn from_bytes(bytes: [u8; 3]) -> Self {
+        Self {
+            major: bytes[0],
+            minor: bytes[1],
+            patch: bytes[2],
+        }
+    }
+}
+
+impl std::fmt::Display for WriterVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A field of a file header, decoded for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    pub offset: usize,
+    pub len: usize,
+    pub name: &'static str,
+    pub value: String,
+}
+
+fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn describe_flags(flags: HeaderFlags) -> String {
+    let mut names = vec![];
+    for (bit, name) in [
+        (HeaderFlags::NATIVE_HZ, "native rate"),
+        (HeaderFlags::START_TIME, "start time"),
+        (HeaderFlags::FRAME_FLAGS, "frame flags"),
+        (HeaderFlags::WRITER_VERSION, "writer version"),
+        (HeaderFlags::ENCRYPTED, "encrypted"),
+        (HeaderFlags::CODEC, "codec"),
+    ] {
+        if flags.contains(bit) {
+            names.push(name.to_string());
+        }
+    }
+    let unknown = flags.0 & !HeaderFlags::KNOWN & !HeaderFlags::EXTENSION_MASK;
+    if unknown != 0 {
+        names.push(format!("unknown 0x{:04x}", unknown));
+    }
+    if flags.0 & HeaderFlags::EXTENSION_MASK != 0 {
+        names.push(format!("extensions 0x{:04x}", flags.0 >> 16));
+    }
+    if names.is_empty() {
+        names.push("none".to_string());
+    }
+    format!("0x{:08x} ({})", flags.0, names.join(", "))
+}
+
+/// Fields of a file header laid out as its file version says, without validating them, for
+/// looking at files that fail to load. Fields past the end of `header` are left out, as is
+/// the rest of the header when the magic doesn't match.
+pub fn header_fields(header: &[u8]) -> Vec<HeaderField> {
+    let mut fields = vec![];
+    let mut field =
+        |offset: usize, len: usize, name: &'static str, value: &dyn Fn(&[u8]) -> String| {
+            if let Some(bytes) = header.get(offset..offset + len) {
+                fields.push(HeaderField {
+                    offset,
+                    len,
+                    name,
+                    value: value(bytes),
+                });
+            }
+        };
+    let u32_le = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let text = |b: &[u8]| format!("{:?}", String::from_utf8_lossy(b));
+    let padding = |b: &[u8]| {
+        if b.iter().all(|&b| b == 0) {
+            "zero".to_string()
+        } else {
+            "not zero".to_string()
+        }
+    };
+
+    field(0, 8, "magic", &|b| {
+        if b == MAGIC {
+            text(b)
+        } else {
+            format!("{} (expected \"RECROCKS\")", text(b))
+        }
+    });
+    if header.get(..8) != Some(&MAGIC[..]) {
+        return fields;
+    }
+    field(8, 4, "file version", &|b| i32_at(b, 0).to_string());
+    field(12, 4, "fps", &|b| i32_at(b, 0).to_string());
+    field(16, 4, "sim id", &text);
+
+    let version = if header.len() >= 12 {
+        i32_at(header, 8)
+    } else {
+        0
+    };
+    match version {
+        ..=1 => field(20, V1_PADDING_SIZE, "padding", &padding),
+        2 => {
+            field(20, 4, "payload version", &|b| i32_at(b, 0).to_string());
+            field(24, V2_PADDING_SIZE, "padding", &padding);
+        }
+        _ => {
+            field(20, 4, "payload version", &|b| i32_at(b, 0).to_string());
+            field(24, 4, "encryption", &|b| match u32_le(b) {
+                ENCRYPTION_NONE => "0 (none)".to_string(),
+                ENCRYPTION_AES_256_GCM => "1 (AES-256-GCM)".to_string(),
+                other => format!("{} (unknown)", other),
+            });
+            field(28, SALT_SIZE, "KDF salt", &|b| {
+                b.iter().map(|b| format!("{:02x}", b)).collect()
+            });
+            field(44, 4, "native rate", &|b| match u32_le(b) {
+                0 => "0 (unknown)".to_string(),
+                hz => format!("{} Hz", hz),
+            });
+            field(48, 4, "codec", &|b| match u32_le(b) {
+                id if id == u32::from(CODEC_ZLIB) => format!("{} (zlib)", id),
+                id if id == u32::from(crate::codec::CODEC_NONE) => format!("{} (none)", id),
+                id => format!("{} (not built in)", id),
+            });
+            field(52, 4, "struct encoding", &|b| match u32_le(b) {
+                STRUCT_ENCODING_LE64 => "0 (little-endian, x64 alignment)".to_string(),
+                other => format!("{} (unknown)", other),
+            });
+            field(56, 8, "start time", &|b| {
+                let ms = i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                if ms > 0 {
+                    format!("{} ms since the Unix epoch", ms)
+                } else {
+                    format!("{} (unknown)", ms)
+                }
+            });
+            field(64, 4, "flags", &|b| describe_flags(HeaderFlags(u32_le(b))));
+            field(68, 3, "writer version", &|b| {
+                if b == [0; 3] {
+                    "0.0.0 (unknown)".to_string()
+                } else {
+                    WriterVersion::from_bytes([b[0], b[1], b[2]]).to_string()
+                }
+            });
+            field(71, PADDING_SIZE, "padding", &padding);
+        }
+    }
+    fields
+}
+
+/// How a saver stores the frames, for `Saver::with_options`.
+pub struct SaverOptions<'a> {
+    pub codec: Box<dyn Codec>,
+    /// Encrypt every frame with a key derived from the passphrase
+    pub passphrase: Option<&'a str>,
+    /// Time the capture started at, `None` if unknown
+    pub start_time: Option<SystemTime>,
+    /// Store the `FrameFlags` passed to `Saver::save_with_flags` with every frame
+    pub frame_flags: bool,
+}
+
 pub struct Saver<W: Write> {
     writer: W,
+    cipher: Option<Aes256Gcm>,
+    codec: Box<dyn Codec>,
+    frame_flags: bool,
+    stats: RecordingStats,
+}
+
+/// Sizes of the frames saved by a `Saver` or loaded by a `Loader`, just counters so they can
+/// be read at any time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingStats {
+    /// Number of frames.
+    pub frames: u64,
+    /// Bytes of the frames as the sim produced them.
+    pub raw_bytes: u64,
+    /// Bytes stored for the frames after compression and encryption, without frame headers.
+    pub compressed_bytes: u64,
+}
+
+impl RecordingStats {
+    /// Raw bytes per compressed byte, 0 without frames.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            0.0
+        } else {
+            self.raw_bytes as f64 / self.compressed_bytes as f64
+        }
+    }
+}
+
+impl std::fmt::Display for RecordingStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames, {} bytes compressed to {} bytes ({:.1}:1)",
+            self.frames,
+            self.raw_bytes,
+            self.compressed_bytes,
+            self.ratio()
+        )
+    }
+}
+
+impl<W: Write> Saver<W> {
+    pub fn new(writer: W, fps: i32, info: SimInfo) -> Result<Self, IOError> {
+        Self::with_codec(writer, fps, info, Box::new(ZlibCodec::default()), None)
+    }
+
+    /// Creates a saver encrypting every frame with a key derived from `passphrase`.
+    pub fn new_encrypted(
+        writer: W,
+        fps: i32,
+        info: SimInfo,
+        passphrase: &str,
+    ) -> Result<Self, IOError> {
+        Self::with_codec(
+            writer,
+            fps,
+            info,
+            Box::new(ZlibCodec::default()),
+            Some(passphrase),
+        )
+    }
+
+    /// Creates a saver storing frames with `codec`, encrypted if `passphrase` is given.
+    pub fn with_codec(
+        writer: W,
+        fps: i32,
+        info: SimInfo,
+        codec: Box<dyn Codec>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, IOError> {
+        let now = Some(SystemTime::now());
+        Self::with_start_time(writer, fps, info, codec, passphrase, now)
+    }
+
+    /// Like `with_codec`, storing `start_time` as the time the capture started instead of
+    /// now, for recordings derived from another one. `None` stores the time as unknown.
+    pub fn with_start_time(
+        writer: W,
+        fps: i32,
+        info: SimInfo,
+        codec: Box<dyn Codec>,
+        passphrase: Option<&str>,
+        start_time: Option<SystemTime>,
+    ) -> Result<Self, IOError> {
+        let options = SaverOptions {
+            codec,
+            passphrase,
+            start_time,
+            frame_flags: false,
+        };
+        Self::with_options(writer, fps, info, options)
+    }
+
+    /// Creates a saver storing the frames as `options` say.
+    pub fn with_options(
+        writer: W,
+        fps: i32,
+        info: SimInfo,
+        options: SaverOptions,
+    ) -> Result<Self, IOError> {
+        let SaverOptions {
+            codec,
+            passphrase,
+            start_time,
+            frame_flags,
+        } = options;
+        let start_unix_ms = start_time
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_millis() as i64);
+        let Some(passphrase) = passphrase else {
+            return Self::create(writer, fps, info, codec, None, start_unix_ms, frame_flags);
+        };
+        let mut salt = [0u8; SALT_SIZE];
+        getrandom::fill(&mut salt).map_err(|_| IOError::EncryptionFailed)?;
+        let saver = Self::create(
+            writer,
+            fps,
+            info,
+            codec,
+            Some(salt),
+            start_unix_ms,
+            frame_flags,
+        );
+        saver.map(|saver| Self {
+            cipher: Some(derive_cipher(passphrase, &salt)),
+            ..saver
+        })
+    }
+
+    fn create(
+        mut writer: W,
+        fps: i32,
+        info: SimInfo,
+        codec: Box<dyn Codec>,
+        salt: Option<[u8; SALT_SIZE]>,
+        start_unix_ms: i64,
+        frame_flags: bool,
+    ) -> Result<Self, IOError> {
+        writer.write_all(MAGIC)?;
+        writer.write_i32::<LittleEndian>(CURRENT_VERSION)?;
+        writer.write_i32::<LittleEndian>(fps)?;
+        writer.write_all(&info.id)?;
+        writer.write_i32::<LittleEndian>(info.payload_version)?;
+
+        match salt {
+            Some(salt) => {
+                writer.write_u32::<LittleEndian>(ENCRYPTION_AES_256_GCM)?;
+                writer.write_all(&salt)?;
+            }
+            None => {
+                writer.write_u32::<LittleEndian>(ENCRYPTION_NONE)?;
+                writer.write_all(&[0u8; SALT_SIZE])?;
+            }
+        }
+        writer.write_u32::<LittleEndian>(info.native_hz)?;
+        writer.write_u32::<LittleEndian>(u32::from(codec.id()))?;
+        writer.write_u32::<LittleEndian>(STRUCT_ENCODING_LE64)?;
+        writer.write_i64::<LittleEndian>(start_unix_ms)?;
+
+        let mut flags = HeaderFlags::default();
+        flags.set(HeaderFlags::NATIVE_HZ, info.native_hz != 0);
+        flags.set(HeaderFlags::START_TIME, start_unix_ms != 0);
+        flags.set(HeaderFlags::FRAME_FLAGS, frame_flags);
+        flags.set(HeaderFlags::WRITER_VERSION, true);
+        flags.set(HeaderFlags::ENCRYPTED, salt.is_some());
+        flags.set(HeaderFlags::CODEC, codec.id() != CODEC_ZLIB);
+        writer.write_u32::<LittleEndian>(flags.0)?;
+        writer.write_all(&WriterVersion::current().to_bytes())?;
+
+        let padding = [0u8; PADDING_SIZE];
+        writer.write_all(&padding)?;
+
+        Ok(Self {
+            writer,
+            cipher: None,
+            codec,
+            frame_flags,
+            stats: RecordingStats::default(),
+        })
+    }
+
+    /// Sets the compression level of the frames saved from now on, for zlib [0-9] with a
+    /// default of 6. Has no effect with codecs without levels.
+    pub fn set_compression_level(&mut self, level: u32) {
+        self.codec.set_level(level);
+    }
+
+    pub fn save(&mut self, data: &[u8]) -> Result<(), IOError> {
+        self.save_with_flags(data, FrameFlags::default())
+    }
+
+    /// Saves a frame captured as `flags` say. The flags are only stored if the saver was
+    /// created with `SaverOptions::frame_flags`.
+    pub fn save_with_flags(&mut self, data: &[u8], flags: FrameFlags) -> Result<(), IOError> {
+        let compressed = self.codec.compress(data);
+
+        // compress then encrypt, encrypted data doesn't compress
+        let (compressed, nonce) = match &self.cipher {
+            Some(cipher) => {
+                let mut nonce = [0u8; NONCE_SIZE];
+                getrandom::fill(&mut nonce).map_err(|_| IOError::EncryptionFailed)?;
+                let encrypted = cipher
+                    .encrypt(&nonce.into(), compressed.as_slice())
+                    .map_err(|_| IOError::EncryptionFailed)?;
+                (encrypted, Some(nonce))
+            }
+            None => (compressed, None),
+        };
+
+        let compressed_len = compressed.len() as u32;
+        let raw_len = data.len() as u32;
+        let mut header_size = match nonce {
+            Some(_) => FRAME_HEADER_SIZE + NONCE_SIZE as i32,
+            None => FRAME_HEADER_SIZE,
+        };
+        if self.frame_flags {
+            header_size += FRAME_FLAGS_SIZE;
+        }
+
+        self.writer.write_i32::<LittleEndian>(header_size)?;
+        self.writer.write_u32::<LittleEndian>(compressed_len)?;
+        self.writer.write_u32::<LittleEndian>(raw_len)?;
+        if let Some(nonce) = nonce {
+            self.writer.write_all(&nonce)?;
+        }
+        if self.frame_flags {
+            self.writer.write_u32::<LittleEndian>(flags.to_bits())?;
+        }
+        self.writer.write_all(&compressed)?;
+
+        self.stats.frames += 1;
+        self.stats.raw_bytes += data.len() as u64;
+        self.stats.compressed_bytes += compressed.len() as u64;
+        Ok(())
+    }
+
+    /// Sizes of the frames saved so far by this saver, for an appending saver without the
+    /// frames already in the file.
+    pub fn stats(&self) -> RecordingStats {
+        self.stats
+    }
+
+    pub fn flush(&mut self) -> Result<(), IOError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Finishes the recording and flushes the writer. Consumes the saver so no more
+    /// frames can be written. Files that were only flushed (e.g. after a crash) are
+    /// still valid and load by a linear scan.
+    pub fn finalize(mut self) -> Result<(), IOError> {
+        self.flush()
+    }
+}
+
+impl<W: Read + Write + Seek> Saver<W> {
+    /// Opens an existing recording for appending. The header is validated against the
+    /// expected fps and sim info, and the writer is positioned at the end of the last
+    /// complete frame, so a frame cut off by an interrupted recording is overwritten.
+    /// Frames are stored with the codec of the recording.
+    pub fn append(writer: W, fps: i32, info: SimInfo) -> Result<Self, IOError> {
+        Self::append_with_codecs(writer, fps, info, &CodecRegistry::default())
+    }
+
+    /// Like `append`, for recordings using a codec of `codecs`.
+    pub fn append_with_codecs(
+        mut writer: W,
+        fps: i32,
+        info: SimInfo,
+        codecs: &CodecRegistry,
+    ) -> Result<Self, IOError> {
+        let (codec, frame_flags, end) = check_append(&mut writer, fps, info, codecs)?;
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(Self {
+            writer,
+            cipher: None,
+            codec,
+            frame_flags,
+            stats: RecordingStats::default(),
+        })
+    }
+}
+
+impl Saver<BufWriter<File>> {
+    /// Like `append`, for a recording file. A frame cut off by an interrupted recording
+    /// is truncated away before the buffered writer appends after the last complete one.
+    pub fn append_file(
+        mut file: File,
+        fps: i32,
+        info: SimInfo,
+        capacity: usize,
+    ) -> Result<Self, IOError> {
+        let (codec, frame_flags, end) =
+            check_append(&mut file, fps, info, &CodecRegistry::default())?;
+        file.set_len(end)?;
+        file.seek(SeekFrom::Start(end))?;
+
+        Ok(Self {
+            writer: BufWriter::with_capacity(capacity, file),
+            cipher: None,
+            codec,
+            frame_flags,
+            stats: RecordingStats::default(),
+        })
+    }
+}
+
+/// Validates the header of a recording frames are appended to, returning its codec,
+/// whether its frames carry flags and the end of its last complete frame.
+fn check_append<R: Read + Seek>(
+    mut reader: R,
+    fps: i32,
+    info: SimInfo,
+    codecs: &CodecRegistry,
+) -> Result<(Box<dyn Codec>, bool, u64), IOError> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut loader = Loader::with_codecs(&mut reader, codecs)?;
+    if loader.is_gzip() {
+        return Err(IOError::GzipAppend);
+    }
+    if loader.version() != CURRENT_VERSION {
+        return Err(IOError::OutdatedVersion(loader.version()));
+    }
+    if loader.fps() != fps {
+        return Err(IOError::HeaderMismatch("fps"));
+    }
+    if loader.id() != info.id {
+        return Err(IOError::HeaderMismatch("sim id"));
+    }
+    if loader.payload_version() != info.payload_version {
+        return Err(IOError::HeaderMismatch("payload version"));
+    }
+    if loader.is_encrypted() {
+        return Err(IOError::HeaderMismatch("encryption"));
+    }
+
+    let codec = codecs
+        .create(loader.codec_id())
+        .ok_or(IOError::UnsupportedCodec(u32::from(loader.codec_id())))?;
+    // appended frames carry flags if the recording's frames do
+    let frame_flags = loader.features().frame_flags;
+    let end = loader.complete_len()?;
+    Ok((codec, frame_flags, end))
+}
+
+fn truncated(e: io::Error) -> IOError {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        IOError::TruncatedFrame
+    } else {
+        IOError::Io(e)
+    }
+}
+
+struct FrameHeader {
+    compressed_len: usize,
+    raw_len: usize,
+    nonce: Option<[u8; NONCE_SIZE]>,
+}
+
+/// Gzip stream over a seekable reader. Seeking forward skips decompressed data, seeking
+/// backward is served from the last decompressed bytes if they reach back far enough and
+/// starts decompressing again from the start of the stream otherwise.
+struct GzReader<R: Read + Seek> {
+    decoder: Option<MultiGzDecoder<R>>,
+    start: u64,
+    // decompressed bytes last read from the decoder, ending at `decoded`
+    history: Vec<u8>,
+    decoded: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> GzReader<R> {
+    fn new(reader: R, start: u64) -> Self {
+        Self {
+            decoder: Some(MultiGzDecoder::new(reader)),
+            start,
+            history: Vec::new(),
+            decoded: 0,
+            position: 0,
+        }
+    }
+
+    fn decoder(&mut self) -> io::Result<&mut MultiGzDecoder<R>> {
+        self.decoder
+            .as_mut()
+            .ok_or_else(|| io::Error::other("gzip reader failed to rewind"))
+    }
+
+    fn rewind_stream(&mut self) -> io::Result<()> {
+        let mut reader = self.decoder.take().map(|d| d.into_inner());
+        if let Some(reader) = reader.as_mut() {
+            reader.seek(SeekFrom::Start(self.start))?;
+        }
+        self.decoder = reader.map(MultiGzDecoder::new);
+        self.history.clear();
+        self.decoded = 0;
+        self.position = 0;
+        Ok(())
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<u64> {
+        let mut chunk = [0u8; 8192];
+        let mut skipped = 0;
+        while skipped < len {
+            let want = (len - skipped).min(chunk.len() as u64) as usize;
+            match self.read(&mut chunk[..want])? {
+                0 => break,
+                read => skipped += read as u64,
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+impl<R: Read + Seek> Read for GzReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // after seeking back within the history, or past the end
+        if self.position != self.decoded {
+            let Some(behind) = self.decoded.checked_sub(self.position) else {
+                return Ok(0);
+            };
+            let from = self.history.len() - behind as usize;
+            let len = buf.len().min(behind as usize);
+            buf[..len].copy_from_slice(&self.history[from..from + len]);
+            self.position += len as u64;
+            return Ok(len);
+        }
+
+        let read = self.decoder()?.read(buf)?;
+        self.history.extend_from_slice(&buf[..read]);
+        if self.history.len() > 2 * GZIP_HISTORY_SIZE {
+            self.history.drain(..self.history.len() - GZIP_HISTORY_SIZE);
+        }
+        self.decoded += read as u64;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for GzReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                // the length is only known after decompressing everything
+                self.skip(u64::MAX)?;
+                self.decoded.checked_add_signed(offset)
+            }
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+
+        if target < self.position {
+            let in_history = self
+                .decoded
+                .checked_sub(target)
+                .is_some_and(|behind| behind <= self.history.len() as u64);
+            if in_history {
+                self.position = target;
+                return Ok(target);
+            }
+            self.rewind_stream()?;
+        }
+        self.skip(target - self.position)?;
+        // like a file, seeking past the end is allowed and reads nothing
+        self.position = target;
+        Ok(target)
+    }
 }
 
-impl<W: Write> Saver<W> {
-    pub fn new(mut writer: W, fps: i32, info: SimInfo) -> Result<Self, IOError> {
-        writer.write_all(MAGIC)?;
-        writer.write_i32::<LittleEndian>(CURRENT_VERSION)?;
-        writer.write_i32::<LittleEndian>(fps)?;
-        writer.write_all(&info.id)?;
-        writer.write_i32::<LittleEndian>(info.payload_version)?;
+enum Source<R: Read + Seek> {
+    Plain(R),
+    Gzip(Box<GzReader<R>>),
+}
+
+impl<R: Read + Seek> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::Plain(reader) => reader.read(buf),
+            Source::Gzip(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for Source<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Source::Plain(reader) => reader.seek(pos),
+            Source::Gzip(reader) => reader.seek(pos),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        // BufReader drops its buffer on seek, but not when asked for the position
+        match self {
+            Source::Plain(reader) => reader.stream_position(),
+            Source::Gzip(reader) => reader.stream_position(),
+        }
+    }
+}
+
+/// Frames skipped and recovered by `Loader::load_salvaging`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SalvageStats {
+    /// Number of corrupt regions skipped.
+    pub corrupt_regions: u64,
+    /// Bytes skipped over in total.
+    pub skipped_bytes: u64,
+    /// Frames loaded after the first corrupt region.
+    pub recovered_frames: u64,
+}
+
+impl std::fmt::Display for SalvageStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames recovered, {} corrupt regions skipped ({} bytes)",
+            self.recovered_frames, self.corrupt_regions, self.skipped_bytes
+        )
+    }
+}
+
+/// Cheap check whether `bytes` could be the start of a frame, before trying to load it.
+fn plausible_frame_start(bytes: &[u8], encrypted: bool, codec_id: u8) -> bool {
+    let Some(header) = bytes.get(..FRAME_HEADER_SIZE as usize) else {
+        // too close to the end of the window to tell, let loading decide
+        return bytes.len() >= 4;
+    };
+    let header_size = i32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let min_header_size = if encrypted {
+        FRAME_HEADER_SIZE + NONCE_SIZE as i32
+    } else {
+        FRAME_HEADER_SIZE
+    };
+    if !(min_header_size..=MAX_FRAME_HEADER_SIZE).contains(&header_size) {
+        return false;
+    }
+    let compressed_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let raw_len = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+    if compressed_len == 0 || raw_len == 0 {
+        return false;
+    }
+
+    // unencrypted zlib data starts with a zlib header: deflate method and a checksum
+    if encrypted || codec_id != CODEC_ZLIB {
+        return true;
+    }
+    match bytes.get(header_size as usize..header_size as usize + 2) {
+        Some(&[cmf, flg]) => cmf & 0x0f == 8 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0,
+        _ => true,
+    }
+}
+
+pub struct Loader<R: Read + Seek> {
+    reader: Source<R>,
+    version: i32,
+    payload_version: i32,
+    fps: i32,
+    id: [u8; 4],
+    native_hz: u32,
+    start_unix_ms: i64,
+    flags: HeaderFlags,
+    writer_version: [u8; 3],
+    codec: Box<dyn Codec>,
+    salt: Option<[u8; SALT_SIZE]>,
+    cipher: Option<Aes256Gcm>,
+    data_start: u64,
+    frame_count: Option<u64>,
+    frame_start: u64,
+    frame_flags: FrameFlags,
+    frame_tick: u64,
+    next_tick: u64,
+    frame_compressed_len: usize,
+    salvage: SalvageStats,
+    stats: RecordingStats,
+}
+
+impl<R: Read + Seek> Loader<R> {
+    /// Reads the file header. Gzip compressed recordings are decompressed on the fly.
+    pub fn new(reader: R) -> Result<Self, IOError> {
+        Self::with_codecs(reader, &CodecRegistry::default())
+    }
+
+    /// Like `new`, looking up the codec of the recording in `codecs`.
+    pub fn with_codecs(mut reader: R, codecs: &CodecRegistry) -> Result<Self, IOError> {
+        let start = reader.stream_position()?;
+        let mut gzip_magic = [0u8; 2];
+        let is_gzip = reader.read_exact(&mut gzip_magic).is_ok() && gzip_magic == GZIP_MAGIC;
+        reader.seek(SeekFrom::Start(start))?;
+        let mut reader = if is_gzip {
+            Source::Gzip(Box::new(GzReader::new(reader, start)))
+        } else {
+            Source::Plain(reader)
+        };
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(IOError::InvalidMagic);
+        }
+
+        let version = reader.read_i32::<LittleEndian>()?;
+        if version > CURRENT_VERSION {
+            return Err(IOError::UnsupportedVersion(version));
+        }
+
+        let fps = reader.read_i32::<LittleEndian>()?;
+        // frames are timed by the fps, a rate below 1 can't be played or converted to times
+        if fps < 1 {
+            return Err(IOError::InvalidFps(fps));
+        }
+
+        let mut id = [0u8; 4];
+        reader.read_exact(&mut id)?;
+
+        let payload_version = if version >= 2 {
+            reader.read_i32::<LittleEndian>()?
+        } else {
+            1
+        };
+
+        let (salt, native_hz, codec, start_unix_ms, flags) = if version >= 3 {
+            let encryption = reader.read_u32::<LittleEndian>()?;
+            let mut salt = [0u8; SALT_SIZE];
+            reader.read_exact(&mut salt)?;
+            let salt = match encryption {
+                ENCRYPTION_NONE => None,
+                ENCRYPTION_AES_256_GCM => Some(salt),
+                other => return Err(IOError::UnsupportedEncryption(other)),
+            };
+            let native_hz = reader.read_u32::<LittleEndian>()?;
+            let codec_id = reader.read_u32::<LittleEndian>()?;
+            let struct_encoding = reader.read_u32::<LittleEndian>()?;
+            if struct_encoding != STRUCT_ENCODING_LE64 {
+                return Err(IOError::UnsupportedStructEncoding(struct_encoding));
+            }
+            let start_unix_ms = reader.read_i64::<LittleEndian>()?;
+            let flags = HeaderFlags(reader.read_u32::<LittleEndian>()?);
+            if flags.unknown_required() != 0 {
+                return Err(IOError::UnsupportedFeatures(flags.unknown_required()));
+            }
+            (salt, native_hz, codec_id, start_unix_ms, flags)
+        } else {
+            (None, 0, u32::from(CODEC_ZLIB), 0, HeaderFlags::default())
+        };
+        let codec = u8::try_from(codec)
+            .ok()
+            .and_then(|id| codecs.create(id))
+            .ok_or(IOError::UnsupportedCodec(codec))?;
+
+        let mut writer_version = [0u8; 3];
+        if version >= 3 {
+            reader.read_exact(&mut writer_version)?;
+        }
+        let padding_size = match version {
+            ..=1 => V1_PADDING_SIZE,
+            2 => V2_PADDING_SIZE,
+            _ => PADDING_SIZE,
+        };
+        let mut padding = vec![0u8; padding_size];
+        reader.read_exact(&mut padding)?;
+
+        let data_start = reader.stream_position()?;
+
+        Ok(Self {
+            reader,
+            version,
+            payload_version,
+            fps,
+            id,
+            native_hz,
+            start_unix_ms,
+            flags,
+            writer_version,
+            codec,
+            salt,
+            cipher: None,
+            data_start,
+            frame_count: None,
+            frame_start: data_start,
+            frame_flags: FrameFlags::default(),
+            frame_tick: 0,
+            next_tick: 0,
+            frame_compressed_len: 0,
+            salvage: SalvageStats::default(),
+            stats: RecordingStats::default(),
+        })
+    }
+
+    /// Derives the key for an encrypted recording. Frames of an encrypted recording can
+    /// only be loaded after this call; a wrong key is only detected when loading a frame.
+    pub fn unlock(&mut self, passphrase: &str) {
+        if let Some(salt) = &self.salt {
+            self.cipher = Some(derive_cipher(passphrase, salt));
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.salt.is_some()
+    }
+
+    /// Whether the whole file is gzip compressed, decompressed on the fly while loading.
+    pub fn is_gzip(&self) -> bool {
+        matches!(self.reader, Source::Gzip(_))
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn payload_version(&self) -> i32 {
+        self.payload_version
+    }
+
+    pub fn fps(&self) -> i32 {
+        self.fps
+    }
+
+    pub fn id(&self) -> [u8; 4] {
+        self.id
+    }
+
+    /// Id of the codec the frames are stored with, see `codec::CodecRegistry`.
+    pub fn codec_id(&self) -> u8 {
+        self.codec.id()
+    }
+
+    /// Rate the sim updated its data at while recording, `None` if unknown.
+    pub fn native_hz(&self) -> Option<u32> {
+        (self.native_hz != 0).then_some(self.native_hz)
+    }
+
+    /// Features the header declares, see `HeaderFlags`.
+    pub fn features(&self) -> FileFeatures {
+        self.flags.into()
+    }
+
+    /// Version of ksana that wrote the file, `None` for files written before it was stored.
+    /// Appending keeps the version of the build that created the file.
+    pub fn writer_version(&self) -> Option<WriterVersion> {
+        self.flags
+            .contains(HeaderFlags::WRITER_VERSION)
+            .then(|| WriterVersion::from_bytes(self.writer_version))
+    }
+
+    /// Capture flags of the frame last loaded or seeked over, `None` if the file doesn't
+    /// store them.
+    pub fn frame_flags(&self) -> Option<FrameFlags> {
+        self.flags
+            .contains(HeaderFlags::FRAME_FLAGS)
+            .then_some(self.frame_flags)
+    }
+
+    /// Bytes stored for the frame last loaded or skipped by `seek`, after compression and
+    /// encryption and without the frame header.
+    pub fn frame_compressed_len(&self) -> usize {
+        self.frame_compressed_len
+    }
+
+    /// Wall-clock time the capture started at, `None` if unknown. Save tick `n` was
+    /// captured about `n / fps` seconds later.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        (self.start_unix_ms > 0)
+            .then(|| UNIX_EPOCH + Duration::from_millis(self.start_unix_ms as u64))
+    }
+
+    /// Save tick the frame last loaded was captured in, counted from the start of the
+    /// recording. With frame flags the ticks without new data are counted, otherwise every
+    /// tick is assumed to have saved a frame and this is the index of the frame.
+    pub fn frame_tick(&self) -> u64 {
+        self.frame_tick
+    }
+
+    /// Wall-clock time save tick `tick` was captured at, timed from the start time and the
+    /// fps, see `frame_tick`.
+    pub fn tick_time(&self, tick: u64) -> Option<SystemTime> {
+        let fps = self.fps.max(1) as u64;
+        self.start_time()
+            .map(|start| start + Duration::from_millis(tick * 1000 / fps))
+    }
+
+    /// Number of frames in the recording. The file has no frame index, so the first call
+    /// scans the frame headers and caches the count; the read position is kept intact.
+    /// A frame cut off at the end of the file is not counted.
+    pub fn duration_frames(&mut self) -> Result<u64, IOError> {
+        if let Some(count) = self.frame_count {
+            return Ok(count);
+        }
+
+        let position = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+
+        let mut count = 0;
+        let scan = loop {
+            match self.seek() {
+                Ok(Some(())) if self.reader.stream_position()? <= end => count += 1,
+                Ok(_) | Err(IOError::TruncatedFrame) => break Ok(count),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.reader.seek(SeekFrom::Start(position))?;
+        let count = scan?;
+        self.frame_count = Some(count);
+        Ok(count)
+    }
+
+    /// Offset just past the last complete frame, where frames are appended to a
+    /// recording that was cut off mid-frame. Keeps the read position.
+    fn complete_len(&mut self) -> Result<u64, IOError> {
+        let position = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+
+        let mut complete = self.data_start;
+        let scan = loop {
+            match self.seek() {
+                Ok(Some(())) => {
+                    let frame_end = self.reader.stream_position()?;
+                    if frame_end > end {
+                        break Ok(complete);
+                    }
+                    complete = frame_end;
+                }
+                Ok(None) | Err(IOError::TruncatedFrame) => break Ok(complete),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.reader.seek(SeekFrom::Start(position))?;
+        scan
+    }
+
+    /// Duration of the recording in seconds, derived from the frame count and fps.
+    pub fn duration_seconds(&mut self) -> Result<f64, IOError> {
+        let frames = self.duration_frames()?;
+        Ok(frames as f64 / self.fps.max(1) as f64)
+    }
+
+    pub fn load(&mut self) -> Result<Option<Vec<u8>>, IOError> {
+        let frame = self.read_frame()?;
+        if let Some((data, compressed_len)) = &frame {
+            self.frame_tick = self.next_tick + u64::from(self.frame_flags.missed_ticks);
+            self.next_tick = self.frame_tick + 1;
+            self.stats.frames += 1;
+            self.stats.raw_bytes += data.len() as u64;
+            self.stats.compressed_bytes += *compressed_len as u64;
+        }
+        Ok(frame.map(|(data, _)| data))
+    }
+
+    /// Loads every frame from the current position to the end of the file, decompressing and
+    /// decrypting them, and returns how many there were. Fails with the index of the first
+    /// frame that doesn't load, counted from the current position, and why; a frame cut off
+    /// at the end is a `TruncatedFrame`.
+    pub fn verify(&mut self) -> Result<u64, (u64, IOError)> {
+        let mut frames = 0;
+        loop {
+            match self.load() {
+                Ok(Some(_)) => frames += 1,
+                Ok(None) => return Ok(frames),
+                Err(e) => return Err((frames, e)),
+            }
+        }
+    }
+
+    /// Sizes of the frames loaded so far, counting every frame `load` and the other load
+    /// methods returned.
+    pub fn stats(&self) -> RecordingStats {
+        self.stats
+    }
+
+    /// Reads the next frame and the number of bytes stored for it.
+    fn read_frame(&mut self) -> Result<Option<(Vec<u8>, usize)>, IOError> {
+        self.frame_start = self.reader.stream_position()?;
+
+        let Some(FrameHeader {
+            compressed_len,
+            raw_len,
+            nonce,
+        }) = self.read_header()?
+        else {
+            return Ok(None);
+        };
+
+        // the lengths come from the file, so only allocate as much as is actually there
+        let mut compressed = Vec::new();
+        (&mut self.reader)
+            .take(compressed_len as u64)
+            .read_to_end(&mut compressed)?;
+        if compressed.len() < compressed_len {
+            return Err(IOError::TruncatedFrame);
+        }
+
+        if let Some(nonce) = nonce {
+            let cipher = self.cipher.as_ref().ok_or(IOError::KeyRequired)?;
+            compressed = cipher
+                .decrypt(&nonce.into(), compressed.as_slice())
+                .map_err(|_| IOError::DecryptionFailed)?;
+        }
+
+        let decompressed = self.codec.decompress(&compressed, raw_len)?;
+        if decompressed.len() != raw_len {
+            return Err(IOError::DecompressionFailed);
+        }
+
+        Ok(Some((decompressed, compressed_len)))
+    }
+
+    /// Like `load`, but instead of failing on a corrupt frame, skips forward to the next
+    /// frame that loads correctly. Returns `None` once no more frames can be found.
+    /// I/O errors and a missing key are still returned, and so is a frame failing to
+    /// decrypt before any frame decrypted: that is a wrong key rather than corruption.
+    pub fn load_salvaging(&mut self) -> Result<Option<Vec<u8>>, IOError> {
+        loop {
+            match self.load() {
+                Err(IOError::DecryptionFailed) if self.stats.frames == 0 => {
+                    return Err(IOError::DecryptionFailed);
+                }
+                Ok(Some(frame)) => {
+                    if self.salvage.corrupt_regions > 0 {
+                        self.salvage.recovered_frames += 1;
+                    }
+                    return Ok(Some(frame));
+                }
+                Ok(None) => return Ok(None),
+                Err(
+                    IOError::InvalidHeaderSize(_)
+                    | IOError::TruncatedFrame
+                    | IOError::DecompressionFailed
+                    | IOError::DecryptionFailed,
+                ) => {
+                    let corrupt_start = self.frame_start;
+                    let Some(next) = self.resync(corrupt_start + 1)? else {
+                        return Ok(None);
+                    };
+                    self.salvage.corrupt_regions += 1;
+                    self.salvage.skipped_bytes += next - corrupt_start;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn salvage_stats(&self) -> SalvageStats {
+        self.salvage
+    }
+
+    /// Scans forward from `offset` for the next position a frame loads correctly from and
+    /// leaves the reader there.
+    fn resync(&mut self, mut offset: u64) -> Result<Option<u64>, IOError> {
+        let mut window = vec![0u8; RESYNC_WINDOW];
+        loop {
+            self.reader.seek(SeekFrom::Start(offset))?;
+            let mut filled = 0;
+            while filled < window.len() {
+                match self.reader.read(&mut window[filled..])? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+            if filled < 4 {
+                return Ok(None);
+            }
+
+            for i in 0..filled - 3 {
+                if !plausible_frame_start(&window[i..filled], self.is_encrypted(), self.codec_id())
+                {
+                    continue;
+                }
+                let candidate = offset + i as u64;
+                self.reader.seek(SeekFrom::Start(candidate))?;
+                // read without counting, the frame is loaded again from there
+                match self.read_frame() {
+                    Ok(Some(_)) => {
+                        self.reader.seek(SeekFrom::Start(candidate))?;
+                        return Ok(Some(candidate));
+                    }
+                    Err(IOError::Io(e)) => return Err(IOError::Io(e)),
+                    Err(IOError::KeyRequired) => return Err(IOError::KeyRequired),
+                    _ => {}
+                }
+            }
+
+            if filled < window.len() {
+                return Ok(None);
+            }
+            // keep the last bytes, a frame header may start there
+            offset += (filled - 3) as u64;
+        }
+    }
+
+    /// Like `load`, but for a file that is still being written: if the next frame is not
+    /// complete yet, nothing is consumed and `None` is returned, so the call can be retried
+    /// once more data has been appended.
+    pub fn load_available(&mut self) -> Result<Option<Vec<u8>>, IOError> {
+        let position = self.reader.stream_position()?;
+        match self.load() {
+            Ok(Some(frame)) => Ok(Some(frame)),
+            Ok(None) | Err(IOError::TruncatedFrame) => {
+                self.reader.seek(SeekFrom::Start(position))?;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn seek(&mut self) -> Result<Option<()>, IOError> {
+        let Some(header) = self.read_header()? else {
+            return Ok(None);
+        };
+        let compressed_len = header.compressed_len;
+
+        self.reader.seek(SeekFrom::Current(compressed_len as i64))?;
+
+        Ok(Some(()))
+    }
+
+    fn read_header(&mut self) -> Result<Option<FrameHeader>, IOError> {
+        let header_size = match self.reader.read_i32::<LittleEndian>() {
+            Ok(size) => size,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if header_size < 12 {
+            return Err(IOError::InvalidHeaderSize(header_size));
+        }
+
+        // past the header size EOF means the frame was cut off
+        let compressed_len = self.reader.read_u32::<LittleEndian>().map_err(truncated)? as usize;
+        let raw_len = self.reader.read_u32::<LittleEndian>().map_err(truncated)? as usize;
+        self.frame_compressed_len = compressed_len;
+
+        let mut extra_header_bytes = header_size - 12;
+
+        let nonce = if self.is_encrypted() {
+            if extra_header_bytes < NONCE_SIZE as i32 {
+                return Err(IOError::InvalidHeaderSize(header_size));
+            }
+            let mut nonce = [0u8; NONCE_SIZE];
+            self.reader.read_exact(&mut nonce).map_err(truncated)?;
+            extra_header_bytes -= NONCE_SIZE as i32;
+            Some(nonce)
+        } else {
+            None
+        };
+
+        self.frame_flags = FrameFlags::default();
+        if self.flags.contains(HeaderFlags::FRAME_FLAGS) && extra_header_bytes >= FRAME_FLAGS_SIZE {
+            let bits = self.reader.read_u32::<LittleEndian>().map_err(truncated)?;
+            self.frame_flags = FrameFlags::from_bits(bits);
+            extra_header_bytes -= FRAME_FLAGS_SIZE;
+        }
+
+        // skip the fields of newer frame headers whatever the file version, they are only
+        // known by their size
+        if extra_header_bytes > 0 {
+            self.reader
+                .seek(SeekFrom::Current(extra_header_bytes as i64))?;
+        }
+
+        Ok(Some(FrameHeader {
+            compressed_len,
+            raw_len,
+            nonce,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CODEC_NONE, NoneCodec};
+    use flate2::Compression;
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_single_frame() {
+        let mut buffer = Vec::new();
+
+        // Write
+        {
+            let mut saver = Saver::new(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            saver.save(b"hello world").unwrap();
+            saver.flush().unwrap();
+        }
+
+        // Read
+        {
+            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+            assert_eq!(loader.fps(), 30);
+            assert_eq!(&loader.id(), b"irac");
+
+            let frame = loader.load().unwrap();
+            assert_eq!(frame, Some(b"hello world".to_vec()));
+
+            // EOF
+            assert_eq!(loader.load().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_finalize() {
+        let mut buffer = Vec::new();
+        {
+            let mut saver = Saver::new(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            saver.save(b"hello world").unwrap();
+            saver.finalize().unwrap();
+        }
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"hello world".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
+    }
+
+    #[test]
+    fn test_multiple_frames() {
+        let mut buffer = Vec::new();
+        let frames: Vec<Vec<u8>> = vec![
+            vec![1, 2, 3, 4],
+            vec![5, 6, 7, 8, 9, 10],
+            vec![0; 1000], // Larger frame to test compression
+        ];
+
+        // Write
+        {
+            let mut saver = Saver::new(
+                &mut buffer,
+                60,
+                SimInfo {
+                    id: *b"acsa",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            for frame in &frames {
+                saver.save(frame).unwrap();
+            }
+            saver.flush().unwrap();
+        }
+
+        // Read
+        {
+            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+            assert_eq!(loader.version(), CURRENT_VERSION);
+            assert_eq!(loader.fps(), 60);
+            assert_eq!(&loader.id(), b"acsa");
+
+            for expected in &frames {
+                let frame = loader.load().unwrap();
+                assert_eq!(frame.as_ref(), Some(expected));
+            }
+
+            // EOF
+            assert_eq!(loader.load().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let mut buffer = Cursor::new(Vec::new());
+
+        {
+            let mut saver = Saver::new(&mut buffer, 30, info).unwrap();
+            saver.save(b"first").unwrap();
+            saver.flush().unwrap();
+        }
+
+        {
+            let mut saver = Saver::append(&mut buffer, 30, info).unwrap();
+            saver.save(b"second").unwrap();
+            saver.flush().unwrap();
+        }
+
+        let mut loader = Loader::new(Cursor::new(buffer.into_inner())).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"first".to_vec()));
+        assert_eq!(loader.load().unwrap(), Some(b"second".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
+    }
+
+    #[test]
+    fn test_append_after_torn_frame() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let mut data = Vec::new();
+        {
+            let mut saver = Saver::new(&mut data, 30, info).unwrap();
+            saver.save(b"first").unwrap();
+            saver.save(&[7; 100]).unwrap();
+            saver.flush().unwrap();
+        }
+        // the second frame cut off, as if the recording was killed mid-write
+        data.truncate(data.len() - 10);
+
+        let mut buffer = Cursor::new(data.clone());
+        {
+            let mut saver = Saver::append(&mut buffer, 30, info).unwrap();
+            saver.save(&[8; 200]).unwrap();
+            saver.flush().unwrap();
+        }
+        let mut loader = Loader::new(Cursor::new(buffer.into_inner())).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"first".to_vec()));
+        assert_eq!(loader.load().unwrap(), Some(vec![8; 200]));
+        assert_eq!(loader.load().unwrap(), None);
+
+        // a file is truncated, whatever the size of the appended frames
+        let path =
+            std::env::temp_dir().join(format!("ksana_append_torn_{}.ksr", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let mut saver = Saver::append_file(file, 30, info, 1024).unwrap();
+            saver.save(b"second").unwrap();
+            saver.finalize().unwrap();
+        }
+        let mut loader = Loader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(b"first".to_vec()));
+        assert_eq!(loader.load().unwrap(), Some(b"second".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_append_mismatch_rejected() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let mut buffer = Cursor::new(Vec::new());
+        Saver::new(&mut buffer, 30, info).unwrap();
+
+        let result = Saver::append(&mut buffer, 60, info);
+        assert!(matches!(result, Err(IOError::HeaderMismatch("fps"))));
+
+        let other = SimInfo {
+            id: *b"acsa",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let result = Saver::append(&mut buffer, 30, other);
+        assert!(matches!(result, Err(IOError::HeaderMismatch("sim id"))));
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let mut buffer = Vec::new();
+        let frames: Vec<Vec<u8>> = vec![b"secret lap".to_vec(), vec![7; 1000]];
+
+        {
+            let mut saver = Saver::new_encrypted(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+                "hunter2",
+            )
+            .unwrap();
+            for frame in &frames {
+                saver.save(frame).unwrap();
+            }
+            saver.finalize().unwrap();
+        }
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert!(loader.is_encrypted());
+        assert!(matches!(loader.load(), Err(IOError::KeyRequired)));
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        loader.unlock("hunter2");
+        for expected in &frames {
+            assert_eq!(loader.load().unwrap().as_ref(), Some(expected));
+        }
+        assert_eq!(loader.load().unwrap(), None);
+
+        // seeking doesn't need the key
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert!(loader.seek().unwrap().is_some());
+        assert!(loader.seek().unwrap().is_some());
+        assert!(loader.seek().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_encrypted_wrong_key() {
+        let mut buffer = Vec::new();
+        {
+            let mut saver = Saver::new_encrypted(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+                "hunter2",
+            )
+            .unwrap();
+            saver.save(b"secret lap").unwrap();
+            saver.finalize().unwrap();
+        }
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        loader.unlock("hunter3");
+        assert!(matches!(loader.load(), Err(IOError::DecryptionFailed)));
+
+        // not taken for corruption to skip over when salvaging
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        loader.unlock("hunter3");
+        assert!(matches!(
+            loader.load_salvaging(),
+            Err(IOError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_v2_header_still_supported() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&2i32.to_le_bytes()); // file version 2
+        buffer.extend_from_slice(&5i32.to_le_bytes()); // fps
+        buffer.extend_from_slice(b"irac"); // id
+        buffer.extend_from_slice(&3i32.to_le_bytes()); // payload version
+        buffer.extend_from_slice(&[0u8; 48]); // v2 padding (no encryption fields)
+
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.version(), 2);
+        assert_eq!(loader.payload_version(), 3);
+        assert!(!loader.is_encrypted());
+
+        // frames are only appended in the current format
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 3,
+            native_hz: 0,
+        };
+        let result = Saver::append(Cursor::new(&mut buffer), 5, info);
+        assert!(matches!(result, Err(IOError::OutdatedVersion(2))));
+    }
+
+    #[test]
+    fn test_larger_frame_header() {
+        let frame = |header_size: i32, data: &[u8], codec: &dyn Codec| {
+            let compressed = codec.compress(data);
+            let mut bytes = header_size.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.resize(header_size as usize, 0xee);
+            bytes.extend_from_slice(&compressed);
+            bytes
+        };
+
+        // v1 files are always zlib, v3 ones with codec none
+        for version in [1i32, 3] {
+            let mut buffer = Vec::new();
+            buffer.extend_from_slice(MAGIC);
+            buffer.extend_from_slice(&version.to_le_bytes());
+            buffer.extend_from_slice(&30i32.to_le_bytes());
+            buffer.extend_from_slice(b"test");
+            let codec: Box<dyn Codec> = if version == 3 {
+                buffer.extend_from_slice(&2i32.to_le_bytes());
+                buffer.extend_from_slice(&[0u8; 4]); // encryption
+                buffer.extend_from_slice(&[0u8; SALT_SIZE]);
+                buffer.extend_from_slice(&0u32.to_le_bytes()); // native rate
+                buffer.extend_from_slice(&u32::from(CODEC_NONE).to_le_bytes());
+                buffer.extend_from_slice(&[0u8; 20]); // struct encoding to padding
+                Box::new(NoneCodec)
+            } else {
+                buffer.extend_from_slice(&[0u8; V1_PADDING_SIZE]);
+                Box::new(ZlibCodec::default())
+            };
+            assert_eq!(buffer.len(), HEADER_SIZE);
+            // a frame header with 8 bytes of fields from the future, then a current one
+            buffer.extend_from_slice(&frame(20, b"future", codec.as_ref()));
+            buffer.extend_from_slice(&frame(12, b"current", codec.as_ref()));
+
+            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+            assert_eq!(loader.duration_frames().unwrap(), 2, "version {}", version);
+            assert_eq!(loader.load().unwrap(), Some(b"future".to_vec()));
+            assert_eq!(loader.load().unwrap(), Some(b"current".to_vec()));
+            assert_eq!(loader.load().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let record = |frames: &[&[u8]]| {
+            let mut buffer = Vec::new();
+            let mut saver = Saver::new(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            for frame in frames {
+                saver.save(frame).unwrap();
+            }
+            saver.finalize().unwrap();
+            buffer
+        };
+        let complete = record(&[b"complete frame"]);
+        let buffer = record(&[b"complete frame", b"interrupted frame"]);
+
+        // cut in the middle of the frame header and in the middle of the frame data
+        for end in [complete.len() + 6, buffer.len() - 3] {
+            let truncated = &buffer[..end];
+            let mut loader = Loader::new(Cursor::new(truncated)).unwrap();
+            assert_eq!(loader.load().unwrap(), Some(b"complete frame".to_vec()));
+            assert!(matches!(loader.load(), Err(IOError::TruncatedFrame)));
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut buffer = Vec::new();
+        let mut saver = Saver::new(
+            &mut buffer,
+            30,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 0,
+            },
+        )
+        .unwrap();
+        for frame in [b"first frame", b"other frame", b"third frame"] {
+            saver.save(frame).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.verify().unwrap(), 3);
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        loader.load().unwrap();
+        assert_eq!(loader.verify().unwrap(), 2);
+
+        let truncated = &buffer[..buffer.len() - 3];
+        let mut loader = Loader::new(Cursor::new(truncated)).unwrap();
+        assert!(matches!(loader.verify(), Err((2, IOError::TruncatedFrame))));
+
+        // the zlib checksum at the end of the second frame
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        loader.load().unwrap();
+        loader.load().unwrap();
+        let second_end = loader.reader.stream_position().unwrap() as usize;
+        let mut corrupt = buffer.clone();
+        corrupt[second_end - 1] ^= 0xff;
+        let mut loader = Loader::new(Cursor::new(&corrupt)).unwrap();
+        assert!(matches!(
+            loader.verify(),
+            Err((1, IOError::DecompressionFailed))
+        ));
+    }
+
+    #[test]
+    fn test_duration() {
+        let mut buffer = Vec::new();
+        {
+            let mut saver = Saver::new(
+                &mut buffer,
+                2,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            for i in 0..5u8 {
+                saver.save(&[i; 16]).unwrap();
+            }
+            saver.finalize().unwrap();
+        }
+
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(vec![0; 16]));
+
+        // counting keeps the read position and survives seeking
+        assert_eq!(loader.duration_frames().unwrap(), 5);
+        assert_eq!(loader.load().unwrap(), Some(vec![1; 16]));
+        assert!(loader.seek().unwrap().is_some());
+        assert_eq!(loader.duration_frames().unwrap(), 5);
+        assert_eq!(loader.duration_seconds().unwrap(), 2.5);
+        assert_eq!(loader.load().unwrap(), Some(vec![3; 16]));
+
+        // a frame cut off at the end is not counted
+        let truncated = &buffer[..buffer.len() - 3];
+        let mut loader = Loader::new(Cursor::new(truncated)).unwrap();
+        assert_eq!(loader.duration_frames().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_load_available_growing_file() {
+        let mut buffer = Vec::new();
+        let mut saver = Saver::new(
+            &mut buffer,
+            2,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 0,
+            },
+        )
+        .unwrap();
+        saver.save(&[1; 16]).unwrap();
+        saver.save(&[2; 16]).unwrap();
+        saver.finalize().unwrap();
+
+        let path = std::env::temp_dir().join(format!("ksana_follow_{}.bin", std::process::id()));
+        // both frames compress to the same size after the 72 byte file header
+        let first_frame_end = buffer.len() - (buffer.len() - 72) / 2;
+        let mut writer = std::fs::File::create(&path).unwrap();
+        writer.write_all(&buffer[..first_frame_end + 2]).unwrap();
+
+        let mut loader = Loader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(loader.load_available().unwrap(), Some(vec![1; 16]));
+        // the second frame is only partially written
+        assert_eq!(loader.load_available().unwrap(), None);
+        assert_eq!(loader.load_available().unwrap(), None);
+
+        writer.write_all(&buffer[first_frame_end + 2..]).unwrap();
+        assert_eq!(loader.load_available().unwrap(), Some(vec![2; 16]));
+        assert_eq!(loader.load_available().unwrap(), None);
+
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_salvaging() {
+        let mut buffer = Vec::new();
+        let mut saver = Saver::new(
+            &mut buffer,
+            2,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 0,
+            },
+        )
+        .unwrap();
+        for i in 0..5u8 {
+            saver.save(&[i; 16]).unwrap();
+        }
+        saver.finalize().unwrap();
+
+        // the frames compress to the same size after the 72 byte file header
+        let frame_len = (buffer.len() - 72) / 5;
+        assert_eq!(72 + frame_len * 5, buffer.len());
+        let frame_start = |i: usize| 72 + frame_len * i;
+
+        // a broken header size on frame 2 and garbage between frames 3 and 4
+        let mut corrupt = buffer.clone();
+        corrupt[frame_start(2)..frame_start(2) + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        corrupt.splice(frame_start(4)..frame_start(4), [0x78; 100]);
+
+        let mut loader = Loader::new(Cursor::new(&corrupt)).unwrap();
+        assert!(matches!(
+            loader.load(),
+            Ok(Some(frame)) if frame == [0; 16]
+        ));
+        assert!(matches!(loader.load(), Ok(Some(_))));
+        assert!(matches!(loader.load(), Err(IOError::InvalidHeaderSize(-1))));
+
+        let mut loader = Loader::new(Cursor::new(&corrupt)).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = loader.load_salvaging().unwrap() {
+            frames.push(frame[0]);
+        }
+        assert_eq!(frames, vec![0, 1, 3, 4]);
+        assert_eq!(
+            loader.salvage_stats(),
+            SalvageStats {
+                corrupt_regions: 2,
+                skipped_bytes: frame_len as u64 + 100,
+                recovered_frames: 2,
+            }
+        );
+        // frames read while scanning for the next good one aren't counted twice
+        assert_eq!(loader.stats().frames, 4);
+        assert_eq!(loader.stats().raw_bytes, 4 * 16);
+
+        // nothing to recover after a corrupt last frame
+        let mut corrupt = buffer.clone();
+        corrupt[frame_start(4)..frame_start(4) + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        let mut loader = Loader::new(Cursor::new(&corrupt)).unwrap();
+        let mut count = 0;
+        while loader.load_salvaging().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 4);
+        assert_eq!(loader.salvage_stats(), SalvageStats::default());
+    }
+
+    /// Counts the bytes read through it.
+    struct CountingReader<R> {
+        inner: R,
+        read: Rc<Cell<u64>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.read.set(self.read.get() + read as u64);
+            Ok(read)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_gzip_salvaging_reads_forward() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let recording = |value: u8| {
+            let mut buffer = Vec::new();
+            let mut saver = Saver::new(&mut buffer, 2, info).unwrap();
+            saver.save(&[value; 16]).unwrap();
+            saver.finalize().unwrap();
+            buffer
+        };
+
+        // incompressible garbage spanning several resync windows between two frames
+        let mut buffer = recording(1);
+        let mut seed = 1u32;
+        buffer.extend((0..8 * RESYNC_WINDOW).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 24) as u8
+        }));
+        buffer.extend_from_slice(&recording(2)[HEADER_SIZE..]);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&buffer).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let read = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(&gzipped),
+            read: read.clone(),
+        };
+        let mut loader = Loader::new(reader).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = loader.load_salvaging().unwrap() {
+            frames.push(frame[0]);
+        }
+        assert_eq!(frames, vec![1, 2]);
+        // seeking back over a window or a candidate frame doesn't decompress from the start
+        assert!(read.get() < 2 * gzipped.len() as u64, "{}", read.get());
+    }
+
+    #[test]
+    fn test_gzip_wrapped() {
+        let mut buffer = Vec::new();
+        {
+            let mut saver = Saver::new(
+                &mut buffer,
+                2,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            for i in 0..5u8 {
+                saver.save(&[i; 16]).unwrap();
+            }
+            saver.finalize().unwrap();
+        }
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&buffer).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let mut loader = Loader::new(Cursor::new(&gzipped)).unwrap();
+        assert!(loader.is_gzip());
+        assert_eq!(loader.fps(), 2);
+        assert_eq!(loader.id(), *b"irac");
+        assert_eq!(loader.load().unwrap(), Some(vec![0; 16]));
+        assert!(loader.seek().unwrap().is_some());
 
-        let padding = [0u8; PADDING_SIZE];
-        writer.write_all(&padding)?;
+        // counting rewinds the stream and returns to the same frame
+        assert_eq!(loader.duration_frames().unwrap(), 5);
+        assert_eq!(loader.load().unwrap(), Some(vec![2; 16]));
+        assert_eq!(loader.load().unwrap(), Some(vec![3; 16]));
+        assert_eq!(loader.load().unwrap(), Some(vec![4; 16]));
+        assert_eq!(loader.load().unwrap(), None);
 
-        Ok(Self { writer })
+        // appended frames would be written after the gzip stream
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        let result = Saver::append(Cursor::new(gzipped), 2, info);
+        assert!(matches!(result, Err(IOError::GzipAppend)));
+        assert!(!Loader::new(Cursor::new(&buffer)).unwrap().is_gzip());
     }
 
-    pub fn save(&mut self, data: &[u8]) -> Result<(), IOError> {
-        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(data)?;
-        let compressed = encoder.finish()?;
-
-        let compressed_len = compressed.len() as u32;
-        let raw_len = data.len() as u32;
+    #[test]
+    fn test_compression_level() {
+        let frame: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        let record = |level: u32| {
+            let mut buffer = Vec::new();
+            let mut saver = Saver::new(
+                &mut buffer,
+                30,
+                SimInfo {
+                    id: *b"irac",
+                    payload_version: 2,
+                    native_hz: 0,
+                },
+            )
+            .unwrap();
+            saver.set_compression_level(level);
+            saver.save(&frame).unwrap();
+            saver.finalize().unwrap();
+            buffer
+        };
 
-        self.writer.write_i32::<LittleEndian>(FRAME_HEADER_SIZE)?;
-        self.writer.write_u32::<LittleEndian>(compressed_len)?;
-        self.writer.write_u32::<LittleEndian>(raw_len)?;
-        self.writer.write_all(&compressed)?;
+        let stored = record(0);
+        let compressed = record(9);
+        assert!(compressed.len() < stored.len());
 
-        Ok(())
+        for buffer in [stored, compressed] {
+            let mut loader = Loader::new(Cursor::new(buffer)).unwrap();
+            assert_eq!(loader.load().unwrap(), Some(frame.clone()));
+        }
     }
 
-    pub fn flush(&mut self) -> Result<(), IOError> {
-        self.writer.flush()?;
-        Ok(())
-    }
-}
+    #[test]
+    fn test_recording_stats() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
 
-pub struct Loader<R: Read + Seek> {
-    reader: R,
-    version: i32,
-    payload_version: i32,
-    fps: i32,
-    id: [u8; 4],
-}
+        let mut buffer = Vec::new();
+        let mut saver = Saver::new(&mut buffer, 30, info).unwrap();
+        assert_eq!(saver.stats().ratio(), 0.0);
+        saver.save(&[0; 4096]).unwrap();
+        saver.save(&[1; 4096]).unwrap();
+        let stats = saver.stats();
+        saver.finalize().unwrap();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.raw_bytes, 8192);
+        // the frames are what follows the file header apart from the two frame headers
+        assert_eq!(stats.compressed_bytes, buffer.len() as u64 - 72 - 2 * 12);
+        assert!(stats.ratio() > 10.0);
 
-impl<R: Read + Seek> Loader<R> {
-    pub fn new(mut reader: R) -> Result<Self, IOError> {
-        let mut magic = [0u8; 8];
-        reader.read_exact(&mut magic)?;
-        if &magic != MAGIC {
-            return Err(IOError::InvalidMagic);
-        }
+        // loading counts the same sizes
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.stats(), RecordingStats::default());
+        while loader.load().unwrap().is_some() {}
+        assert_eq!(loader.stats(), stats);
 
-        let version = reader.read_i32::<LittleEndian>()?;
-        if version > CURRENT_VERSION {
-            return Err(IOError::UnsupportedVersion(version));
+        // skipping frames tells their stored size too
+        let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        let mut compressed_bytes = 0;
+        while loader.seek().unwrap().is_some() {
+            compressed_bytes += loader.frame_compressed_len() as u64;
         }
+        assert_eq!(compressed_bytes, stats.compressed_bytes);
 
-        let fps = reader.read_i32::<LittleEndian>()?;
-
-        let mut id = [0u8; 4];
-        reader.read_exact(&mut id)?;
+        let mut buffer = Vec::new();
+        let mut saver =
+            Saver::with_codec(&mut buffer, 30, info, Box::new(NoneCodec), None).unwrap();
+        saver.save(b"stored as is").unwrap();
+        assert_eq!(saver.stats().ratio(), 1.0);
+        assert_eq!(
+            saver.stats().to_string(),
+            "1 frames, 12 bytes compressed to 12 bytes (1.0:1)"
+        );
+    }
 
-        let payload_version = if version >= 2 {
-            let pv = reader.read_i32::<LittleEndian>()?;
-            let mut padding = [0u8; PADDING_SIZE];
-            reader.read_exact(&mut padding)?;
-            pv
-        } else {
-            let mut padding = [0u8; PADDING_SIZE + 4]; // v1 had 52 bytes of padding
-            reader.read_exact(&mut padding)?;
-            1
+    #[test]
+    fn test_codec_none_round_trip() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
         };
+        let frames = [b"hello world".to_vec(), vec![7; 100], vec![]];
 
-        Ok(Self {
-            reader,
-            version,
-            payload_version,
-            fps,
-            id,
-        })
-    }
+        for passphrase in [None, Some("secret")] {
+            let mut buffer = Vec::new();
+            let mut saver =
+                Saver::with_codec(&mut buffer, 30, info, Box::new(NoneCodec), passphrase).unwrap();
+            for frame in &frames {
+                saver.save(frame).unwrap();
+            }
+            saver.finalize().unwrap();
 
-    pub fn version(&self) -> i32 {
-        self.version
-    }
+            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+            assert_eq!(loader.codec_id(), CODEC_NONE);
+            if let Some(passphrase) = passphrase {
+                loader.unlock(passphrase);
+            } else {
+                // stored as is: the data follows the 12 byte frame header
+                assert_eq!(&buffer[72 + 12..72 + 12 + 11], b"hello world");
+            }
+            for frame in &frames {
+                assert_eq!(loader.load().unwrap().as_ref(), Some(frame));
+            }
+            assert_eq!(loader.load().unwrap(), None);
+        }
 
-    pub fn payload_version(&self) -> i32 {
-        self.payload_version
+        // appending keeps storing frames uncompressed
+        let mut buffer = Vec::new();
+        Saver::with_codec(&mut buffer, 30, info, Box::new(NoneCodec), None)
+            .unwrap()
+            .finalize()
+            .unwrap();
+        let mut cursor = Cursor::new(buffer);
+        let mut saver = Saver::append(&mut cursor, 30, info).unwrap();
+        saver.save(&[5; 64]).unwrap();
+        saver.finalize().unwrap();
+        assert_eq!(cursor.get_ref().len(), 72 + 12 + 64);
+        let mut loader = Loader::new(Cursor::new(cursor.into_inner())).unwrap();
+        assert_eq!(loader.load().unwrap(), Some(vec![5; 64]));
+
+        // old files have zeroed padding where the codec is, so they read as zlib
+        let mut buffer = Vec::new();
+        Saver::new(&mut buffer, 30, info).unwrap();
+        assert_eq!(&buffer[48..52], &[0; 4]);
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.codec_id(), CODEC_ZLIB);
     }
 
-    pub fn fps(&self) -> i32 {
-        self.fps
+    #[test]
+    fn test_unsupported_codec_rejected() {
+        let mut buffer = Vec::new();
+        Saver::new(
+            &mut buffer,
+            30,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 0,
+            },
+        )
+        .unwrap();
+        buffer[48..52].copy_from_slice(&7u32.to_le_bytes());
+        let result = Loader::new(Cursor::new(&buffer));
+        assert!(matches!(result, Err(IOError::UnsupportedCodec(7))));
     }
 
-    pub fn id(&self) -> [u8; 4] {
-        self.id
+    #[test]
+    fn test_struct_encoding() {
+        let mut buffer = Vec::new();
+        Saver::new(
+            &mut buffer,
+            30,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 0,
+            },
+        )
+        .unwrap();
+        // older files have zeroed padding there, which is the layout they were written with
+        assert_eq!(&buffer[52..56], &STRUCT_ENCODING_LE64.to_le_bytes());
+        assert!(Loader::new(Cursor::new(&buffer)).is_ok());
+
+        buffer[52..56].copy_from_slice(&1u32.to_le_bytes());
+        let result = Loader::new(Cursor::new(&buffer));
+        assert!(matches!(result, Err(IOError::UnsupportedStructEncoding(1))));
     }
 
-    pub fn load(&mut self) -> Result<Option<Vec<u8>>, IOError> {
-        let size = self.read_header()?;
-        let (compressed_len, raw_len) = match size {
-            Some((c, r)) => (c, r),
-            None => return Ok(None),
+    #[test]
+    fn test_start_time() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
         };
+        let start = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let mut buffer = Vec::new();
+        Saver::with_start_time(
+            &mut buffer,
+            30,
+            info,
+            Box::new(ZlibCodec::default()),
+            None,
+            Some(start),
+        )
+        .unwrap();
+        assert_eq!(&buffer[56..64], &1_700_000_000_123i64.to_le_bytes());
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.start_time(), Some(start));
+        assert_eq!(
+            loader.tick_time(45),
+            Some(start + Duration::from_millis(1500))
+        );
 
-        let mut compressed = vec![0u8; compressed_len];
-        self.reader.read_exact(&mut compressed)?;
-
-        let mut decoder = ZlibDecoder::new(&compressed[..]);
-        let mut decompressed = Vec::with_capacity(raw_len);
-        decoder
-            .read_to_end(&mut decompressed)
-            .map_err(|_| IOError::DecompressionFailed)?;
+        let before = SystemTime::now() - Duration::from_millis(1);
+        let mut buffer = Vec::new();
+        Saver::new(&mut buffer, 30, info).unwrap();
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert!(loader.start_time().unwrap() >= before);
 
-        Ok(Some(decompressed))
+        // older files have zeroed padding there
+        buffer[56..64].fill(0);
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.start_time(), None);
+        assert_eq!(loader.tick_time(45), None);
     }
 
-    pub fn seek(&mut self) -> Result<Option<()>, IOError> {
-        let size = self.read_header()?;
-        let (compressed_len, _) = match size {
-            Some((c, r)) => (c, r),
-            None => return Ok(None),
+    #[test]
+    fn test_header_fields() {
+        let mut buffer = Vec::new();
+        Saver::with_start_time(
+            &mut buffer,
+            30,
+            SimInfo {
+                id: *b"irac",
+                payload_version: 2,
+                native_hz: 60,
+            },
+            Box::new(NoneCodec),
+            None,
+            Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)),
+        )
+        .unwrap();
+        buffer[64..68].copy_from_slice(&0x0001_8003u32.to_le_bytes());
+
+        let fields = header_fields(&buffer);
+        assert_eq!(fields.iter().map(|f| f.len).sum::<usize>(), HEADER_SIZE);
+        let value = |name| {
+            fields
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| f.value.as_str())
+                .unwrap()
         };
+        assert_eq!(value("sim id"), "\"irac\"");
+        assert_eq!(value("native rate"), "60 Hz");
+        assert_eq!(value("codec"), "1 (none)");
+        assert_eq!(value("start time"), "1700000000123 ms since the Unix epoch");
+        assert_eq!(
+            value("flags"),
+            "0x00018003 (native rate, start time, unknown 0x8000, extensions 0x0001)"
+        );
+        assert_eq!(
+            value("writer version"),
+            WriterVersion::current().to_string()
+        );
+        assert_eq!(value("padding"), "zero");
 
-        self.reader.seek(SeekFrom::Current(compressed_len as i64))?;
+        // a v1 header is all padding after the sim id, a cut off one has what fits
+        let mut v1 = MAGIC.to_vec();
+        v1.extend_from_slice(&1i32.to_le_bytes());
+        v1.extend_from_slice(&5i32.to_le_bytes());
+        v1.extend_from_slice(b"acsa");
+        v1.extend_from_slice(&[0, 1]);
+        let names: Vec<_> = header_fields(&v1).iter().map(|f| f.name).collect();
+        assert_eq!(names, ["magic", "file version", "fps", "sim id"]);
 
-        Ok(Some(()))
+        let fields = header_fields(b"BADMAGIC and more");
+        assert_eq!(fields.len(), 1);
+        assert!(fields[0].value.contains("expected"));
     }
 
-    fn read_header(&mut self) -> Result<Option<(usize, usize)>, IOError> {
-        let header_size = match self.reader.read_i32::<LittleEndian>() {
-            Ok(size) => size,
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
-            Err(e) => return Err(e.into()),
+    #[test]
+    fn test_frame_flags() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 60,
         };
-        if header_size < 12 {
-            return Err(IOError::InvalidHeaderSize(header_size));
+        let late = FrameFlags { missed_ticks: 3 };
+        let later = FrameFlags { missed_ticks: 1 };
+
+        for passphrase in [None, Some("secret")] {
+            let mut buffer = Vec::new();
+            let options = SaverOptions {
+                codec: Box::new(ZlibCodec::default()),
+                passphrase,
+                start_time: None,
+                frame_flags: true,
+            };
+            let mut saver = Saver::with_options(&mut buffer, 30, info, options).unwrap();
+            saver.save(b"first").unwrap();
+            saver.save_with_flags(b"second", late).unwrap();
+            saver.save_with_flags(b"third", later).unwrap();
+            saver.finalize().unwrap();
+
+            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
+            if let Some(passphrase) = passphrase {
+                loader.unlock(passphrase);
+            }
+            assert!(loader.features().frame_flags);
+            assert_eq!(loader.load().unwrap().unwrap(), b"first");
+            assert_eq!(loader.frame_flags(), Some(FrameFlags::default()));
+            assert_eq!(loader.frame_tick(), 0);
+            assert_eq!(loader.load().unwrap().unwrap(), b"second");
+            assert_eq!(loader.frame_flags(), Some(late));
+            // captured after the 3 ticks without new data
+            assert_eq!(loader.frame_tick(), 4);
+            loader.seek().unwrap().unwrap();
+            assert_eq!(loader.frame_flags(), Some(later));
+            assert!(loader.load().unwrap().is_none());
         }
 
-        let compressed_len = match self.reader.read_u32::<LittleEndian>() {
-            Ok(len) => len as usize,
-            Err(e) => return Err(e.into()),
+        // without the feature the flags are dropped and cost nothing
+        let mut plain = Vec::new();
+        let mut saver = Saver::new(&mut plain, 30, info).unwrap();
+        saver.save_with_flags(b"first", late).unwrap();
+        saver.finalize().unwrap();
+        assert_eq!(i32::from_le_bytes(plain[72..76].try_into().unwrap()), 12);
+        let mut loader = Loader::new(Cursor::new(&plain)).unwrap();
+        assert_eq!(loader.load().unwrap().unwrap(), b"first");
+        assert_eq!(loader.frame_flags(), None);
+
+        // appended frames keep carrying them
+        let mut buffer = Vec::new();
+        let options = SaverOptions {
+            codec: Box::new(ZlibCodec::default()),
+            passphrase: None,
+            start_time: None,
+            frame_flags: true,
         };
+        let mut saver = Saver::with_options(&mut buffer, 30, info, options).unwrap();
+        saver.save(b"first").unwrap();
+        saver.finalize().unwrap();
+        let mut cursor = Cursor::new(buffer);
+        let mut saver = Saver::append(&mut cursor, 30, info).unwrap();
+        saver.save_with_flags(b"appended", late).unwrap();
+        saver.finalize().unwrap();
+        cursor.set_position(0);
+        let mut loader = Loader::new(cursor).unwrap();
+        loader.seek().unwrap().unwrap();
+        assert_eq!(loader.load().unwrap().unwrap(), b"appended");
+        assert_eq!(loader.frame_flags(), Some(late));
+    }
 
-        let raw_len = self.reader.read_u32::<LittleEndian>()? as usize;
+    #[test]
+    fn test_header_flags() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 60,
+        };
+        let mut buffer = Vec::new();
+        Saver::with_start_time(
+            &mut buffer,
+            30,
+            info,
+            Box::new(NoneCodec),
+            Some("secret"),
+            Some(SystemTime::now()),
+        )
+        .unwrap();
+        let flags = HeaderFlags::NATIVE_HZ
+            | HeaderFlags::START_TIME
+            | HeaderFlags::WRITER_VERSION
+            | HeaderFlags::ENCRYPTED
+            | HeaderFlags::CODEC;
+        assert_eq!(&buffer[64..68], &flags.to_le_bytes());
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(
+            loader.features(),
+            FileFeatures {
+                native_hz: true,
+                start_time: true,
+                frame_flags: false,
+                writer_version: true,
+                encrypted: true,
+                codec: true,
+                extensions: 0,
+            }
+        );
 
-        // Skip any extra header bytes if present
-        if self.version() >= 2 {
-            let extra_header_bytes = header_size - 12;
-            if extra_header_bytes > 0 {
-                self.reader
-                    .seek(SeekFrom::Current(extra_header_bytes as i64))?;
+        let mut buffer = Vec::new();
+        Saver::with_start_time(
+            &mut buffer,
+            30,
+            SimInfo {
+                native_hz: 0,
+                ..info
+            },
+            Box::new(ZlibCodec::default()),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(&buffer[64..68], &HeaderFlags::WRITER_VERSION.to_le_bytes());
+        assert_eq!(
+            Loader::new(Cursor::new(&buffer)).unwrap().features(),
+            FileFeatures {
+                writer_version: true,
+                ..FileFeatures::default()
             }
-        }
+        );
 
-        Ok(Some((compressed_len, raw_len)))
-    }
-}
+        // unknown optional and extension bits are passed through
+        buffer[64..68].copy_from_slice(&0x00a5_0080u32.to_le_bytes());
+        let features = Loader::new(Cursor::new(&buffer)).unwrap().features();
+        assert_eq!(features.extensions, 0x00a5);
+        assert!(!features.native_hz);
+        assert!(!features.writer_version);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+        // unknown required bits are not
+        buffer[64..68].copy_from_slice(&0x00a5_8000u32.to_le_bytes());
+        let result = Loader::new(Cursor::new(&buffer));
+        assert!(matches!(result, Err(IOError::UnsupportedFeatures(0x8000))));
+    }
 
     #[test]
-    fn test_single_frame() {
+    fn test_writer_version() {
+        let info = SimInfo {
+            id: *b"acsa",
+            payload_version: 1,
+            native_hz: 0,
+        };
         let mut buffer = Vec::new();
-
-        // Write
-        {
-            let mut saver = Saver::new(
-                &mut buffer,
-                30,
-                SimInfo {
-                    id: *b"irac",
-                    payload_version: 2,
-                },
-            )
+        Saver::new(&mut buffer, 10, info)
+            .unwrap()
+            .finalize()
             .unwrap();
-            saver.save(b"hello world").unwrap();
-            saver.flush().unwrap();
-        }
+        let current = WriterVersion::current();
+        assert_eq!(current.to_string(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(&buffer[68..71], &current.to_bytes());
+        assert_eq!(buffer[71], 0);
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        assert_eq!(loader.writer_version(), Some(current));
 
-        // Read
-        {
-            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
-            assert_eq!(loader.fps(), 30);
-            assert_eq!(&loader.id(), b"irac");
+        // appending keeps the version of the file
+        buffer[68..71].copy_from_slice(&[0, 1, 2]);
+        let mut cursor = Cursor::new(buffer);
+        let mut saver = Saver::append(&mut cursor, 10, info).unwrap();
+        saver.save(b"frame").unwrap();
+        saver.finalize().unwrap();
+        let mut buffer = cursor.into_inner();
+        let loader = Loader::new(Cursor::new(&buffer)).unwrap();
+        let version = loader.writer_version().unwrap();
+        assert_eq!(version.to_string(), "0.1.2");
+        assert!(version < current);
 
-            let frame = loader.load().unwrap();
-            assert_eq!(frame, Some(b"hello world".to_vec()));
+        // files written before the version was stored have the flag cleared
+        buffer[64..68].copy_from_slice(&[0; 4]);
+        buffer[68..71].copy_from_slice(&[0; 3]);
+        assert_eq!(
+            Loader::new(Cursor::new(&buffer)).unwrap().writer_version(),
+            None
+        );
+    }
 
-            // EOF
-            assert_eq!(loader.load().unwrap(), None);
+    /// Reverses the bytes, enough to tell it apart from the built-in codecs.
+    struct ReverseCodec;
+
+    impl Codec for ReverseCodec {
+        fn compress(&self, data: &[u8]) -> Vec<u8> {
+            data.iter().rev().copied().collect()
+        }
+
+        fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>, IOError> {
+            if data.len() != raw_len {
+                return Err(IOError::DecompressionFailed);
+            }
+            Ok(data.iter().rev().copied().collect())
+        }
+
+        fn id(&self) -> u8 {
+            42
         }
     }
 
     #[test]
-    fn test_multiple_frames() {
+    fn test_custom_codec() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
         let mut buffer = Vec::new();
-        let frames: Vec<Vec<u8>> = vec![
-            vec![1, 2, 3, 4],
-            vec![5, 6, 7, 8, 9, 10],
-            vec![0; 1000], // Larger frame to test compression
-        ];
+        let mut saver =
+            Saver::with_codec(&mut buffer, 30, info, Box::new(ReverseCodec), None).unwrap();
+        saver.save(b"abc").unwrap();
+        saver.finalize().unwrap();
+        assert_eq!(&buffer[48..52], &42u32.to_le_bytes());
+        assert_eq!(&buffer[72 + 12..], b"cba");
 
-        // Write
-        {
-            let mut saver = Saver::new(
-                &mut buffer,
-                60,
-                SimInfo {
-                    id: *b"acsa",
-                    payload_version: 2,
-                },
-            )
-            .unwrap();
-            for frame in &frames {
-                saver.save(frame).unwrap();
-            }
-            saver.flush().unwrap();
-        }
+        // unknown to the default registry
+        let result = Loader::new(Cursor::new(&buffer));
+        assert!(matches!(result, Err(IOError::UnsupportedCodec(42))));
 
-        // Read
-        {
-            let mut loader = Loader::new(Cursor::new(&buffer)).unwrap();
-            assert_eq!(loader.version(), CURRENT_VERSION);
-            assert_eq!(loader.fps(), 60);
-            assert_eq!(&loader.id(), b"acsa");
+        let mut codecs = CodecRegistry::default();
+        codecs.register(42, || Box::new(ReverseCodec));
 
-            for expected in &frames {
-                let frame = loader.load().unwrap();
-                assert_eq!(frame.as_ref(), Some(expected));
-            }
+        let mut cursor = Cursor::new(buffer);
+        let mut saver = Saver::append_with_codecs(&mut cursor, 30, info, &codecs).unwrap();
+        saver.save(b"de").unwrap();
+        saver.finalize().unwrap();
 
-            // EOF
-            assert_eq!(loader.load().unwrap(), None);
-        }
+        let mut loader = Loader::with_codecs(Cursor::new(cursor.into_inner()), &codecs).unwrap();
+        assert_eq!(loader.codec_id(), 42);
+        assert_eq!(loader.load().unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(loader.load().unwrap(), Some(b"de".to_vec()));
+        assert_eq!(loader.load().unwrap(), None);
     }
 
     #[test]
@@ -304,6 +2705,7 @@ mod tests {
             SimInfo {
                 id: *b"test",
                 payload_version: 2,
+                native_hz: 0,
             },
         )
         .unwrap();
@@ -315,8 +2717,17 @@ mod tests {
         // - 4 fps
         // - 4 id
         // - 4 payload version
-        // - 48 padding
+        // - 4 encryption
+        // - 16 salt
+        // - 4 native_hz
+        // - 4 codec
+        // - 4 struct encoding
+        // - 8 start_unix_ms
+        // - 4 HeaderFlags
+        // - 3 writer version
+        // - 1 padding (PADDING_SIZE)
         assert_eq!(buffer.len(), 72);
+        assert_eq!(buffer.len(), HEADER_SIZE);
     }
 
     #[test]
@@ -328,6 +2739,7 @@ mod tests {
             SimInfo {
                 id: *b"irac",
                 payload_version: 7,
+                native_hz: 60,
             },
         )
         .unwrap();
@@ -336,6 +2748,7 @@ mod tests {
         assert_eq!(loader.fps(), 10);
         assert_eq!(&loader.id(), b"irac");
         assert_eq!(loader.payload_version(), 7);
+        assert_eq!(loader.native_hz(), Some(60));
     }
 
     #[test]
@@ -351,6 +2764,7 @@ mod tests {
         let loader = Loader::new(Cursor::new(&buffer)).unwrap();
         assert_eq!(loader.version(), 1);
         assert_eq!(loader.payload_version(), 1);
+        assert_eq!(loader.native_hz(), None);
     }
 
     #[test]
@@ -362,4 +2776,22 @@ mod tests {
         let result = Loader::new(Cursor::new(&buffer));
         assert!(matches!(result, Err(IOError::UnsupportedVersion(_))));
     }
+
+    #[test]
+    fn test_invalid_fps_rejected() {
+        let info = SimInfo {
+            id: *b"irac",
+            payload_version: 2,
+            native_hz: 0,
+        };
+        for fps in [0, -30] {
+            let mut buffer = Vec::new();
+            let mut saver = Saver::new(&mut buffer, fps, info).unwrap();
+            saver.save(b"frame").unwrap();
+            saver.finalize().unwrap();
+
+            let result = Loader::new(Cursor::new(&buffer));
+            assert!(matches!(result, Err(IOError::InvalidFps(f)) if f == fps));
+        }
+    }
 }
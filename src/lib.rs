@@ -0,0 +1,37 @@
+//! Library target exposing the recording format and the loop recording a connected sim in
+//! [`recorder`]. Analysis tools read the frames of a recording deserialized with
+//! [`frames::DecodedFrames`] and the data structures in [`sims`]. With the `ffi` feature
+//! enabled the C ABI in [`ffi`] allows other languages to read recordings.
+
+pub mod broadcast;
+pub mod codec;
+pub mod frames;
+pub mod io;
+pub mod recorder;
+pub mod traits;
+
+/// The frame data of the sims as it is recorded. Connecting to the sims and playing the
+/// frames back is left to the `ksana` binary.
+pub mod sims {
+    // The sim data is copied between the shared memory and the recordings as raw bytes of
+    // #[repr(C)] structs, which assumes the layout of the Windows x64 sims
+    #[cfg(not(all(target_endian = "little", target_pointer_width = "64")))]
+    compile_error!("ksana only supports little-endian 64-bit targets");
+
+    pub mod ac {
+        pub mod data;
+    }
+    pub mod assettocorsa {
+        pub mod data;
+    }
+    pub mod interpolate;
+    pub mod iracing {
+        pub mod data;
+        pub mod vars;
+    }
+}
+
+pub use traits::{Clock, Connector, Player, SimInfo, Sleeper};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
@@ -1,17 +1,22 @@
 use clap::{Parser, Subcommand};
+use std::process::ExitCode;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
 
 mod commands;
-mod io;
+mod exit;
 mod shm;
 mod sims;
 mod sleeper;
-mod traits;
+mod telemetry;
+mod udp;
 
-pub use traits::{Connector, Player, SimInfo, Sleeper};
+use exit::Exit;
+use ksana::{codec, frames, io, recorder, traits};
+
+pub use ksana::{Clock, Connector, Player, SimInfo, Sleeper};
 
 #[cfg(not(windows))]
 compile_error!("This project only supports Windows");
@@ -31,9 +36,15 @@ struct Cli {
 enum Commands {
     /// Record raw telemetry data to file (default)
     Record {
-        /// Frames per second [1-60]
-        #[arg(short, long, default_value_t = 5)]
-        fps: u32,
+        /// Frames per second [1-60], or "auto" to pick the rate recommended for the
+        /// connected sim. Defaults to 5
+        #[arg(short, long)]
+        fps: Option<commands::record::Fps>,
+
+        /// How often to poll the sim for new data [fps-1000]. Defaults to fps. Polling
+        /// faster than fps saves the latest data the sim produced within each frame.
+        #[arg(long)]
+        poll_hz: Option<u32>,
 
         /// Maximum duration to record (e.g. "10s", "5m"). If not specified,
         /// recording will continue until Ctrl+C is pressed or the sim is exited.
@@ -41,46 +52,707 @@ enum Commands {
         /// allowed.
         #[arg(long)]
         max_duration: Option<String>,
+
+        /// Append to an existing recording instead of creating a new file. The
+        /// recording must match the connected sim and the requested fps.
+        #[arg(long)]
+        append: Option<String>,
+
+        /// Encrypt the recording with AES-256-GCM using a key derived from --key
+        #[arg(long, requires = "key", conflicts_with = "append")]
+        encrypt: bool,
+
+        /// Passphrase to derive the encryption key from
+        #[arg(long, requires = "encrypt")]
+        key: Option<String>,
+
+        /// Stop recording after this many frames were saved
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        limit_frames: Option<u64>,
+
+        /// Store frames without compression, for when a slow CPU drops frames. Files are
+        /// several times larger
+        #[arg(long, conflicts_with = "append")]
+        no_compress: bool,
+
+        /// Size of the file write buffer in MB [1-1024]. Larger buffers mean fewer writes
+        /// at high fps, at the cost of as much memory
+        #[arg(
+            long,
+            default_value_t = commands::DEFAULT_IO_BUFFER_MB,
+            value_parser = clap::value_parser!(u32).range(1..=1024)
+        )]
+        io_buffer_mb: u32,
+
+        /// Flush the recording to disk every N frames, or every DURATION like 30s or 1m, so
+        /// a crash loses at most that much instead of the whole write buffer
+        #[arg(long, value_name = "N|DURATION", value_parser = commands::record::parse_flush_every)]
+        flush_every: Option<commands::record::FlushEvery>,
+
+        /// Only record these kinds of packets of sims sending several (F1), comma separated
+        /// (e.g. "carTelemetry,lapData"). The other packets can't be played back
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = sims::f1::data::parse_packet_kind
+        )]
+        packet_filter: Option<Vec<u8>>,
+
+        /// Skip iRacing frames whose header offsets point outside the shared memory or at
+        /// overlapping regions, as happens while the sim exits, and warn instead
+        #[arg(long, alias = "strict")]
+        validate_offsets: bool,
+
+        /// iRacing shared memory to record from instead of Local\IRSDKMemMapFileName, e.g.
+        /// the --map-name of a player
+        #[arg(long, value_name = "NAME")]
+        map_name: Option<String>,
+
+        /// Store with every frame how many ticks passed without new data from the sim before
+        /// it, for judging the capture quality with inspect. Adds 4 bytes per frame
+        #[arg(long, conflicts_with = "append")]
+        frame_flags: bool,
+
+        /// Also write an untouched copy of the sim's shared memory with every frame to
+        /// <recording>.raw, for debugging the recorder. iRacing and Project CARS 2 only
+        #[arg(long, conflicts_with = "append")]
+        raw_sidecar: bool,
+
+        /// How long to wait between scans for a running sim, in ms [10-60000]
+        #[arg(
+            long,
+            default_value_t = commands::record::DEFAULT_CONNECT_POLL_MS,
+            value_parser = clap::value_parser!(u64).range(10..=60000)
+        )]
+        connect_poll_ms: u64,
+
+        /// Double the wait after every scan without a sim, up to this many ms [10-600000]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(10..=600000))]
+        connect_backoff_max_ms: Option<u64>,
+
+        /// After recording stops, load every frame of the file again and print OK or the
+        /// first frame that fails to load, to catch write errors while still at the sim
+        #[arg(long)]
+        verify_on_stop: bool,
+
+        /// Only scan for this sim. Without it the shared memory sims are scanned for, the UDP
+        /// sims (dirt, beamng, f1) are only recorded with --sim or --prefer
+        #[arg(long, value_name = "SIM")]
+        sim: Option<commands::record::RecordSim>,
+
+        /// Sim to try first when scanning for a running sim, so it wins when several are up,
+        /// e.g. another one only through mappings a launcher left behind
+        #[arg(long, value_name = "SIM", conflicts_with = "sim")]
+        prefer: Option<commands::record::RecordSim>,
     },
     /// Play back recorded file as if it is being streamed from the simulator
     Play {
         /// Input file to play
         #[arg(short, long)]
         input: String,
+
+        /// Passphrase of an encrypted recording
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Keep waiting for new frames at the end of the file, for playing a recording
+        /// that is still being written
+        #[arg(long)]
+        follow: bool,
+
+        /// Skip over corrupt frames instead of stopping at the first one
+        #[arg(long, conflicts_with = "follow")]
+        salvage: bool,
+
+        /// Size of the file read buffer in MB [1-1024]. Larger buffers mean fewer reads
+        /// at high fps, at the cost of as much memory
+        #[arg(
+            long,
+            default_value_t = commands::DEFAULT_IO_BUFFER_MB,
+            value_parser = clap::value_parser!(u32).range(1..=1024)
+        )]
+        io_buffer_mb: u32,
+
+        /// Rewrite the Assetto Corsa packet ids to increase by one every frame, hiding
+        /// gaps from skipped or trimmed frames. The recorded ids are kept by default
+        #[arg(long)]
+        resequence: bool,
+
+        /// Play FACTOR frames per recorded frame [2-8], interpolating the float channels of
+        /// iRacing and Assetto Corsa recordings in between, e.g. 2 to feed a 60 fps consumer
+        /// from a 30 fps recording. Adds one recorded frame of latency
+        #[arg(
+            long,
+            value_name = "FACTOR",
+            value_parser = clap::value_parser!(u32).range(2..=8)
+        )]
+        interpolate: Option<u32>,
+
+        /// Write the last frame again at least HZ times per second [1-100] while no new
+        /// frame is due, e.g. while following a recording, for consumers that time out when
+        /// the shared memory isn't updated. Also signals the iRacing data valid event
+        #[arg(
+            long,
+            value_name = "HZ",
+            value_parser = clap::value_parser!(u32).range(1..=100)
+        )]
+        keepalive_hz: Option<u32>,
+
+        /// Write at most FPS frames per second [1-1000], interpolated ones included,
+        /// slowing playback below real time if the recording is faster, to protect a
+        /// consumer that can't keep up
+        #[arg(
+            long,
+            value_name = "FPS",
+            value_parser = clap::value_parser!(u32).range(1..=1000),
+            conflicts_with = "sync_event"
+        )]
+        max_fps: Option<u32>,
+
+        /// Where to play the frames: the sim's shared memory or UDP port, nowhere, or a log
+        /// with a line per frame, for testing playback without a consumer
+        #[arg(long, value_enum, default_value_t)]
+        player: commands::play::PlayerKind,
+
+        /// Log file of the file player. Defaults to the input file with .log appended
+        #[arg(long, value_name = "FILE")]
+        player_log: Option<String>,
+
+        /// Play even if the shared memory of the sim already exists. Without it playback is
+        /// refused, as the sim appears to be running and both would write to the same memory
+        #[arg(long)]
+        force: bool,
+
+        /// iRacing shared memory to play to instead of Local\IRSDKMemMapFileName, for
+        /// running several players side by side
+        #[arg(long, value_name = "NAME")]
+        map_name: Option<String>,
+
+        /// iRacing data valid event to signal instead of Local\IRSDKDataValidEvent
+        #[arg(long, value_name = "NAME")]
+        event_name: Option<String>,
+
+        /// Skip a frame the player fails to write, e.g. because a consumer briefly locked
+        /// the shared memory, instead of aborting the playback
+        #[arg(long)]
+        tolerant: bool,
+
+        /// Abort tolerant playback after this many failed writes in a row
+        #[arg(
+            long,
+            value_name = "N",
+            requires = "tolerant",
+            default_value_t = commands::play::DEFAULT_MAX_UPDATE_FAILURES,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        max_update_failures: u32,
+
+        /// Write a frame each time the consumer signals the event NAME instead of at the
+        /// recorded rate, for consumers that can't keep up. Playback is timed if the
+        /// consumer hasn't created the event
+        #[arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = ["interpolate", "keepalive_hz"]
+        )]
+        sync_event: Option<String>,
+
+        /// Milliseconds playback gets to stop after Ctrl+C [100-600000] before ksana exits
+        /// without stopping the player, e.g. when a write is blocked by a consumer
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = commands::play::DEFAULT_QUIT_TIMEOUT_MS,
+            value_parser = clap::value_parser!(u64).range(100..=600_000)
+        )]
+        quit_timeout_ms: u64,
+
+        /// Write the first frame, then wait for Enter before playing the rest, so consumers
+        /// can be set up against a known frame
+        #[arg(long)]
+        start_paused: bool,
+    },
+    /// Play several iRacing recordings at once, each to its own shared memory, aligned by the
+    /// time they were captured, e.g. to compare the laps of several drivers side by side
+    PlayMulti {
+        /// Input file to play, repeat for every recording
+        #[arg(short, long = "input", required = true)]
+        inputs: Vec<String>,
+
+        /// Shared memory to play the input at the same position to, e.g. Local\KsanaDriver1,
+        /// repeat for every input. The player signals <NAME>DataValidEvent
+        #[arg(short, long = "target", value_name = "NAME", required = true)]
+        targets: Vec<String>,
+
+        /// Start all recordings together instead of aligning them by the time they were
+        /// captured, e.g. for laps of different sessions
+        #[arg(long)]
+        ignore_start_times: bool,
+    },
+    /// Check whether a recording can be played here without playing it: the file, its sim,
+    /// a running sim, creating the shared memory and privileges
+    Doctor {
+        /// Input file to check
+        #[arg(short, long)]
+        input: String,
+
+        /// Passphrase of an encrypted recording
+        #[arg(long)]
+        key: Option<String>,
     },
     /// Inspect recorded file and print basic info about it
     Inspect {
         /// Input file to inspect
         #[arg(short, long)]
         input: String,
+
+        /// Also print a histogram of the compressed frame sizes, e.g. to spot the large
+        /// frames of session info updates
+        #[arg(long)]
+        histogram: bool,
+    },
+    /// Print the raw header bytes of a recording and the fields they decode to, also for
+    /// files that fail to load
+    Header {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+    },
+    /// List the channels of an iRacing recording with their type, count, unit and
+    /// description
+    ListChannels {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+    },
+    /// List which channels of an iRacing recording changed, by descending variance with
+    /// their ranges, and which stayed constant
+    ChangedChannels {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+    },
+    /// Write the session info YAML of an iRacing recording to a file, from the last frame
+    /// carrying it
+    SessionInfo {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+
+        /// Output YAML file
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Export the driven line of an iRacing recording as an x,y CSV
+    TrackMap {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+
+        /// Output CSV file
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Recompress an existing recording, e.g. at a higher compression level
+    Optimize {
+        /// Input file to recompress
+        #[arg(short, long)]
+        input: String,
+
+        /// Output file
+        #[arg(short, long)]
+        output: String,
+
+        /// Compression level [0-9]
+        #[arg(long, default_value_t = 9)]
+        level: u32,
+
+        /// Passphrase of an encrypted recording, the output is encrypted with it as well
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Only copy the first frames of the recording
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        limit_frames: Option<u64>,
+
+        /// Skip over corrupt frames instead of failing, to repair a damaged recording
+        #[arg(long)]
+        salvage: bool,
+
+        /// Only copy the frames from this time of day as HH:MM[:SS], e.g. 14:32:00, or offset
+        /// from the start of the recording, e.g. +90s or +1:30, on
+        #[arg(long, value_parser = commands::optimize::parse_time_point)]
+        since: Option<commands::optimize::TimePoint>,
+
+        /// Only copy the frames up to this time of day as HH:MM[:SS], e.g. 14:35:30, or
+        /// offset from the start of the recording, e.g. 4m45s or +4:45.5
+        #[arg(long, value_parser = commands::optimize::parse_time_point)]
+        until: Option<commands::optimize::TimePoint>,
+    },
+    /// Split an iRacing recording into one file per complete lap
+    SplitLaps {
+        /// Input file to split
+        #[arg(short, long)]
+        input: String,
+
+        /// Directory to write lap files to
+        #[arg(short, long)]
+        output_dir: String,
+    },
+    /// Export channels of an iRacing recording as SRT subtitles for video overlays
+    Srt {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+
+        /// Output SRT file
+        #[arg(short, long)]
+        output: String,
+
+        /// Comma separated channels to show, e.g. Speed,Gear,RPM
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            required_unless_present = "channels_file"
+        )]
+        channels: Vec<String>,
+
+        /// File with the channels to show, one per line, `#` starts a comment. Added after
+        /// the channels given with --channels
+        #[arg(long)]
+        channels_file: Option<String>,
+
+        /// One cue per lap instead of one per second
+        #[arg(long)]
+        per_lap: bool,
+    },
+    /// Convert a recording to the sim independent channels for other tools
+    Convert {
+        /// Input file to read
+        #[arg(short, long)]
+        input: String,
+
+        /// Output file
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: commands::convert::ConvertFormat,
+    },
+    /// Write a recording with synthetic data, for testing apps without a real recording
+    Generate {
+        /// Sim to generate data for
+        #[arg(long, value_enum)]
+        sim: commands::generate::Sim,
+
+        /// Length of the recording in seconds
+        #[arg(long, default_value_t = 60)]
+        seconds: u32,
+
+        /// Frames per second
+        #[arg(long, default_value_t = 60)]
+        fps: u32,
+
+        /// Output file
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Copy the shared memory of a running sim to a mapping with another name in real time,
+    /// for testing a second consumer against its own copy
+    Mirror {
+        /// Sim to mirror
+        #[arg(long, value_enum)]
+        sim: commands::mirror::MirrorSim,
+
+        /// Name of the mapping to write the copy to, e.g. Local\KsanaMirror
+        #[arg(long)]
+        to_name: String,
+
+        /// How often to poll the sim for new data [1-1000]
+        #[arg(
+            long,
+            default_value_t = 120,
+            value_parser = clap::value_parser!(u32).range(1..=1000)
+        )]
+        poll_hz: u32,
     },
+    /// Build a recording from a CSV file with one channel per column and one frame per row
+    Import {
+        /// CSV file to read, with a header row of channel names
+        #[arg(long)]
+        csv: String,
+
+        /// Sim to build the recording for, only iRacing is supported
+        #[arg(long, value_enum)]
+        sim: commands::generate::Sim,
+
+        /// Frames per second, one frame per row
+        #[arg(long, default_value_t = 60)]
+        fps: u32,
+
+        /// Output file
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Print the start of a shared memory mapping in hex with guesses at the values, for
+    /// working out the layout of a sim
+    Probe {
+        /// Name of the mapping, e.g. Local\IRSDKMemMapFileName
+        #[arg(short, long)]
+        name: String,
+
+        /// Number of bytes to read from the start of the mapping [1-1048576]
+        #[arg(
+            short,
+            long,
+            default_value_t = commands::probe::DEFAULT_PROBE_BYTES,
+            value_parser = clap::value_parser!(u32).range(1..=1048576)
+        )]
+        bytes: u32,
+    },
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(exit) => exit.into(),
+        Err(e) => {
+            // as anyhow prints an error returned from main, with its causes
+            eprintln!("Error: {:?}", e);
+            exit::for_error(&e).into()
+        }
+    }
 }
 
-fn main() -> anyhow::Result<()> {
+fn run() -> anyhow::Result<Exit> {
     let cli = Cli::parse();
 
     let should_quit = Arc::new(AtomicBool::new(false));
     let quit_flag = should_quit.clone();
 
     ctrlc::set_handler(move || {
-        should_quit.store(true, Ordering::Relaxed);
+        // the first press stops gracefully, a second one gets out of a stuck flush
+        if should_quit.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "\nCtrl+C received again. Exiting immediately, the output may be incomplete."
+            );
+            std::process::exit(130);
+        }
         println!("\nCtrl+C received. Stopping... Please wait patiently.");
+        println!("Press Ctrl+C again to exit immediately.");
     })?;
 
+    let mut exit = Exit::Clean;
     match cli.command.unwrap_or(Commands::Record {
-        fps: 5,
+        fps: None,
+        poll_hz: None,
         max_duration: None,
+        append: None,
+        encrypt: false,
+        key: None,
+        limit_frames: None,
+        no_compress: false,
+        io_buffer_mb: commands::DEFAULT_IO_BUFFER_MB,
+        flush_every: None,
+        packet_filter: None,
+        validate_offsets: false,
+        map_name: None,
+        frame_flags: false,
+        raw_sidecar: false,
+        connect_poll_ms: commands::record::DEFAULT_CONNECT_POLL_MS,
+        connect_backoff_max_ms: None,
+        verify_on_stop: false,
+        sim: None,
+        prefer: None,
     }) {
-        Commands::Record { fps, max_duration } => {
-            commands::record::run(quit_flag, fps.clamp(1, 60), max_duration)?;
+        Commands::Record {
+            fps,
+            poll_hz,
+            max_duration,
+            append,
+            encrypt,
+            key,
+            limit_frames,
+            no_compress,
+            io_buffer_mb,
+            flush_every,
+            packet_filter,
+            validate_offsets,
+            map_name,
+            frame_flags,
+            raw_sidecar,
+            connect_poll_ms,
+            connect_backoff_max_ms,
+            verify_on_stop,
+            sim,
+            prefer,
+        } => {
+            let options = commands::record::RecordOptions {
+                fps: fps.unwrap_or(commands::record::Fps::Unset),
+                poll_hz,
+                max_duration,
+                append,
+                key: if encrypt { key } else { None },
+                limit_frames,
+                no_compress,
+                io_buffer_mb,
+                flush_every,
+                packet_filter,
+                validate_offsets,
+                map_name,
+                frame_flags,
+                raw_sidecar,
+                connect_poll: commands::record::ConnectPoll {
+                    interval_ms: connect_poll_ms,
+                    backoff_max_ms: connect_backoff_max_ms,
+                },
+                verify_on_stop,
+                sim,
+                prefer,
+            };
+            exit = commands::record::run(quit_flag, options)?.into();
+        }
+        Commands::Play {
+            input,
+            key,
+            follow,
+            salvage,
+            io_buffer_mb,
+            resequence,
+            interpolate,
+            keepalive_hz,
+            max_fps,
+            player,
+            player_log,
+            force,
+            map_name,
+            event_name,
+            tolerant,
+            max_update_failures,
+            sync_event,
+            quit_timeout_ms,
+            start_paused,
+        } => {
+            let options = commands::play::PlayOptions {
+                key,
+                follow,
+                salvage,
+                io_buffer_mb,
+                resequence,
+                interpolate: interpolate.unwrap_or(1),
+                keepalive_hz,
+                max_fps,
+                player,
+                player_log,
+                force,
+                map_name,
+                event_name,
+                tolerant: tolerant.then_some(max_update_failures),
+                sync_event,
+                quit_timeout_ms,
+                start_paused,
+            };
+            commands::play::run(quit_flag, &input, options)?;
+        }
+        Commands::PlayMulti {
+            inputs,
+            targets,
+            ignore_start_times,
+        } => {
+            commands::play_multi::run(quit_flag, &inputs, &targets, ignore_start_times)?;
+        }
+        Commands::Doctor { input, key } => {
+            commands::doctor::run(&input, key.as_deref())?;
+        }
+        Commands::Inspect { input, histogram } => {
+            commands::inspect::run(&input, histogram)?;
+        }
+        Commands::Header { input } => {
+            commands::header::run(&input)?;
+        }
+        Commands::ListChannels { input } => {
+            commands::list_channels::run(&input)?;
+        }
+        Commands::ChangedChannels { input } => {
+            commands::changed_channels::run(&input)?;
+        }
+        Commands::SessionInfo { input, output } => {
+            commands::session_info::run(&input, &output)?;
+        }
+        Commands::TrackMap { input, output } => {
+            commands::track_map::run(&input, &output)?;
+        }
+        Commands::Optimize {
+            input,
+            output,
+            level,
+            key,
+            limit_frames,
+            salvage,
+            since,
+            until,
+        } => {
+            let window = commands::optimize::TimeWindow { since, until };
+            commands::optimize::run(
+                &input,
+                &output,
+                level.min(9),
+                key,
+                limit_frames,
+                salvage,
+                window,
+            )?;
+        }
+        Commands::SplitLaps { input, output_dir } => {
+            commands::split_laps::run(&input, &output_dir)?;
+        }
+        Commands::Srt {
+            input,
+            output,
+            mut channels,
+            channels_file,
+            per_lap,
+        } => {
+            if let Some(path) = channels_file {
+                channels.extend(commands::srt::read_channels_file(&path)?);
+            }
+            commands::srt::run(&input, &output, &channels, per_lap)?;
+        }
+        Commands::Convert {
+            input,
+            output,
+            format,
+        } => {
+            commands::convert::run(&input, &output, format)?;
+        }
+        Commands::Generate {
+            sim,
+            seconds,
+            fps,
+            output,
+        } => {
+            commands::generate::run(sim, seconds, fps, &output)?;
+        }
+        Commands::Mirror {
+            sim,
+            to_name,
+            poll_hz,
+        } => {
+            exit = commands::mirror::run(quit_flag, sim, &to_name, poll_hz)?.into();
         }
-        Commands::Play { input } => {
-            commands::play::run(quit_flag, &input)?;
+        Commands::Import {
+            csv,
+            sim,
+            fps,
+            output,
+        } => {
+            commands::import::run(&csv, sim, fps, &output)?;
         }
-        Commands::Inspect { input } => {
-            commands::inspect::run(&input)?;
+        Commands::Probe { name, bytes } => {
+            commands::probe::run(&name, bytes as usize)?;
         }
     }
 
-    Ok(())
+    Ok(exit)
 }
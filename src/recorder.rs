@@ -0,0 +1,306 @@
+//! The recording loop, saving the frames of a connected sim at a fixed rate. The `ksana`
+//! binary records with it; embedders can drive it with their own connector and output, e.g.
+//! to show the frames live in a GUI while they are recorded.
+
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::broadcast::FrameBroadcast;
+use crate::io::{FrameFlags, IOError, Saver};
+use crate::traits::{Clock, Connector, Pacing};
+
+#[derive(thiserror::Error, Debug)]
+pub enum RecordingError {
+    #[error("Failed to save frame: {0}")]
+    SavingFrameFailed(#[from] IOError),
+}
+
+pub enum RecordingFinished {
+    SimDisconnected,
+    QuitRequested,
+    MaxDurationReached,
+    FrameLimitReached,
+}
+
+/// How often the recording is flushed to disk while recording, bounding what a crash of
+/// the process or the machine loses to what was saved since.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlushEvery {
+    Frames(u64),
+    Duration(Duration),
+}
+
+impl FlushEvery {
+    /// Whether a flush is due `frames` saved frames and `elapsed` after the last one.
+    fn is_due(self, frames: u64, elapsed: Duration) -> bool {
+        match self {
+            FlushEvery::Frames(every) => frames >= every,
+            FlushEvery::Duration(every) => elapsed >= every,
+        }
+    }
+}
+
+/// Limits of a recording and where else its frames go, see `record`.
+#[derive(Default)]
+pub struct LoopOptions {
+    /// Stop once this much time passed
+    pub max_duration: Option<Duration>,
+    /// Stop after this many frames were saved
+    pub limit_frames: Option<u64>,
+    /// Flush the output periodically instead of only when the write buffer is full
+    pub flush_every: Option<FlushEvery>,
+    /// Also hands every saved frame to in-process consumers, e.g. a GUI
+    pub broadcast: Option<Arc<FrameBroadcast>>,
+}
+
+/// Where saved frames go: the recording and optionally a sidecar with the raw shared
+/// memory of every frame, the nth sidecar frame belonging to the nth recorded frame.
+pub struct Output<W: Write> {
+    pub saver: Saver<W>,
+    pub raw_sidecar: Option<Saver<W>>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn flush(&mut self) -> Result<(), IOError> {
+        self.saver.flush()?;
+        if let Some(raw_sidecar) = &mut self.raw_sidecar {
+            raw_sidecar.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Flushes the output when recording unwinds from a panic, so the frames saved so far
+/// still load by a linear scan.
+struct FlushOnUnwind<'a, W: Write>(&'a mut Output<W>);
+
+impl<W: Write> Drop for FlushOnUnwind<'_, W> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let _ = self.0.flush();
+        }
+    }
+}
+
+/// Save and poll rates resolved for the connected sim.
+pub struct Rates {
+    pub fps: u32,
+    pub poll_hz: u32,
+}
+
+// the wall clock moving this much more or less than the monotonic clock was adjusted
+const CLOCK_JUMP_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Watches the wall clock against the monotonic clock while recording. Frames are timed from
+/// the start time by their index, so an adjustment of the wall clock, e.g. an NTP sync,
+/// doesn't move them, but times printed or taken from the clock afterwards are off by it.
+struct ClockJumps {
+    instant: Instant,
+    wall_time: SystemTime,
+}
+
+impl ClockJumps {
+    fn new(clock: &dyn Clock) -> Self {
+        Self {
+            instant: clock.now(),
+            wall_time: clock.wall_time(),
+        }
+    }
+
+    /// Seconds the wall clock moved since the last check beyond the time that passed,
+    /// negative if it was set back, or None if within `CLOCK_JUMP_TOLERANCE`.
+    fn check(&mut self, clock: &dyn Clock) -> Option<f64> {
+        let (instant, wall_time) = (clock.now(), clock.wall_time());
+        let passed = instant.duration_since(self.instant).as_secs_f64();
+        let moved = match wall_time.duration_since(self.wall_time) {
+            Ok(forward) => forward.as_secs_f64(),
+            Err(back) => -back.duration().as_secs_f64(),
+        };
+        self.instant = instant;
+        self.wall_time = wall_time;
+
+        let jump = moved - passed;
+        (jump.abs() >= CLOCK_JUMP_TOLERANCE.as_secs_f64()).then_some(jump)
+    }
+}
+
+/// Saves the frames of the connected `connector` to `output` at `rates.fps` until quit is
+/// requested, the sim stops sending data or a limit of `options` is reached. Polls the
+/// connector `rates.poll_hz` times per second and saves the latest data of every tick.
+/// The output is flushed as `options.flush_every` asks, and when unwinding from a panic,
+/// but not finalized.
+pub fn record<W: Write>(
+    quit_flag: &AtomicBool,
+    options: &LoopOptions,
+    rates: &Rates,
+    connector: &mut dyn Connector,
+    output: &mut Output<W>,
+    pacing: Pacing,
+) -> Result<RecordingFinished, RecordingError> {
+    let guard = FlushOnUnwind(output);
+    let output = &mut *guard.0;
+    let tick_ms = 1000.0 / rates.fps as f64;
+    let poll_ms = 1000.0 / rates.poll_hz.max(rates.fps) as f64;
+    let mut no_data_count = 0;
+    let max_no_data = 20; // disconnect after ~20 frames with no data
+    let mut saved_frames: u64 = 0;
+    let mut flushed_frames: u64 = 0;
+    let mut last_flush = pacing.clock.now();
+
+    // the first frame of every recording, also one appended to an existing file, has to be
+    // complete to be playable, so none of it may be left out as unchanged
+    connector.reset();
+
+    let start = pacing.clock.now();
+    let mut clock_jumps = ClockJumps::new(pacing.clock);
+
+    while !quit_flag.load(Ordering::Relaxed) {
+        if let Some(max_dur) = options.max_duration
+            && pacing.clock.now().duration_since(start) >= max_dur
+        {
+            return Ok(RecordingFinished::MaxDurationReached);
+        }
+
+        if let Some(jump) = clock_jumps.check(pacing.clock) {
+            eprintln!(
+                "Warning: the system clock was adjusted by {:+.1}s, frame times stay relative to the start of the recording",
+                jump
+            );
+        }
+
+        let start = pacing.clock.now();
+
+        // poll faster than the save rate until the tick ends, keeping the latest frame so the
+        // saved one is as fresh as the sim's data at the end of the tick
+        let mut frame: Option<Vec<u8>> = None;
+        loop {
+            let poll_start = pacing.clock.now();
+            if let Some(newer) = connector.update() {
+                frame = Some(match frame.take() {
+                    Some(dropped) => connector.merge(dropped, newer),
+                    None => newer,
+                });
+            }
+            let elapsed_ms = pacing.elapsed_ms(start);
+            if elapsed_ms + poll_ms >= tick_ms || quit_flag.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let poll_elapsed_ms = pacing.elapsed_ms(poll_start);
+            if poll_elapsed_ms < poll_ms {
+                pacing.sleeper.sleep_ms((poll_ms - poll_elapsed_ms) as u64);
+            }
+        }
+
+        match frame {
+            Some(data) => {
+                let flags = FrameFlags {
+                    missed_ticks: u16::try_from(no_data_count).unwrap_or(u16::MAX),
+                };
+                no_data_count = 0;
+                if let Err(e) = output.saver.save_with_flags(&data, flags) {
+                    return Err(RecordingError::SavingFrameFailed(e));
+                }
+                if let Some(raw_sidecar) = &mut output.raw_sidecar {
+                    // an empty frame keeps the sidecar in step if the mapping went away
+                    let raw = connector.peek_raw().unwrap_or_default();
+                    raw_sidecar.save(&raw)?;
+                }
+                if let Some(broadcast) = &options.broadcast {
+                    broadcast.send(&data);
+                }
+                saved_frames += 1;
+                if let Some(every) = options.flush_every
+                    && every.is_due(
+                        saved_frames - flushed_frames,
+                        pacing.clock.now().duration_since(last_flush),
+                    )
+                {
+                    output.flush()?;
+                    flushed_frames = saved_frames;
+                    last_flush = pacing.clock.now();
+                }
+                if options
+                    .limit_frames
+                    .is_some_and(|limit| saved_frames >= limit)
+                {
+                    return Ok(RecordingFinished::FrameLimitReached);
+                }
+            }
+            None => {
+                no_data_count += 1;
+                if no_data_count > max_no_data {
+                    return Ok(RecordingFinished::SimDisconnected);
+                }
+            }
+        }
+
+        let elapsed_ms = pacing.elapsed_ms(start);
+        if elapsed_ms < tick_ms {
+            pacing.sleeper.sleep_ms((tick_ms - elapsed_ms) as u64);
+        }
+    }
+
+    Ok(RecordingFinished::QuitRequested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Clock whose times only change when set.
+    struct SetClock {
+        now: Cell<Instant>,
+        wall_time: Cell<SystemTime>,
+    }
+
+    impl SetClock {
+        fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+            self.wall_time.set(self.wall_time.get() + duration);
+        }
+    }
+
+    impl Clock for SetClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn wall_time(&self) -> SystemTime {
+            self.wall_time.get()
+        }
+    }
+
+    #[test]
+    fn test_clock_jumps() {
+        let clock = SetClock {
+            now: Cell::new(Instant::now()),
+            wall_time: Cell::new(SystemTime::now()),
+        };
+        let mut jumps = ClockJumps::new(&clock);
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(jumps.check(&clock), None);
+
+        // set back by an NTP sync while a second passed
+        clock.advance(Duration::from_secs(1));
+        clock
+            .wall_time
+            .set(clock.wall_time() - Duration::from_secs(5));
+        assert_eq!(jumps.check(&clock), Some(-5.0));
+        // reported once, the next check counts from the adjusted time
+        assert_eq!(jumps.check(&clock), None);
+
+        clock
+            .wall_time
+            .set(clock.wall_time() + Duration::from_millis(2500));
+        assert_eq!(jumps.check(&clock), Some(2.5));
+        clock
+            .wall_time
+            .set(clock.wall_time() + Duration::from_millis(300));
+        assert_eq!(jumps.check(&clock), None);
+    }
+}
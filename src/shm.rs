@@ -3,28 +3,105 @@ use std::ptr::NonNull;
 
 use thiserror::Error;
 
-use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0};
+use windows::Win32::Security::{GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation};
 use windows::Win32::System::Memory::{
-    CreateFileMappingA, FILE_MAP_READ, FILE_MAP_WRITE, MEMORY_MAPPED_VIEW_ADDRESS, MapViewOfFile,
-    OpenFileMappingA, PAGE_READWRITE, UnmapViewOfFile,
+    CreateFileMappingA, FILE_MAP_READ, FILE_MAP_WRITE, MEMORY_BASIC_INFORMATION,
+    MEMORY_MAPPED_VIEW_ADDRESS, MapViewOfFile, OpenFileMappingA, PAGE_READWRITE, UnmapViewOfFile,
+    VirtualQuery,
+};
+use windows::Win32::System::Threading::{
+    CreateEventA, EVENT_MODIFY_STATE, GetCurrentProcess, OpenEventA, OpenProcessToken,
+    SYNCHRONIZATION_SYNCHRONIZE, SetEvent, WaitForSingleObject,
 };
-use windows::Win32::System::Threading::{CreateEventA, SetEvent};
 use windows::core::PCSTR;
 
+// one page, the mapping of a probe only has to exist
+const PROBE_SIZE: usize = 4096;
+
+/// Errors carry the Windows error of the failed call, its message and code tell e.g. a
+/// mapping that doesn't exist (0x80070002) from one that may not be opened (0x80070005).
 #[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum SharedMemoryError {
-    #[error("Failed to open shared memory '{name}': not found or inaccessible")]
-    OpenFailed { name: String },
+    #[error("Invalid name '{name}': contains a null character")]
+    InvalidName { name: String },
+
+    #[error("Failed to open shared memory '{name}': {error}")]
+    OpenFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+
+    #[error("Failed to create shared memory '{name}': {error}")]
+    CreateFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+
+    #[error("Failed to map view of shared memory '{name}': {error}")]
+    MapFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+
+    #[error("Failed to create event '{name}': {error}")]
+    EventCreateFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+
+    #[error("Failed to open event '{name}': {error}")]
+    EventOpenFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+}
 
-    #[error("Failed to create shared memory '{name}'")]
-    CreateFailed { name: String },
+/// Whether a mapping named `name` exists, i.e. some process created it and still holds it
+/// open, like a running sim or an app reading it.
+pub fn mapping_exists(name: &str) -> bool {
+    let Ok(name_cstr) = CString::new(name) else {
+        return false;
+    };
+    let handle = unsafe {
+        OpenFileMappingA(
+            FILE_MAP_READ.0,
+            false,
+            PCSTR::from_raw(name_cstr.as_ptr() as *const u8),
+        )
+    };
+    match handle {
+        Ok(handle) => {
+            unsafe { CloseHandle(handle).ok() };
+            true
+        }
+        Err(_) => false,
+    }
+}
 
-    #[error("Failed to map view of shared memory '{name}'")]
-    MapFailed { name: String },
+/// Whether the process runs elevated, i.e. as administrator with UAC. Mappings created by
+/// an elevated process can only be opened by other elevated processes.
+pub fn is_elevated() -> bool {
+    let mut token = HANDLE::default();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.is_err() {
+        return false;
+    }
 
-    #[error("Failed to create event '{name}'")]
-    EventCreateFailed { name: String },
+    let mut elevation = TOKEN_ELEVATION::default();
+    let mut returned = 0u32;
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut _),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut returned,
+        )
+    };
+    unsafe { CloseHandle(token).ok() };
+
+    result.is_ok() && elevation.TokenIsElevated != 0
 }
 
 /// A read-only view into shared memory created by another process.
@@ -35,8 +112,11 @@ pub struct SharedMemoryReader {
 }
 
 impl SharedMemoryReader {
+    /// Opens the mapping `name` expected to be `size` bytes. The size is capped at what is
+    /// actually mapped, a stale mapping of the same name left by another program may be
+    /// smaller than expected.
     pub fn open(name: &str, size: usize) -> Result<Self, SharedMemoryError> {
-        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::OpenFailed {
+        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::InvalidName {
             name: name.to_string(),
         })?;
 
@@ -48,16 +128,40 @@ impl SharedMemoryReader {
                 PCSTR::from_raw(name_cstr.as_ptr() as *const u8),
             )
         }
-        .map_err(|_| SharedMemoryError::OpenFailed {
+        .map_err(|error| SharedMemoryError::OpenFailed {
             name: name.to_string(),
+            error,
         })?;
 
         let view = unsafe { MapViewOfFile(handle, FILE_MAP_READ, 0, 0, 0) };
 
         if view.Value.is_null() {
+            let error = windows::core::Error::from_thread();
             unsafe { CloseHandle(handle).ok() };
             return Err(SharedMemoryError::MapFailed {
                 name: name.to_string(),
+                error,
+            });
+        }
+
+        // the view spans the whole mapping rounded up to pages, reading past it faults
+        let mut info = MEMORY_BASIC_INFORMATION::default();
+        let queried = unsafe {
+            VirtualQuery(
+                Some(view.Value as *const _),
+                &mut info,
+                size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if queried == 0 {
+            let error = windows::core::Error::from_thread();
+            unsafe {
+                UnmapViewOfFile(view).ok();
+                CloseHandle(handle).ok();
+            }
+            return Err(SharedMemoryError::MapFailed {
+                name: name.to_string(),
+                error,
             });
         }
 
@@ -65,15 +169,34 @@ impl SharedMemoryReader {
             handle,
             #[allow(clippy::unwrap_used)]  // safe because we checked for null above
             view: NonNull::new(view.Value as *mut u8).unwrap(),
-            size,
+            size: size.min(info.RegionSize),
         })
     }
 
+    /// Like `open`, but retries up to `attempts` times with `delay_ms` between attempts
+    /// while the mapping doesn't exist yet, e.g. when the sim is still starting up.
+    pub fn open_with_retry(
+        name: &str,
+        size: usize,
+        attempts: u32,
+        delay_ms: u64,
+    ) -> Result<Self, SharedMemoryError> {
+        let mut attempt = 1;
+        loop {
+            match Self::open(name, size) {
+                Err(SharedMemoryError::OpenFailed { .. }) if attempt < attempts => {
+                    std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
     pub fn as_ptr(&self) -> *const u8 {
         self.view.as_ptr()
     }
 
-    #[allow(dead_code)]
     pub fn size(&self) -> usize {
         self.size
     }
@@ -99,7 +222,7 @@ pub struct SharedMemoryWriter {
 
 impl SharedMemoryWriter {
     pub fn create(name: &str, size: usize) -> Result<Self, SharedMemoryError> {
-        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::CreateFailed {
+        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::InvalidName {
             name: name.to_string(),
         })?;
 
@@ -113,16 +236,19 @@ impl SharedMemoryWriter {
                 PCSTR::from_raw(name_cstr.as_ptr() as *const u8),
             )
         }
-        .map_err(|_| SharedMemoryError::CreateFailed {
+        .map_err(|error| SharedMemoryError::CreateFailed {
             name: name.to_string(),
+            error,
         })?;
 
         let view = unsafe { MapViewOfFile(handle, FILE_MAP_WRITE, 0, 0, size) };
 
         if view.Value.is_null() {
+            let error = windows::core::Error::from_thread();
             unsafe { CloseHandle(handle).ok() };
             return Err(SharedMemoryError::MapFailed {
                 name: name.to_string(),
+                error,
             });
         }
 
@@ -138,6 +264,16 @@ impl SharedMemoryWriter {
         })
     }
 
+    /// Creates the mapping `name` and drops it right away, to tell whether a player could
+    /// create it. A mapping that already exists is left alone and `Ok(false)` returned:
+    /// creating it would open it and zero the data of whoever created it.
+    pub fn probe(name: &str) -> Result<bool, SharedMemoryError> {
+        if mapping_exists(name) {
+            return Ok(false);
+        }
+        Self::create(name, PROBE_SIZE).map(|_| true)
+    }
+
     pub unsafe fn write(&mut self, offset: usize, data: &[u8]) {
         debug_assert!(offset + data.len() <= self.size);
         unsafe {
@@ -149,7 +285,6 @@ impl SharedMemoryWriter {
         }
     }
 
-    #[allow(dead_code)]
     pub fn size(&self) -> usize {
         self.size
     }
@@ -173,7 +308,7 @@ pub struct EventHandle {
 
 impl EventHandle {
     pub fn create(name: &str) -> Result<Self, SharedMemoryError> {
-        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::EventCreateFailed {
+        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::InvalidName {
             name: name.to_string(),
         })?;
 
@@ -185,8 +320,30 @@ impl EventHandle {
                 PCSTR::from_raw(name_cstr.as_ptr() as *const u8),
             )
         }
-        .map_err(|_| SharedMemoryError::EventCreateFailed {
+        .map_err(|error| SharedMemoryError::EventCreateFailed {
             name: name.to_string(),
+            error,
+        })?;
+
+        Ok(Self { handle })
+    }
+
+    /// Opens the event `name` created by another process, for waiting on it and signaling it.
+    pub fn open(name: &str) -> Result<Self, SharedMemoryError> {
+        let name_cstr = CString::new(name).map_err(|_| SharedMemoryError::InvalidName {
+            name: name.to_string(),
+        })?;
+
+        let handle = unsafe {
+            OpenEventA(
+                SYNCHRONIZATION_SYNCHRONIZE | EVENT_MODIFY_STATE,
+                false,
+                PCSTR::from_raw(name_cstr.as_ptr() as *const u8),
+            )
+        }
+        .map_err(|error| SharedMemoryError::EventOpenFailed {
+            name: name.to_string(),
+            error,
         })?;
 
         Ok(Self { handle })
@@ -195,6 +352,12 @@ impl EventHandle {
     pub fn signal(&self) {
         unsafe { SetEvent(self.handle).ok() };
     }
+
+    /// Waits up to `timeout_ms` for the event to be signaled, true if it was. An auto-reset
+    /// event is reset by the wait that returns true.
+    pub fn wait(&self, timeout_ms: u32) -> bool {
+        unsafe { WaitForSingleObject(self.handle, timeout_ms) == WAIT_OBJECT_0 }
+    }
 }
 
 impl Drop for EventHandle {
@@ -209,6 +372,33 @@ impl Drop for EventHandle {
 mod tests {
     use super::*;
 
+    #[test]
+    #[cfg(not(miri))]
+    fn test_mapping_exists() {
+        let name = "Local\\KsanaTestShmExists";
+        assert!(!mapping_exists(name));
+        let writer = SharedMemoryWriter::create(name, 64).unwrap();
+        assert!(mapping_exists(name));
+        drop(writer);
+        assert!(!mapping_exists(name));
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_probe() {
+        let name = "Local\\KsanaTestShmProbe";
+        assert!(SharedMemoryWriter::probe(name).unwrap());
+        // the probe doesn't keep the mapping
+        assert!(!mapping_exists(name));
+
+        let mut writer = SharedMemoryWriter::create(name, 64).unwrap();
+        unsafe { writer.write(0, b"kept") };
+        assert!(!SharedMemoryWriter::probe(name).unwrap());
+        let reader = SharedMemoryReader::open(name, 64).unwrap();
+        let data = unsafe { std::slice::from_raw_parts(reader.as_ptr(), 4) };
+        assert_eq!(data, b"kept");
+    }
+
     #[test]
     #[cfg(not(miri))]
     fn test_create_and_read_shared_memory() {
@@ -263,10 +453,64 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(not(miri))]
+    fn test_open_with_retry() {
+        let name = "Local\\KsanaTestShmRetry";
+        let size = 1024;
+        let (created_tx, created_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+
+        let creator = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let _writer = SharedMemoryWriter::create(name, size).unwrap();
+            created_tx.send(()).unwrap();
+            done_rx.recv().ok();
+        });
+
+        let reader = SharedMemoryReader::open_with_retry(name, size, 50, 10);
+        assert!(reader.is_ok());
+
+        created_rx.recv().unwrap();
+        done_tx.send(()).unwrap();
+        creator.join().unwrap();
+
+        let result = SharedMemoryReader::open_with_retry("Local\\NonexistentShm12345", size, 3, 1);
+        assert!(matches!(result, Err(SharedMemoryError::OpenFailed { .. })));
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_open_event() {
+        let name = "Local\\KsanaTestEvent";
+        assert!(matches!(
+            EventHandle::open(name),
+            Err(SharedMemoryError::EventOpenFailed { .. })
+        ));
+
+        let created = EventHandle::create(name).unwrap();
+        let opened = EventHandle::open(name).unwrap();
+        assert!(!opened.wait(0));
+        created.signal();
+        assert!(opened.wait(0));
+        // auto-reset by the wait
+        assert!(!opened.wait(0));
+        opened.signal();
+        assert!(created.wait(0));
+    }
+
     #[test]
     #[cfg(not(miri))]
     fn test_open_nonexistent_fails() {
         let result = SharedMemoryReader::open("Local\\NonexistentShm12345", 1024);
-        assert!(matches!(result, Err(SharedMemoryError::OpenFailed { .. })));
+        let code = match result {
+            Err(SharedMemoryError::OpenFailed { error, .. }) => Some(error.code().0 as u32),
+            _ => None,
+        };
+        // ERROR_FILE_NOT_FOUND
+        assert_eq!(code, Some(0x80070002));
+
+        let result = SharedMemoryReader::open("Local\\Bad\0Name", 1024);
+        assert!(matches!(result, Err(SharedMemoryError::InvalidName { .. })));
     }
 }
@@ -5,6 +5,7 @@ use crate::sims::ac::data::{AC_OFF, FrameData, GraphicsLike, PhysicsLike, Static
 pub struct Connector<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
     reader: Option<SharedMemoryReader<G, P, S>>,
     prev_statics: Option<S>,
+    last_packet_id: Option<i32>,
     graphics_name: &'static str,
     physics_name: &'static str,
     static_name: &'static str,
@@ -23,6 +24,7 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> Connector<G, P, S> {
         Self {
             reader: None,
             prev_statics: None,
+            last_packet_id: None,
             graphics_name,
             physics_name,
             static_name,
@@ -49,12 +51,18 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> crate::Connector for Connec
         }
 
         self.reader = Some(reader);
+        self.last_packet_id = None;
         true
     }
 
     fn disconnect(&mut self) {
         self.reader = None;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
         self.prev_statics = None;
+        self.last_packet_id = None;
     }
 
     fn update(&mut self) -> Option<Vec<u8>> {
@@ -65,6 +73,12 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> crate::Connector for Connec
             return None;
         }
 
+        if self.last_packet_id == Some(graphics.packet_id()) {
+            // No new data
+            return None;
+        }
+        self.last_packet_id = Some(graphics.packet_id());
+
         let physics = reader.read_physics();
         let statics = reader.read_statics();
 
@@ -82,10 +96,135 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> crate::Connector for Connec
         Some(frame.serialize())
     }
 
+    fn merge(&self, dropped: Vec<u8>, newer: Vec<u8>) -> Vec<u8> {
+        let (Ok(dropped), Ok(mut frame)) = (
+            FrameData::<G, P, S>::deserialize(&dropped, self.payload_version),
+            FrameData::<G, P, S>::deserialize(&newer, self.payload_version),
+        ) else {
+            return newer;
+        };
+        if dropped.statics.is_none() || frame.statics.is_some() {
+            return newer;
+        }
+        frame.statics = dropped.statics;
+        frame.serialize()
+    }
+
     fn info(&self) -> SimInfo {
         SimInfo {
             id: self.sim_id,
             payload_version: self.payload_version,
+            native_hz: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connector as _;
+    use crate::sims::ac::data::{GraphicsPage, PhysicsPage, StaticPage};
+    use crate::sims::ac::shmio::SharedMemoryWriter;
+
+    type TestGraphics = GraphicsPage<1024>;
+    type TestPhysics = PhysicsPage<512>;
+    type TestStatic = StaticPage<256>;
+
+    type FrameData = crate::sims::ac::data::FrameData<TestGraphics, TestPhysics, TestStatic>;
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_repeated_packet_id_skipped() {
+        let names = (
+            "Local\\KsanaTestAcConnectorGraphics",
+            "Local\\KsanaTestAcConnectorPhysics",
+            "Local\\KsanaTestAcConnectorStatic",
+        );
+
+        let mut writer = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            names.0, names.1, names.2,
+        )
+        .unwrap();
+        let mut connector = Connector::<TestGraphics, TestPhysics, TestStatic>::new(
+            names.0, names.1, names.2, *b"test", 2,
+        );
+
+        let mut frame = FrameData::default();
+        frame.graphics.status = 2;
+        frame.graphics.packet_id = 1;
+        writer.update(&frame.serialize(), 2).unwrap();
+
+        assert!(connector.connect());
+        assert!(connector.update().is_some());
+        assert!(connector.update().is_none());
+        assert!(connector.update().is_none());
+
+        frame.graphics.packet_id = 2;
+        writer.update(&frame.serialize(), 2).unwrap();
+        assert!(connector.update().is_some());
+        assert!(connector.update().is_none());
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_reset_returns_full_frame() {
+        let names = (
+            "Local\\KsanaTestAcConnectorResetGraphics",
+            "Local\\KsanaTestAcConnectorResetPhysics",
+            "Local\\KsanaTestAcConnectorResetStatic",
+        );
+
+        let mut writer = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            names.0, names.1, names.2,
+        )
+        .unwrap();
+        let mut connector = Connector::<TestGraphics, TestPhysics, TestStatic>::new(
+            names.0, names.1, names.2, *b"test", 2,
+        );
+
+        let mut frame = FrameData::default();
+        frame.graphics.status = 2;
+        frame.graphics.packet_id = 1;
+        writer.update(&frame.serialize(), 2).unwrap();
+
+        assert!(connector.connect());
+        let first = FrameData::deserialize(&connector.update().unwrap(), 2).unwrap();
+        assert!(first.statics.is_some());
+        assert!(connector.update().is_none());
+
+        // the same packet again, with the statics as they are unchanged as well
+        connector.reset();
+        let after_reset = FrameData::deserialize(&connector.update().unwrap(), 2).unwrap();
+        assert_eq!(after_reset.graphics.packet_id, 1);
+        assert!(after_reset.statics.is_some());
+        assert!(connector.update().is_none());
+    }
+
+    #[test]
+    fn test_merge_keeps_dropped_statics() {
+        let connector = Connector::<TestGraphics, TestPhysics, TestStatic>::new(
+            "graphics", "physics", "static", *b"test", 2,
+        );
+        let frame = |packet_id: i32, statics: Option<TestStatic>| {
+            let mut frame = FrameData {
+                statics,
+                ..Default::default()
+            };
+            frame.graphics.packet_id = packet_id;
+            frame.serialize()
+        };
+        let mut statics = TestStatic::default();
+        statics.content[0] = 7;
+
+        let merged = connector.merge(frame(1, Some(statics)), frame(2, None));
+        let merged = FrameData::deserialize(&merged, 2).unwrap();
+        assert_eq!(merged.graphics.packet_id, 2);
+        assert_eq!(merged.statics, Some(statics));
+
+        // the statics of the newer frame are the current ones
+        let mut newer_statics = statics;
+        newer_statics.content[0] = 8;
+        let merged = connector.merge(frame(1, Some(statics)), frame(2, Some(newer_statics)));
+        assert_eq!(merged, frame(2, Some(newer_statics)));
+    }
+}
@@ -3,8 +3,12 @@
 //! Not intended for direct use by external code.
 
 use std::io;
+use std::ops::RangeInclusive;
+
+use crate::sims::interpolate::{lerp, lerp_angle, lerp_counter};
 
 pub const AC_OFF: i32 = 0;
+pub const AC_LIVE: i32 = 2;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -54,6 +58,102 @@ impl<const PADDING: usize> Default for StaticPage<PADDING> {
     }
 }
 
+// The static page starts with the shared memory and the game versions as 15 wchar strings
+const VERSION_LEN: usize = 15;
+const SM_VERSION_OFFSET: usize = 0;
+const AC_VERSION_OFFSET: usize = SM_VERSION_OFFSET + VERSION_LEN * 2;
+
+impl<const PADDING: usize> StaticPage<PADDING> {
+    pub fn sm_version_str(&self) -> String {
+        self.version_str(SM_VERSION_OFFSET)
+    }
+
+    pub fn ac_version_str(&self) -> String {
+        self.version_str(AC_VERSION_OFFSET)
+    }
+
+    /// Decodes the UTF-16 version string at `offset` up to the null terminator.
+    fn version_str(&self, offset: usize) -> String {
+        let Some(bytes) = self.content.get(offset..offset + VERSION_LEN * 2) else {
+            return String::new();
+        };
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&u| u != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+fn f32_at(content: &[u8], offset: usize) -> f32 {
+    content
+        .get(offset..offset + 4)
+        .map_or(0.0, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn i32_at(content: &[u8], offset: usize) -> i32 {
+    content
+        .get(offset..offset + 4)
+        .map_or(0, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// SPageFilePhysics starts with packetId, gas, brake, fuel, gear, rpms, steerAngle and speedKmh,
+// the same in all AC games
+const PHYSICS_GAS_OFFSET: usize = 4;
+const PHYSICS_BRAKE_OFFSET: usize = 8;
+const PHYSICS_GEAR_OFFSET: usize = 16;
+const PHYSICS_RPMS_OFFSET: usize = 20;
+const PHYSICS_STEER_ANGLE_OFFSET: usize = 24;
+const PHYSICS_SPEED_KMH_OFFSET: usize = 28;
+
+impl<const PADDING: usize> PhysicsPage<PADDING> {
+    pub fn gas(&self) -> f32 {
+        f32_at(&self.content, PHYSICS_GAS_OFFSET)
+    }
+
+    pub fn brake(&self) -> f32 {
+        f32_at(&self.content, PHYSICS_BRAKE_OFFSET)
+    }
+
+    /// 0 is reverse, 1 neutral
+    pub fn gear(&self) -> i32 {
+        i32_at(&self.content, PHYSICS_GEAR_OFFSET)
+    }
+
+    pub fn rpms(&self) -> i32 {
+        i32_at(&self.content, PHYSICS_RPMS_OFFSET)
+    }
+
+    /// Steering input, -1 to 1
+    pub fn steer_angle(&self) -> f32 {
+        f32_at(&self.content, PHYSICS_STEER_ANGLE_OFFSET)
+    }
+
+    pub fn speed_kmh(&self) -> f32 {
+        f32_at(&self.content, PHYSICS_SPEED_KMH_OFFSET)
+    }
+}
+
+// 4 byte slots of SPageFilePhysics holding ints (gear, flags, enums and counts) in AC and
+// ACC, every other slot up to the end of the ACC struct is a float. Slot 0 is packetId
+const PHYSICS_INT_SLOTS: [usize; 24] = [
+    0, 4, 5, 61, 62, 66, 79, 80, 81, 82, 83, 85, 86, 104, 145, 146, 147, 168, 169, 183, 184, 193,
+    194, 195,
+];
+// heading, pitch and roll, in radians
+const PHYSICS_ANGLE_SLOTS: [usize; 3] = [52, 53, 54];
+
+// SPageFileGraphic after packetId and status: session, 4 time strings of 15 wchars, then
+// completedLaps
+const GRAPHICS_COMPLETED_LAPS_OFFSET: usize = 4 + 4 * 30;
+
+impl<const PADDING: usize> GraphicsPage<PADDING> {
+    pub fn completed_laps(&self) -> i32 {
+        i32_at(&self.content, GRAPHICS_COMPLETED_LAPS_OFFSET)
+    }
+}
+
 // All sim frame payloads begin with a 16-byte frame header: 1 byte type + 15 bytes reserved.
 // This is the standard across all sims and allows future extension without a file version bump.
 const FRAME_TYPE_WITH_STATICS: u8 = 0x01;
@@ -66,11 +166,17 @@ impl<const PADDING: usize> SimPage for PhysicsPage<PADDING> {}
 impl<const PADDING: usize> SimPage for GraphicsPage<PADDING> {}
 impl<const PADDING: usize> SimPage for StaticPage<PADDING> {}
 
-// We need to be able to read the AC status without knowing the exact page type
+// We need to be able to read the AC status and packet id without knowing the exact page type
 pub trait GraphicsLike: SimPage {
     fn status(&self) -> i32;
+    fn packet_id(&self) -> i32;
+    fn set_packet_id(&mut self, packet_id: i32);
+}
+pub trait PhysicsLike: SimPage {
+    /// Page at `t` between this page and `next`, for playing at a multiple of the
+    /// recorded rate.
+    fn interpolate(&self, next: &Self, t: f64) -> Self;
 }
-pub trait PhysicsLike: SimPage {}
 
 // We need to be able to detect if the Static page changed, so need to be able to compare it
 pub trait StaticLike: SimPage + PartialEq {}
@@ -79,8 +185,41 @@ impl<const PADDING: usize> GraphicsLike for GraphicsPage<PADDING> {
     fn status(&self) -> i32 {
         self.status
     }
+
+    fn packet_id(&self) -> i32 {
+        self.packet_id
+    }
+
+    fn set_packet_id(&mut self, packet_id: i32) {
+        self.packet_id = packet_id;
+    }
+}
+impl<const PADDING: usize> PhysicsLike for PhysicsPage<PADDING> {
+    fn interpolate(&self, next: &Self, t: f64) -> Self {
+        let mut page = *self;
+        for (slot, (value, next)) in page
+            .content
+            .chunks_exact_mut(4)
+            .zip(next.content.chunks_exact(4))
+            .enumerate()
+        {
+            let a = f32_at(value, 0) as f64;
+            let b = f32_at(next, 0) as f64;
+            let blended = if slot == 0 {
+                // packetId
+                lerp_counter(i32_at(value, 0), i32_at(next, 0), t).to_le_bytes()
+            } else if PHYSICS_INT_SLOTS.contains(&slot) {
+                continue;
+            } else if PHYSICS_ANGLE_SLOTS.contains(&slot) {
+                (lerp_angle(a, b, t) as f32).to_le_bytes()
+            } else {
+                (lerp(a, b, t) as f32).to_le_bytes()
+            };
+            value.copy_from_slice(&blended);
+        }
+        page
+    }
 }
-impl<const PADDING: usize> PhysicsLike for PhysicsPage<PADDING> {}
 impl<const PADDING: usize> StaticLike for StaticPage<PADDING> {}
 
 pub struct FrameData<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
@@ -110,6 +249,30 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> FrameData<G, P, S> {
         size_of::<S>()
     }
 
+    /// Size the player creates the shared memory mappings with, the largest of the pages.
+    /// Readers map only their own page, so a larger real mapping is fine.
+    pub const fn mapping_size() -> usize {
+        let mut size = Self::graphics_size();
+        if Self::physics_size() > size {
+            size = Self::physics_size();
+        }
+        if Self::static_size() > size {
+            size = Self::static_size();
+        }
+        size
+    }
+
+    /// Sizes a serialized frame can have, without and with the static page.
+    pub fn serialized_size_range(payload_version: i32) -> RangeInclusive<usize> {
+        let header_size = if payload_version >= 2 {
+            FRAME_HEADER_SIZE
+        } else {
+            0
+        };
+        let min = header_size + Self::graphics_size() + Self::physics_size();
+        min..=min + Self::static_size()
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         let total_size = if self.statics.is_some() {
             FRAME_HEADER_SIZE + Self::graphics_size() + Self::physics_size() + Self::static_size()
@@ -156,6 +319,8 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> FrameData<G, P, S> {
         buffer
     }
 
+    /// Parses a frame written by `serialize`. The frame must hold the full size of every
+    /// page it contains, shorter frames fail with `InvalidData` before any page is copied.
     pub fn deserialize(bytes: &[u8], payload_version: i32) -> io::Result<Self> {
         let (has_statics, data_offset) = if payload_version >= 2 {
             if bytes.len() < FRAME_HEADER_SIZE {
@@ -189,7 +354,10 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> FrameData<G, P, S> {
             (has_statics, 0)
         };
 
-        let min_size = data_offset + Self::graphics_size() + Self::physics_size();
+        let mut min_size = data_offset + Self::graphics_size() + Self::physics_size();
+        if has_statics {
+            min_size += Self::static_size();
+        }
         if bytes.len() < min_size {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -255,6 +423,43 @@ mod tests {
         assert_eq!(GRAPHICS_STATUS_OFFSET, 4);
     }
 
+    #[test]
+    fn test_physics_interpolate() {
+        let page = |packet_id: i32, gas: f32, gear: i32, heading: f32| {
+            let mut page = P::default();
+            page.content[0..4].copy_from_slice(&packet_id.to_le_bytes());
+            page.content[PHYSICS_GAS_OFFSET..PHYSICS_GAS_OFFSET + 4]
+                .copy_from_slice(&gas.to_le_bytes());
+            page.content[PHYSICS_GEAR_OFFSET..PHYSICS_GEAR_OFFSET + 4]
+                .copy_from_slice(&gear.to_le_bytes());
+            page.content[52 * 4..53 * 4].copy_from_slice(&heading.to_le_bytes());
+            page
+        };
+
+        let between = page(100, 0.2, 3, 3.0).interpolate(&page(110, 0.6, 4, -3.0), 0.5);
+        assert_eq!(i32_at(&between.content, 0), 105);
+        assert!((between.gas() - 0.4).abs() < 1e-6);
+        assert_eq!(between.gear(), 3);
+        let heading = f32_at(&between.content, 52 * 4);
+        assert!((heading.abs() - std::f32::consts::PI).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_version_strings() {
+        let mut statics = S::default();
+        for (i, unit) in "1.7".encode_utf16().enumerate() {
+            statics.content[i * 2..i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        for (i, unit) in "1.16".encode_utf16().enumerate() {
+            let offset = AC_VERSION_OFFSET + i * 2;
+            statics.content[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+
+        assert_eq!(statics.sm_version_str(), "1.7");
+        assert_eq!(statics.ac_version_str(), "1.16");
+        assert_eq!(StaticPage::<8>::default().ac_version_str(), "");
+    }
+
     #[test]
     fn test_default_frame_data_is_zero() {
         let frame = Frame::default();
@@ -282,6 +487,30 @@ mod tests {
         assert!(deserialized.statics.is_none());
     }
 
+    #[test]
+    fn test_serialized_size_range() {
+        let mut frame = Frame::default();
+        let range = Frame::serialized_size_range(2);
+        assert_eq!(*range.start(), frame.serialize().len());
+        frame.statics = Some(S::default());
+        assert_eq!(*range.end(), frame.serialize().len());
+
+        let v1 = Frame::serialized_size_range(1);
+        assert_eq!(*v1.start(), Frame::graphics_size() + Frame::physics_size());
+    }
+
+    #[test]
+    fn test_deserialize_truncated_statics() {
+        let frame = Frame {
+            statics: Some(S::default()),
+            ..Default::default()
+        };
+        let bytes = frame.serialize();
+
+        let result = Frame::deserialize(&bytes[..bytes.len() - 1], 2);
+        assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+
     #[test]
     fn test_deserialize_v1_backward_compat() {
         // Simulate a v1 recording: raw struct bytes with no frame header,
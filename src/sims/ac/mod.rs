@@ -1,4 +1,5 @@
 pub mod connector;
-pub mod data;
 pub mod player;
 pub mod shmio;
+
+pub use ksana::sims::ac::data;
@@ -1,9 +1,15 @@
-use super::data::{GraphicsLike, PhysicsLike, StaticLike};
+use std::ops::RangeInclusive;
+
+use super::data::{FrameData, GraphicsLike, PhysicsLike, StaticLike};
 use super::shmio::SharedMemoryWriter;
+use crate::sims::interpolate::lerp_counter;
 
 pub struct Player<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
     writer: SharedMemoryWriter<G, P, S>,
     payload_version: i32,
+    resequence: bool,
+    /// Packet id of the last frame written when resequencing
+    last_packet_id: Option<i32>,
 }
 
 impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> Player<G, P, S> {
@@ -11,16 +17,234 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> Player<G, P, S> {
         Self {
             writer,
             payload_version,
+            resequence: false,
+            last_packet_id: None,
         }
     }
+
+    /// Rewrites the packet ids to count up by one from the first frame, for consumers that
+    /// flag gaps left by skipped or trimmed frames as dropped data.
+    pub fn set_resequence(&mut self, resequence: bool) {
+        self.resequence = resequence;
+        self.last_packet_id = None;
+    }
 }
 
 impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> crate::Player for Player<G, P, S> {
     fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
-        self.writer.update(data, self.payload_version)
+        if !self.resequence {
+            return self.writer.update(data, self.payload_version);
+        }
+
+        let mut frame = FrameData::<G, P, S>::deserialize(data, self.payload_version)?;
+        let packet_id = match self.last_packet_id {
+            Some(last) => last.wrapping_add(1),
+            None => frame.graphics.packet_id(),
+        };
+        frame.graphics.set_packet_id(packet_id);
+        self.last_packet_id = Some(packet_id);
+        self.writer.write(&frame);
+        Ok(())
     }
 
     fn stop(&mut self) {
         self.writer.stop()
     }
+
+    fn expected_frame_size(&self) -> Option<RangeInclusive<usize>> {
+        Some(FrameData::<G, P, S>::serialized_size_range(
+            self.payload_version,
+        ))
+    }
+
+    fn interpolate(&self, previous: &[u8], next: &[u8], t: f64) -> anyhow::Result<Option<Vec<u8>>> {
+        // payload version 1 frames have no frame header to mark a frame without the static
+        // data, so a synthesized frame couldn't be played back
+        if self.payload_version < 2 {
+            return Ok(None);
+        }
+
+        let previous = FrameData::<G, P, S>::deserialize(previous, self.payload_version)?;
+        let next = FrameData::<G, P, S>::deserialize(next, self.payload_version)?;
+
+        // the graphics page is mostly timing, flags and strings, only the packet id moves on
+        let mut graphics = previous.graphics;
+        graphics.set_packet_id(lerp_counter(
+            previous.graphics.packet_id(),
+            next.graphics.packet_id(),
+            t,
+        ));
+        let frame = FrameData::<G, P, S> {
+            graphics,
+            physics: previous.physics.interpolate(&next.physics, t),
+            statics: None,
+        };
+        Ok(Some(frame.serialize()))
+    }
+}
+
+// Make sure readers don't see a running sim after playback ends, even on a panic
+impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> Drop for Player<G, P, S> {
+    fn drop(&mut self) {
+        self.writer.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sims::ac::data::{AC_OFF, FrameData, GraphicsPage, PhysicsPage, StaticPage};
+    use crate::sims::ac::shmio::SharedMemoryReader;
+
+    type TestGraphics = GraphicsPage<1024>;
+    type TestPhysics = PhysicsPage<512>;
+    type TestStatic = StaticPage<256>;
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_drop_writes_ac_off() {
+        let id = format!("KsanaTestAcPlayerDrop-{}", std::process::id());
+        let names = [
+            format!("{}-graphics", id),
+            format!("{}-physics", id),
+            format!("{}-static", id),
+        ];
+
+        let writer = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+        let mut player = Player::from_writer(writer, 2);
+
+        let reader = SharedMemoryReader::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+
+        let mut frame = FrameData::<TestGraphics, TestPhysics, TestStatic>::default();
+        frame.graphics.status = 2;
+        crate::Player::update(&mut player, &frame.serialize()).unwrap();
+        assert_eq!(reader.read_graphics().status, 2);
+
+        drop(player);
+        assert_eq!(reader.read_graphics().status, AC_OFF);
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_resequence_packet_ids() {
+        let id = format!("KsanaTestAcPlayerResequence-{}", std::process::id());
+        let names = [
+            format!("{}-graphics", id),
+            format!("{}-physics", id),
+            format!("{}-static", id),
+        ];
+
+        let writer = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+        let mut player = Player::from_writer(writer, 2);
+
+        let reader = SharedMemoryReader::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+
+        let mut frame = FrameData::<TestGraphics, TestPhysics, TestStatic>::default();
+        let mut play = |player: &mut Player<_, _, _>, packet_id| {
+            frame.graphics.packet_id = packet_id;
+            crate::Player::update(player, &frame.serialize()).unwrap();
+            reader.read_graphics().packet_id
+        };
+
+        // original ids by default
+        assert_eq!(play(&mut player, 100), 100);
+        assert_eq!(play(&mut player, 250), 250);
+
+        // gaps and jumps back are smoothed out from the first frame on
+        player.set_resequence(true);
+        let played: Vec<i32> = [10, 50, 51, 7, i32::MAX]
+            .into_iter()
+            .map(|packet_id| play(&mut player, packet_id))
+            .collect();
+        assert_eq!(played, [10, 11, 12, 13, 14]);
+    }
+
+    /// Fake sim played back to other mappings frame by frame after recording, comparing the
+    /// pages a client reads from each.
+    #[test]
+    #[cfg(not(miri))]
+    fn test_record_play_round_trip() {
+        use crate::Connector as _;
+        use crate::io::{Loader, Saver};
+        use crate::sims::ac::connector::Connector;
+        use std::io::Cursor;
+
+        let id = format!("KsanaTestAcRoundTrip-{}", std::process::id());
+        // the connector takes static names, the sim mappings only live as long as the test
+        let sim_names = (
+            "KsanaTestAcRoundTripSimGraphics",
+            "KsanaTestAcRoundTripSimPhysics",
+            "KsanaTestAcRoundTripSimStatic",
+        );
+        let names = [
+            format!("{}-graphics", id),
+            format!("{}-physics", id),
+            format!("{}-static", id),
+        ];
+        let mut sim = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            sim_names.0,
+            sim_names.1,
+            sim_names.2,
+        )
+        .unwrap();
+        let mut connector = Connector::<TestGraphics, TestPhysics, TestStatic>::new(
+            sim_names.0,
+            sim_names.1,
+            sim_names.2,
+            *b"acsa",
+            2,
+        );
+
+        // the statics only change once, frames after the first leave them out
+        let mut frame = FrameData::<TestGraphics, TestPhysics, TestStatic>::default();
+        frame.graphics.status = 2;
+        let mut statics = TestStatic::default();
+        statics.content[..5].copy_from_slice(b"monza");
+        frame.statics = Some(statics);
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 60, connector.info()).unwrap();
+        let mut recorded = vec![];
+        for packet_id in 1..=3 {
+            frame.graphics.packet_id = packet_id;
+            frame.graphics.content[0] = packet_id as u8;
+            frame.physics.content.fill(packet_id as u8 * 7);
+            sim.update(&frame.serialize(), 2).unwrap();
+            if packet_id == 1 {
+                assert!(connector.connect());
+            }
+            saver.save(&connector.update().unwrap()).unwrap();
+            recorded.push((frame.graphics, frame.physics, frame.statics));
+        }
+        saver.finalize().unwrap();
+
+        let writer = SharedMemoryWriter::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+        let mut player = Player::from_writer(writer, 2);
+        let reader = SharedMemoryReader::<TestGraphics, TestPhysics, TestStatic>::new(
+            &names[0], &names[1], &names[2],
+        )
+        .unwrap();
+        let mut loader = Loader::new(Cursor::new(&recording)).unwrap();
+        for (graphics, physics, statics) in recorded {
+            crate::Player::update(&mut player, &loader.load().unwrap().unwrap()).unwrap();
+            assert_eq!(reader.read_graphics(), graphics);
+            assert_eq!(reader.read_physics(), physics);
+            assert_eq!(Some(reader.read_statics()), statics);
+        }
+        assert_eq!(loader.load().unwrap(), None);
+    }
 }
@@ -1,4 +1,5 @@
 use std::marker::PhantomData;
+use std::sync::atomic::{Ordering, fence};
 
 use crate::shm::SharedMemoryReader as ShmReader;
 use crate::shm::SharedMemoryWriter as ShmWriter;
@@ -6,6 +7,39 @@ use crate::sims::ac::data::FrameData;
 
 use super::data::{GraphicsLike, PhysicsLike, StaticLike};
 
+// The pages are created one after another by the sim, so once the graphics page exists the
+// other two are given a moment to appear
+const PAGE_OPEN_ATTEMPTS: u32 = 5;
+const PAGE_OPEN_DELAY_MS: u64 = 100;
+
+// AC rewrites a page within microseconds, retrying a torn copy without a pause is enough
+const READ_ATTEMPTS: u32 = 16;
+
+/// Packet id at the start of the graphics and the physics page, which the sim increments
+/// with every update of the page.
+fn packet_id(shm: &ShmReader) -> i32 {
+    unsafe { std::ptr::read_volatile(shm.as_ptr() as *const i32) }
+}
+
+/// Copies a page with `read` between two reads of its packet id with `packet_id`. The copy
+/// is torn if the id changed, as the sim updated the page meanwhile, then it is retried. The
+/// sim doesn't mark a write in progress, so a copy that is torn without the id changing
+/// goes unnoticed, and after `READ_ATTEMPTS` the last copy is returned anyway.
+fn read_consistent<T>(packet_id: impl Fn() -> i32, read: impl Fn() -> T) -> T {
+    let mut attempts = 1;
+    loop {
+        let before = packet_id();
+        fence(Ordering::Acquire);
+        let page = read();
+        fence(Ordering::Acquire);
+        if packet_id() == before || attempts == READ_ATTEMPTS {
+            return page;
+        }
+        attempts += 1;
+        std::hint::spin_loop();
+    }
+}
+
 pub struct SharedMemoryReader<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
     graphics_shm: ShmReader,
     physics_shm: ShmReader,
@@ -18,8 +52,20 @@ pub struct SharedMemoryReader<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
 impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> SharedMemoryReader<G, P, S> {
     pub fn new(graphics_name: &str, physics_name: &str, static_name: &str) -> Option<Self> {
         let graphics = ShmReader::open(graphics_name, size_of::<G>()).ok()?;
-        let physics = ShmReader::open(physics_name, size_of::<P>()).ok()?;
-        let statics = ShmReader::open(static_name, size_of::<S>()).ok()?;
+        let physics = ShmReader::open_with_retry(
+            physics_name,
+            size_of::<P>(),
+            PAGE_OPEN_ATTEMPTS,
+            PAGE_OPEN_DELAY_MS,
+        )
+        .ok()?;
+        let statics = ShmReader::open_with_retry(
+            static_name,
+            size_of::<S>(),
+            PAGE_OPEN_ATTEMPTS,
+            PAGE_OPEN_DELAY_MS,
+        )
+        .ok()?;
 
         Some(Self {
             graphics_shm: graphics,
@@ -32,17 +78,17 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> SharedMemoryReader<G, P, S>
     }
 
     pub fn read_graphics(&self) -> G {
-        unsafe {
-            let ptr = self.graphics_shm.as_ptr() as *const G;
-            std::ptr::read(ptr)
-        }
+        read_consistent(
+            || packet_id(&self.graphics_shm),
+            || unsafe { std::ptr::read(self.graphics_shm.as_ptr() as *const G) },
+        )
     }
 
     pub fn read_physics(&self) -> P {
-        unsafe {
-            let ptr = self.physics_shm.as_ptr() as *const P;
-            std::ptr::read(ptr)
-        }
+        read_consistent(
+            || packet_id(&self.physics_shm),
+            || unsafe { std::ptr::read(self.physics_shm.as_ptr() as *const P) },
+        )
     }
 
     pub fn read_statics(&self) -> S {
@@ -64,9 +110,10 @@ pub struct SharedMemoryWriter<G: GraphicsLike, P: PhysicsLike, S: StaticLike> {
 
 impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> SharedMemoryWriter<G, P, S> {
     pub fn new(graphics_name: &str, physics_name: &str, static_name: &str) -> Option<Self> {
-        let graphics = ShmWriter::create(graphics_name, size_of::<G>()).ok()?;
-        let physics = ShmWriter::create(physics_name, size_of::<P>()).ok()?;
-        let statics = ShmWriter::create(static_name, size_of::<S>()).ok()?;
+        let size = FrameData::<G, P, S>::mapping_size();
+        let graphics = ShmWriter::create(graphics_name, size).ok()?;
+        let physics = ShmWriter::create(physics_name, size).ok()?;
+        let statics = ShmWriter::create(static_name, size).ok()?;
 
         Some(Self {
             graphics_shm: Some(graphics),
@@ -79,14 +126,19 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> SharedMemoryWriter<G, P, S>
     }
 
     pub fn update(&mut self, data: &[u8], payload_version: i32) -> anyhow::Result<()> {
+        let frame = FrameData::<G, P, S>::deserialize(data, payload_version)?;
+        self.write(&frame);
+        Ok(())
+    }
+
+    /// Writes the pages of a parsed frame, the static page only if the frame has one.
+    pub fn write(&mut self, frame: &FrameData<G, P, S>) {
         let graphics_shm = self
             .graphics_shm
             .as_mut()
             .expect("Graphics not initialized");
         let physics_shm = self.physics_shm.as_mut().expect("Physics not initialized");
 
-        let frame = FrameData::<G, P, S>::deserialize(data, payload_version)?;
-
         unsafe {
             // graphics
             let graphics_bytes = std::slice::from_raw_parts(
@@ -113,7 +165,6 @@ impl<G: GraphicsLike, P: PhysicsLike, S: StaticLike> SharedMemoryWriter<G, P, S>
                 static_shm.write(0, statics_bytes);
             }
         }
-        Ok(())
     }
 
     pub fn stop(&mut self) {
@@ -154,6 +205,43 @@ mod tests {
         nanos ^ (pid << 32)
     }
 
+    #[test]
+    fn test_read_consistent_retries_torn_copies() {
+        use crate::sims::ac::shmio::{READ_ATTEMPTS, read_consistent};
+        use std::cell::Cell;
+
+        // the sim updates the page during the first copy
+        let id = Cell::new(1);
+        let copies = Cell::new(0);
+        let page = read_consistent(
+            || id.get(),
+            || {
+                copies.set(copies.get() + 1);
+                let copy = (id.get(), copies.get());
+                if copies.get() == 1 {
+                    id.set(2);
+                }
+                copy
+            },
+        );
+        assert_eq!(page, (2, 2));
+
+        // a page that never holds still is returned after the last attempt
+        let id = Cell::new(0);
+        let copies = Cell::new(0);
+        let page = read_consistent(
+            || {
+                id.set(id.get() + 1);
+                id.get()
+            },
+            || {
+                copies.set(copies.get() + 1);
+                copies.get()
+            },
+        );
+        assert_eq!(page, READ_ATTEMPTS);
+    }
+
     #[test]
     #[cfg(not(miri))]
     fn test_read_write() {
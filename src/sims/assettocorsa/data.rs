@@ -1,21 +1,37 @@
+pub use crate::sims::ac::data::AC_LIVE;
+use crate::sims::ac::data::FrameData as AcFrameData;
 use crate::sims::ac::data::GraphicsPage as AcGraphicsPage;
 use crate::sims::ac::data::PhysicsPage as AcPhysicsPage;
 use crate::sims::ac::data::StaticPage as AcStaticPage;
 
 pub const CURRENT_PAYLOAD_VERSION: i32 = 2;
 
-pub type PhysicsPage = AcPhysicsPage<1024>; // padded with some headroom, real sizeof in AC is 568 bytes, ACC 800 bytes
+pub type PhysicsPage = AcPhysicsPage<1024>; // padded with some headroom
 pub type GraphicsPage = AcGraphicsPage<2040>; // 8 bytes for packet_id and status
-pub type StaticPage = AcStaticPage<2048>; // padded with some headroom, real sizeof in AC is 1044, ACC 1336
+pub type StaticPage = AcStaticPage<2048>; // padded with some headroom
+pub type FrameData = AcFrameData<GraphicsPage, PhysicsPage, StaticPage>;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Size of the shared memory mappings the player creates, see `FrameData::mapping_size`.
+pub const MAPPING_SIZE: usize = FrameData::mapping_size();
 
-    #[test]
-    fn test_header_sizes() {
-        assert_eq!(size_of::<PhysicsPage>(), 1024);
-        assert_eq!(size_of::<GraphicsPage>(), 2048);
-        assert_eq!(size_of::<StaticPage>(), 2048);
+// Real sizeof of the pages in the supported games, AC and ACC respectively
+pub const PHYSICS_SIZES: [usize; 2] = [568, 800];
+pub const GRAPHICS_SIZES: [usize; 2] = [296, 1588];
+pub const STATIC_SIZES: [usize; 2] = [1044, 1336];
+
+// The page sizes are part of the recording format, changing them breaks reading existing
+// recordings. Pages smaller than the game structs would silently truncate the recorded
+// data, so growing a game struct past the padding must fail the build instead
+const _: () = {
+    assert!(size_of::<PhysicsPage>() == 1024);
+    assert!(size_of::<GraphicsPage>() == 2048);
+    assert!(size_of::<StaticPage>() == 2048);
+
+    let mut i = 0;
+    while i < PHYSICS_SIZES.len() {
+        assert!(PHYSICS_SIZES[i] <= size_of::<PhysicsPage>());
+        assert!(GRAPHICS_SIZES[i] <= size_of::<GraphicsPage>());
+        assert!(STATIC_SIZES[i] <= size_of::<StaticPage>());
+        i += 1;
     }
-}
+};
@@ -6,13 +6,15 @@ use crate::sims::ac::shmio::SharedMemoryWriter;
 pub type AssettoCorsaPlayer = AcPlayer<GraphicsPage, PhysicsPage, StaticPage>;
 
 impl AssettoCorsaPlayer {
-    pub fn new(payload_version: i32) -> anyhow::Result<Self> {
+    pub fn new(payload_version: i32, resequence: bool) -> anyhow::Result<Self> {
         let writer = SharedMemoryWriter::<GraphicsPage, PhysicsPage, StaticPage>::new(
             AC_GRAPHICS_SHM,
             AC_PHYSICS_SHM,
             AC_STATIC_SHM,
         )
         .ok_or_else(|| anyhow::anyhow!("Failed to initialize shared memory"))?;
-        Ok(Self::from_writer(writer, payload_version))
+        let mut player = Self::from_writer(writer, payload_version);
+        player.set_resequence(resequence);
+        Ok(player)
     }
 }
@@ -0,0 +1,5 @@
+use super::data::OutGauge;
+use crate::udp::PacketConnector;
+
+/// Receives the OutGauge packets the game sends to its telemetry port.
+pub type BeamNGConnector = PacketConnector<OutGauge>;
@@ -0,0 +1,144 @@
+// BeamNG.drive OutGauge UDP telemetry, enabled in Options > Other > Protocols. The packet is
+// the OutGauge struct of Live for Speed with the optional id field, which BeamNG always
+// sends. A frame is a whole packet.
+
+use std::io;
+
+use crate::udp::SimPacket;
+
+pub const CURRENT_PAYLOAD_VERSION: i32 = 1;
+
+/// Port the game sends to unless configured otherwise
+pub const DEFAULT_PORT: u16 = 4444;
+
+pub const PACKET_SIZE: usize = size_of::<OutGauge>();
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OutGauge {
+    /// Milliseconds, not set by BeamNG
+    pub time: u32,
+    /// Short car name, `beam` for BeamNG
+    pub car: [u8; 4],
+    /// `OG_` bits of the OutGauge protocol, e.g. 16384 when speed is shown in km/h
+    pub flags: u16,
+    /// 0 is reverse, 1 neutral, 2 first gear
+    pub gear: u8,
+    /// Player id, not set by BeamNG
+    pub plid: u8,
+    /// m/s
+    pub speed: f32,
+    pub rpm: f32,
+    /// bar
+    pub turbo: f32,
+    /// °C
+    pub eng_temp: f32,
+    /// 0 to 1
+    pub fuel: f32,
+    /// bar
+    pub oil_pressure: f32,
+    /// °C
+    pub oil_temp: f32,
+    /// Lights available on the dash, `DL_` bits of the OutGauge protocol, e.g. 4 is the
+    /// handbrake
+    pub dash_lights: u32,
+    /// Lights switched on
+    pub show_lights: u32,
+    /// 0 to 1
+    pub throttle: f32,
+    /// 0 to 1
+    pub brake: f32,
+    /// 0 to 1
+    pub clutch: f32,
+    /// Dash texts, nul terminated
+    pub display1: [u8; 16],
+    pub display2: [u8; 16],
+    /// OutGauge id set in the game options
+    pub id: i32,
+}
+
+// The packet is sent as is, any padding would shift the fields
+const _: () = assert!(size_of::<OutGauge>() == 96);
+
+impl OutGauge {
+    pub fn serialize(&self) -> Vec<u8> {
+        unsafe {
+            std::slice::from_raw_parts(self as *const Self as *const u8, PACKET_SIZE).to_vec()
+        }
+    }
+
+    /// Parses a packet, which must have the exact size including the id.
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "OutGauge packet has {} bytes, expected {}",
+                    bytes.len(),
+                    PACKET_SIZE
+                ),
+            ));
+        }
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+impl SimPacket for OutGauge {
+    const SIM_ID: [u8; 4] = *b"bng_";
+    const PAYLOAD_VERSION: i32 = CURRENT_PAYLOAD_VERSION;
+    const DEFAULT_PORT: u16 = DEFAULT_PORT;
+    const SIZE: usize = PACKET_SIZE;
+
+    fn validate(bytes: &[u8]) -> io::Result<()> {
+        Self::deserialize(bytes).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A packet in 3rd gear at 74 km/h with the handbrake light on.
+    fn sample() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // time
+        bytes.extend_from_slice(b"beam");
+        bytes.extend_from_slice(&0xc000u16.to_le_bytes());
+        bytes.extend_from_slice(&[4, 0]); // gear, plid
+        for value in [20.6f32, 5120.0, 0.35, 88.5, 0.62, 3.1, 96.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0x0405u32.to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes()); // handbrake
+        for value in [0.86f32, 0.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"Fuel 62%\0\0\0\0\0\0\0\0");
+        bytes.extend_from_slice(&[0; 16]);
+        bytes.extend_from_slice(&7i32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_decode_sample() {
+        let bytes = sample();
+        assert_eq!(bytes.len(), 96);
+
+        let packet = OutGauge::deserialize(&bytes).unwrap();
+        assert_eq!(&packet.car, b"beam");
+        assert_eq!(packet.flags, 0xc000);
+        assert_eq!(packet.gear, 4);
+        assert_eq!(packet.speed, 20.6);
+        assert_eq!(packet.rpm, 5120.0);
+        assert_eq!(packet.oil_temp, 96.0);
+        assert_eq!(packet.dash_lights, 0x0405);
+        assert_eq!(packet.show_lights, 4);
+        assert_eq!(packet.throttle, 0.86);
+        assert_eq!(&packet.display1[..8], b"Fuel 62%");
+        assert_eq!(packet.id, 7);
+
+        assert_eq!(packet.serialize(), bytes);
+        // Live for Speed leaves out the id unless configured
+        assert!(OutGauge::deserialize(&bytes[..92]).is_err());
+    }
+}
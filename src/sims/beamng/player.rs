@@ -0,0 +1,6 @@
+use super::data::OutGauge;
+use crate::udp::PacketPlayer;
+
+/// Sends the recorded OutGauge packets to the port dashboards and apps listen on, as the
+/// game would.
+pub type BeamNGPlayer = PacketPlayer<OutGauge>;
@@ -0,0 +1,5 @@
+use super::data::Packet;
+use crate::udp::PacketConnector;
+
+/// Receives the packets the game sends to its telemetry port.
+pub type DirtConnector = PacketConnector<Packet>;
@@ -0,0 +1,155 @@
+// DiRT Rally 2.0 UDP telemetry with extradata=3 in hardware_settings_config.xml, also sent by
+// EA SPORTS WRC with its DiRT Rally 2.0 compatible packet structure. A packet is 66 little
+// endian floats, integer values (gear, lap, position) are sent as floats too. A frame is a
+// whole packet.
+
+use std::io;
+
+use crate::udp::SimPacket;
+
+pub const CURRENT_PAYLOAD_VERSION: i32 = 1;
+
+/// Port the games send to unless configured otherwise
+pub const DEFAULT_PORT: u16 = 20777;
+
+pub const PACKET_SIZE: usize = size_of::<Packet>();
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Packet {
+    pub run_time: f32,
+    pub lap_time: f32,
+    pub distance: f32,
+    pub progress: f32,
+    pub position: [f32; 3],
+    pub speed: f32,
+    pub velocity: [f32; 3],
+    pub roll: [f32; 3],
+    pub pitch: [f32; 3],
+    /// Wheel order is rear left, rear right, front left, front right in all wheel arrays
+    pub suspension_position: [f32; 4],
+    pub suspension_velocity: [f32; 4],
+    pub wheel_speed: [f32; 4],
+    pub throttle: f32,
+    pub steering: f32,
+    pub brake: f32,
+    pub clutch: f32,
+    /// -1 is reverse, 0 neutral
+    pub gear: f32,
+    pub g_force_lateral: f32,
+    pub g_force_longitudinal: f32,
+    pub lap: f32,
+    /// Divided by 10
+    pub rpm: f32,
+    pub sli_pro_support: f32,
+    pub race_position: f32,
+    pub kers_level: f32,
+    pub kers_max_level: f32,
+    pub drs: f32,
+    pub traction_control: f32,
+    pub anti_lock_brakes: f32,
+    pub fuel_in_tank: f32,
+    pub fuel_capacity: f32,
+    pub in_pit: f32,
+    pub sector: f32,
+    pub sector_1_time: f32,
+    pub sector_2_time: f32,
+    pub brake_temperature: [f32; 4],
+    pub tyre_pressure: [f32; 4],
+    pub laps_completed: f32,
+    pub total_laps: f32,
+    pub track_length: f32,
+    pub last_lap_time: f32,
+    /// Divided by 10
+    pub max_rpm: f32,
+    /// Divided by 10
+    pub idle_rpm: f32,
+    pub max_gears: f32,
+}
+
+// The packet is sent as is, any padding would shift the fields
+const _: () = assert!(size_of::<Packet>() == 66 * size_of::<f32>());
+
+impl Packet {
+    pub fn serialize(&self) -> Vec<u8> {
+        unsafe {
+            std::slice::from_raw_parts(self as *const Self as *const u8, PACKET_SIZE).to_vec()
+        }
+    }
+
+    /// Parses a packet, which must have the exact extradata=3 size.
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "DiRT packet has {} bytes, expected {} (extradata=3)",
+                    bytes.len(),
+                    PACKET_SIZE
+                ),
+            ));
+        }
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+impl SimPacket for Packet {
+    const SIM_ID: [u8; 4] = *b"dirt";
+    const PAYLOAD_VERSION: i32 = CURRENT_PAYLOAD_VERSION;
+    const DEFAULT_PORT: u16 = DEFAULT_PORT;
+    const SIZE: usize = PACKET_SIZE;
+
+    fn validate(bytes: &[u8]) -> io::Result<()> {
+        Self::deserialize(bytes).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A packet on a stage: 3rd gear at 74 km/h, 1.2km into a 9.8km stage.
+    fn sample() -> Vec<u8> {
+        #[rustfmt::skip]
+        let values: [f32; 66] = [
+            95.25, 94.5, 1204.8, 0.1229, // run time, lap time, distance, progress
+            -812.4, 121.7, 2310.9, // position
+            20.6, // speed
+            12.1, -0.3, 16.6, // velocity
+            0.81, 0.02, -0.58, // roll
+            0.58, -0.04, 0.81, // pitch
+            21.5, 22.0, 18.3, 18.9, // suspension position
+            0.12, -0.08, 0.31, 0.27, // suspension velocity
+            20.9, 20.8, 20.5, 20.6, // wheel speed
+            0.86, -0.12, 0.0, 0.0, 3.0, // throttle, steering, brake, clutch, gear
+            0.35, 0.21, 0.0, 512.0, // g-forces, lap, rpm / 10
+            0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, // sli pro, position, kers, drs, tc, abs
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, // fuel, pit, sector and sector times
+            212.0, 209.5, 305.0, 301.5, // brake temperature
+            0.0, 0.0, 0.0, 0.0, // tyre pressure
+            0.0, 1.0, 9801.3, 0.0, // laps, track length, last lap time
+            780.0, 90.0, 6.0, // max rpm / 10, idle rpm / 10, gears
+        ];
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_decode_sample() {
+        let bytes = sample();
+        assert_eq!(bytes.len(), 264);
+
+        let packet = Packet::deserialize(&bytes).unwrap();
+        assert_eq!(packet.run_time, 95.25);
+        assert_eq!(packet.position, [-812.4, 121.7, 2310.9]);
+        assert_eq!(packet.speed, 20.6);
+        assert_eq!(packet.wheel_speed, [20.9, 20.8, 20.5, 20.6]);
+        assert_eq!(packet.gear, 3.0);
+        assert_eq!(packet.rpm, 512.0);
+        assert_eq!(packet.brake_temperature[2], 305.0);
+        assert_eq!(packet.track_length, 9801.3);
+        assert_eq!(packet.max_gears, 6.0);
+
+        assert_eq!(packet.serialize(), bytes);
+        assert!(Packet::deserialize(&bytes[..256]).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod connector;
+pub mod data;
+pub mod player;
@@ -0,0 +1,5 @@
+use super::data::Packet;
+use crate::udp::PacketPlayer;
+
+/// Sends the recorded packets to the port telemetry apps listen on, as the game would.
+pub type DirtPlayer = PacketPlayer<Packet>;
@@ -0,0 +1,174 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use super::data::{CURRENT_PAYLOAD_VERSION, DEFAULT_PORT, PacketHeader, pack_frame};
+use crate::udp::UdpReceiver;
+use crate::{Connector, SimInfo};
+
+/// Whether `packet` is an F1 packet of a kind in `filter`, of any kind without a filter.
+fn is_packet(packet: &[u8], filter: Option<&[u8]>) -> bool {
+    PacketHeader::deserialize(packet)
+        .is_ok_and(|header| filter.is_none_or(|filter| filter.contains(&header.packet_id)))
+}
+
+/// Receives the packets the game sends to `addr`. The game sends the kinds of packets at
+/// different rates, so a frame holds all packets received since the last update instead of
+/// the latest one. The connection is the socket and ends when the packets stop.
+pub struct F1Connector {
+    receiver: Option<UdpReceiver>,
+    addr: SocketAddr,
+    // packet ids to record, all if None
+    packet_filter: Option<Vec<u8>>,
+    // received while connecting, returned by the first update
+    pending: Vec<Vec<u8>>,
+}
+
+impl F1Connector {
+    pub fn new() -> Self {
+        Self::create((Ipv4Addr::LOCALHOST, DEFAULT_PORT).into())
+    }
+
+    /// Connector recording only the packets with ids in `packet_ids`, the others are dropped
+    /// when received.
+    pub fn with_packet_filter(packet_ids: Vec<u8>) -> Self {
+        Self {
+            packet_filter: Some(packet_ids),
+            ..Self::new()
+        }
+    }
+
+    fn create(addr: SocketAddr) -> Self {
+        Self {
+            receiver: None,
+            addr,
+            packet_filter: None,
+            pending: vec![],
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<Vec<u8>>> {
+        let filter = self.packet_filter.as_deref();
+        self.receiver
+            .as_mut()?
+            .all(|packet| is_packet(packet, filter))
+            .ok()
+    }
+}
+
+impl Default for F1Connector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Connector for F1Connector {
+    fn connect(&mut self) -> bool {
+        if self.receiver.is_none() {
+            // the port may be taken by another telemetry app, retried on the next attempt
+            self.receiver = UdpReceiver::bind(self.addr).ok();
+        }
+
+        match self.receive() {
+            Some(packets) if !packets.is_empty() => {
+                self.pending = packets;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.receiver = None;
+        self.pending.clear();
+    }
+
+    fn update(&mut self) -> Option<Vec<u8>> {
+        let packets = self.receive()?;
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend(packets);
+        (!pending.is_empty()).then(|| pack_frame(&pending))
+    }
+
+    /// Frames hold every packet received, the packets of both are kept.
+    fn merge(&self, mut dropped: Vec<u8>, newer: Vec<u8>) -> Vec<u8> {
+        dropped.extend_from_slice(&newer);
+        dropped
+    }
+
+    fn info(&self) -> SimInfo {
+        SimInfo {
+            id: *b"f1__",
+            payload_version: CURRENT_PAYLOAD_VERSION,
+            native_hz: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sims::f1::data::unpack_frame;
+    use crate::udp::UdpSender;
+
+    /// Header only packet of an F1 2021 game, enough for the connector.
+    fn packet(packet_id: u8) -> Vec<u8> {
+        let mut bytes = 2021u16.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[1, 18, 1, packet_id]);
+        bytes.extend_from_slice(&[0; 18]);
+        bytes
+    }
+
+    #[test]
+    fn test_receives_all_packets() {
+        // a free port, released again for the connector to bind
+        let addr = UdpReceiver::bind((Ipv4Addr::LOCALHOST, 0).into())
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut connector = F1Connector::create(addr);
+        assert!(!connector.connect());
+
+        let game = UdpSender::new(addr).unwrap();
+        game.send(&packet(0)).unwrap();
+        game.send(b"not F1").unwrap();
+        let connected = (0..100).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            connector.connect()
+        });
+        assert!(connected);
+
+        game.send(&packet(6)).unwrap();
+        game.send(&packet(2)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let frame = connector.update().unwrap();
+        let ids: Vec<u8> = unpack_frame(&frame)
+            .unwrap()
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, [0, 6, 2]);
+        assert_eq!(connector.update(), None);
+    }
+
+    #[test]
+    fn test_merge_keeps_all_packets() {
+        let connector = F1Connector::new();
+        let merged = connector.merge(
+            pack_frame(&[packet(0), packet(6)]),
+            pack_frame(&[packet(2)]),
+        );
+        let ids: Vec<u8> = unpack_frame(&merged)
+            .unwrap()
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, [0, 6, 2]);
+    }
+
+    #[test]
+    fn test_packet_filter() {
+        assert!(is_packet(&packet(6), None));
+        assert!(is_packet(&packet(6), Some(&[2, 6])));
+        assert!(!is_packet(&packet(0), Some(&[2, 6])));
+        assert!(!is_packet(b"not F1", None));
+    }
+}
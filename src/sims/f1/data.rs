@@ -0,0 +1,422 @@
+// Codemasters / EA SPORTS F1 UDP telemetry of F1 2020 and later, enabled in Settings >
+// Telemetry Settings. The game sends several kinds of packets, all starting with a common
+// header whose packet id tells the kind, little endian and without padding. Only the header
+// and the car telemetry packet are decoded, the other packets are recorded as they are.
+//
+// A frame holds every packet received since the previous frame, in order, each as:
+// - Packet id: u8, the id of the packet header, so frames can be filtered without parsing
+// - Length: u16 little-endian
+// - Packet: [u8; length]
+
+use std::io::{self, Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+pub const CURRENT_PAYLOAD_VERSION: i32 = 1;
+
+/// Port the games send to unless configured otherwise, the same as DiRT's
+pub const DEFAULT_PORT: u16 = 20777;
+
+pub const PACKET_CAR_TELEMETRY: u8 = 6;
+
+/// Names of the packet ids for `--packet-filter`, as in the F1 packet documentation. Later
+/// games added packets at the end, older games don't send those.
+const PACKET_NAMES: [&str; 16] = [
+    "motion",
+    "session",
+    "lapData",
+    "event",
+    "participants",
+    "carSetups",
+    "carTelemetry",
+    "carStatus",
+    "finalClassification",
+    "lobbyInfo",
+    "carDamage",
+    "sessionHistory",
+    "tyreSets",
+    "motionEx",
+    "timeTrial",
+    "lapPositions",
+];
+
+/// Packet id of a packet name, case insensitive.
+pub fn parse_packet_kind(name: &str) -> Result<u8, String> {
+    PACKET_NAMES
+        .iter()
+        .position(|known| known.eq_ignore_ascii_case(name.trim()))
+        .map(|id| id as u8)
+        .ok_or_else(|| {
+            format!(
+                "unknown packet \"{}\", expected one of: {}",
+                name,
+                PACKET_NAMES.join(", ")
+            )
+        })
+}
+
+/// Cars in the car arrays of the packets, also in games with fewer cars on track
+pub const MAX_CARS: usize = 22;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PacketHeader {
+    /// Year of the game's packet format, e.g. 2021
+    pub packet_format: u16,
+    /// Last two digits of the year, 0 before F1 23
+    pub game_year: u8,
+    pub game_major_version: u8,
+    pub game_minor_version: u8,
+    pub packet_version: u8,
+    pub packet_id: u8,
+    pub session_uid: u64,
+    /// Seconds
+    pub session_time: f32,
+    pub frame_identifier: u32,
+    /// Like `frame_identifier` but not reset by flashbacks, same as it before F1 23
+    pub overall_frame_identifier: u32,
+    pub player_car_index: u8,
+    /// 255 if there is no second player
+    pub secondary_player_car_index: u8,
+}
+
+impl PacketHeader {
+    /// Size of the header in the packets of `packet_format`.
+    pub fn size(packet_format: u16) -> usize {
+        if packet_format >= 2023 { 29 } else { 24 }
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Cursor::new(bytes);
+        let packet_format = reader.read_u16::<LittleEndian>()?;
+        if packet_format < 2020 {
+            return Err(invalid(format!(
+                "F1 packet format {} is not supported, expected 2020 or later",
+                packet_format
+            )));
+        }
+        let recent = packet_format >= 2023;
+
+        let game_year = if recent { reader.read_u8()? } else { 0 };
+        let game_major_version = reader.read_u8()?;
+        let game_minor_version = reader.read_u8()?;
+        let packet_version = reader.read_u8()?;
+        let packet_id = reader.read_u8()?;
+        let session_uid = reader.read_u64::<LittleEndian>()?;
+        let session_time = reader.read_f32::<LittleEndian>()?;
+        let frame_identifier = reader.read_u32::<LittleEndian>()?;
+        let overall_frame_identifier = if recent {
+            reader.read_u32::<LittleEndian>()?
+        } else {
+            frame_identifier
+        };
+
+        Ok(Self {
+            packet_format,
+            game_year,
+            game_major_version,
+            game_minor_version,
+            packet_version,
+            packet_id,
+            session_uid,
+            session_time,
+            frame_identifier,
+            overall_frame_identifier,
+            player_car_index: reader.read_u8()?,
+            secondary_player_car_index: reader.read_u8()?,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CarTelemetry {
+    /// km/h
+    pub speed: u16,
+    /// 0 to 1
+    pub throttle: f32,
+    /// -1 full left to 1 full right
+    pub steer: f32,
+    /// 0 to 1
+    pub brake: f32,
+    /// 0 to 100
+    pub clutch: u8,
+    /// -1 is reverse, 0 neutral
+    pub gear: i8,
+    pub engine_rpm: u16,
+    pub drs: u8,
+    pub rev_lights_percent: u8,
+    /// Bit 0 the leftmost LED, 0 before F1 2021
+    pub rev_lights_bit_value: u16,
+    /// Wheel order is rear left, rear right, front left, front right in all wheel arrays, °C
+    pub brakes_temperature: [u16; 4],
+    /// °C
+    pub tyres_surface_temperature: [u8; 4],
+    /// °C
+    pub tyres_inner_temperature: [u8; 4],
+    /// °C
+    pub engine_temperature: u16,
+    /// PSI
+    pub tyres_pressure: [f32; 4],
+    pub surface_type: [u8; 4],
+}
+
+impl CarTelemetry {
+    fn read(reader: &mut impl Read, packet_format: u16) -> io::Result<Self> {
+        let mut telemetry = Self {
+            speed: reader.read_u16::<LittleEndian>()?,
+            throttle: reader.read_f32::<LittleEndian>()?,
+            steer: reader.read_f32::<LittleEndian>()?,
+            brake: reader.read_f32::<LittleEndian>()?,
+            clutch: reader.read_u8()?,
+            gear: reader.read_i8()?,
+            engine_rpm: reader.read_u16::<LittleEndian>()?,
+            drs: reader.read_u8()?,
+            rev_lights_percent: reader.read_u8()?,
+            ..Default::default()
+        };
+        if packet_format >= 2021 {
+            telemetry.rev_lights_bit_value = reader.read_u16::<LittleEndian>()?;
+        }
+        reader.read_u16_into::<LittleEndian>(&mut telemetry.brakes_temperature)?;
+        reader.read_exact(&mut telemetry.tyres_surface_temperature)?;
+        reader.read_exact(&mut telemetry.tyres_inner_temperature)?;
+        telemetry.engine_temperature = reader.read_u16::<LittleEndian>()?;
+        reader.read_f32_into::<LittleEndian>(&mut telemetry.tyres_pressure)?;
+        reader.read_exact(&mut telemetry.surface_type)?;
+        Ok(telemetry)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CarTelemetryPacket {
+    pub header: PacketHeader,
+    pub car_telemetry: Vec<CarTelemetry>,
+    /// Multi function display panel, 255 if closed
+    pub mfd_panel_index: u8,
+    pub mfd_panel_index_secondary_player: u8,
+    /// 0 if no gear is suggested
+    pub suggested_gear: i8,
+}
+
+impl CarTelemetryPacket {
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        let header = PacketHeader::deserialize(bytes)?;
+        if header.packet_id != PACKET_CAR_TELEMETRY {
+            return Err(invalid(format!(
+                "F1 packet {} is not a car telemetry packet",
+                header.packet_id
+            )));
+        }
+
+        let mut reader = Cursor::new(bytes);
+        reader.set_position(PacketHeader::size(header.packet_format) as u64);
+        let car_telemetry = (0..MAX_CARS)
+            .map(|_| CarTelemetry::read(&mut reader, header.packet_format))
+            .collect::<io::Result<Vec<_>>>()?;
+        if header.packet_format == 2020 {
+            // button status, sent in an event packet since F1 2021
+            reader.read_u32::<LittleEndian>()?;
+        }
+
+        Ok(Self {
+            header,
+            car_telemetry,
+            mfd_panel_index: reader.read_u8()?,
+            mfd_panel_index_secondary_player: reader.read_u8()?,
+            suggested_gear: reader.read_i8()?,
+        })
+    }
+
+    /// Telemetry of the player's car, `None` if the index is out of range.
+    pub fn player(&self) -> Option<&CarTelemetry> {
+        self.car_telemetry
+            .get(usize::from(self.header.player_car_index))
+    }
+}
+
+/// Frame of `packets`, tagged with the packet ids of their headers. Packets without a valid
+/// header or larger than a frame entry can hold are left out.
+pub fn pack_frame(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    for packet in packets {
+        let (Ok(header), Ok(len)) = (
+            PacketHeader::deserialize(packet),
+            u16::try_from(packet.len()),
+        ) else {
+            continue;
+        };
+        frame.push(header.packet_id);
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(packet);
+    }
+    frame
+}
+
+/// Packets of a frame with their packet ids, in the order they were received.
+pub fn unpack_frame(frame: &[u8]) -> io::Result<Vec<(u8, &[u8])>> {
+    let mut packets = vec![];
+    let mut rest = frame;
+    while let [packet_id, len_low, len_high, tail @ ..] = rest {
+        let len = usize::from(u16::from_le_bytes([*len_low, *len_high]));
+        if tail.len() < len {
+            return Err(invalid(format!(
+                "F1 frame ends in packet {}, {} of {} bytes",
+                packet_id,
+                tail.len(),
+                len
+            )));
+        }
+        packets.push((*packet_id, &tail[..len]));
+        rest = &tail[len..];
+    }
+    if !rest.is_empty() {
+        return Err(invalid("F1 frame ends in a packet tag".to_string()));
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET_MOTION: u8 = 0;
+    const PACKET_SESSION: u8 = 1;
+    const PACKET_LAP_DATA: u8 = 2;
+
+    /// Header of an F1 2021 packet at 95.25 seconds into the session, player in car 3.
+    fn header_2021(packet_id: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&2021u16.to_le_bytes());
+        bytes.extend_from_slice(&[1, 18, 1, packet_id]); // game version, packet version and id
+        bytes.extend_from_slice(&0x1234_5678_9abc_def0u64.to_le_bytes());
+        bytes.extend_from_slice(&95.25f32.to_le_bytes());
+        bytes.extend_from_slice(&5715u32.to_le_bytes());
+        bytes.extend_from_slice(&[3, 255]); // player and secondary player car
+        bytes
+    }
+
+    /// Car telemetry of a car in 3rd gear at 211 km/h.
+    fn car_2021(speed: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&speed.to_le_bytes());
+        for value in [0.86f32, -0.12, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 3]); // clutch, gear
+        bytes.extend_from_slice(&10_850u16.to_le_bytes());
+        bytes.extend_from_slice(&[1, 72]); // drs, rev lights percent
+        bytes.extend_from_slice(&0x00ffu16.to_le_bytes());
+        for value in [512u16, 508, 640, 655] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[98, 99, 101, 102]);
+        bytes.extend_from_slice(&[104, 104, 106, 107]);
+        bytes.extend_from_slice(&112u16.to_le_bytes());
+        for value in [21.5f32, 21.5, 23.0, 23.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    fn car_telemetry_2021() -> Vec<u8> {
+        let mut bytes = header_2021(PACKET_CAR_TELEMETRY);
+        for car in 0..MAX_CARS {
+            bytes.extend_from_slice(&car_2021(200 + car as u16));
+        }
+        bytes.extend_from_slice(&[255, 255, 4]); // mfd panels, suggested gear
+        bytes
+    }
+
+    #[test]
+    fn test_header() {
+        let header = PacketHeader::deserialize(&header_2021(PACKET_LAP_DATA)).unwrap();
+        assert_eq!(header.packet_format, 2021);
+        assert_eq!(header.game_year, 0);
+        assert_eq!(header.packet_id, PACKET_LAP_DATA);
+        assert_eq!(header.session_uid, 0x1234_5678_9abc_def0);
+        assert_eq!(header.session_time, 95.25);
+        assert_eq!(header.overall_frame_identifier, 5715);
+        assert_eq!(header.player_car_index, 3);
+        assert_eq!(header.secondary_player_car_index, 255);
+
+        // F1 23 added the game year and the overall frame identifier
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&2023u16.to_le_bytes());
+        bytes.extend_from_slice(&[23, 1, 5, 1, PACKET_MOTION]);
+        bytes.extend_from_slice(&42u64.to_le_bytes());
+        bytes.extend_from_slice(&12.5f32.to_le_bytes());
+        bytes.extend_from_slice(&700u32.to_le_bytes());
+        bytes.extend_from_slice(&900u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 255]);
+        assert_eq!(bytes.len(), PacketHeader::size(2023));
+        let header = PacketHeader::deserialize(&bytes).unwrap();
+        assert_eq!(header.game_year, 23);
+        assert_eq!(header.packet_id, PACKET_MOTION);
+        assert_eq!(header.frame_identifier, 700);
+        assert_eq!(header.overall_frame_identifier, 900);
+
+        assert!(PacketHeader::deserialize(&bytes[..20]).is_err());
+        let mut old = header_2021(PACKET_MOTION);
+        old[..2].copy_from_slice(&2019u16.to_le_bytes());
+        assert!(PacketHeader::deserialize(&old).is_err());
+    }
+
+    #[test]
+    fn test_car_telemetry() {
+        let bytes = car_telemetry_2021();
+        assert_eq!(bytes.len(), 1347);
+
+        let packet = CarTelemetryPacket::deserialize(&bytes).unwrap();
+        assert_eq!(packet.car_telemetry.len(), MAX_CARS);
+        assert_eq!(packet.suggested_gear, 4);
+
+        let player = packet.player().unwrap();
+        assert_eq!(player.speed, 203);
+        assert_eq!(player.throttle, 0.86);
+        assert_eq!(player.steer, -0.12);
+        assert_eq!(player.gear, 3);
+        assert_eq!(player.engine_rpm, 10_850);
+        assert_eq!(player.rev_lights_bit_value, 0x00ff);
+        assert_eq!(player.brakes_temperature, [512, 508, 640, 655]);
+        assert_eq!(player.tyres_inner_temperature[3], 107);
+        assert_eq!(player.engine_temperature, 112);
+        assert_eq!(player.tyres_pressure, [21.5, 21.5, 23.0, 23.0]);
+
+        assert!(CarTelemetryPacket::deserialize(&bytes[..1000]).is_err());
+        assert!(CarTelemetryPacket::deserialize(&header_2021(PACKET_SESSION)).is_err());
+    }
+
+    #[test]
+    fn test_parse_packet_kind() {
+        assert_eq!(parse_packet_kind("carTelemetry"), Ok(PACKET_CAR_TELEMETRY));
+        assert_eq!(parse_packet_kind("lapdata"), Ok(PACKET_LAP_DATA));
+        assert_eq!(parse_packet_kind(" motion "), Ok(PACKET_MOTION));
+        assert!(parse_packet_kind("telemetry").is_err());
+    }
+
+    #[test]
+    fn test_frame() {
+        let telemetry = car_telemetry_2021();
+        let lap_data = header_2021(PACKET_LAP_DATA);
+        let frame = pack_frame(&[telemetry.clone(), b"not F1".to_vec(), lap_data.clone()]);
+        assert_eq!(frame[0], PACKET_CAR_TELEMETRY);
+        assert_eq!(&frame[1..3], &1347u16.to_le_bytes());
+
+        let packets = unpack_frame(&frame).unwrap();
+        assert_eq!(
+            packets,
+            [
+                (PACKET_CAR_TELEMETRY, telemetry.as_slice()),
+                (PACKET_LAP_DATA, lap_data.as_slice())
+            ]
+        );
+
+        assert!(unpack_frame(&frame[..frame.len() - 1]).is_err());
+        assert!(unpack_frame(&[PACKET_MOTION, 1]).is_err());
+        assert!(unpack_frame(&[]).unwrap().is_empty());
+    }
+}
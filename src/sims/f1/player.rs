@@ -0,0 +1,65 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use super::data::{DEFAULT_PORT, unpack_frame};
+use crate::Player;
+use crate::udp::UdpSender;
+
+/// Sends the recorded packets to the port telemetry apps listen on, as the game would. The
+/// packets of a frame are sent together, in the order they were received.
+pub struct F1Player {
+    sender: UdpSender,
+}
+
+impl F1Player {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::create((Ipv4Addr::LOCALHOST, DEFAULT_PORT).into())
+    }
+
+    fn create(target: SocketAddr) -> anyhow::Result<Self> {
+        Ok(Self {
+            sender: UdpSender::new(target)?,
+        })
+    }
+}
+
+impl Player for F1Player {
+    fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        // a frame cut short is not sent at all rather than partly
+        for (_, packet) in unpack_frame(data)? {
+            self.sender.send(packet)?;
+        }
+        Ok(())
+    }
+
+    // the game just stops sending, so do the apps
+    fn stop(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sims::f1::data::pack_frame;
+    use crate::udp::UdpReceiver;
+
+    #[test]
+    fn test_replays_packets() {
+        let mut app = UdpReceiver::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let mut player = F1Player::create(app.local_addr().unwrap()).unwrap();
+
+        let packets: Vec<Vec<u8>> = [6u8, 2]
+            .iter()
+            .map(|&id| {
+                let mut bytes = 2021u16.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&[1, 18, 1, id]);
+                bytes.extend_from_slice(&[0; 18]);
+                bytes
+            })
+            .collect();
+        let frame = pack_frame(&packets);
+        player.update(&frame).unwrap();
+        assert!(player.update(&frame[1..]).is_err());
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(app.all(|_| true).unwrap(), packets);
+    }
+}
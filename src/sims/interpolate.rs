@@ -0,0 +1,53 @@
+//! Blending of values between two recorded frames, for the players that synthesize
+//! intermediate frames with `Player::interpolate`.
+
+use std::f64::consts::{PI, TAU};
+
+/// Linear interpolation of a continuous value.
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Interpolates an angle in radians the short way round, so a heading crossing from pi to
+/// -pi doesn't sweep through 0.
+pub fn lerp_angle(a: f64, b: f64, t: f64) -> f64 {
+    let delta = (b - a + PI).rem_euclid(TAU) - PI;
+    (a + delta * t + PI).rem_euclid(TAU) - PI
+}
+
+/// Interpolates a fraction of a lap in [0, 1), wrapping at the start/finish line.
+pub fn lerp_lap_fraction(a: f64, b: f64, t: f64) -> f64 {
+    let delta = (b - a + 0.5).rem_euclid(1.0) - 0.5;
+    (a + delta * t).rem_euclid(1.0)
+}
+
+/// Interpolates a counter such as a tick or packet id, rounded down to whole steps. Counters
+/// that don't increase are held, a reset must not count backwards.
+pub fn lerp_counter(a: i32, b: i32, t: f64) -> i32 {
+    if b <= a {
+        return a;
+    }
+    a + ((b - a) as f64 * t) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapping() {
+        assert_eq!(lerp(10.0, 20.0, 0.25), 12.5);
+
+        let heading = lerp_angle(3.0, -3.0, 0.5);
+        assert!((heading.abs() - PI).abs() < 1e-9);
+        assert!((lerp_angle(-0.5, 0.5, 0.5)).abs() < 1e-9);
+
+        assert!((lerp_lap_fraction(0.98, 0.02, 0.5)).abs() < 1e-9);
+        assert!((lerp_lap_fraction(0.98, 0.02, 0.25) - 0.99).abs() < 1e-9);
+        assert!((lerp_lap_fraction(0.2, 0.4, 0.5) - 0.3).abs() < 1e-9);
+
+        assert_eq!(lerp_counter(100, 102, 0.5), 101);
+        assert_eq!(lerp_counter(100, 101, 0.5), 100);
+        assert_eq!(lerp_counter(100, 5, 0.5), 100);
+    }
+}
@@ -1,4 +1,8 @@
-use super::data::{CURRENT_PAYLOAD_VERSION, FrameData, Header, IRSDK_MEMMAPFILENAME, VarHeader};
+use super::data::{
+    CURRENT_PAYLOAD_VERSION, FrameData, Header, IRSDK_MAX_BUFS, IRSDK_MEMMAPFILENAME, VarHeader,
+};
+use super::shm::IRacingShm;
+use super::vars::VarType;
 use crate::shm::SharedMemoryReader;
 use crate::{Connector, SimInfo};
 
@@ -6,79 +10,125 @@ const DEFAULT_SHM_SIZE: usize = 1024 * 1024 * 32;
 
 pub struct IRacingConnector {
     shm: Option<SharedMemoryReader>,
+    shm_name: String,
+    shm_size: usize,
     last_session_info_update: i32,
     last_tick_count: i32,
     last_var_headers: Vec<VarHeader>,
+    // num_vars and var_header_offset the var headers were last read with, they are only
+    // read again when these or the session info change
+    last_var_layout: Option<(i32, i32)>,
+    // session info is always captured on the first update, even if the update counter
+    // happens to match the reset value
+    session_info_pending: bool,
+    tick_rate: i32,
+    // skip frames whose header points outside the mapping or at overlapping regions, as
+    // written while the sim shuts down
+    validate_offsets: bool,
+    offsets_warned: bool,
 }
 
 impl IRacingConnector {
     pub fn new() -> Self {
+        Self::create(IRSDK_MEMMAPFILENAME, DEFAULT_SHM_SIZE)
+    }
+
+    /// Connector reading the mapping `shm_name` instead of the one iRacing uses, e.g. the
+    /// one of a player or a mirror. With `validate_offsets` the header offsets of every
+    /// frame are checked, skipping frames with an inconsistent layout instead of recording
+    /// whatever the offsets point at.
+    pub fn with_name(shm_name: &str, validate_offsets: bool) -> Self {
+        Self {
+            validate_offsets,
+            ..Self::create(shm_name, DEFAULT_SHM_SIZE)
+        }
+    }
+
+    fn create(shm_name: &str, shm_size: usize) -> Self {
         Self {
             shm: None,
+            shm_name: shm_name.to_string(),
+            shm_size,
             last_session_info_update: 0,
             last_tick_count: 0,
             last_var_headers: vec![],
+            last_var_layout: None,
+            session_info_pending: true,
+            tick_rate: 0,
+            validate_offsets: false,
+            offsets_warned: false,
         }
     }
+}
 
-    fn read_header(&self) -> Option<Header> {
-        let shm = self.shm.as_ref()?;
-        unsafe {
-            let ptr = shm.as_ptr() as *const Header;
-            Some(std::ptr::read(ptr))
-        }
-    }
-
-    fn read_var_headers(&self, header: &Header) -> Vec<VarHeader> {
-        let shm = match self.shm.as_ref() {
-            Some(s) => s,
-            None => return vec![],
-        };
+/// Region of `len` bytes at `offset`, if it lies between the header and the end of a
+/// mapping of `mapped` bytes.
+fn region_fits(offset: i32, len: i64, mapped: usize) -> bool {
+    let offset = i64::from(offset);
+    offset >= Header::SIZE as i64 && len >= 0 && offset + len <= mapped as i64
+}
 
-        let mut var_headers = Vec::with_capacity(header.num_vars as usize);
-        let base_ptr = shm.as_ptr();
+/// Checks the offsets of `header` against a mapping of `mapped` bytes, describing the first
+/// one that doesn't fit.
+fn check_header(header: &Header, mapped: usize) -> Result<(), String> {
+    let num_buf = usize::try_from(header.num_buf).unwrap_or(0);
+    if !(1..=IRSDK_MAX_BUFS).contains(&num_buf) {
+        return Err(format!("{} buffers", header.num_buf));
+    }
+    if header.buf_len <= 0 {
+        return Err(format!("buffer length {}", header.buf_len));
+    }
 
-        for i in 0..header.num_vars {
-            unsafe {
-                let vh_ptr = base_ptr.add(header.var_header_offset as usize) as *const VarHeader;
-                let vh_ptr = vh_ptr.add(i as usize);
-                var_headers.push(std::ptr::read(vh_ptr));
-            }
+    let var_headers_len = i64::from(header.num_vars) * size_of::<VarHeader>() as i64;
+    if !region_fits(header.var_header_offset, var_headers_len, mapped) {
+        return Err(format!(
+            "{} var headers at offset {} outside the {} byte mapping",
+            header.num_vars, header.var_header_offset, mapped
+        ));
+    }
+    for (i, var_buf) in header.var_buf[..num_buf].iter().enumerate() {
+        if !region_fits(var_buf.buf_offset, i64::from(header.buf_len), mapped) {
+            return Err(format!(
+                "buffer {} of {} bytes at offset {} outside the {} byte mapping",
+                i, header.buf_len, var_buf.buf_offset, mapped
+            ));
         }
-
-        var_headers
     }
+    if !region_fits(
+        header.session_info_offset,
+        i64::from(header.session_info_len),
+        mapped,
+    ) {
+        return Err(format!(
+            "session info of {} bytes at offset {} outside the {} byte mapping",
+            header.session_info_len, header.session_info_offset, mapped
+        ));
+    }
+    Ok(())
+}
 
-    fn read_session_info(&self, header: &Header) -> Vec<u8> {
-        // this function is only called when we're connected, otherwise it's a bug so fail fast
-        let shm = self
-            .shm
-            .as_ref()
-            .expect("Shared memory reader should be connected");
-
-        unsafe {
-            let ptr = shm.as_ptr().add(header.session_info_offset as usize);
-            let slice = std::slice::from_raw_parts(ptr, header.session_info_len as usize);
-            let len = slice.iter().position(|&b| b == 0).unwrap_or(slice.len());
-            slice[..len].to_vec()
+/// Checks that every variable lies within a buffer of `buf_len` bytes.
+fn check_vars(var_headers: &[VarHeader], buf_len: i32) -> Result<(), String> {
+    for vh in var_headers {
+        let size = VarType::from_raw(vh.var_type).map_or(0, |t| t.size()) as i64;
+        let end = i64::from(vh.offset) + i64::from(vh.count) * size;
+        if size == 0 || vh.offset < 0 || vh.count < 0 || end > i64::from(buf_len) {
+            return Err(format!(
+                "variable {} at offset {} outside the {} byte buffer",
+                vh.name_str(),
+                vh.offset,
+                buf_len
+            ));
         }
     }
+    Ok(())
+}
 
-    fn read_raw_data(&self, header: &Header) -> Vec<u8> {
-        // this function is only called when we're connected, otherwise it's a bug so fail fast
-        let shm = self
-            .shm
-            .as_ref()
-            .expect("Shared memory reader should be connected");
-
-        let latest_idx = header.latest_buf_index();
-        let buf_offset = header.var_buf[latest_idx].buf_offset as usize;
-
-        unsafe {
-            let ptr = shm.as_ptr().add(buf_offset);
-            let slice = std::slice::from_raw_parts(ptr, header.buf_len as usize);
-            slice.to_vec()
-        }
+/// Prints a warning about a skipped frame, once until a frame passes the checks again.
+fn warn_skipped(warned: &mut bool, problem: &str) {
+    if !*warned {
+        eprintln!("Warning: skipping iRacing frames with {}", problem);
+        *warned = true;
     }
 }
 
@@ -90,16 +140,19 @@ impl Default for IRacingConnector {
 
 impl Connector for IRacingConnector {
     fn connect(&mut self) -> bool {
-        match SharedMemoryReader::open(IRSDK_MEMMAPFILENAME, DEFAULT_SHM_SIZE) {
+        match SharedMemoryReader::open(&self.shm_name, self.shm_size) {
             Ok(shm) => {
-                let ptr = shm.as_ptr() as *const Header;
-                let header = unsafe { std::ptr::read(ptr) };
+                // a stale mapping of the same name may be too small for the header or for
+                // the buffers its header points to
+                let view = IRacingShm::new(&shm);
+                let header = view
+                    .header()
+                    .filter(|h| h.is_connected() && view.latest_buffer(h).is_some());
 
-                if header.is_connected() {
+                if let Some(header) = header {
+                    self.tick_rate = header.tick_rate;
                     self.shm = Some(shm);
-                    self.last_session_info_update = 0;
-                    self.last_tick_count = 0;
-                    self.last_var_headers = vec![];
+                    self.reset();
                     true
                 } else {
                     false
@@ -111,13 +164,22 @@ impl Connector for IRacingConnector {
 
     fn disconnect(&mut self) {
         self.shm = None;
+        self.reset();
+        self.tick_rate = 0;
+    }
+
+    fn reset(&mut self) {
         self.last_session_info_update = 0;
         self.last_tick_count = 0;
         self.last_var_headers = vec![];
+        self.last_var_layout = None;
+        self.session_info_pending = true;
     }
 
     fn update(&mut self) -> Option<Vec<u8>> {
-        let header = self.read_header()?;
+        let shm = self.shm.as_ref()?;
+        let view = IRacingShm::new(shm);
+        let header = view.header()?;
 
         if !header.is_connected() {
             return None;
@@ -130,27 +192,53 @@ impl Connector for IRacingConnector {
             // No new data
             return None;
         }
-        self.last_tick_count = current_tick;
 
-        // var headers — only include when changed
-        let new_var_headers = self.read_var_headers(&header);
-        let var_headers = if new_var_headers != self.last_var_headers {
-            self.last_var_headers = new_var_headers.clone();
-            Some(new_var_headers)
+        if self.validate_offsets
+            && let Err(problem) = check_header(&header, shm.size())
+        {
+            warn_skipped(&mut self.offsets_warned, &problem);
+            return None;
+        }
+
+        let session_info_changed = self.session_info_pending
+            || header.session_info_update != self.last_session_info_update;
+        let var_layout = (header.num_vars, header.var_header_offset);
+        let var_layout_changed = session_info_changed || self.last_var_layout != Some(var_layout);
+
+        // read everything first so a layout out of bounds doesn't leave partially updated state
+        let new_var_headers = if var_layout_changed {
+            Some(view.var_headers(&header)?)
         } else {
             None
         };
-
-        // session info
-        let session_info = if header.session_info_update != self.last_session_info_update {
-            self.last_session_info_update = header.session_info_update;
-            Some(self.read_session_info(&header))
+        if self.validate_offsets {
+            let var_headers = new_var_headers.as_deref().unwrap_or(&self.last_var_headers);
+            if let Err(problem) = check_vars(var_headers, header.buf_len) {
+                warn_skipped(&mut self.offsets_warned, &problem);
+                return None;
+            }
+            self.offsets_warned = false;
+        }
+        let session_info = if session_info_changed {
+            Some(view.session_info(&header)?)
         } else {
             None
         };
+        let raw_data = view.latest_buffer(&header)?;
+
+        self.last_tick_count = current_tick;
+        self.last_session_info_update = header.session_info_update;
+        self.session_info_pending = false;
+        self.last_var_layout = Some(var_layout);
 
-        // data
-        let raw_data = self.read_raw_data(&header);
+        // var headers — only include when changed
+        let var_headers = match new_var_headers {
+            Some(new_var_headers) if new_var_headers != self.last_var_headers => {
+                self.last_var_headers = new_var_headers.clone();
+                Some(new_var_headers)
+            }
+            _ => None,
+        };
 
         // serialize frame
         let frame = FrameData {
@@ -167,6 +255,349 @@ impl Connector for IRacingConnector {
         SimInfo {
             id: *b"irac",
             payload_version: CURRENT_PAYLOAD_VERSION,
+            native_hz: self.native_hz().unwrap_or(0),
+        }
+    }
+
+    fn native_hz(&self) -> Option<u32> {
+        u32::try_from(self.tick_rate).ok().filter(|&hz| hz > 0)
+    }
+
+    fn merge(&self, dropped: Vec<u8>, newer: Vec<u8>) -> Vec<u8> {
+        let (Ok(dropped), Ok(mut frame)) = (
+            FrameData::deserialize(&dropped, CURRENT_PAYLOAD_VERSION),
+            FrameData::deserialize(&newer, CURRENT_PAYLOAD_VERSION),
+        ) else {
+            return newer;
+        };
+        if dropped.var_headers.is_none() && dropped.session_info.is_none() {
+            return newer;
+        }
+        frame.var_headers = frame.var_headers.or(dropped.var_headers);
+        frame.session_info = frame.session_info.or(dropped.session_info);
+        frame.serialize().unwrap_or(newer)
+    }
+
+    fn peek_raw(&self) -> Option<Vec<u8>> {
+        IRacingShm::new(self.shm.as_ref()?).raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::SharedMemoryWriter;
+    use crate::sims::iracing::data::{Header, VarBuf};
+
+    fn as_bytes<T>(value: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_session_info_on_first_frame() {
+        let name = "Local\\KsanaTestIRacingConnectorSessionInfo";
+        let size = 4096;
+
+        // session_info_update matches the value the connector starts from
+        let mut header = Header {
+            status: 1,
+            tick_rate: 60,
+            session_info_update: 0,
+            session_info_len: 13,
+            session_info_offset: 1024,
+            num_vars: 1,
+            var_header_offset: 512,
+            num_buf: 1,
+            buf_len: 4,
+            ..Default::default()
+        };
+        header.var_buf[0] = VarBuf {
+            tick_count: 1,
+            buf_offset: 2048,
+            pad: [0; 2],
+        };
+        let var_header = VarHeader {
+            var_type: 4,
+            ..Default::default()
+        };
+
+        let mut writer = SharedMemoryWriter::create(name, size).unwrap();
+        unsafe {
+            writer.write(0, as_bytes(&header));
+            writer.write(512, as_bytes(&var_header));
+            writer.write(1024, b"WeekendInfo:\n");
+            writer.write(2048, &1.5f32.to_le_bytes());
+        }
+
+        let mut connector = IRacingConnector::create(name, size);
+        assert!(connector.connect());
+
+        let first = FrameData::deserialize(&connector.update().unwrap(), 2).unwrap();
+        assert_eq!(first.session_info.as_deref(), Some(&b"WeekendInfo:\n"[..]));
+        assert!(first.var_headers.is_some());
+
+        header.var_buf[0].tick_count = 2;
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+        let second = FrameData::deserialize(&connector.update().unwrap(), 2).unwrap();
+        assert!(second.session_info.is_none());
+        assert!(second.var_headers.is_none());
+
+        // and again on the first frame after resetting
+        header.var_buf[0].tick_count = 3;
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+        connector.reset();
+        let after_reset = FrameData::deserialize(&connector.update().unwrap(), 2).unwrap();
+        assert!(after_reset.session_info.is_some());
+    }
+
+    #[test]
+    fn test_merge_keeps_dropped_parts() {
+        let connector = IRacingConnector::new();
+        let frame = |tick_count: i32, full: bool| {
+            let mut header = Header {
+                num_vars: 1,
+                ..Default::default()
+            };
+            header.var_buf[0].tick_count = tick_count;
+            FrameData {
+                header,
+                var_headers: full.then(|| vec![VarHeader::default()]),
+                session_info: full.then(|| b"WeekendInfo:\n".to_vec()),
+                raw_data: tick_count.to_le_bytes().to_vec(),
+            }
+            .serialize()
+            .unwrap()
+        };
+
+        let merged = connector.merge(frame(1, true), frame(2, false));
+        let merged = FrameData::deserialize(&merged, CURRENT_PAYLOAD_VERSION).unwrap();
+        assert_eq!(merged.header.var_buf[0].tick_count, 2);
+        assert_eq!(merged.raw_data, 2i32.to_le_bytes());
+        assert_eq!(merged.var_headers.map(|headers| headers.len()), Some(1));
+        assert_eq!(merged.session_info.as_deref(), Some(&b"WeekendInfo:\n"[..]));
+
+        // nothing to keep from a data only frame
+        assert_eq!(
+            connector.merge(frame(1, false), frame(2, false)),
+            frame(2, false)
+        );
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_mapping_smaller_than_expected() {
+        let name = "Local\\KsanaTestIRacingConnectorSmallMapping";
+        let size = 4096;
+
+        // a connected header pointing past the end of the one page mapping
+        let mut header = Header {
+            status: 1,
+            tick_rate: 60,
+            num_buf: 1,
+            buf_len: 4096,
+            ..Default::default()
+        };
+        header.var_buf[0] = VarBuf {
+            tick_count: 1,
+            buf_offset: 2048,
+            pad: [0; 2],
+        };
+        let mut writer = SharedMemoryWriter::create(name, size).unwrap();
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+
+        let reader = SharedMemoryReader::open(name, DEFAULT_SHM_SIZE).unwrap();
+        assert_eq!(reader.size(), size);
+
+        let mut connector = IRacingConnector::create(name, DEFAULT_SHM_SIZE);
+        assert!(!connector.connect());
+        assert!(connector.update().is_none());
+
+        // the same header fits once the buffer does
+        header.buf_len = 4;
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+        assert!(connector.connect());
+    }
+
+    #[test]
+    fn test_check_layout() {
+        let mut header = Header {
+            num_vars: 2,
+            var_header_offset: 112,
+            session_info_offset: 400,
+            session_info_len: 100,
+            num_buf: 2,
+            buf_len: 16,
+            ..Default::default()
+        };
+        header.var_buf[0].buf_offset = 500;
+        header.var_buf[1].buf_offset = 516;
+        assert!(check_header(&header, 532).is_ok());
+        assert!(check_header(&header, 531).is_err());
+
+        // unused buffers aren't checked
+        header.var_buf[2].buf_offset = -1;
+        assert!(check_header(&header, 532).is_ok());
+
+        let mut torn_down = header;
+        torn_down.num_buf = 0;
+        assert!(check_header(&torn_down, 532).is_err());
+
+        let mut overlapping = header;
+        overlapping.var_header_offset = 0;
+        assert!(check_header(&overlapping, 532).is_err());
+
+        let mut overflowing = header;
+        overflowing.var_buf[1].buf_offset = i32::MAX;
+        assert!(check_header(&overflowing, 532).is_err());
+
+        let var = VarHeader {
+            var_type: VarType::Float as i32,
+            offset: 8,
+            count: 2,
+            ..Default::default()
+        };
+        assert!(check_vars(&[var], 16).is_ok());
+        assert!(check_vars(&[var], 15).is_err());
+        assert!(check_vars(&[VarHeader { var_type: 9, ..var }], 16).is_err());
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_validated_offsets() {
+        let name = "Local\\KsanaTestIRacingConnectorValidatedOffsets";
+        let size = 4096;
+
+        let mut header = Header {
+            status: 1,
+            tick_rate: 60,
+            session_info_offset: 1024,
+            var_header_offset: 512,
+            num_buf: 1,
+            buf_len: 4,
+            ..Default::default()
+        };
+        header.var_buf[0] = VarBuf {
+            tick_count: 1,
+            buf_offset: 2048,
+            pad: [0; 2],
+        };
+        let mut writer = SharedMemoryWriter::create(name, size).unwrap();
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+
+        let mut lenient = IRacingConnector::create(name, size);
+        let mut strict = IRacingConnector {
+            validate_offsets: true,
+            ..IRacingConnector::create(name, size)
+        };
+        assert!(lenient.connect());
+        assert!(strict.connect());
+        assert!(lenient.update().is_some());
+        assert!(strict.update().is_some());
+
+        // buffer pointing into the header, as seen while the sim exits
+        header.var_buf[0] = VarBuf {
+            tick_count: 2,
+            buf_offset: 0,
+            pad: [0; 2],
+        };
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+        assert!(lenient.update().is_some());
+        assert!(strict.update().is_none());
+        assert!(strict.offsets_warned);
+
+        header.var_buf[0].buf_offset = 2048;
+        unsafe {
+            writer.write(0, as_bytes(&header));
+        }
+        assert!(strict.update().is_some());
+        assert!(!strict.offsets_warned);
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_var_headers_read_on_layout_change() {
+        let name = "Local\\KsanaTestIRacingConnectorVarLayout";
+        let size = 4096;
+
+        let mut header = Header {
+            status: 1,
+            tick_rate: 60,
+            num_vars: 1,
+            var_header_offset: 512,
+            num_buf: 1,
+            buf_len: 4,
+            ..Default::default()
+        };
+        header.var_buf[0] = VarBuf {
+            tick_count: 1,
+            buf_offset: 2048,
+            pad: [0; 2],
+        };
+        let speed = VarHeader {
+            var_type: VarType::Float as i32,
+            count: 1,
+            ..Default::default()
+        };
+        let rpm = VarHeader { offset: 4, ..speed };
+        let mut writer = SharedMemoryWriter::create(name, size).unwrap();
+        unsafe {
+            writer.write(0, as_bytes(&header));
+            writer.write(512, as_bytes(&speed));
+        }
+
+        let mut connector = IRacingConnector::create(name, size);
+        assert!(connector.connect());
+        let mut next_frame = |header: &mut Header, writer: &mut SharedMemoryWriter| {
+            header.var_buf[0].tick_count += 1;
+            unsafe {
+                writer.write(0, as_bytes(header));
+            }
+            let frame = connector.update().unwrap();
+            (FrameData::deserialize(&frame, 2).unwrap(), frame.len())
+        };
+
+        let (first, full_len) = next_frame(&mut header, &mut writer);
+        assert_eq!(first.var_headers, Some(vec![speed]));
+        let (second, data_only_len) = next_frame(&mut header, &mut writer);
+        assert!(second.var_headers.is_none());
+        assert_eq!(full_len - data_only_len, size_of::<VarHeader>());
+
+        // headers rewritten in place aren't read again while the layout stays the same
+        unsafe {
+            writer.write(512, as_bytes(&rpm));
+        }
+        assert!(next_frame(&mut header, &mut writer).0.var_headers.is_none());
+
+        header.var_header_offset = 768;
+        unsafe {
+            writer.write(768, as_bytes(&rpm));
+        }
+        assert_eq!(
+            next_frame(&mut header, &mut writer).0.var_headers,
+            Some(vec![rpm])
+        );
+
+        header.num_vars = 2;
+        unsafe {
+            writer.write(768 + size_of::<VarHeader>(), as_bytes(&speed));
         }
+        assert_eq!(
+            next_frame(&mut header, &mut writer).0.var_headers,
+            Some(vec![rpm, speed])
+        );
+        assert!(next_frame(&mut header, &mut writer).0.var_headers.is_none());
     }
 }
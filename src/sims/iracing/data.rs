@@ -1,4 +1,5 @@
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::borrow::Cow;
 use std::io::{self, Cursor, Read};
 
 pub const CURRENT_PAYLOAD_VERSION: i32 = 2;
@@ -15,6 +16,14 @@ const FRAME_HEADER_RESERVED: usize = 15;
 pub const IRSDK_MAX_DESC: usize = 64;
 
 pub const IRSDK_MEMMAPFILENAME: &str = "Local\\IRSDKMemMapFileName";
+pub const IRSDK_DATAVALIDEVENTNAME: &str = "Local\\IRSDKDataValidEvent";
+
+// Safety limits for frames read from recordings, well above what the sim produces (a few
+// hundred vars, tens of KB of data and session info up to a few hundred KB). Frames
+// exceeding them are rejected by `FrameData::deserialize` and never reach the player.
+pub const MAX_NUM_VARS: usize = 4096;
+pub const MAX_SESSION_INFO_LEN: usize = 4 * 1024 * 1024;
+pub const MAX_RAW_DATA_LEN: usize = 4 * 1024 * 1024;
 
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +31,12 @@ pub enum StatusField {
     Connected = 1,
 }
 
+/// Decodes a fixed-size irsdk string field, stopping at the first null byte.
+fn fixed_str(bytes: &[u8]) -> Cow<'_, str> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len])
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct VarBuf {
@@ -58,6 +73,20 @@ impl Default for VarHeader {
     }
 }
 
+impl VarHeader {
+    pub fn name_str(&self) -> Cow<'_, str> {
+        fixed_str(&self.name)
+    }
+
+    pub fn desc_str(&self) -> Cow<'_, str> {
+        fixed_str(&self.desc)
+    }
+
+    pub fn unit_str(&self) -> Cow<'_, str> {
+        fixed_str(&self.unit)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Header {
@@ -97,6 +126,17 @@ impl Default for Header {
     }
 }
 
+// The structs are read from and written to the irsdk memory map as raw bytes, so their
+// layout has to match irsdk_defines.h exactly:
+//   - VarBuf: 4 + 4 + 8 (pad) = 16 bytes
+//   - VarHeader: 4 + 4 + 4 + 1 + 3 (pad) + 32 (name) + 64 (desc) + 32 (unit) = 144 bytes
+//   - Header: 12 ints (48 bytes) + 4 VarBufs (64 bytes) = 112 bytes
+const _: () = {
+    assert!(size_of::<VarBuf>() == 16);
+    assert!(size_of::<VarHeader>() == 144);
+    assert!(size_of::<Header>() == 112);
+};
+
 impl Header {
     pub const SIZE: usize = std::mem::size_of::<Self>();
 
@@ -104,9 +144,14 @@ impl Header {
         (self.status & StatusField::Connected as i32) != 0
     }
 
+    /// Buffer with the highest tick count. A corrupt `num_buf` is clamped to the buffers
+    /// the header has, 0 buffers or less leave buffer 0.
     pub fn latest_buf_index(&self) -> usize {
+        let num_buf = usize::try_from(self.num_buf)
+            .unwrap_or(0)
+            .min(IRSDK_MAX_BUFS);
         let mut latest = 0;
-        for i in 1..self.num_buf as usize {
+        for i in 1..num_buf {
             if self.var_buf[i].tick_count > self.var_buf[latest].tick_count {
                 latest = i;
             }
@@ -177,6 +222,9 @@ impl FrameData {
         Some(buffer)
     }
 
+    /// Parses a frame written by `serialize`. The var header count and the session info
+    /// and raw data lengths are checked against the remaining bytes before allocating, so a
+    /// corrupt frame fails with `InvalidData` instead of allocating more than its own size.
     pub fn deserialize(bytes: &[u8], payload_version: i32) -> io::Result<Self> {
         let mut cursor = Cursor::new(bytes);
 
@@ -205,7 +253,10 @@ impl FrameData {
         // var headers — count is always header.num_vars; frame type determines presence
         let var_header_size = std::mem::size_of::<VarHeader>();
         let var_headers: Option<Vec<VarHeader>> = if frame_type == FRAME_TYPE_FULL {
-            let mut headers = Vec::with_capacity(header.num_vars as usize);
+            let num_vars = usize::try_from(header.num_vars).map_err(|_| invalid_length())?;
+            check_limit(num_vars, MAX_NUM_VARS, "var header count")?;
+            check_remaining(&cursor, num_vars.saturating_mul(var_header_size))?;
+            let mut headers = Vec::with_capacity(num_vars);
             for _ in 0..header.num_vars {
                 let mut vh_bytes = vec![0u8; var_header_size];
                 cursor.read_exact(&mut vh_bytes)?;
@@ -220,6 +271,12 @@ impl FrameData {
 
         // session info
         let session_info_len = cursor.read_u64::<LittleEndian>()? as usize;
+        check_limit(
+            session_info_len,
+            MAX_SESSION_INFO_LEN,
+            "session info length",
+        )?;
+        check_remaining(&cursor, session_info_len)?;
         let session_info: Option<Vec<u8>> = if session_info_len > 0 {
             let mut session_info_bytes = vec![0u8; session_info_len];
             cursor.read_exact(&mut session_info_bytes)?;
@@ -230,6 +287,8 @@ impl FrameData {
 
         // data
         let raw_data_len = cursor.read_u64::<LittleEndian>()? as usize;
+        check_limit(raw_data_len, MAX_RAW_DATA_LEN, "raw data length")?;
+        check_remaining(&cursor, raw_data_len)?;
         let mut raw_data = vec![0u8; raw_data_len];
         cursor.read_exact(&mut raw_data)?;
 
@@ -242,6 +301,32 @@ impl FrameData {
     }
 }
 
+fn invalid_length() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "iRacing frame length exceeds the frame size",
+    )
+}
+
+fn check_limit(value: usize, limit: usize, what: &str) -> io::Result<()> {
+    if value > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("iRacing frame {what} {value} exceeds the limit of {limit}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if fewer than `len` bytes are left in the frame.
+fn check_remaining(cursor: &Cursor<&[u8]>, len: usize) -> io::Result<()> {
+    let remaining = (cursor.get_ref().len() as u64).saturating_sub(cursor.position());
+    if len as u64 > remaining {
+        return Err(invalid_length());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,21 +342,25 @@ mod tests {
     }
 
     #[test]
-    fn test_header_size() {
-        // Header: 12 ints (48 bytes) + 4 VarBufs (64 bytes) = 112 bytes
-        assert_eq!(Header::SIZE, 112);
-    }
-
-    #[test]
-    fn test_var_header_size() {
-        // VarHeader should be 144 bytes (4+4+4+1+3 + 32 + 64 + 32 = 144)
-        assert_eq!(std::mem::size_of::<VarHeader>(), 144);
-    }
-
-    #[test]
-    fn test_var_buf_size() {
-        // VarBuf should be 16 bytes (4+4+8 = 16)
-        assert_eq!(std::mem::size_of::<VarBuf>(), 16);
+    fn test_var_header_strings() {
+        let vh = VarHeader {
+            name: pad::<IRSDK_MAX_STRING>(b"Speed"),
+            desc: pad::<IRSDK_MAX_DESC>(b"GPS vehicle speed"),
+            unit: pad::<IRSDK_MAX_STRING>(b"m/s"),
+            ..Default::default()
+        };
+        assert_eq!(vh.name_str(), "Speed");
+        assert_eq!(vh.desc_str(), "GPS vehicle speed");
+        assert_eq!(vh.unit_str(), "m/s");
+
+        let full = VarHeader {
+            name: [b'n'; IRSDK_MAX_STRING],
+            desc: [b'd'; IRSDK_MAX_DESC],
+            ..Default::default()
+        };
+        assert_eq!(full.name_str(), "n".repeat(IRSDK_MAX_STRING));
+        assert_eq!(full.desc_str(), "d".repeat(IRSDK_MAX_DESC));
+        assert_eq!(full.unit_str(), "");
     }
 
     #[test]
@@ -285,13 +374,24 @@ mod tests {
 
     #[test]
     fn test_latest_buf_index() {
-        let mut header = Header::default();
-        header.num_buf = 3;
+        let mut header = Header {
+            num_buf: 3,
+            ..Default::default()
+        };
         header.var_buf[0].tick_count = 100;
         header.var_buf[1].tick_count = 150;
         header.var_buf[2].tick_count = 120;
 
         assert_eq!(header.latest_buf_index(), 1);
+
+        // corrupt counts don't index past the buffers
+        header.var_buf[3].tick_count = 200;
+        header.num_buf = 10;
+        assert_eq!(header.latest_buf_index(), 3);
+        header.num_buf = -1;
+        assert_eq!(header.latest_buf_index(), 0);
+        header.num_buf = 0;
+        assert_eq!(header.latest_buf_index(), 0);
     }
 
     #[test]
@@ -465,6 +565,75 @@ mod tests {
         assert_eq!(deserialized.raw_data, frame.raw_data);
     }
 
+    #[test]
+    fn test_deserialize_rejects_oversized_lengths() {
+        let frame = FrameData {
+            header: Header {
+                num_vars: 0,
+                ..Default::default()
+            },
+            var_headers: None,
+            session_info: None,
+            raw_data: vec![1, 2, 3, 4],
+        };
+        let serialized = frame.serialize().unwrap();
+
+        // raw data length is the last length field, right before the data
+        let mut bytes = serialized.clone();
+        let len_offset = bytes.len() - 4 - 8;
+        bytes[len_offset..len_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = FrameData::deserialize(&bytes, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // full frame claiming more var headers than the frame holds
+        let mut bytes = serialized;
+        bytes[0] = FRAME_TYPE_FULL;
+        let num_vars_offset = 16 + std::mem::offset_of!(Header, num_vars);
+        bytes[num_vars_offset..num_vars_offset + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+        let err = FrameData::deserialize(&bytes, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_deserialize_enforces_limits() {
+        let frame = FrameData {
+            header: Header::default(),
+            var_headers: None,
+            session_info: None,
+            raw_data: vec![0; MAX_RAW_DATA_LEN],
+        };
+        assert!(FrameData::deserialize(&frame.serialize().unwrap(), 2).is_ok());
+
+        // the data is all there, but more than any sim produces
+        let frame = FrameData {
+            raw_data: vec![0; MAX_RAW_DATA_LEN + 1],
+            ..frame
+        };
+        let err = FrameData::deserialize(&frame.serialize().unwrap(), 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("raw data length"));
+
+        let frame = FrameData {
+            session_info: Some(vec![b' '; MAX_SESSION_INFO_LEN + 1]),
+            raw_data: vec![],
+            ..frame
+        };
+        let err = FrameData::deserialize(&frame.serialize().unwrap(), 2).unwrap_err();
+        assert!(err.to_string().contains("session info length"));
+
+        let frame = FrameData {
+            header: Header {
+                num_vars: MAX_NUM_VARS as i32 + 1,
+                ..Default::default()
+            },
+            var_headers: Some(vec![VarHeader::default(); MAX_NUM_VARS + 1]),
+            session_info: None,
+            raw_data: vec![],
+        };
+        let err = FrameData::deserialize(&frame.serialize().unwrap(), 2).unwrap_err();
+        assert!(err.to_string().contains("var header count"));
+    }
+
     #[test]
     fn test_deserialize_v1_backward_compat() {
         // Simulate a v1 iRacing recording: Header + VarHeaders (always present, no 16-byte
@@ -1,3 +1,5 @@
 pub mod connector;
-pub mod data;
 pub mod player;
+pub mod shm;
+
+pub use ksana::sims::iracing::{data, vars};
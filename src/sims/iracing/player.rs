@@ -1,26 +1,75 @@
-use super::data::{FrameData, Header, IRSDK_MEMMAPFILENAME, VarHeader};
+use super::data::{FrameData, Header, VarHeader};
+use super::vars;
 use crate::Player;
 use crate::shm::{EventHandle, SharedMemoryWriter};
+use crate::sims::interpolate::lerp_counter;
 
+// Fits the data at any offsets a real sim uses, sizes are capped by the limits in data.rs
 const DEFAULT_SHM_SIZE: usize = 1024 * 1024 * 1024;
-const IRSDK_DATAVALIDEVENTNAME: &str = "Local\\IRSDKDataValidEvent";
 
 pub struct IRacingPlayer {
     shm: SharedMemoryWriter,
     event: EventHandle,
     payload_version: i32,
+    /// Layout of the raw data last written
+    var_headers: Vec<VarHeader>,
+    /// Where `var_headers` are in the shared memory
+    var_header_offset: i32,
 }
 
 impl IRacingPlayer {
-    pub fn new(payload_version: i32) -> anyhow::Result<Self> {
-        let shm = SharedMemoryWriter::create(IRSDK_MEMMAPFILENAME, DEFAULT_SHM_SIZE)?;
-        let event = EventHandle::create(IRSDK_DATAVALIDEVENTNAME)?;
+    /// Player writing to the mapping `shm_name` and signalling `event_name`, the iRacing
+    /// `IRSDK_MEMMAPFILENAME` and `IRSDK_DATAVALIDEVENTNAME` unless several players run
+    /// side by side.
+    pub fn with_names(
+        shm_name: &str,
+        event_name: &str,
+        payload_version: i32,
+    ) -> anyhow::Result<Self> {
+        Self::create(shm_name, DEFAULT_SHM_SIZE, event_name, payload_version)
+    }
+
+    /// Player writing to the mapping `shm_name` instead of the one iRacing uses, signalling
+    /// `<shm_name>DataValidEvent` so readers of the real sim aren't woken up.
+    pub fn with_name(shm_name: &str, payload_version: i32) -> anyhow::Result<Self> {
+        Self::with_names(
+            shm_name,
+            &format!("{}DataValidEvent", shm_name),
+            payload_version,
+        )
+    }
+
+    fn create(
+        shm_name: &str,
+        shm_size: usize,
+        event_name: &str,
+        payload_version: i32,
+    ) -> anyhow::Result<Self> {
+        let shm = SharedMemoryWriter::create(shm_name, shm_size)?;
+        let event = EventHandle::create(event_name)?;
         Ok(Self {
             shm,
             event,
             payload_version,
+            var_headers: vec![],
+            var_header_offset: 0,
         })
     }
+
+    /// Offset of `len` bytes at `offset` in the shared memory, or an error if they don't
+    /// fit, so offsets from a corrupt recording can't write outside of the mapping.
+    fn extent(&self, offset: i32, len: usize, what: &str) -> anyhow::Result<usize> {
+        let start = usize::try_from(offset).ok();
+        match start.zip(start.and_then(|s| s.checked_add(len))) {
+            Some((start, end)) if end <= self.shm.size() => Ok(start),
+            _ => anyhow::bail!(
+                "{} at offset {} with length {} is outside of the shared memory",
+                what,
+                offset,
+                len
+            ),
+        }
+    }
 }
 
 impl Player for IRacingPlayer {
@@ -28,29 +77,60 @@ impl Player for IRacingPlayer {
         let frame = FrameData::deserialize(data, self.payload_version)?;
 
         let latest_idx = frame.header.latest_buf_index();
-        let buf_offset = frame.header.var_buf[latest_idx].buf_offset as usize;
+        let var_header_size = std::mem::size_of::<VarHeader>();
+
+        // check every extent before writing anything, a frame is written whole or not at all
+        let buf_offset = self.extent(
+            frame.header.var_buf[latest_idx].buf_offset,
+            frame.raw_data.len(),
+            "Raw data",
+        )?;
+        // frames without var headers reuse the last ones, which only need writing again if
+        // the header moved them
+        let var_headers = match &frame.var_headers {
+            Some(var_headers) => Some(var_headers),
+            None if frame.header.var_header_offset != self.var_header_offset
+                && !self.var_headers.is_empty() =>
+            {
+                Some(&self.var_headers)
+            }
+            None => None,
+        };
+        let var_header_offset = match var_headers {
+            Some(var_headers) => Some(self.extent(
+                frame.header.var_header_offset,
+                var_headers.len() * var_header_size,
+                "Var headers",
+            )?),
+            None => None,
+        };
+        let session_info_offset = match &frame.session_info {
+            Some(session_info) => Some(self.extent(
+                frame.header.session_info_offset,
+                session_info.len(),
+                "Session info",
+            )?),
+            None => None,
+        };
 
         unsafe {
             // raw telemetry data
             self.shm.write(buf_offset, &frame.raw_data);
 
-            // var headers — only written when present (unchanged frames omit them;
+            // var headers — only written when present or moved (unchanged frames omit them;
             // SHM already holds the previous values)
-            if let Some(var_headers) = &frame.var_headers {
-                let var_header_size = std::mem::size_of::<VarHeader>();
+            if let (Some(var_headers), Some(base)) = (var_headers, var_header_offset) {
                 for (i, vh) in var_headers.iter().enumerate() {
                     let vh_bytes = std::slice::from_raw_parts(
                         vh as *const VarHeader as *const u8,
                         var_header_size,
                     );
-                    let offset = frame.header.var_header_offset as usize + i * var_header_size;
-                    self.shm.write(offset, vh_bytes);
+                    self.shm.write(base + i * var_header_size, vh_bytes);
                 }
             }
 
             // session info
-            if let Some(session_info) = &frame.session_info {
-                let offset = frame.header.session_info_offset as usize;
+            if let (Some(session_info), Some(offset)) = (&frame.session_info, session_info_offset) {
                 self.shm.write(offset, session_info);
             }
 
@@ -64,9 +144,51 @@ impl Player for IRacingPlayer {
 
         self.event.signal();
 
+        if var_header_offset.is_some() {
+            self.var_header_offset = frame.header.var_header_offset;
+        }
+        if let Some(var_headers) = frame.var_headers {
+            self.var_headers = var_headers;
+        }
+
         Ok(())
     }
 
+    fn interpolate(&self, previous: &[u8], next: &[u8], t: f64) -> anyhow::Result<Option<Vec<u8>>> {
+        // payload version 1 frames always carry the var headers, a synthesized frame can't
+        // leave them out
+        if self.payload_version < 2 {
+            return Ok(None);
+        }
+
+        let previous = FrameData::deserialize(previous, self.payload_version)?;
+        let next = FrameData::deserialize(next, self.payload_version)?;
+
+        // new var headers may change the layout, hold the previous values until they are in
+        let raw_data =
+            if next.var_headers.is_none() && next.raw_data.len() == previous.raw_data.len() {
+                vars::interpolate(&self.var_headers, &previous.raw_data, &next.raw_data, t)
+            } else {
+                previous.raw_data
+            };
+
+        // clients only read a buffer whose tick increased, so the frame goes to the buffer
+        // of the previous frame with a tick between the two
+        let mut header = previous.header;
+        let latest = header.latest_buf_index();
+        let next_tick = next.header.var_buf[next.header.latest_buf_index()].tick_count;
+        header.var_buf[latest].tick_count =
+            lerp_counter(header.var_buf[latest].tick_count, next_tick, t);
+
+        Ok(FrameData {
+            header,
+            var_headers: None,
+            session_info: None,
+            raw_data,
+        }
+        .serialize())
+    }
+
     fn stop(&mut self) {
         unsafe {
             let status_offset = std::mem::offset_of!(Header, status);
@@ -75,3 +197,300 @@ impl Player for IRacingPlayer {
         }
     }
 }
+
+// Make sure clients don't see a connected sim after playback ends, even on a panic
+impl Drop for IRacingPlayer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::SharedMemoryReader;
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_drop_clears_status() {
+        let id = std::process::id();
+        let shm_name = format!("Local\\KsanaTestIRacingPlayerDrop-{}", id);
+        let event_name = format!("Local\\KsanaTestIRacingPlayerDropEvent-{}", id);
+        let status_offset = std::mem::offset_of!(Header, status);
+
+        let mut player = IRacingPlayer::create(&shm_name, 4096, &event_name, 2).unwrap();
+        unsafe {
+            player.shm.write(status_offset, &1i32.to_le_bytes());
+        }
+
+        let reader = SharedMemoryReader::open(&shm_name, 4096).unwrap();
+        let status = || unsafe {
+            std::ptr::read_unaligned(reader.as_ptr().add(status_offset) as *const i32)
+        };
+        assert_eq!(status(), 1);
+
+        drop(player);
+        assert_eq!(status(), 0);
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_interpolate_advances_tick() {
+        use crate::sims::iracing::vars::{VarType, read_f64};
+
+        let id = std::process::id();
+        let shm_name = format!("Local\\KsanaTestIRacingPlayerInterpolate-{}", id);
+        let event_name = format!("Local\\KsanaTestIRacingPlayerInterpolateEvent-{}", id);
+        let mut player = IRacingPlayer::create(&shm_name, 4096, &event_name, 2).unwrap();
+
+        let mut speed = VarHeader {
+            var_type: VarType::Float as i32,
+            count: 1,
+            ..Default::default()
+        };
+        speed.name[..5].copy_from_slice(b"Speed");
+        let frame = |tick: i32, value: f32, var_headers: Option<Vec<VarHeader>>| {
+            let mut frame = FrameData {
+                header: Header {
+                    num_buf: 1,
+                    num_vars: 1,
+                    buf_len: 4,
+                    var_header_offset: 1024,
+                    ..Default::default()
+                },
+                var_headers,
+                session_info: None,
+                raw_data: value.to_le_bytes().to_vec(),
+            };
+            frame.header.var_buf[0].tick_count = tick;
+            frame.header.var_buf[0].buf_offset = 2048;
+            frame.serialize().unwrap()
+        };
+
+        let previous = frame(10, 40.0, Some(vec![speed]));
+        let next = frame(14, 50.0, None);
+        player.update(&previous).unwrap();
+
+        let between = player.interpolate(&previous, &next, 0.5).unwrap().unwrap();
+        let between = FrameData::deserialize(&between, 2).unwrap();
+        assert_eq!(between.header.var_buf[0].tick_count, 12);
+        assert!(between.var_headers.is_none());
+        assert_eq!(
+            read_f64(&[speed], &between.raw_data, "Speed", 0),
+            Some(45.0)
+        );
+        assert!(player.update(&between.serialize().unwrap()).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_reuses_var_headers() {
+        let id = std::process::id();
+        let shm_name = format!("Local\\KsanaTestIRacingPlayerVarHeaders-{}", id);
+        let event_name = format!("Local\\KsanaTestIRacingPlayerVarHeadersEvent-{}", id);
+        let mut player = IRacingPlayer::create(&shm_name, 4096, &event_name, 2).unwrap();
+        let reader = SharedMemoryReader::open(&shm_name, 4096).unwrap();
+        let var_header_at = |offset: usize| unsafe {
+            std::ptr::read_unaligned(reader.as_ptr().add(offset) as *const VarHeader)
+        };
+
+        let speed = VarHeader {
+            var_type: 4,
+            count: 1,
+            ..Default::default()
+        };
+        // only the first frame carries the var headers, as recorded
+        let frames: Vec<FrameData> = (1..=3)
+            .map(|tick| {
+                let mut frame = FrameData {
+                    header: Header {
+                        num_buf: 1,
+                        num_vars: 1,
+                        buf_len: 4,
+                        var_header_offset: 1024,
+                        ..Default::default()
+                    },
+                    var_headers: (tick == 1).then(|| vec![speed]),
+                    session_info: None,
+                    raw_data: (tick as f32).to_le_bytes().to_vec(),
+                };
+                frame.header.var_buf[0].tick_count = tick;
+                frame.header.var_buf[0].buf_offset = 2048;
+                frame
+            })
+            .collect();
+        let serialized: Vec<Vec<u8>> = frames.iter().map(|f| f.serialize().unwrap()).collect();
+        assert!(serialized[1].len() < serialized[0].len());
+
+        for (frame, data) in frames.iter().zip(&serialized) {
+            let round_trip = FrameData::deserialize(data, 2).unwrap();
+            assert_eq!(round_trip.var_headers, frame.var_headers);
+            player.update(data).unwrap();
+            assert_eq!(var_header_at(1024), speed);
+        }
+        assert_eq!(player.var_headers, vec![speed]);
+
+        // a header pointing elsewhere gets the last var headers there
+        let mut moved = frames[2].clone();
+        moved.header.var_header_offset = 1536;
+        player.update(&moved.serialize().unwrap()).unwrap();
+        assert_eq!(var_header_at(1536), speed);
+        assert_eq!(player.var_header_offset, 1536);
+
+        moved.header.var_header_offset = 4090;
+        assert!(player.update(&moved.serialize().unwrap()).is_err());
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_rejects_data_outside_shm() {
+        let id = std::process::id();
+        let shm_name = format!("Local\\KsanaTestIRacingPlayerExtent-{}", id);
+        let event_name = format!("Local\\KsanaTestIRacingPlayerExtentEvent-{}", id);
+        let mut player = IRacingPlayer::create(&shm_name, 4096, &event_name, 2).unwrap();
+
+        let mut frame = FrameData {
+            header: Header {
+                num_buf: 1,
+                buf_len: 8,
+                ..Default::default()
+            },
+            var_headers: None,
+            session_info: None,
+            raw_data: vec![1; 8],
+        };
+        frame.header.var_buf[0].buf_offset = 4088;
+        assert!(player.update(&frame.serialize().unwrap()).is_ok());
+
+        frame.header.var_buf[0].buf_offset = 4089;
+        assert!(player.update(&frame.serialize().unwrap()).is_err());
+
+        frame.header.var_buf[0].buf_offset = -1;
+        assert!(player.update(&frame.serialize().unwrap()).is_err());
+
+        frame.header.var_buf[0].buf_offset = 0;
+        frame.session_info = Some(b"WeekendInfo:\n".to_vec());
+        frame.header.session_info_offset = i32::MAX;
+        assert!(player.update(&frame.serialize().unwrap()).is_err());
+    }
+
+    /// Fake sim with two vars in three buffers, played back to another mapping frame by
+    /// frame after recording, comparing what a client reads from each.
+    #[test]
+    #[cfg(not(miri))]
+    fn test_record_play_round_trip() {
+        use crate::Connector as _;
+        use crate::io::{Loader, Saver};
+        use crate::sims::iracing::connector::IRacingConnector;
+        use crate::sims::iracing::data::VarBuf;
+        use std::io::Cursor;
+
+        let id = std::process::id();
+        let sim_name = format!("Local\\KsanaTestIRacingRoundTripSim-{}", id);
+        let shm_name = format!("Local\\KsanaTestIRacingRoundTrip-{}", id);
+        let event_name = format!("Local\\KsanaTestIRacingRoundTripEvent-{}", id);
+        let size = 4096;
+        let as_bytes = |header: &Header| unsafe {
+            std::slice::from_raw_parts(header as *const Header as *const u8, Header::SIZE).to_vec()
+        };
+
+        let session_info = b"WeekendInfo:\n TrackName: ksana\n";
+        let mut header = Header {
+            ver: 2,
+            status: 1,
+            tick_rate: 60,
+            session_info_update: 1,
+            session_info_len: session_info.len() as i32,
+            session_info_offset: 1024,
+            num_vars: 2,
+            var_header_offset: 256,
+            num_buf: 3,
+            buf_len: 8,
+            ..Default::default()
+        };
+        for (i, buf) in header.var_buf.iter_mut().take(3).enumerate() {
+            *buf = VarBuf {
+                tick_count: 0,
+                buf_offset: 2048 + 64 * i as i32,
+                pad: [0; 2],
+            };
+        }
+        let mut var_headers = [
+            VarHeader {
+                var_type: 4,
+                count: 1,
+                ..Default::default()
+            },
+            VarHeader {
+                var_type: 4,
+                offset: 4,
+                count: 1,
+                ..Default::default()
+            },
+        ];
+        var_headers[0].name[..5].copy_from_slice(b"Speed");
+        var_headers[1].name[..3].copy_from_slice(b"RPM");
+        let var_headers_len = 2 * std::mem::size_of::<VarHeader>();
+
+        let mut sim = SharedMemoryWriter::create(&sim_name, size).unwrap();
+        unsafe {
+            for (i, var_header) in var_headers.iter().enumerate() {
+                let bytes = std::slice::from_raw_parts(
+                    var_header as *const VarHeader as *const u8,
+                    std::mem::size_of::<VarHeader>(),
+                );
+                sim.write(256 + i * std::mem::size_of::<VarHeader>(), bytes);
+            }
+            sim.write(1024, session_info);
+        }
+        let sim_reader = SharedMemoryReader::open(&sim_name, size).unwrap();
+        let snapshot = |reader: &SharedMemoryReader| unsafe {
+            std::slice::from_raw_parts(reader.as_ptr(), size).to_vec()
+        };
+
+        // every tick goes to the next buffer, the way the sim rotates them
+        let mut connector = IRacingConnector::with_name(&sim_name, true);
+        let mut recording = Vec::new();
+        let mut saver = Saver::new(&mut recording, 60, connector.info()).unwrap();
+        let mut recorded = vec![];
+        for tick in 1..=4 {
+            let latest = tick as usize % 3;
+            let offset = header.var_buf[latest].buf_offset as usize;
+            header.var_buf[latest].tick_count = tick;
+            unsafe {
+                sim.write(offset, &(tick as f32 * 10.0).to_le_bytes());
+                sim.write(offset + 4, &(tick as f32 * 1000.0).to_le_bytes());
+                sim.write(0, &as_bytes(&header));
+            }
+            if tick == 1 {
+                assert!(connector.connect());
+            }
+            saver.save(&connector.update().unwrap()).unwrap();
+            recorded.push((snapshot(&sim_reader), offset));
+        }
+        saver.finalize().unwrap();
+
+        let mut player = IRacingPlayer::create(&shm_name, size, &event_name, 2).unwrap();
+        let reader = SharedMemoryReader::open(&shm_name, size).unwrap();
+        let mut loader = Loader::new(Cursor::new(&recording)).unwrap();
+        for (sim_bytes, buf_offset) in &recorded {
+            player.update(&loader.load().unwrap().unwrap()).unwrap();
+            let played = snapshot(&reader);
+            for (start, len) in [
+                (0, Header::SIZE),
+                (256, var_headers_len),
+                (1024, session_info.len()),
+                (*buf_offset, 8),
+            ] {
+                assert_eq!(
+                    played[start..start + len],
+                    sim_bytes[start..start + len],
+                    "{} bytes at {}",
+                    len,
+                    start
+                );
+            }
+        }
+        assert_eq!(loader.load().unwrap(), None);
+    }
+}
@@ -0,0 +1,170 @@
+use super::data::{Header, VarHeader};
+use crate::shm::SharedMemoryReader;
+
+/// A typed view over the iRacing shared memory layout. Every accessor is bounds-checked
+/// against the mapping size and returns `None` if the layout points outside of it.
+pub struct IRacingShm<'a> {
+    shm: &'a SharedMemoryReader,
+}
+
+impl<'a> IRacingShm<'a> {
+    pub fn new(shm: &'a SharedMemoryReader) -> Self {
+        Self { shm }
+    }
+
+    fn bytes(&self, offset: i32, len: usize) -> Option<&'a [u8]> {
+        let offset = usize::try_from(offset).ok()?;
+        let end = offset.checked_add(len)?;
+        if end > self.shm.size() {
+            return None;
+        }
+
+        unsafe {
+            Some(std::slice::from_raw_parts(
+                self.shm.as_ptr().add(offset),
+                len,
+            ))
+        }
+    }
+
+    /// Copy of the whole mapping as the sim left it.
+    pub fn raw(&self) -> Option<Vec<u8>> {
+        self.bytes(0, self.shm.size()).map(|b| b.to_vec())
+    }
+
+    pub fn header(&self) -> Option<Header> {
+        let bytes = self.bytes(0, Header::SIZE)?;
+        unsafe { Some(std::ptr::read_unaligned(bytes.as_ptr() as *const Header)) }
+    }
+
+    pub fn var_headers(&self, header: &Header) -> Option<Vec<VarHeader>> {
+        let count = usize::try_from(header.num_vars).ok()?;
+        let var_header_size = std::mem::size_of::<VarHeader>();
+        let bytes = self.bytes(
+            header.var_header_offset,
+            count.checked_mul(var_header_size)?,
+        )?;
+
+        let var_headers = bytes
+            .chunks_exact(var_header_size)
+            .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const VarHeader) })
+            .collect();
+
+        Some(var_headers)
+    }
+
+    /// Session info YAML with the trailing null padding stripped.
+    pub fn session_info(&self, header: &Header) -> Option<Vec<u8>> {
+        let len = usize::try_from(header.session_info_len).ok()?;
+        let bytes = self.bytes(header.session_info_offset, len)?;
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(bytes[..len].to_vec())
+    }
+
+    /// Raw telemetry data of the buffer with the highest tick count.
+    pub fn latest_buffer(&self, header: &Header) -> Option<Vec<u8>> {
+        let var_buf = header.var_buf.get(header.latest_buf_index())?;
+        let len = usize::try_from(header.buf_len).ok()?;
+        self.bytes(var_buf.buf_offset, len).map(|b| b.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::SharedMemoryWriter;
+    use crate::sims::iracing::data::VarBuf;
+
+    fn as_bytes<T>(value: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_read_layout() {
+        let name = "Local\\KsanaTestIRacingShm";
+        let size = 4096;
+
+        let header = Header {
+            status: 1,
+            session_info_len: 64,
+            session_info_offset: 1024,
+            num_vars: 2,
+            var_header_offset: 512,
+            num_buf: 2,
+            buf_len: 8,
+            var_buf: [
+                VarBuf {
+                    tick_count: 10,
+                    buf_offset: 2048,
+                    pad: [0; 2],
+                },
+                VarBuf {
+                    tick_count: 11,
+                    buf_offset: 3072,
+                    pad: [0; 2],
+                },
+                VarBuf::default(),
+                VarBuf::default(),
+            ],
+            ..Default::default()
+        };
+        let var_header = VarHeader {
+            var_type: 4,
+            ..Default::default()
+        };
+
+        let mut writer = SharedMemoryWriter::create(name, size).unwrap();
+        unsafe {
+            writer.write(0, as_bytes(&header));
+            writer.write(512, as_bytes(&var_header));
+            writer.write(512 + size_of::<VarHeader>(), as_bytes(&var_header));
+            writer.write(1024, b"WeekendInfo:\n");
+            writer.write(3072, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        }
+
+        let reader = SharedMemoryReader::open(name, size).unwrap();
+        let view = IRacingShm::new(&reader);
+
+        let read_header = view.header().unwrap();
+        assert_eq!(read_header.num_vars, 2);
+        assert_eq!(view.var_headers(&read_header).unwrap(), vec![var_header; 2]);
+        assert_eq!(view.session_info(&read_header).unwrap(), b"WeekendInfo:\n");
+        assert_eq!(
+            view.latest_buffer(&read_header).unwrap(),
+            vec![1, 2, 3, 4, 5, 6, 7, 8]
+        );
+
+        let raw = view.raw().unwrap();
+        assert_eq!(raw.len(), size);
+        assert_eq!(&raw[1024..1037], b"WeekendInfo:\n");
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_out_of_bounds() {
+        let name = "Local\\KsanaTestIRacingShmBounds";
+        let size = 1024;
+
+        let _writer = SharedMemoryWriter::create(name, size).unwrap();
+        let reader = SharedMemoryReader::open(name, size).unwrap();
+        let view = IRacingShm::new(&reader);
+
+        let mut header = Header {
+            num_vars: 100,
+            var_header_offset: 512,
+            session_info_len: 10,
+            session_info_offset: -1,
+            num_buf: 1,
+            buf_len: 2048,
+            ..Default::default()
+        };
+
+        assert!(view.var_headers(&header).is_none());
+        assert!(view.session_info(&header).is_none());
+        assert!(view.latest_buffer(&header).is_none());
+
+        header.num_vars = -1;
+        assert!(view.var_headers(&header).is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Decoding and encoding of individual telemetry channels from the iRacing raw data buffer using the
+//! var headers describing the buffer layout.
+
+use super::data::VarHeader;
+use crate::sims::interpolate::{lerp, lerp_angle, lerp_lap_fraction};
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarType {
+    Char = 0,
+    Bool = 1,
+    Int = 2,
+    BitField = 3,
+    Float = 4,
+    Double = 5,
+}
+
+impl VarType {
+    pub fn from_raw(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Char),
+            1 => Some(Self::Bool),
+            2 => Some(Self::Int),
+            3 => Some(Self::BitField),
+            4 => Some(Self::Float),
+            5 => Some(Self::Double),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Char | Self::Bool => 1,
+            Self::Int | Self::BitField | Self::Float => 4,
+            Self::Double => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Char(u8),
+    Bool(bool),
+    Int(i32),
+    BitField(u32),
+    Float(f32),
+    Double(f64),
+}
+
+impl Value {
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Self::Char(v) => v as f64,
+            Self::Bool(v) => v as u8 as f64,
+            Self::Int(v) => v as f64,
+            Self::BitField(v) => v as f64,
+            Self::Float(v) => v as f64,
+            Self::Double(v) => v,
+        }
+    }
+}
+
+/// Looks up a channel by name.
+pub fn find<'a>(var_headers: &'a [VarHeader], name: &str) -> Option<&'a VarHeader> {
+    var_headers.iter().find(|vh| vh.name_str() == name)
+}
+
+/// Reads element `index` of the channel described by `vh` from the raw data buffer.
+/// Returns `None` if the type is unknown or the value lies outside the buffer.
+pub fn read(vh: &VarHeader, raw_data: &[u8], index: usize) -> Option<Value> {
+    let var_type = VarType::from_raw(vh.var_type)?;
+    if vh.offset < 0 || index >= vh.count.max(0) as usize {
+        return None;
+    }
+
+    let start = vh.offset as usize + index * var_type.size();
+    let bytes = raw_data.get(start..start + var_type.size())?;
+
+    let value = match var_type {
+        VarType::Char => Value::Char(bytes[0]),
+        VarType::Bool => Value::Bool(bytes[0] != 0),
+        VarType::Int => Value::Int(i32::from_le_bytes(bytes.try_into().ok()?)),
+        VarType::BitField => Value::BitField(u32::from_le_bytes(bytes.try_into().ok()?)),
+        VarType::Float => Value::Float(f32::from_le_bytes(bytes.try_into().ok()?)),
+        VarType::Double => Value::Double(f64::from_le_bytes(bytes.try_into().ok()?)),
+    };
+
+    Some(value)
+}
+
+/// Writes `value` as element `index` of the channel described by `vh` into the raw data
+/// buffer, converted to the channel type. Returns `None` if the type is unknown or the
+/// value lies outside the buffer.
+pub fn write(vh: &VarHeader, raw_data: &mut [u8], index: usize, value: f64) -> Option<()> {
+    let var_type = VarType::from_raw(vh.var_type)?;
+    if vh.offset < 0 || index >= vh.count.max(0) as usize {
+        return None;
+    }
+
+    let start = vh.offset as usize + index * var_type.size();
+    let bytes = raw_data.get_mut(start..start + var_type.size())?;
+
+    match var_type {
+        VarType::Char => bytes[0] = value as u8,
+        VarType::Bool => bytes[0] = u8::from(value != 0.0),
+        VarType::Int => bytes.copy_from_slice(&(value as i32).to_le_bytes()),
+        VarType::BitField => bytes.copy_from_slice(&(value as u32).to_le_bytes()),
+        VarType::Float => bytes.copy_from_slice(&(value as f32).to_le_bytes()),
+        VarType::Double => bytes.copy_from_slice(&value.to_le_bytes()),
+    }
+
+    Some(())
+}
+
+/// Looks up a channel by name and reads element `index` of it as `f64`.
+pub fn read_f64(
+    var_headers: &[VarHeader],
+    raw_data: &[u8],
+    name: &str,
+    index: usize,
+) -> Option<f64> {
+    let vh = find(var_headers, name)?;
+    read(vh, raw_data, index).map(|v| v.as_f64())
+}
+
+/// Raw data at `t` between the `previous` and `next` buffers of the layout `var_headers`.
+/// Float and double channels are interpolated, angles (unit `rad`) the short way round and
+/// lap fractions across the start/finish line. Chars, bools, ints and bitfields are flags,
+/// enums or counts and hold the previous value.
+pub fn interpolate(var_headers: &[VarHeader], previous: &[u8], next: &[u8], t: f64) -> Vec<u8> {
+    let mut raw = previous.to_vec();
+
+    for vh in var_headers {
+        let lap_fraction = vh.name_str().ends_with("LapDistPct");
+        let blend: fn(f64, f64, f64) -> f64 = if lap_fraction {
+            lerp_lap_fraction
+        } else if vh.unit_str() == "rad" {
+            lerp_angle
+        } else {
+            lerp
+        };
+
+        for index in 0..vh.count.max(0) as usize {
+            let (Some(a), Some(b)) = (read(vh, previous, index), read(vh, next, index)) else {
+                continue;
+            };
+            // lap fractions are -1 for cars not in the world
+            if lap_fraction && (a.as_f64() < 0.0 || b.as_f64() < 0.0) {
+                continue;
+            }
+
+            let bytes = match (a, b) {
+                (Value::Float(a), Value::Float(b)) => {
+                    (blend(a as f64, b as f64, t) as f32).to_le_bytes().to_vec()
+                }
+                (Value::Double(a), Value::Double(b)) => blend(a, b, t).to_le_bytes().to_vec(),
+                _ => continue,
+            };
+            // read checked the value is inside both buffers
+            let start = vh.offset as usize + index * bytes.len();
+            raw[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+    }
+
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sims::iracing::data::IRSDK_MAX_STRING;
+
+    fn var_header(name: &str, var_type: VarType, offset: i32, count: i32) -> VarHeader {
+        let mut vh = VarHeader {
+            var_type: var_type as i32,
+            offset,
+            count,
+            ..Default::default()
+        };
+        vh.name[..name.len()].copy_from_slice(name.as_bytes());
+        vh
+    }
+
+    #[test]
+    fn test_find() {
+        let headers = vec![
+            var_header("Speed", VarType::Float, 0, 1),
+            var_header("SpeedLimit", VarType::Float, 4, 1),
+        ];
+
+        assert_eq!(find(&headers, "SpeedLimit").unwrap().offset, 4);
+        assert_eq!(find(&headers, "Speed").unwrap().offset, 0);
+        assert!(find(&headers, "Spee").is_none());
+        assert!(find(&headers, &"x".repeat(IRSDK_MAX_STRING + 1)).is_none());
+    }
+
+    #[test]
+    fn test_read_values() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&1.5f32.to_le_bytes());
+        raw.extend_from_slice(&7i32.to_le_bytes());
+        raw.extend_from_slice(&8i32.to_le_bytes());
+        raw.extend_from_slice(&2.25f64.to_le_bytes());
+        raw.push(1);
+
+        let speed = var_header("Speed", VarType::Float, 0, 1);
+        let laps = var_header("CarIdxLap", VarType::Int, 4, 2);
+        let time = var_header("SessionTime", VarType::Double, 12, 1);
+        let on_track = var_header("IsOnTrack", VarType::Bool, 20, 1);
+
+        assert_eq!(read(&speed, &raw, 0), Some(Value::Float(1.5)));
+        assert_eq!(read(&laps, &raw, 0), Some(Value::Int(7)));
+        assert_eq!(read(&laps, &raw, 1), Some(Value::Int(8)));
+        assert_eq!(read(&time, &raw, 0), Some(Value::Double(2.25)));
+        assert_eq!(read(&on_track, &raw, 0), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_write_values() {
+        let speed = var_header("Speed", VarType::Float, 0, 1);
+        let laps = var_header("CarIdxLap", VarType::Int, 4, 2);
+        let time = var_header("SessionTime", VarType::Double, 12, 1);
+        let on_track = var_header("IsOnTrack", VarType::Bool, 20, 1);
+
+        let mut raw = vec![0u8; 21];
+        write(&speed, &mut raw, 0, 1.5).unwrap();
+        write(&laps, &mut raw, 1, 8.0).unwrap();
+        write(&time, &mut raw, 0, 2.25).unwrap();
+        write(&on_track, &mut raw, 0, 1.0).unwrap();
+
+        assert_eq!(read(&speed, &raw, 0), Some(Value::Float(1.5)));
+        assert_eq!(read(&laps, &raw, 0), Some(Value::Int(0)));
+        assert_eq!(read(&laps, &raw, 1), Some(Value::Int(8)));
+        assert_eq!(read(&time, &raw, 0), Some(Value::Double(2.25)));
+        assert_eq!(read(&on_track, &raw, 0), Some(Value::Bool(true)));
+
+        assert_eq!(write(&laps, &mut raw, 2, 1.0), None);
+        assert_eq!(write(&time, &mut raw[..16], 0, 1.0), None);
+    }
+
+    #[test]
+    fn test_read_out_of_bounds() {
+        let raw = vec![0u8; 8];
+
+        let past_count = var_header("CarIdxLap", VarType::Int, 0, 2);
+        assert_eq!(read(&past_count, &raw, 2), None);
+
+        let past_buffer = var_header("Speed", VarType::Double, 4, 1);
+        assert_eq!(read(&past_buffer, &raw, 0), None);
+
+        let mut unknown_type = var_header("Speed", VarType::Float, 0, 1);
+        unknown_type.var_type = 42;
+        assert_eq!(read(&unknown_type, &raw, 0), None);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut yaw = var_header("Yaw", VarType::Float, 8, 1);
+        yaw.unit[..3].copy_from_slice(b"rad");
+        let headers = [
+            var_header("Speed", VarType::Float, 0, 1),
+            var_header("Gear", VarType::Int, 4, 1),
+            yaw,
+            var_header("CarIdxLapDistPct", VarType::Float, 12, 2),
+            var_header("SessionTime", VarType::Double, 20, 1),
+        ];
+        let raw = |speed: f32, gear: i32, yaw: f32, pct: [f32; 2], time: f64| {
+            let mut raw = Vec::new();
+            raw.extend_from_slice(&speed.to_le_bytes());
+            raw.extend_from_slice(&gear.to_le_bytes());
+            raw.extend_from_slice(&yaw.to_le_bytes());
+            raw.extend_from_slice(&pct[0].to_le_bytes());
+            raw.extend_from_slice(&pct[1].to_le_bytes());
+            raw.extend_from_slice(&time.to_le_bytes());
+            raw
+        };
+
+        let previous = raw(40.0, 3, 3.0, [0.9, -1.0], 10.0);
+        let next = raw(50.0, 4, -3.0, [0.3, 0.5], 11.0);
+        let between = interpolate(&headers, &previous, &next, 0.5);
+
+        let value = |name, index| read_f64(&headers, &between, name, index).unwrap();
+        assert_eq!(value("Speed", 0), 45.0);
+        assert_eq!(value("Gear", 0), 3.0);
+        assert!((value("Yaw", 0).abs() - std::f64::consts::PI).abs() < 1e-3);
+        assert!((value("CarIdxLapDistPct", 0) - 0.1).abs() < 1e-6);
+        assert_eq!(value("CarIdxLapDistPct", 1), -1.0);
+        assert_eq!(value("SessionTime", 0), 10.5);
+    }
+}
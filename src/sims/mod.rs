@@ -1,3 +1,9 @@
 mod ac;
 pub mod assettocorsa;
+pub mod beamng;
+pub mod dirt;
+pub mod f1;
 pub mod iracing;
+pub mod pcars2;
+
+use ksana::sims::interpolate;
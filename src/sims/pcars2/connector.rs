@@ -0,0 +1,165 @@
+use std::sync::atomic::{Ordering, fence};
+
+use super::data::{
+    CURRENT_PAYLOAD_VERSION, GAME_EXITED, PAGE_SIZE, SEQUENCE_NUMBER_OFFSET, SharedMemoryPage,
+};
+use super::shm::PCARS2_SHM;
+use crate::shm::SharedMemoryReader;
+use crate::{Connector, SimInfo};
+
+// A write of the game takes microseconds, so a torn read is retried right away
+const READ_ATTEMPTS: u32 = 16;
+
+fn sequence_number(shm: &SharedMemoryReader) -> u32 {
+    unsafe { std::ptr::read_volatile(shm.as_ptr().add(SEQUENCE_NUMBER_OFFSET) as *const u32) }
+}
+
+/// Copies the page between two reads of the sequence number. The copy is torn if the game
+/// was writing (odd number) or started writing during the copy (the numbers differ), then
+/// it is retried. `None` if no attempt got a consistent copy.
+fn read_page(shm: &SharedMemoryReader) -> Option<SharedMemoryPage> {
+    for _ in 0..READ_ATTEMPTS {
+        let before = sequence_number(shm);
+        if before.is_multiple_of(2) {
+            fence(Ordering::Acquire);
+            let page = unsafe { std::ptr::read(shm.as_ptr() as *const SharedMemoryPage) };
+            fence(Ordering::Acquire);
+            if sequence_number(shm) == before {
+                return Some(page);
+            }
+        }
+        std::hint::spin_loop();
+    }
+    None
+}
+
+pub struct Pcars2Connector {
+    shm: Option<SharedMemoryReader>,
+    shm_name: &'static str,
+    last_sequence_number: Option<u32>,
+}
+
+impl Pcars2Connector {
+    pub fn new() -> Self {
+        Self::create(PCARS2_SHM)
+    }
+
+    fn create(shm_name: &'static str) -> Self {
+        Self {
+            shm: None,
+            shm_name,
+            last_sequence_number: None,
+        }
+    }
+}
+
+impl Default for Pcars2Connector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Connector for Pcars2Connector {
+    fn connect(&mut self) -> bool {
+        let Ok(shm) = SharedMemoryReader::open(self.shm_name, PAGE_SIZE) else {
+            return false;
+        };
+        // the mapping outlives the game if another app keeps it open
+        match read_page(&shm) {
+            Some(page) if page.game_state != GAME_EXITED => {
+                self.shm = Some(shm);
+                self.reset();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.shm = None;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.last_sequence_number = None;
+    }
+
+    fn update(&mut self) -> Option<Vec<u8>> {
+        let page = read_page(self.shm.as_ref()?)?;
+
+        if page.game_state == GAME_EXITED {
+            return None;
+        }
+
+        if self.last_sequence_number == Some(page.sequence_number) {
+            // No new data
+            return None;
+        }
+        self.last_sequence_number = Some(page.sequence_number);
+
+        Some(page.as_bytes().to_vec())
+    }
+
+    fn info(&self) -> SimInfo {
+        SimInfo {
+            id: *b"pc2_",
+            payload_version: CURRENT_PAYLOAD_VERSION,
+            native_hz: 0,
+        }
+    }
+
+    fn peek_raw(&self) -> Option<Vec<u8>> {
+        read_page(self.shm.as_ref()?).map(|page| page.as_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::SharedMemoryWriter;
+    use crate::sims::pcars2::data::{GAME_INGAME_PLAYING, GAME_STATE_OFFSET};
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_torn_read_retried() {
+        let name = "Local\\KsanaTestPcars2Connector";
+        let mut writer = SharedMemoryWriter::create(name, PAGE_SIZE).unwrap();
+        let mut page = SharedMemoryPage {
+            game_state: GAME_INGAME_PLAYING,
+            sequence_number: 2,
+            ..Default::default()
+        };
+        page.content[0] = 1;
+        unsafe {
+            writer.write(0, page.as_bytes());
+        }
+
+        let mut connector = Pcars2Connector::create(name);
+        assert!(connector.connect());
+        let first = SharedMemoryPage::deserialize(&connector.update().unwrap()).unwrap();
+        assert_eq!(first, page);
+        assert!(connector.update().is_none());
+
+        // the game is in the middle of writing the next page
+        unsafe {
+            writer.write(SEQUENCE_NUMBER_OFFSET, &3u32.to_le_bytes());
+            writer.write(std::mem::offset_of!(SharedMemoryPage, content), &[2]);
+        }
+        assert!(connector.update().is_none());
+
+        unsafe {
+            writer.write(SEQUENCE_NUMBER_OFFSET, &4u32.to_le_bytes());
+        }
+        let second = SharedMemoryPage::deserialize(&connector.update().unwrap()).unwrap();
+        assert_eq!(second.sequence_number, 4);
+        assert_eq!(second.content[0], 2);
+        assert!(connector.update().is_none());
+
+        // an exited game is not recorded
+        unsafe {
+            writer.write(SEQUENCE_NUMBER_OFFSET, &6u32.to_le_bytes());
+            writer.write(GAME_STATE_OFFSET, &GAME_EXITED.to_le_bytes());
+        }
+        assert!(connector.update().is_none());
+    }
+}
@@ -0,0 +1,102 @@
+// Project CARS 2 shared memory, also used by Automobilista 2. The layout follows the
+// SharedMemory struct of the game's SDK header, only the fields ksana needs are typed and
+// the rest is copied as is. A frame is the whole page.
+
+use std::io;
+
+pub const CURRENT_PAYLOAD_VERSION: i32 = 1;
+
+// mGameState values
+pub const GAME_EXITED: u32 = 0;
+pub const GAME_INGAME_PLAYING: u32 = 2;
+
+/// Offset of mSequenceNumber, the first field added in Project CARS 2 (SDK version 8)
+pub const SEQUENCE_NUMBER_OFFSET: usize = 7320;
+
+/// The SDK struct is around 21KB, padded up to the end of its last memory page
+pub const PAGE_SIZE: usize = 24 * 1024;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedMemoryPage {
+    pub version: u32,
+    pub build_version_number: u32,
+    pub game_state: u32,
+    pub session_state: u32,
+    pub race_state: u32,
+    pub content: [u8; SEQUENCE_NUMBER_OFFSET - 20],
+    /// Incremented by the game before and after writing the page, odd while a write is in
+    /// progress
+    pub sequence_number: u32,
+    pub tail: [u8; PAGE_SIZE - SEQUENCE_NUMBER_OFFSET - 4],
+}
+
+pub const GAME_STATE_OFFSET: usize = std::mem::offset_of!(SharedMemoryPage, game_state);
+
+// Both offsets are part of the recording format and shared with the apps reading the page
+const _: () = {
+    assert!(size_of::<SharedMemoryPage>() == PAGE_SIZE);
+    assert!(std::mem::offset_of!(SharedMemoryPage, sequence_number) == SEQUENCE_NUMBER_OFFSET);
+};
+
+impl Default for SharedMemoryPage {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            build_version_number: 0,
+            game_state: GAME_EXITED,
+            session_state: 0,
+            race_state: 0,
+            content: [0; SEQUENCE_NUMBER_OFFSET - 20],
+            sequence_number: 0,
+            tail: [0; PAGE_SIZE - SEQUENCE_NUMBER_OFFSET - 4],
+        }
+    }
+}
+
+impl SharedMemoryPage {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, PAGE_SIZE) }
+    }
+
+    /// Parses a frame, which must be exactly one page.
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Project CARS 2 frame has {} bytes, expected {}",
+                    bytes.len(),
+                    PAGE_SIZE
+                ),
+            ));
+        }
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut page = SharedMemoryPage {
+            version: 9,
+            game_state: GAME_INGAME_PLAYING,
+            sequence_number: 42,
+            ..Default::default()
+        };
+        page.content[100] = 7;
+        page.tail[0] = 8;
+
+        let bytes = page.as_bytes();
+        assert_eq!(
+            &bytes[SEQUENCE_NUMBER_OFFSET..SEQUENCE_NUMBER_OFFSET + 4],
+            &42u32.to_le_bytes()
+        );
+        assert_eq!(SharedMemoryPage::deserialize(bytes).unwrap(), page);
+
+        assert!(SharedMemoryPage::deserialize(&bytes[1..]).is_err());
+    }
+}
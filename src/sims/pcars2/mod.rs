@@ -0,0 +1,4 @@
+pub mod connector;
+pub mod data;
+pub mod player;
+pub mod shm;
@@ -0,0 +1,142 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{Ordering, fence};
+
+use super::data::{
+    GAME_EXITED, GAME_STATE_OFFSET, PAGE_SIZE, SEQUENCE_NUMBER_OFFSET, SharedMemoryPage,
+};
+use super::shm::PCARS2_SHM;
+use crate::Player;
+use crate::shm::SharedMemoryWriter;
+
+pub struct Pcars2Player {
+    shm: Option<SharedMemoryWriter>,
+    // the recorded sequence numbers are replaced, readers only need them to change
+    sequence_number: u32,
+}
+
+impl Pcars2Player {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::create(PCARS2_SHM)
+    }
+
+    /// Player writing to the mapping `shm_name` instead of the one the game uses.
+    pub fn with_name(shm_name: &str) -> anyhow::Result<Self> {
+        Self::create(shm_name)
+    }
+
+    fn create(shm_name: &str) -> anyhow::Result<Self> {
+        let shm = SharedMemoryWriter::create(shm_name, PAGE_SIZE)?;
+        Ok(Self {
+            shm: Some(shm),
+            sequence_number: 0,
+        })
+    }
+
+    /// Writes `parts` (offset and bytes, not overlapping the sequence number) the way the
+    /// game does: the sequence number is odd while writing and even again after, so
+    /// readers retry a copy taken in between.
+    fn write_fenced(&mut self, parts: &[(usize, &[u8])]) -> anyhow::Result<()> {
+        let shm = self
+            .shm
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Player is stopped"))?;
+
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        unsafe {
+            shm.write(SEQUENCE_NUMBER_OFFSET, &self.sequence_number.to_le_bytes());
+        }
+        fence(Ordering::Release);
+
+        for (offset, bytes) in parts {
+            unsafe {
+                shm.write(*offset, bytes);
+            }
+        }
+
+        fence(Ordering::Release);
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        unsafe {
+            shm.write(SEQUENCE_NUMBER_OFFSET, &self.sequence_number.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl Player for Pcars2Player {
+    fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let page = SharedMemoryPage::deserialize(data)?;
+        let bytes = page.as_bytes();
+        self.write_fenced(&[
+            (0, &bytes[..SEQUENCE_NUMBER_OFFSET]),
+            (
+                SEQUENCE_NUMBER_OFFSET + 4,
+                &bytes[SEQUENCE_NUMBER_OFFSET + 4..],
+            ),
+        ])
+    }
+
+    fn stop(&mut self) {
+        // nothing to do if already stopped
+        let _ = self.write_fenced(&[(GAME_STATE_OFFSET, &GAME_EXITED.to_le_bytes())]);
+        self.shm = None;
+    }
+
+    fn expected_frame_size(&self) -> Option<RangeInclusive<usize>> {
+        Some(PAGE_SIZE..=PAGE_SIZE)
+    }
+}
+
+// Make sure readers don't see a running game after playback ends, even on a panic
+impl Drop for Pcars2Player {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shm::SharedMemoryReader;
+    use crate::sims::pcars2::data::GAME_INGAME_PLAYING;
+
+    fn read(reader: &SharedMemoryReader) -> SharedMemoryPage {
+        unsafe { std::ptr::read(reader.as_ptr() as *const SharedMemoryPage) }
+    }
+
+    #[test]
+    #[cfg(not(miri))]
+    fn test_sequence_fences() {
+        let name = format!("Local\\KsanaTestPcars2Player-{}", std::process::id());
+        let mut player = Pcars2Player::create(&name).unwrap();
+        let reader = SharedMemoryReader::open(&name, PAGE_SIZE).unwrap();
+
+        let mut page = SharedMemoryPage {
+            version: 9,
+            game_state: GAME_INGAME_PLAYING,
+            sequence_number: 1001,
+            ..Default::default()
+        };
+        page.tail[10] = 5;
+
+        player.update(page.as_bytes()).unwrap();
+        let played = read(&reader);
+        // even and counted by the player, not the recorded number
+        assert_eq!(played.sequence_number, 2);
+        assert_eq!(
+            SharedMemoryPage {
+                sequence_number: 1001,
+                ..played
+            },
+            page
+        );
+
+        player.update(page.as_bytes()).unwrap();
+        assert_eq!(read(&reader).sequence_number, 4);
+
+        drop(player);
+        let stopped = read(&reader);
+        assert_eq!(stopped.game_state, GAME_EXITED);
+        assert_eq!(stopped.sequence_number, 6);
+        assert_eq!(stopped.tail[10], 5);
+    }
+}
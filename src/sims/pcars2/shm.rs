@@ -0,0 +1 @@
+pub const PCARS2_SHM: &str = "$pcars2$";
@@ -1,6 +1,22 @@
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
-use super::traits::Sleeper;
+pub use super::traits::Pacing;
+use super::traits::{Clock, Sleeper};
+
+// idle waits are split so a quit request takes effect within this time
+pub const QUIT_CHECK_MS: u64 = 50;
+
+/// Sleeps `ms` in short steps, returning early once `quit_flag` is set. For idle waits that
+/// can be long, e.g. between scans for a sim, so Ctrl+C doesn't wait for them to end.
+pub fn sleep_unless_quit(quit_flag: &AtomicBool, sleeper: &dyn Sleeper, ms: u64) {
+    let mut remaining_ms = ms;
+    while remaining_ms > 0 && !quit_flag.load(Ordering::Relaxed) {
+        let step_ms = remaining_ms.min(QUIT_CHECK_MS);
+        sleeper.sleep_ms(step_ms);
+        remaining_ms -= step_ms;
+    }
+}
 
 #[derive(Default)]
 pub struct AdaptiveSleeper {}
@@ -8,7 +24,7 @@ pub struct AdaptiveSleeper {}
 impl Sleeper for AdaptiveSleeper {
     fn sleep_ms(&self, ms: u64) {
         let start = Instant::now();
-        std::thread::sleep(Duration::from_millis(ms - 1));
+        std::thread::sleep(Duration::from_millis(ms.saturating_sub(1)));
         while start.elapsed().as_millis() < ms as u128 {
             std::hint::spin_loop();
         }
@@ -24,3 +40,120 @@ impl Sleeper for SimpleSleeper {
         std::thread::sleep(Duration::from_millis(ms));
     }
 }
+
+#[derive(Default)]
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Simulated time, advanced by sleeping on it instead of waiting, so paced loops run
+/// instantly and always see the same times.
+#[cfg(test)]
+pub struct MockClock {
+    now: std::cell::Cell<Instant>,
+    wall_time: std::cell::Cell<SystemTime>,
+}
+
+#[cfg(test)]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            now: std::cell::Cell::new(Instant::now()),
+            wall_time: std::cell::Cell::new(SystemTime::now()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Passes `duration`, e.g. the time the work between two sleeps takes.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+        self.wall_time.set(self.wall_time.get() + duration);
+    }
+
+    /// Sets the wall clock like an NTP sync would, without time passing.
+    pub fn set_wall_time(&self, wall_time: SystemTime) {
+        self.wall_time.set(wall_time);
+    }
+
+    pub fn pacing(&self) -> Pacing<'_> {
+        Pacing {
+            sleeper: self,
+            clock: self,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.wall_time.get()
+    }
+}
+
+#[cfg(test)]
+impl Sleeper for MockClock {
+    fn sleep_ms(&self, ms: u64) {
+        self.advance(Duration::from_millis(ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records the sleeps and sets the quit flag during the sleep `quit_on`.
+    struct MockSleeper<'a> {
+        sleeps: RefCell<Vec<u64>>,
+        quit_on: usize,
+        quit_flag: &'a AtomicBool,
+    }
+
+    impl Sleeper for MockSleeper<'_> {
+        fn sleep_ms(&self, ms: u64) {
+            let mut sleeps = self.sleeps.borrow_mut();
+            sleeps.push(ms);
+            if sleeps.len() == self.quit_on {
+                self.quit_flag.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sleep_unless_quit() {
+        let quit_flag = AtomicBool::new(false);
+        let sleeper = MockSleeper {
+            sleeps: RefCell::new(Vec::new()),
+            quit_on: usize::MAX,
+            quit_flag: &quit_flag,
+        };
+        sleep_unless_quit(&quit_flag, &sleeper, 120);
+        assert_eq!(*sleeper.sleeps.borrow(), [50, 50, 20]);
+
+        // the flag is checked between the steps, the rest of the wait is skipped
+        let sleeper = MockSleeper {
+            sleeps: RefCell::new(Vec::new()),
+            quit_on: 2,
+            quit_flag: &quit_flag,
+        };
+        sleep_unless_quit(&quit_flag, &sleeper, 1000);
+        assert_eq!(*sleeper.sleeps.borrow(), [50, 50]);
+
+        sleep_unless_quit(&quit_flag, &sleeper, 1000);
+        assert_eq!(sleeper.sleeps.borrow().len(), 2);
+    }
+}
@@ -1,25 +1,98 @@
+use std::ops::RangeInclusive;
+use std::time::{Instant, SystemTime};
+
 use crate::io::IOError;
 
 pub trait Sleeper {
     fn sleep_ms(&self, ms: u64);
 }
 
+/// Source of the time paced loops measure their ticks with, so tests can run them on a
+/// simulated clock.
+pub trait Clock {
+    /// Monotonic time, for measuring intervals
+    fn now(&self) -> Instant;
+    /// Wall-clock time, for stamping absolute times. May jump when the system clock is
+    /// adjusted
+    fn wall_time(&self) -> SystemTime;
+}
+
+/// What a loop keeping a rate sleeps with and reads the time from.
+#[derive(Clone, Copy)]
+pub struct Pacing<'a> {
+    pub sleeper: &'a dyn Sleeper,
+    pub clock: &'a dyn Clock,
+}
+
+impl Pacing<'_> {
+    /// Milliseconds passed since `start`.
+    pub fn elapsed_ms(&self, start: Instant) -> f64 {
+        self.clock.now().duration_since(start).as_secs_f64() * 1000.0
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct SimInfo {
     pub id: [u8; 4],
     pub payload_version: i32,
+    /// Rate the sim updates its data at, 0 if unknown
+    pub native_hz: u32,
 }
 
 pub trait Connector {
+    /// Tries once to connect to the sim, false if it isn't running. Quit is only checked
+    /// between attempts, so an attempt should return promptly, within a few milliseconds,
+    /// whether the sim is up, half up or not running, rather than wait for it.
     fn connect(&mut self) -> bool;
     fn disconnect(&mut self);
     fn update(&mut self) -> Option<Vec<u8>>;
     fn info(&self) -> SimInfo;
+
+    /// Forgets which data was already returned by `update`, so the next update returns the
+    /// current data as a complete frame even if it didn't change, without reconnecting.
+    fn reset(&mut self) {}
+
+    /// Frame standing in for `dropped` and `newer`, two frames returned by `update` one
+    /// after the other of which only one is saved. Parts `newer` leaves out as unchanged
+    /// since `dropped` are taken from `dropped`. Connectors returning complete frames keep
+    /// the default, `newer`.
+    fn merge(&self, _dropped: Vec<u8>, newer: Vec<u8>) -> Vec<u8> {
+        newer
+    }
+
+    /// Rate the connected sim updates its data at, if the sim reports it.
+    fn native_hz(&self) -> Option<u32> {
+        None
+    }
+
+    /// Copy of the whole shared memory mapping the connector reads, including the parts
+    /// `update` leaves out. `None` if not connected or the sim has no single mapping.
+    fn peek_raw(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 pub trait Player {
     fn update(&mut self, data: &[u8]) -> anyhow::Result<()>;
     fn stop(&mut self);
+
+    /// Range of valid frame sizes for sims with fixed-size frames, `None` if frames vary in
+    /// size. Used to catch recordings of another sim before they are written to shared memory.
+    fn expected_frame_size(&self) -> Option<RangeInclusive<usize>> {
+        None
+    }
+
+    /// Frame at `t` (0 < t < 1) between the recorded frames `previous` and `next`, for
+    /// playing at a multiple of the recorded rate. `previous` is the last frame passed to
+    /// `update`. `None` if the sim doesn't support interpolation.
+    fn interpolate(
+        &self,
+        _previous: &[u8],
+        _next: &[u8],
+        _t: f64,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -41,4 +114,19 @@ pub enum PlayError {
 
     #[error("Failed to update player: {0}")]
     FailedToUpdatePlayer(anyhow::Error),
+
+    #[error("Failed to update player {count} times in a row: {error}")]
+    TooManyUpdateFailures { count: u32, error: anyhow::Error },
+
+    #[error(
+        "Shared memory {0} already exists, the sim appears to be running (--force plays anyway)"
+    )]
+    SimRunning(String),
+
+    #[error("Frame size {actual} doesn't match the sim, expected {min}-{max} bytes")]
+    FrameSizeMismatch {
+        actual: usize,
+        min: usize,
+        max: usize,
+    },
 }
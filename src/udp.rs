@@ -0,0 +1,313 @@
+use std::io::{self, ErrorKind};
+use std::marker::PhantomData;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+
+use crate::{Connector, Player, SimInfo};
+
+// Largest payload of a UDP datagram, packets are always received whole
+const MAX_PACKET_SIZE: usize = 65507;
+
+/// Non-blocking socket receiving the telemetry packets a sim sends to a port.
+pub struct UdpReceiver {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl UdpReceiver {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+
+    #[cfg(test)]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Receives every queued packet and returns the newest one `accept` is true for, `None`
+    /// if there was none. Older packets are dropped, a frame is the latest state of the sim.
+    pub fn latest(&mut self, accept: impl Fn(&[u8]) -> bool) -> io::Result<Option<Vec<u8>>> {
+        let mut latest = None;
+        loop {
+            match self.socket.recv(&mut self.buffer) {
+                Ok(len) if accept(&self.buffer[..len]) => {
+                    latest = Some(self.buffer[..len].to_vec());
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(latest),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Receives every queued packet and returns the ones `accept` is true for, in the order
+    /// they were received. For sims spreading their data over several kinds of packets.
+    pub fn all(&mut self, accept: impl Fn(&[u8]) -> bool) -> io::Result<Vec<Vec<u8>>> {
+        let mut packets = vec![];
+        loop {
+            match self.socket.recv(&mut self.buffer) {
+                Ok(len) if accept(&self.buffer[..len]) => {
+                    packets.push(self.buffer[..len].to_vec());
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(packets),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Socket sending packets to the address apps expect a sim to send them to.
+pub struct UdpSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl UdpSender {
+    pub fn new(target: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        Ok(Self { socket, target })
+    }
+
+    pub fn send(&self, packet: &[u8]) -> io::Result<()> {
+        self.socket.send_to(packet, self.target)?;
+        Ok(())
+    }
+}
+
+/// Telemetry packet of a sim sending its whole state in a single kind of packet of a fixed
+/// size. A frame is a whole packet, recorded with `PacketConnector` and played back with
+/// `PacketPlayer`.
+pub trait SimPacket {
+    /// Id of the recordings
+    const SIM_ID: [u8; 4];
+    const PAYLOAD_VERSION: i32;
+    /// Port the sim sends to unless configured otherwise
+    const DEFAULT_PORT: u16;
+    /// Size of every packet, packets of other sizes are something else and ignored
+    const SIZE: usize;
+
+    /// Checks that recorded bytes are a well-formed packet before they are sent.
+    fn validate(bytes: &[u8]) -> io::Result<()>;
+}
+
+/// Receives the packets the sim sends to `addr`. The sims only send while driving, the
+/// connection is the socket and ends when the packets stop.
+pub struct PacketConnector<P: SimPacket> {
+    receiver: Option<UdpReceiver>,
+    addr: SocketAddr,
+    // received while connecting, returned by the first update
+    pending: Option<Vec<u8>>,
+    packet: PhantomData<P>,
+}
+
+impl<P: SimPacket> PacketConnector<P> {
+    pub fn new() -> Self {
+        Self::create((Ipv4Addr::LOCALHOST, P::DEFAULT_PORT).into())
+    }
+
+    fn create(addr: SocketAddr) -> Self {
+        Self {
+            receiver: None,
+            addr,
+            pending: None,
+            packet: PhantomData,
+        }
+    }
+
+    fn is_packet(packet: &[u8]) -> bool {
+        packet.len() == P::SIZE
+    }
+}
+
+impl<P: SimPacket> Default for PacketConnector<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: SimPacket> Connector for PacketConnector<P> {
+    fn connect(&mut self) -> bool {
+        if self.receiver.is_none() {
+            // the port may be taken by another telemetry app, retried on the next attempt
+            self.receiver = UdpReceiver::bind(self.addr).ok();
+        }
+        let Some(receiver) = self.receiver.as_mut() else {
+            return false;
+        };
+
+        match receiver.latest(Self::is_packet) {
+            Ok(Some(packet)) => {
+                self.pending = Some(packet);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.receiver = None;
+        self.pending = None;
+    }
+
+    fn update(&mut self) -> Option<Vec<u8>> {
+        let latest = self.receiver.as_mut()?.latest(Self::is_packet).ok()?;
+        latest.or_else(|| self.pending.take())
+    }
+
+    fn info(&self) -> SimInfo {
+        SimInfo {
+            id: P::SIM_ID,
+            payload_version: P::PAYLOAD_VERSION,
+            native_hz: 0,
+        }
+    }
+}
+
+/// Sends the recorded packets to the port telemetry apps listen on, as the sim would.
+pub struct PacketPlayer<P: SimPacket> {
+    sender: UdpSender,
+    packet: PhantomData<P>,
+}
+
+impl<P: SimPacket> PacketPlayer<P> {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::create((Ipv4Addr::LOCALHOST, P::DEFAULT_PORT).into())
+    }
+
+    fn create(target: SocketAddr) -> anyhow::Result<Self> {
+        Ok(Self {
+            sender: UdpSender::new(target)?,
+            packet: PhantomData,
+        })
+    }
+}
+
+impl<P: SimPacket> Player for PacketPlayer<P> {
+    fn update(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        // only well-formed packets are sent, apps expect the exact size
+        P::validate(data)?;
+        self.sender.send(data)?;
+        Ok(())
+    }
+
+    // the sim just stops sending, so do the apps
+    fn stop(&mut self) {}
+
+    fn expected_frame_size(&self) -> Option<RangeInclusive<usize>> {
+        Some(P::SIZE..=P::SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Waits until a packet sent over loopback is queued, `latest` never blocks.
+    fn wait_latest(
+        receiver: &mut UdpReceiver,
+        accept: impl Fn(&[u8]) -> bool + Copy,
+    ) -> Option<Vec<u8>> {
+        for _ in 0..100 {
+            if let Some(packet) = receiver.latest(accept).unwrap() {
+                return Some(packet);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        None
+    }
+
+    struct TestPacket;
+
+    impl SimPacket for TestPacket {
+        const SIM_ID: [u8; 4] = *b"test";
+        const PAYLOAD_VERSION: i32 = 1;
+        const DEFAULT_PORT: u16 = 0;
+        const SIZE: usize = 8;
+
+        fn validate(bytes: &[u8]) -> io::Result<()> {
+            if bytes.len() != Self::SIZE {
+                return Err(io::Error::from(ErrorKind::InvalidData));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_latest_accepted_packet() {
+        let mut receiver = UdpReceiver::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        assert_eq!(receiver.latest(|_| true).unwrap(), None);
+
+        let sender = UdpSender::new(receiver.local_addr().unwrap()).unwrap();
+        sender.send(b"first").unwrap();
+        sender.send(b"second").unwrap();
+        sender.send(b"bad").unwrap();
+        // the packets are queued in order, wait for the last one before draining
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let latest = wait_latest(&mut receiver, |packet| packet.len() > 3);
+        assert_eq!(latest.as_deref(), Some(&b"second"[..]));
+        assert_eq!(receiver.latest(|_| true).unwrap(), None);
+
+        sender.send(b"first").unwrap();
+        sender.send(b"bad").unwrap();
+        sender.send(b"second").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            receiver.all(|packet| packet.len() > 3).unwrap(),
+            [b"first".to_vec(), b"second".to_vec()]
+        );
+        assert!(receiver.all(|_| true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_connector_receives_latest_packet() {
+        // a free port, released again for the connector to bind
+        let addr = UdpReceiver::bind((Ipv4Addr::LOCALHOST, 0).into())
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut connector = PacketConnector::<TestPacket>::create(addr);
+        assert!(!connector.connect());
+        assert_eq!(connector.info().id, *b"test");
+
+        let sim = UdpSender::new(addr).unwrap();
+        sim.send(b"packet 1").unwrap();
+        // another kind of packet on the same port
+        sim.send(b"extradata=0 packet").unwrap();
+        let connected = (0..100).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            connector.connect()
+        });
+        assert!(connected);
+        assert_eq!(connector.update().as_deref(), Some(&b"packet 1"[..]));
+        assert_eq!(connector.update(), None);
+
+        sim.send(b"packet 2").unwrap();
+        sim.send(b"packet 3").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(connector.update().as_deref(), Some(&b"packet 3"[..]));
+        assert_eq!(connector.update(), None);
+    }
+
+    #[test]
+    fn test_player_rebroadcasts_packets() {
+        let mut app = UdpReceiver::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let mut player = PacketPlayer::<TestPacket>::create(app.local_addr().unwrap()).unwrap();
+
+        player.update(b"packet 1").unwrap();
+        assert!(player.update(b"packet").is_err());
+        assert_eq!(player.expected_frame_size(), Some(8..=8));
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            app.latest(|_| true).unwrap().as_deref(),
+            Some(&b"packet 1"[..])
+        );
+    }
+}